@@ -54,7 +54,9 @@ pub enum Action {
     Player(PlayerAction),
     ListAction(ListAction),
     Playlists(PlaylistsAction),
+    Queue(QueueAction),
     FileBrowser(FileBrowserAction),
+    Search(SearchAction),
 }
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug, Hash, EnumString, Ord, PartialOrd)]
@@ -91,6 +93,8 @@ pub enum ListAction {
     OpenClose,
     CollapseAll,
     ExpandAll,
+    FilterSongs,
+    EditTags,
 }
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug, Hash, EnumString, Ord, PartialOrd)]
@@ -103,6 +107,8 @@ pub enum ScreenAction {
     Queue,
     FileBrowser,
     Help,
+    Search,
+    Stats,
 }
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug, Hash, EnumString, Ord, PartialOrd)]
@@ -113,10 +119,18 @@ pub enum PlayerAction {
     VolumeDown,
     SeekForwards,
     SeekBackwards,
+    SpeedUp,
+    SpeedDown,
+    SpeedReset,
+    EqualizerNext,
+    EqualizerPrevious,
+    SelectOutputDevice,
     RepeatNone,
     RepeatOne,
     RepeatQueue,
     RepeatToggle,
+    ShuffleToggle,
+    RadioToggle,
 }
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug, Hash, EnumString, Ord, PartialOrd)]
@@ -136,6 +150,20 @@ pub enum PlaylistsAction {
     ViewToggleAlbum,
     ViewToggleYear,
     ViewToggleTrackNumber,
+    NewSmartPlaylist,
+    EditSmartPlaylist,
+    Export,
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash, EnumString, Ord, PartialOrd)]
+pub enum QueueAction {
+    /// Keeps a song radio mode suggested, as if it had been added by hand.
+    Promote,
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug, Hash, EnumString, Ord, PartialOrd)]
+pub enum SearchAction {
+    AddToPlaylist,
 }
 
 impl TryFrom<&str> for Action {
@@ -169,8 +197,12 @@ impl TryFrom<&str> for Action {
             ListAction::try_from(child).map(Action::ListAction)
         } else if parent == "Playlists" {
             PlaylistsAction::try_from(child).map(Action::Playlists)
+        } else if parent == "Queue" {
+            QueueAction::try_from(child).map(Action::Queue)
         } else if parent == "FileBrowser" {
             FileBrowserAction::try_from(child).map(Action::FileBrowser)
+        } else if parent == "Search" {
+            SearchAction::try_from(child).map(Action::Search)
         } else {
             Err(strum::ParseError::VariantNotFound)
         }
@@ -236,8 +268,13 @@ impl Actions {
             .cloned()
             .unwrap_or_default();
 
+        // Ctrl and Alt make keys actions rather than characters, unless they're pressed together, like AltGr is.
+        let is_typed = !key.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+            || key.modifiers.contains(KeyModifiers::CONTROL | KeyModifiers::ALT);
+
         if let KeyCode::Char(c) = key.code
-            && (c.is_alphanumeric() || c.is_ascii_whitespace())
+            && is_typed
+            && !c.is_control()
         {
             actions.push(Action::Text(TextAction::Char(c)));
         }
@@ -1,26 +1,40 @@
 use std::{
+    collections::HashSet,
     error::Error,
+    path::{Path, PathBuf},
     sync::{
         Arc,
+        Mutex,
         atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, channel},
     },
+    thread,
     time::Duration,
 };
 
 use crossterm::{event, event::Event};
+use notify::{RecursiveMode, Watcher};
+use ratatui_image::picker::Picker;
 use tokio::task;
 
+#[cfg(unix)]
+use crate::control::{self, ControlServer};
 use crate::{
-    actions::{Action, Actions, OnAction, OnActionMut},
+    actions::{Action, Actions, OnAction, OnActionMut, PlayerAction},
     components::*,
-    main_player::MainPlayer,
+    files::{LibraryChanges, LibraryIndex, expand_home},
+    main_player::{MainPlayer, Session},
     mpris::Mpris,
+    output::OutputTarget,
     settings::Settings,
     state::State,
+    structs::Song,
     term::set_terminal,
     theme::Theme,
 };
 
+const LIBRARY_SETTLE_TIME: Duration = Duration::from_secs(2);
+
 pub async fn run() -> Result<(), Box<dyn Error>> {
     #[cfg(target_os = "linux")]
     let mpris = match Mpris::new().await {
@@ -56,11 +70,25 @@ fn run_sync(mpris: Option<Mpris>) -> Result<(), Box<dyn Error>> {
     let settings = Settings::from_file();
     let theme = Theme::from_built_in(settings.theme);
 
+    // The terminal is asked which graphics protocols it supports, so this has to happen before reading any events.
+    let picker = settings.album_art.then(|| {
+        Picker::from_query_stdio().unwrap_or_else(|err| {
+            log::warn!("Could not query the terminal for graphics support. Falling back to half blocks. {err:?}");
+            Picker::halfblocks()
+        })
+    });
+
     let state = State::from_file();
 
     // if _output_stream is dropped playback will end & attached `OutputStreamHandle`s will no longer work.
     // Creating the output_stream indirectly spawns the cpal_alsa_out thread, and creates the mixer tied to it.
-    let player = Arc::new(MainPlayer::spawn(mpris, state.queue_items));
+    let player = Arc::new(MainPlayer::spawn(
+        mpris,
+        state.queue_items,
+        settings.clone(),
+        OutputTarget::Device(settings.output_device.clone()),
+        Session::App,
+    ));
     let queue_changed = Arc::new(AtomicBool::default());
 
     player.on_queue_changed({
@@ -71,15 +99,29 @@ fn run_sync(mpris: Option<Mpris>) -> Result<(), Box<dyn Error>> {
         }
     });
 
+    // Errors are reported from the player threads. The root component picks them up on the next frame.
+    let player_error = Arc::new(Mutex::new(None::<String>));
+
     player.on_error({
+        let player_error = player_error.clone();
         move |error| {
             log::error!("Error reported by multi_track_player: {error}");
-            // TODO: communicate to root component
+            *player_error.lock().unwrap() = Some(error);
         }
     });
 
+    // Lets scripts control the player, like MPRIS does, but without D-Bus.
+    #[cfg(unix)]
+    let _control_server = ControlServer::start(control::socket_path(), Arc::downgrade(&player))
+        .inspect_err(|err| log::warn!("Could not start the control socket. Error was: {err}"))
+        .ok();
+
+    let library_folders = settings.library_folders.clone();
+
     let focus_stolen = Arc::new(AtomicBool::default());
-    let mut root_component = Root::new(&actions, settings, theme, Arc::downgrade(&player));
+    let mut root_component = Root::new(&actions, settings, theme, Arc::downgrade(&player), picker);
+
+    let library_changes = scan_library_folders(&library_folders, root_component.library_songs());
 
     root_component.on_queue_changed({
         let player = player.clone();
@@ -109,10 +151,18 @@ fn run_sync(mpris: Option<Mpris>) -> Result<(), Box<dyn Error>> {
     loop {
         if queue_changed.swap(false, Ordering::AcqRel) {
             player.queue().with_items(|songs| {
-                root_component.set_queue(songs.clone().into());
+                root_component.set_queue(songs.clone().into(), player.suggested_songs());
             });
         }
 
+        if let Some(error) = player_error.lock().unwrap().take() {
+            root_component.show_error(error);
+        }
+
+        while let Ok(changes) = library_changes.try_recv() {
+            root_component.apply_library_changes(changes);
+        }
+
         terminal.draw(|frame| {
             frame.render_widget(&mut root_component, frame.area());
         })?;
@@ -127,12 +177,16 @@ fn run_sync(mpris: Option<Mpris>) -> Result<(), Box<dyn Error>> {
             if actions.contains(&Action::Quit) {
                 break;
             } else if let Some(action) = actions.iter().find_map(|action| {
-                if let Action::Player(action) = action {
+                // Picking an output device takes the command line, which belongs to the root component.
+                if let Action::Player(action) = action
+                    && *action != PlayerAction::SelectOutputDevice
+                {
                     Some(action)
                 } else {
                     None
                 }
             }) && !focus_stolen.load(Ordering::Relaxed)
+                && !(root_component.is_typing() && actions.iter().any(|action| matches!(action, Action::Text(_))))
             {
                 player.on_action(vec![*action]);
                 player.single_track_player().on_action(vec![*action]);
@@ -174,3 +228,115 @@ fn run_sync(mpris: Option<Mpris>) -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+/// Reading the tags of a whole library can take a while, so the library folders are scanned while the UI is already up.
+/// After that, they're watched, and scanned again when something changes in them.
+/// Songs of `library_songs` the scan finds for the first time keep their id. The folders of the ones that were added by
+/// hand from elsewhere are watched too, for files that go missing or come back.
+fn scan_library_folders(folders: &[PathBuf], library_songs: Vec<Song>) -> Receiver<LibraryChanges> {
+    let (sender, receiver) = channel();
+
+    let folders: Vec<PathBuf> = folders.iter().map(|folder| expand_home(folder)).collect();
+    let outside: Vec<Song> = library_songs
+        .iter()
+        .filter(|song| !folders.iter().any(|folder| song.path.starts_with(folder)))
+        .cloned()
+        .collect();
+
+    if folders.is_empty() && outside.is_empty() {
+        return receiver;
+    }
+
+    thread::Builder::new()
+        .name("library_scan".to_string())
+        .spawn(move || {
+            let mut index = LibraryIndex::from_file();
+            index.seed_ids(&library_songs);
+            drop(library_songs);
+
+            let mut missing: HashSet<PathBuf> = outside
+                .iter()
+                .map(|song| song.path.clone())
+                .filter(|path| !path.exists())
+                .collect();
+
+            let mut rescan = |index: &mut LibraryIndex| {
+                let mut changes = LibraryChanges::default();
+
+                if !folders.is_empty() {
+                    changes = index.rescan(&folders);
+                    if let Err(err) = index.to_file() {
+                        log::error!("Could not save the library index. Error was: {err}");
+                    }
+                }
+
+                for song in &outside {
+                    let Some(id) = song.library_id else {
+                        continue;
+                    };
+                    if !song.path.exists() {
+                        if missing.insert(song.path.clone()) {
+                            changes.missing.push(id);
+                        }
+                    } else if missing.remove(&song.path) {
+                        changes.found.push(id);
+                    }
+                }
+
+                log::debug!(
+                    "library scan: {} added, {} modified, {} removed, {} missing, {} found",
+                    changes.added.len(),
+                    changes.modified.len(),
+                    changes.removed.len(),
+                    changes.missing.len(),
+                    changes.found.len()
+                );
+
+                changes.is_empty() || sender.send(changes).is_ok()
+            };
+
+            if !rescan(&mut index) {
+                return;
+            }
+
+            let (events_sender, events) = channel::<notify::Result<notify::Event>>();
+            let mut watcher = match notify::recommended_watcher(events_sender) {
+                Ok(watcher) => watcher,
+                Err(err) => {
+                    log::warn!("Could not watch the library folders. Error was: {err}");
+                    return;
+                }
+            };
+            for folder in &folders {
+                if let Err(err) = watcher.watch(folder, RecursiveMode::Recursive) {
+                    log::warn!("Could not watch {folder:?}. Error was: {err}");
+                }
+            }
+            let outside_folders: HashSet<&Path> = outside.iter().filter_map(|song| song.path.parent()).collect();
+            for folder in outside_folders {
+                if let Err(err) = watcher.watch(folder, RecursiveMode::NonRecursive) {
+                    log::warn!("Could not watch {folder:?}. Error was: {err}");
+                }
+            }
+
+            // Reading files, like the scan itself does, is reported too, and is not a change.
+            let is_change = |event: notify::Result<notify::Event>| event.is_ok_and(|event| !event.kind.is_access());
+
+            while let Ok(event) = events.recv() {
+                if !is_change(event) {
+                    continue;
+                }
+
+                // Copying an album takes a while. Wait for things to settle down, instead of scanning once per file.
+                while events.recv_timeout(LIBRARY_SETTLE_TIME).is_ok() {}
+
+                if !rescan(&mut index) {
+                    break;
+                }
+            }
+            log::trace!("library scan thread will close now.");
+        })
+        .unwrap();
+
+    receiver
+}
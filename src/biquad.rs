@@ -0,0 +1,89 @@
+use std::f64::consts::PI;
+
+/// A second order IIR filter, in transposed direct form II.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// Coefficients are expected to be normalized already, so `a0` is 1 and left out.
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self { b, a, z: [0.0; 2] }
+    }
+
+    fn normalized(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self::new([b[0] / a[0], b[1] / a[0], b[2] / a[0]], [a[1] / a[0], a[2] / a[0]])
+    }
+
+    // The following are from Robert Bristow-Johnson's Audio EQ Cookbook.
+
+    pub fn peaking(sample_rate: u32, frequency: f64, q: f64, gain: f64) -> Self {
+        let (a, cos, alpha) = Self::intermediates(sample_rate, frequency, q, gain);
+        Self::normalized([1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a], [
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        ])
+    }
+
+    pub fn low_shelf(sample_rate: u32, frequency: f64, q: f64, gain: f64) -> Self {
+        let (a, cos, alpha) = Self::intermediates(sample_rate, frequency, q, gain);
+        let sq = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            [
+                a * ((a + 1.0) - (a - 1.0) * cos + sq),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - sq),
+            ],
+            [
+                (a + 1.0) + (a - 1.0) * cos + sq,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - sq,
+            ],
+        )
+    }
+
+    pub fn high_shelf(sample_rate: u32, frequency: f64, q: f64, gain: f64) -> Self {
+        let (a, cos, alpha) = Self::intermediates(sample_rate, frequency, q, gain);
+        let sq = 2.0 * a.sqrt() * alpha;
+        Self::normalized(
+            [
+                a * ((a + 1.0) + (a - 1.0) * cos + sq),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - sq),
+            ],
+            [
+                (a + 1.0) - (a - 1.0) * cos + sq,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - sq,
+            ],
+        )
+    }
+
+    fn intermediates(sample_rate: u32, frequency: f64, q: f64, gain: f64) -> (f64, f64, f64) {
+        let sample_rate = sample_rate as f64;
+        // Frequencies at or above Nyquist would make the filter unstable.
+        let frequency = frequency.clamp(1.0, sample_rate * 0.49);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let a = 10f64.powf(gain / 40.0);
+        let alpha = w0.sin() / (2.0 * q.max(0.01));
+        (a, w0.cos(), alpha)
+    }
+
+    /// Takes the coefficients of another filter, keeping the state of this one, so the output doesn't click.
+    pub fn set_coefficients(&mut self, other: &Biquad) {
+        self.b = other.b;
+        self.a = other.a;
+    }
+
+    #[inline]
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
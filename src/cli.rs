@@ -1,4 +1,11 @@
-use std::{borrow::Cow, io::stdout, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::stdout,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
 
 use clap::{Parser, Subcommand, ValueEnum};
 use crossterm::{
@@ -17,15 +24,24 @@ use lofty::{
     tag::{ItemValue, Tag},
 };
 use log::error;
+use serde::Serialize;
 
+#[cfg(unix)]
+use crate::control::{self, Request, Response};
 use crate::{
     actions::{Action, Actions, DEFAULT_ACTIONS_STR},
     auto_update::{CARGO_PKG_VERSION, RELEASE_VERSION_OVERRIDE},
+    components::{saved_library_songs, saved_soundtrack_songs, song_vec_to_map},
     cue::CueSheet,
     duration::duration_to_string,
-    main_player::MainPlayer,
+    files::{Loudness, Relinker, SongStats, TrackLoudness, edit_stored_songs, relink_stored_songs},
+    loudness::{LoudnessMeter, REFERENCE_LOUDNESS, integrated_loudness, measure_song},
+    main_player::{MainPlayer, Session},
+    output::OutputTarget,
+    scrobble::{LASTFM_API_URL, ScrobbleError, lastfm_auth_url, lastfm_session_key, lastfm_token},
     settings::Settings,
-    structs::Song,
+    structs::{Song, export_playlist_file},
+    ui::song_to_string,
 };
 
 #[derive(Parser, Debug)]
@@ -35,6 +51,14 @@ struct Args {
 
     #[arg(value_name = "FILE")]
     path: Option<PathBuf>,
+
+    /// If Jolteon is already running, add FILE to the front of its queue, rather than the back.
+    #[arg(long, requires = "path", default_value_t = false)]
+    next: bool,
+
+    /// If Jolteon is already running, add FILE to the front of its queue and skip to it.
+    #[arg(long, requires = "path", conflicts_with = "next", default_value_t = false)]
+    play_now: bool,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -76,6 +100,89 @@ enum Command {
         #[arg(value_enum, short, long, default_value_t = OutputFormat::Text)]
         output: OutputFormat,
     },
+    /// Measure the EBU R128 loudness and true peak of a file, cue sheet, or the songs in a directory.
+    Analyze {
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+
+        #[arg(value_enum, short, long, default_value_t = OutputFormat::Text)]
+        output: OutputFormat,
+
+        /// Store the results, so they're used as ReplayGain for songs that have no ReplayGain tags.
+        #[arg(short, long, default_value_t = false)]
+        save: bool,
+    },
+    /// Play a file, cue sheet, or the songs in a directory into a WAV file, as they'd sound in the player.
+    Render {
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+
+        /// How many times faster than real time to play.
+        #[arg(short, long, default_value_t = 20.0)]
+        pace: f32,
+    },
+    /// List songs in the library, soundtracks, playlists and the queue whose files are missing, and point them to
+    /// where the files are now.
+    Relink {
+        /// A folder that was moved or renamed. Missing files that were in it are looked for in --to.
+        #[arg(long, value_name = "FOLDER", requires = "to")]
+        from: Option<PathBuf>,
+
+        /// Where the folder given with --from is now.
+        #[arg(long, value_name = "FOLDER", requires = "from")]
+        to: Option<PathBuf>,
+
+        /// Folders to look for missing files in, by path and then by their tags. Can be given more than once.
+        #[arg(short, long, value_name = "FOLDER")]
+        search: Vec<PathBuf>,
+
+        /// Only list what would be relinked.
+        #[arg(short = 'n', long, default_value_t = false)]
+        dry_run: bool,
+    },
+    /// Authorize Jolteon to scrobble to a Last.fm account, and print the settings to do it with.
+    /// The API key and secret are those of an API account, from https://www.last.fm/api/account/create.
+    LastfmLogin {
+        #[arg(long)]
+        api_key: String,
+
+        #[arg(long)]
+        api_secret: String,
+    },
+    /// Work with the playlists in ~/.config/jolteon/playlists.toml.
+    Playlist {
+        #[command(subcommand)]
+        command: PlaylistCommand,
+    },
+    /// Control the running instance of Jolteon, through its control socket.
+    #[cfg(unix)]
+    Ctl {
+        #[command(subcommand)]
+        request: Request,
+
+        #[arg(value_enum, short, long, default_value_t = OutputFormat::Text, global = true)]
+        output: OutputFormat,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum PlaylistCommand {
+    /// Write a playlist to an M3U, M3U8, PLS or XSPF file, for other players. The format is picked by the file's
+    /// extension.
+    Export {
+        #[arg(value_name = "NAME")]
+        name: String,
+
+        #[arg(short, long, value_name = "FILE")]
+        output: PathBuf,
+
+        /// Write the paths of songs relative to the folder of FILE, rather than absolute.
+        #[arg(short, long, default_value_t = false)]
+        relative: bool,
+    },
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -85,6 +192,33 @@ enum ColorOption {
     Never,
 }
 
+/// Hands `path` over to the instance of Jolteon that's already running, if there's one, and exits.
+/// File managers open files this way, and expect them to end up in the player that's already open.
+#[cfg(unix)]
+fn enqueue_in_running_instance(path: &Path, next: bool, play_now: bool) {
+    let socket_path = control::socket_path();
+    let request = Request::Enqueue {
+        // The running instance doesn't share our working directory.
+        path: path.canonicalize().unwrap_or_else(|_| path.to_path_buf()),
+        front: next,
+        play_now,
+    };
+
+    match control::send(&socket_path, &request) {
+        Ok(Response::Error(err)) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        Ok(_) => {
+            println!("Added {path:?} to the queue of the running instance of Jolteon");
+            std::process::exit(0);
+        }
+        Err(err) => {
+            log::debug!("No running instance of Jolteon at {socket_path:?} ({err}). Playing {path:?} here.");
+        }
+    }
+}
+
 /// Parses cli arguments. If a command is passed, this function will run it and exit the process.
 ///
 /// This function returns no value, but it will directly exit the process if
@@ -102,6 +236,9 @@ pub fn cli() {
         Some(command) => command,
         None => {
             if let Some(path) = args.path {
+                #[cfg(unix)]
+                enqueue_in_running_instance(&path, args.next, args.play_now);
+
                 if path.ends_with(".cue") {
                     Command::Cue {
                         path,
@@ -144,7 +281,15 @@ pub fn cli() {
             );
 
             let song_length = song.length;
-            let player = Arc::new(MainPlayer::spawn(None, vec![song]));
+            let settings = Settings::from_file();
+            let output_target = OutputTarget::Device(settings.output_device.clone());
+            let player = Arc::new(MainPlayer::spawn(
+                None,
+                vec![song],
+                settings,
+                output_target,
+                Session::Standalone,
+            ));
 
             player.on_error({
                 move |error| {
@@ -308,6 +453,367 @@ pub fn cli() {
                 print_tag_items(tag, false);
             }
         }
+        Command::Analyze { path, output, save } => {
+            let path = path.canonicalize().unwrap_or(path);
+            let songs = songs_at_path(&path);
+
+            if songs.is_empty() {
+                eprintln!("No songs found at {path:?}");
+                std::process::exit(1);
+            }
+
+            let mut meters: HashMap<Song, LoudnessMeter> = HashMap::new();
+
+            for song in &songs {
+                eprintln!("Analyzing {:?}", song.title);
+                match measure_song(song) {
+                    Ok(meter) => {
+                        meters.insert(song.clone(), meter);
+                    }
+                    Err(err) => {
+                        eprintln!("Could not analyze {:?}: {err}", song.path);
+                    }
+                }
+            }
+
+            let mut albums: HashMap<Song, (Option<f64>, f64)> = HashMap::new();
+
+            for album_songs in song_vec_to_map(meters.keys().cloned().collect())
+                .into_values()
+                .flat_map(HashMap::into_values)
+            {
+                let blocks: Vec<f64> = album_songs
+                    .iter()
+                    .flat_map(|song| meters[song].blocks().iter().copied())
+                    .collect();
+                let loudness = integrated_loudness(&blocks);
+                let peak = album_songs
+                    .iter()
+                    .map(|song| meters[song].true_peak())
+                    .fold(0.0, f64::max);
+
+                for song in album_songs {
+                    albums.insert(song, (loudness, peak));
+                }
+            }
+
+            let tracks: Vec<(&Song, TrackLoudness)> = songs
+                .iter()
+                .filter_map(|song| {
+                    let meter = meters.get(song)?;
+                    let album = albums.get(song);
+                    Some((song, TrackLoudness {
+                        path: song.path.clone(),
+                        start_time: song.start_time,
+                        loudness: meter.integrated_loudness(),
+                        peak: meter.true_peak(),
+                        album_loudness: album.and_then(|(loudness, _)| *loudness),
+                        album_peak: album.map(|(_, peak)| *peak),
+                    }))
+                })
+                .collect();
+
+            if output == OutputFormat::Text {
+                fn loudness_to_string(loudness: Option<f64>) -> String {
+                    match loudness {
+                        Some(loudness) => format!(
+                            "{loudness:.2} LUFS (gain {gain:+.2} dB)",
+                            gain = REFERENCE_LOUDNESS - loudness
+                        ),
+                        None => "silent".to_string(),
+                    }
+                }
+
+                fn peak_to_string(peak: f64) -> String {
+                    format!("true peak {:.2} dBTP", 20.0 * peak.log10())
+                }
+
+                for (song, track) in &tracks {
+                    println!("{}", song.title);
+                    println!(
+                        "  track: {}, {}",
+                        loudness_to_string(track.loudness),
+                        peak_to_string(track.peak)
+                    );
+                    if let Some(album_peak) = track.album_peak {
+                        println!(
+                            "  album: {}, {}",
+                            loudness_to_string(track.album_loudness),
+                            peak_to_string(album_peak)
+                        );
+                    }
+                }
+            } else {
+                #[derive(Serialize)]
+                struct AnalyzedSong<'a> {
+                    title: &'a str,
+                    artist: Option<&'a str>,
+                    album: Option<&'a str>,
+                    #[serde(flatten)]
+                    loudness: &'a TrackLoudness,
+                }
+
+                let tracks: Vec<AnalyzedSong> = tracks
+                    .iter()
+                    .map(|(song, track)| AnalyzedSong {
+                        title: &song.title,
+                        artist: song.artist.as_deref(),
+                        album: song.album.as_deref(),
+                        loudness: track,
+                    })
+                    .collect();
+
+                match serde_json::to_string_pretty(&tracks) {
+                    Ok(tracks) => {
+                        println!("{tracks}");
+                    }
+                    Err(err) => {
+                        error!("{err:#?}");
+                    }
+                };
+            }
+
+            if save {
+                let mut loudness = Loudness::from_file();
+                loudness.merge(tracks.into_iter().map(|(_, track)| track).collect());
+                if let Err(err) = loudness.to_file() {
+                    eprintln!("Could not save results! {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Render { path, output, pace } => {
+            if pace.is_nan() || pace <= 0.0 {
+                eprintln!("The pace must be greater than 0");
+                std::process::exit(1);
+            }
+
+            let path = path.canonicalize().unwrap_or(path);
+            let songs = songs_at_path(&path);
+
+            if songs.is_empty() {
+                eprintln!("No songs found at {path:?}");
+                std::process::exit(1);
+            }
+
+            let total_length: Duration = songs.iter().map(|song| song.length).sum();
+            eprintln!(
+                "Rendering {} song(s), {} long, into {output:?}",
+                songs.len(),
+                duration_to_string(total_length)
+            );
+
+            let (queue_ended_tx, queue_ended_rx) = std::sync::mpsc::channel();
+            let output_target = OutputTarget::Wav { path: output, pace };
+            let player = MainPlayer::spawn(None, songs, Settings::from_file(), output_target, Session::Standalone);
+
+            player.on_error(|error| {
+                eprintln!("{error}");
+            });
+
+            player.on_queue_ended(move || {
+                let _ = queue_ended_tx.send(());
+            });
+
+            let mut last_song = None;
+            while queue_ended_rx.recv_timeout(Duration::from_millis(100)).is_err() {
+                let song = player.playing_song();
+                if song.is_some() && song != last_song {
+                    eprintln!("{}", song.as_ref().map_or("", |song| song.title.as_str()));
+                    last_song = song;
+                }
+            }
+
+            // The file is finished once the player lets go of the output.
+            player.quit();
+        }
+        Command::Relink {
+            from,
+            to,
+            search,
+            dry_run,
+        } => {
+            #[cfg(unix)]
+            if !dry_run && control::send(&control::socket_path(), &Request::Status).is_ok() {
+                eprintln!(
+                    "Jolteon is running. Close it first, or it'll undo the changes when it saves its own on close."
+                );
+                std::process::exit(1);
+            }
+
+            let absolute = |path: PathBuf| path.canonicalize().unwrap_or(path);
+            let relinker = Relinker {
+                moved: from
+                    .zip(to)
+                    .map(|(from, to)| (std::path::absolute(&from).unwrap_or(from), absolute(to))),
+                search: search.into_iter().map(absolute).collect(),
+            };
+
+            let mut songs = Vec::new();
+            if let Err(err) = edit_stored_songs(|song| {
+                songs.push(song.clone());
+                false
+            }) {
+                eprintln!("Could not read the stored songs: {err}");
+                std::process::exit(1);
+            }
+
+            let mut missing: Vec<&Path> = songs
+                .iter()
+                .map(|song| song.path.as_path())
+                .filter(|path| !path.exists())
+                .collect();
+            missing.sort();
+            missing.dedup();
+
+            if missing.is_empty() {
+                println!("No files are missing.");
+                std::process::exit(0);
+            }
+
+            let moves = relinker.find(&songs);
+
+            for path in &missing {
+                match moves.get(*path) {
+                    Some(new_path) => println!("{} -> {}", path.display(), new_path.display()),
+                    None => println!("{} (missing)", path.display()),
+                }
+            }
+            println!("Found {} of {} missing files.", moves.len(), missing.len());
+
+            if !dry_run && !moves.is_empty() {
+                match relink_stored_songs(&moves) {
+                    Ok(count) => println!("Relinked {count} songs."),
+                    Err(err) => {
+                        eprintln!("Could not save the relinked songs: {err}");
+                        std::process::exit(1);
+                    }
+                }
+            }
+        }
+        Command::LastfmLogin { api_key, api_secret } => {
+            // The HTTP client blocks, which it can't do on the async runtime's threads.
+            let result = std::thread::spawn(move || {
+                let token = lastfm_token(LASTFM_API_URL, &api_key, &api_secret)?;
+                println!("Open this link, and allow Jolteon to access your account:");
+                println!("{}", lastfm_auth_url(&api_key, &token));
+                println!("Then press Enter.");
+                let _ = std::io::stdin().read_line(&mut String::new());
+                let session_key = lastfm_session_key(LASTFM_API_URL, &api_key, &api_secret, &token)?;
+                println!("Add this to ~/.config/jolteon/settings.toml:\n");
+                println!("[scrobbling.lastfm]");
+                println!("api_key = {api_key:?}");
+                println!("api_secret = {api_secret:?}");
+                println!("session_key = {session_key:?}");
+                Ok::<(), ScrobbleError>(())
+            })
+            .join()
+            .unwrap();
+            if let Err(err) = result {
+                eprintln!("Could not log in to Last.fm: {err}");
+                std::process::exit(1);
+            }
+        }
+        Command::Playlist {
+            command: PlaylistCommand::Export { name, output, relative },
+        } => {
+            let playlists = crate::files::Playlists::from_file();
+            let Some(playlist) = playlists.playlists.into_iter().find(|pl| pl.name == name) else {
+                eprintln!("There is no playlist called {name:?}");
+                std::process::exit(1);
+            };
+            let songs = match &playlist.smart {
+                Some(smart) => {
+                    let mut library = saved_library_songs();
+                    library.extend(saved_soundtrack_songs());
+                    smart.songs(&library, &SongStats::from_file())
+                }
+                None => playlist.songs,
+            };
+            if let Err(err) = export_playlist_file(&output, &playlist.name, &songs, relative) {
+                eprintln!("Could not export the playlist: {err}");
+                std::process::exit(1);
+            }
+            println!("Wrote {} song(s) to {output:?}", songs.len());
+        }
+        #[cfg(unix)]
+        Command::Ctl { mut request, output } => {
+            // The running instance doesn't share our working directory.
+            if let Request::Enqueue { path, .. } = &mut request {
+                *path = path.canonicalize().unwrap_or_else(|_| path.clone());
+            }
+
+            let socket_path = control::socket_path();
+            let response = match control::send(&socket_path, &request) {
+                Ok(response) => response,
+                Err(err) => {
+                    eprintln!("Could not talk to Jolteon at {socket_path:?}. Is it running? ({err})");
+                    std::process::exit(1);
+                }
+            };
+
+            if output == OutputFormat::Json {
+                match serde_json::to_string_pretty(&response) {
+                    Ok(response) => {
+                        println!("{response}");
+                    }
+                    Err(err) => {
+                        error!("{err:#?}");
+                    }
+                };
+            }
+
+            match response {
+                Response::Error(err) => {
+                    eprintln!("{err}");
+                    std::process::exit(1);
+                }
+                _ if output == OutputFormat::Json => {}
+                Response::Ok => {}
+                Response::Status(status) => {
+                    match &status.song {
+                        Some(song) => {
+                            println!("Playing: {}", song_to_string(song));
+                            println!(
+                                "Position: {} / {}",
+                                duration_to_string(Duration::from_secs_f64(status.position)),
+                                duration_to_string(song.length)
+                            );
+                        }
+                        None => {
+                            println!("Not playing");
+                        }
+                    }
+                    if status.is_paused {
+                        println!("Paused");
+                    }
+                    println!("Volume: {}%", status.volume);
+                    println!("Speed: {:.2}×", status.speed as f32 / 100.);
+                    println!("Repeat: {:?}", status.repeat_mode);
+                    println!("Shuffle: {:?}", status.shuffle_mode);
+                    if status.radio {
+                        println!("Radio: on");
+                    }
+                    println!(
+                        "Queue: {} song(s), {}",
+                        status.queue_length,
+                        duration_to_string(Duration::from_secs_f64(status.queue_duration))
+                    );
+                }
+                Response::Queue(songs) => {
+                    for (index, song) in songs.iter().enumerate() {
+                        println!(
+                            "{index:>3}  {}  ({})",
+                            song_to_string(song),
+                            duration_to_string(song.length)
+                        );
+                    }
+                }
+            }
+
+            // Output is meant for scripts, so it doesn't get the trailing empty line the other commands print.
+            std::process::exit(0);
+        }
         Command::Cue { path, flat, output } => {
             let cue = CueSheet::from_file(path.as_path());
             match cue {
@@ -350,3 +856,10 @@ pub fn cli() {
 
     std::process::exit(0);
 }
+
+fn songs_at_path(path: &Path) -> Vec<Song> {
+    Song::from_path(path).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        vec![]
+    })
+}
@@ -1,3 +1,4 @@
+mod album_art;
 mod file_browser;
 mod focus_group;
 mod help;
@@ -7,18 +8,27 @@ mod playlists;
 mod query;
 mod queue;
 mod root;
+mod search;
 mod song_list;
 mod soundtracks;
+mod stats;
 mod tree;
 
-pub use file_browser::{FileBrowser, FileBrowserSelection, dir_entry_is_song, directory_to_songs_and_folders};
+pub use album_art::AlbumArt;
+pub use file_browser::{
+    FileBrowser, FileBrowserSelection, VALID_EXTENSIONS, dir_entry_is_song, directory_to_songs_and_folders,
+    path_is_not_hidden,
+};
 pub use focus_group::*;
 pub use help::Help;
 pub use library::*;
 pub use list::List;
 pub use playlists::Playlists;
+pub use query::{FilterError, SongFilter};
 pub use queue::Queue;
 pub use root::*;
+pub use search::{Search, SearchSource};
 pub use song_list::{SongList, SongListViewOptions};
 pub use soundtracks::*;
+pub use stats::Stats;
 pub use tree::*;
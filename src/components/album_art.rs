@@ -0,0 +1,3 @@
+mod album_art;
+
+pub use album_art::AlbumArt;
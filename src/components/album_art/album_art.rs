@@ -0,0 +1,268 @@
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        Mutex,
+        mpsc::{Sender, channel},
+    },
+    thread,
+};
+
+use image::DynamicImage;
+use lofty::{file::TaggedFileExt, picture::PictureType, probe::Probe};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Rect, Size},
+    widgets::{Widget, WidgetRef},
+};
+use ratatui_image::{Image, Resize, picker::Picker, protocol::Protocol};
+
+/// Looked for next to songs that have no picture embedded, in this order, regardless of case.
+const COVER_FILE_NAMES: [&str; 3] = ["cover", "folder", "front"];
+const COVER_FILE_EXTENSIONS: [&str; 3] = ["jpg", "jpeg", "png"];
+
+/// Pictures are scaled down to fit this many pixels on each side as soon as they're loaded.
+/// That's already more than the panels they're shown in, and keeps encoding them for the terminal cheap.
+const MAX_PICTURE_SIZE: u32 = 600;
+
+/// The picture embedded in the file, preferably the front cover, or else one next to it, like `cover.jpg`.
+pub fn find_album_art(path: &Path) -> Option<DynamicImage> {
+    embedded_picture(path)
+        .or_else(|| cover_file(path))
+        .map(|image| image.thumbnail(MAX_PICTURE_SIZE, MAX_PICTURE_SIZE))
+}
+
+fn embedded_picture(path: &Path) -> Option<DynamicImage> {
+    let tagged_file = Probe::open(path).ok()?.read().ok()?;
+    let pictures: Vec<_> = tagged_file.tags().iter().flat_map(|tag| tag.pictures()).collect();
+    let picture = pictures
+        .iter()
+        .find(|picture| picture.pic_type() == PictureType::CoverFront)
+        .or(pictures.first())?;
+
+    image::load_from_memory(picture.data())
+        .inspect_err(|err| log::warn!("could not decode the picture embedded in {path:?}: {err}"))
+        .ok()
+}
+
+fn cover_file(path: &Path) -> Option<DynamicImage> {
+    let files: Vec<PathBuf> = path
+        .parent()?
+        .read_dir()
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+
+    let matches = |file: &PathBuf, name: &str| {
+        let is = |part: Option<&std::ffi::OsStr>, expected: &str| {
+            part.and_then(|part| part.to_str())
+                .is_some_and(|part| part.eq_ignore_ascii_case(expected))
+        };
+        is(file.file_stem(), name)
+            && COVER_FILE_EXTENSIONS
+                .iter()
+                .any(|extension| is(file.extension(), extension))
+    };
+
+    let file = COVER_FILE_NAMES
+        .iter()
+        .find_map(|name| files.iter().find(|file| matches(file, name)))?;
+
+    image::open(file)
+        .inspect_err(|err| log::warn!("could not decode {file:?}: {err}"))
+        .ok()
+}
+
+#[derive(Default)]
+struct State {
+    path: Option<PathBuf>,
+    picture: Option<DynamicImage>,
+    /// The picture, encoded for the terminal to fit in an area of the given size.
+    protocol: Option<(Size, Protocol)>,
+}
+
+/// The album art of one song at a time.
+///
+/// Pictures are loaded in a separate thread, and encoded for the terminal once per size they're rendered at,
+/// so rendering them on every frame stays cheap.
+/// Without a picker, which is how album art is turned off, nothing is ever shown.
+pub struct AlbumArt {
+    picker: Option<Picker>,
+    requests: Sender<PathBuf>,
+    loaded: Arc<Mutex<Option<(PathBuf, Option<DynamicImage>)>>>,
+    state: RefCell<State>,
+}
+
+impl AlbumArt {
+    pub fn new(picker: Option<Picker>) -> Self {
+        let (requests, requests_rx) = channel::<PathBuf>();
+        let loaded = Arc::new(Mutex::new(None));
+
+        if picker.is_some() {
+            thread::Builder::new()
+                .name("album_art".to_string())
+                .spawn({
+                    let loaded = loaded.clone();
+                    move || {
+                        while let Ok(mut path) = requests_rx.recv() {
+                            // Only the latest request matters. The others were skipped past already.
+                            while let Ok(newer) = requests_rx.try_recv() {
+                                path = newer;
+                            }
+                            let picture = find_album_art(&path);
+                            *loaded.lock().unwrap() = Some((path, picture));
+                        }
+                        log::trace!("album art thread will close now.");
+                    }
+                })
+                .unwrap();
+        }
+
+        Self {
+            picker,
+            requests,
+            loaded,
+            state: RefCell::new(State::default()),
+        }
+    }
+
+    /// Shows the album art of the song at `path`, once it's loaded.
+    pub fn set_path(&self, path: Option<&Path>) {
+        let mut state = self.state.borrow_mut();
+        if state.path.as_deref() == path {
+            return;
+        }
+
+        *state = State {
+            path: path.map(Path::to_path_buf),
+            ..State::default()
+        };
+
+        if self.picker.is_some()
+            && let Some(path) = path
+        {
+            let _ = self.requests.send(path.to_path_buf());
+        }
+    }
+
+    /// Whether there's a picture to show. Callers use it to decide whether to make room for it.
+    pub fn has_picture(&self) -> bool {
+        let mut state = self.state.borrow_mut();
+
+        if let Ok(mut loaded) = self.loaded.try_lock()
+            && loaded
+                .as_ref()
+                .is_some_and(|(path, _)| Some(path) == state.path.as_ref())
+            && let Some((_, picture)) = loaded.take()
+        {
+            state.picture = picture;
+            state.protocol = None;
+        }
+
+        state.picture.is_some()
+    }
+}
+
+impl WidgetRef for AlbumArt {
+    fn render_ref(&self, area: Rect, buf: &mut Buffer) {
+        let (Some(picker), true) = (&self.picker, self.has_picture()) else {
+            return;
+        };
+
+        let mut state = self.state.borrow_mut();
+        let size = area.as_size();
+
+        if state
+            .protocol
+            .as_ref()
+            .is_none_or(|(protocol_size, _)| *protocol_size != size)
+            && let Some(picture) = state.picture.clone()
+        {
+            state.protocol = picker
+                .new_protocol(picture, size, Resize::Fit(None))
+                .inspect_err(|err| log::error!("could not encode album art: {err}"))
+                .ok()
+                .map(|protocol| (size, protocol));
+        }
+
+        if let Some((_, protocol)) = &state.protocol {
+            Image::new(protocol).render(area, buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, Instant},
+    };
+
+    use image::{Rgb, RgbImage};
+    use ratatui::style::Color;
+
+    use super::*;
+
+    #[test]
+    fn finds_cover_files() {
+        let directory = std::env::temp_dir().join(format!("jolteon-album-art-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let song = directory.join("song.wav");
+        fs::write(&song, b"not really a song").unwrap();
+
+        assert!(find_album_art(&song).is_none());
+
+        RgbImage::from_pixel(4, 4, Rgb([0, 0, 255]))
+            .save(directory.join("Folder.PNG"))
+            .unwrap();
+        RgbImage::from_pixel(2000, 1000, Rgb([255, 0, 0]))
+            .save(directory.join("cover.png"))
+            .unwrap();
+
+        // `cover` is preferred over `folder`, and large pictures are scaled down.
+        let picture = find_album_art(&song).unwrap();
+        assert_eq!(
+            (picture.width(), picture.height()),
+            (MAX_PICTURE_SIZE, MAX_PICTURE_SIZE / 2)
+        );
+        assert_eq!(picture.to_rgb8().get_pixel(0, 0), &Rgb([255, 0, 0]));
+
+        fs::remove_file(directory.join("cover.png")).unwrap();
+        let picture = find_album_art(&song).unwrap();
+        assert_eq!(picture.to_rgb8().get_pixel(0, 0), &Rgb([0, 0, 255]));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn renders_half_blocks() {
+        let directory = std::env::temp_dir().join(format!("jolteon-album-art-render-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let song = directory.join("song.wav");
+        RgbImage::from_pixel(8, 8, Rgb([255, 0, 0]))
+            .save(directory.join("cover.jpg"))
+            .unwrap();
+
+        let album_art = AlbumArt::new(Some(Picker::halfblocks()));
+        album_art.set_path(Some(&song));
+
+        let start = Instant::now();
+        while !album_art.has_picture() {
+            assert!(start.elapsed() < Duration::from_secs(10), "the picture never loaded");
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let area = Rect::new(0, 0, 8, 4);
+        let mut buf = Buffer::empty(area);
+        album_art.render_ref(area, &mut buf);
+        // A solid color ends up as the background of blank cells.
+        assert!(matches!(buf[(0, 0)].bg, Color::Rgb(r, g, b) if r > 200 && g < 50 && b < 50));
+
+        album_art.set_path(None);
+        assert!(!album_art.has_picture());
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
 mod current_directory;
+mod directory_watcher;
 pub mod file_browser;
 mod file_browser_selection;
 mod file_meta;
@@ -7,4 +8,6 @@ pub mod keyboard_handler;
 pub mod widget;
 
 pub use file_browser::*;
-pub use file_browser_selection::{FileBrowserSelection, dir_entry_is_song, directory_to_songs_and_folders};
+pub use file_browser_selection::{
+    FileBrowserSelection, VALID_EXTENSIONS, dir_entry_is_song, directory_to_songs_and_folders, path_is_not_hidden,
+};
@@ -0,0 +1,117 @@
+use std::{
+    cell::RefCell,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+/// How long the watched directories must go without changes before the file browser reads them again.
+/// A file being copied changes many times per second.
+const SETTLE_TIME: Duration = Duration::from_millis(500);
+
+/// Watches the directories the file browser is showing, so it can read them again when files are added, removed,
+/// renamed or written to.
+pub struct DirectoryWatcher {
+    watcher: RefCell<Option<RecommendedWatcher>>,
+    watched: RefCell<Vec<PathBuf>>,
+    changed_at: Arc<Mutex<Option<Instant>>>,
+}
+
+impl DirectoryWatcher {
+    pub fn new() -> Self {
+        let changed_at = Arc::new(Mutex::new(None));
+
+        let watcher = notify::recommended_watcher({
+            let changed_at = changed_at.clone();
+            move |event: notify::Result<notify::Event>| {
+                // The file browser reads the files it shows, and that's reported too.
+                if event.is_ok_and(|event| !event.kind.is_access()) {
+                    *changed_at.lock().unwrap() = Some(Instant::now());
+                }
+            }
+        })
+        .inspect_err(|err| log::warn!("Could not watch the file browser's directories. Error was: {err}"))
+        .ok();
+
+        Self {
+            watcher: RefCell::new(watcher),
+            watched: RefCell::new(vec![]),
+            changed_at,
+        }
+    }
+
+    /// Watches `directories`, and stops watching the ones that were watched before but aren't in it anymore.
+    pub fn watch(&self, directories: Vec<PathBuf>) {
+        let mut watched = self.watched.borrow_mut();
+        if *watched == directories {
+            return;
+        }
+
+        let mut watcher = self.watcher.borrow_mut();
+        let Some(watcher) = watcher.as_mut() else {
+            return;
+        };
+
+        for directory in watched.iter().filter(|d| !directories.contains(d)) {
+            if let Err(err) = watcher.unwatch(directory) {
+                log::debug!("DirectoryWatcher: could not unwatch {directory:?}: {err}");
+            }
+        }
+        for directory in directories.iter().filter(|d| !watched.contains(d)) {
+            if let Err(err) = watcher.watch(directory, RecursiveMode::NonRecursive) {
+                log::debug!("DirectoryWatcher: could not watch {directory:?}: {err}");
+            }
+        }
+
+        *watched = directories;
+    }
+
+    /// Whether something changed in the watched directories, and they've been quiet for a moment since.
+    pub fn take_change(&self) -> bool {
+        let mut changed_at = self.changed_at.lock().unwrap();
+        if changed_at.is_some_and(|changed_at| changed_at.elapsed() >= SETTLE_TIME) {
+            *changed_at = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread};
+
+    use super::*;
+
+    #[test]
+    fn reports_changes_once_they_settle() {
+        let directory = std::env::temp_dir().join(format!("jolteon-directory-watcher-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+
+        let watcher = DirectoryWatcher::new();
+        watcher.watch(vec![directory.clone()]);
+
+        fs::write(directory.join("new.txt"), "new").unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!watcher.take_change(), "changes are only reported once they settle");
+
+        thread::sleep(SETTLE_TIME);
+        assert!(watcher.take_change());
+        assert!(!watcher.take_change());
+
+        // Reading files is not a change.
+        fs::read(directory.join("new.txt")).unwrap();
+        thread::sleep(SETTLE_TIME + Duration::from_millis(100));
+        assert!(!watcher.take_change());
+
+        watcher.watch(vec![]);
+        fs::remove_file(directory.join("new.txt")).unwrap();
+        thread::sleep(SETTLE_TIME + Duration::from_millis(100));
+        assert!(!watcher.take_change(), "directories that are no longer watched are not reported");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -15,11 +15,13 @@ use std::{
 
 use super::{
     current_directory::CurrentDirectory,
+    directory_watcher::DirectoryWatcher,
     file_browser_selection::{FileBrowserSelection, directory_to_songs_and_folders},
 };
 use crate::{
     actions::Actions,
     components::{
+        AlbumArt,
         FocusGroup,
         List,
         file_browser::{file_meta::FileMeta, help::FileBrowserHelp},
@@ -44,22 +46,26 @@ pub struct FileBrowser<'a> {
     pub(super) history: Rc<RefCell<HashMap<PathBuf, (usize, usize)>>>,
 
     pub(super) current_directory: Rc<CurrentDirectory>,
+    pub(super) directory_watcher: DirectoryWatcher,
     pub(super) on_enqueue_fn: Rc<RefCell<Option<Box<dyn Fn(Vec<Song>) + 'a>>>>,
     pub(super) on_add_to_lib_fn: Rc<RefCell<Option<Box<dyn Fn(Vec<Song>) + 'a>>>>,
+    pub(super) on_import_playlist_fn: Rc<RefCell<Option<Box<dyn Fn(String, Vec<Song>) + 'a>>>>,
 
     pub(super) show_hidden_files: Arc<AtomicBool>,
 }
 
 impl<'a> FileBrowser<'a> {
-    pub fn new(actions: &'a Actions, theme: Theme, current_directory: PathBuf) -> Self {
+    pub fn new(actions: &'a Actions, theme: Theme, current_directory: PathBuf, album_art: AlbumArt) -> Self {
         let show_hidden_files = Arc::new(AtomicBool::new(false));
         let items = directory_to_songs_and_folders(&current_directory, show_hidden_files.load(Ordering::Acquire));
         let mut children_list = List::new(theme, vec![]);
-        let file_meta = Rc::new(FileMeta::new(theme));
+        let file_meta = Rc::new(FileMeta::new(theme, album_art));
         let current_directory = Rc::new(CurrentDirectory::new(theme, current_directory));
         let history = Rc::new(RefCell::new(HashMap::new()));
         let on_enqueue_fn: Rc<RefCell<Option<Box<dyn Fn(Vec<Song>) + 'a>>>> = Rc::new(RefCell::new(None));
         let on_add_to_lib_fn: Rc<RefCell<Option<Box<dyn Fn(Vec<Song>) + 'a>>>> = Rc::new(RefCell::new(None));
+        let on_import_playlist_fn: Rc<RefCell<Option<Box<dyn Fn(String, Vec<Song>) + 'a>>>> =
+            Rc::new(RefCell::new(None));
 
         let (io_thread, files_from_io_thread) = {
             let (tx, rx) = channel::<PathBuf>();
@@ -98,7 +104,9 @@ impl<'a> FileBrowser<'a> {
         };
 
         children_list.line_style(|i| match i {
-            FileBrowserSelection::Song(_) | FileBrowserSelection::CueSheet(_) => None,
+            FileBrowserSelection::Song(_) | FileBrowserSelection::CueSheet(_) | FileBrowserSelection::Playlist(_) => {
+                None
+            }
             _ => Some(ratatui::style::Style::new().add_modifier(ratatui::style::Modifier::DIM)),
         });
         children_list.on_select({
@@ -124,14 +132,25 @@ impl<'a> FileBrowser<'a> {
                         let songs = Song::from_cue_sheet(cue);
                         on_enqueue_fn(songs);
                     }
+                    FileBrowserSelection::Playlist(playlist) => {
+                        on_enqueue_fn(playlist.songs());
+                    }
                     _ => {}
                 }
             }
         });
         children_list.on_confirm_alt({
             let on_add_to_lib_fn = Rc::clone(&on_add_to_lib_fn);
+            let on_import_playlist_fn = Rc::clone(&on_import_playlist_fn);
 
             move |item| {
+                if let FileBrowserSelection::Playlist(playlist) = item {
+                    if let Some(cb) = &*on_import_playlist_fn.borrow() {
+                        cb(playlist.name.clone(), playlist.songs());
+                    }
+                    return;
+                }
+
                 let cb = on_add_to_lib_fn.borrow();
 
                 let Some(cb) = &*cb else {
@@ -250,13 +269,27 @@ impl<'a> FileBrowser<'a> {
                         on_enqueue_fn(songs);
                     }
                 }
+                FileBrowserSelection::Playlist(playlist) => {
+                    let on_enqueue_fn = on_enqueue_fn.borrow();
+                    if let Some(on_enqueue_fn) = &*on_enqueue_fn {
+                        on_enqueue_fn(playlist.songs());
+                    }
+                }
                 _ => {}
             }
         });
         parents_list.on_confirm_alt({
             let on_add_to_lib_fn = Rc::clone(&on_add_to_lib_fn);
+            let on_import_playlist_fn = Rc::clone(&on_import_playlist_fn);
 
             move |item| {
+                if let FileBrowserSelection::Playlist(playlist) = item {
+                    if let Some(cb) = &*on_import_playlist_fn.borrow() {
+                        cb(playlist.name.clone(), playlist.songs());
+                    }
+                    return;
+                }
+
                 let cb = on_add_to_lib_fn.borrow();
 
                 let Some(cb) = &*cb else {
@@ -297,8 +330,10 @@ impl<'a> FileBrowser<'a> {
             files_from_io_thread,
 
             current_directory,
+            directory_watcher: DirectoryWatcher::new(),
             on_enqueue_fn,
             on_add_to_lib_fn,
+            on_import_playlist_fn,
             history,
             help: FileBrowserHelp::new(actions, theme),
 
@@ -325,6 +360,11 @@ impl<'a> FileBrowser<'a> {
         *self.on_add_to_lib_fn.borrow_mut() = Some(Box::new(cb));
     }
 
+    /// Called with the name and songs of playlist files, to turn them into playlists.
+    pub fn on_import_playlist(&self, cb: impl Fn(String, Vec<Song>) + 'a) {
+        *self.on_import_playlist_fn.borrow_mut() = Some(Box::new(cb));
+    }
+
     pub fn navigate_up(&self) {
         let current_directory = self.current_directory.path();
 
@@ -376,6 +416,63 @@ impl<'a> FileBrowser<'a> {
     pub fn current_directory(&self) -> PathBuf {
         self.current_directory.path()
     }
+
+    /// The songs of the selected file or folder in the focused list. The file whose details are shown counts as selected.
+    pub fn selected_songs(&self) -> Vec<Song> {
+        let list = if self.parents_list.is_focused() {
+            &self.parents_list
+        } else {
+            &self.children_list
+        };
+        selected_file(list)
+            .map(FileBrowserSelection::into_songs)
+            .unwrap_or_default()
+    }
+
+    /// Reads the current directory and the selected one again if something changed in them, keeping the selections
+    /// where they were.
+    pub(super) fn refresh_if_changed(&self) {
+        let mut directories = vec![self.current_directory.path()];
+        if let Some(FileBrowserSelection::Directory(path)) = selected_file(&self.parents_list) {
+            directories.push(path);
+        }
+        self.directory_watcher.watch(directories);
+
+        if !self.directory_watcher.take_change() {
+            return;
+        }
+
+        log::debug!("FileBrowser: refreshing {:?}", self.current_directory.path());
+
+        let show_hidden_files = self.show_hidden_files.load(Ordering::Acquire);
+
+        let parents = directory_to_songs_and_folders(&self.current_directory.path(), show_hidden_files);
+        set_items_keeping_selection(&self.parents_list, parents);
+
+        let children = match selected_file(&self.parents_list) {
+            Some(FileBrowserSelection::Directory(path)) => directory_to_songs_and_folders(&path, show_hidden_files),
+            _ => vec![],
+        };
+        set_items_keeping_selection(&self.children_list, children);
+
+        match selected_file(&self.children_list) {
+            Some(file) => self.file_meta.set_file(file),
+            None => self.file_meta.clear(),
+        }
+    }
+}
+
+fn selected_file(list: &List<'_, FileBrowserSelection>) -> Option<FileBrowserSelection> {
+    list.with_items(|files| files.get(list.selected_index()).map(|file| (*file).clone()))
+}
+
+fn set_items_keeping_selection(list: &List<'_, FileBrowserSelection>, files: Vec<FileBrowserSelection>) {
+    let selected_path = selected_file(list).map(|file| file.to_path());
+    let selected_index = selected_path
+        .and_then(|path| files.iter().position(|file| file.to_path() == path))
+        .unwrap_or_else(|| list.selected_index().min(files.len().saturating_sub(1)));
+    let scroll_position = list.scroll_position().min(selected_index);
+    list.set_items_s(files, selected_index, scroll_position);
 }
 
 impl Drop for FileBrowser<'_> {
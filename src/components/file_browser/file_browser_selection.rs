@@ -7,7 +7,7 @@ use std::{
 
 use crate::{
     cue::CueSheet,
-    structs::{Jolt, Song},
+    structs::{Jolt, PlaylistFile, PlaylistFormat, Song},
 };
 
 pub const VALID_EXTENSIONS: [&str; 7] = ["mp3", "mp4", "m4a", "wav", "flac", "ogg", "aac"];
@@ -16,6 +16,7 @@ pub const VALID_EXTENSIONS: [&str; 7] = ["mp3", "mp4", "m4a", "wav", "flac", "og
 pub enum FileBrowserSelection {
     Song(Song),
     CueSheet(CueSheet),
+    Playlist(PlaylistFile),
     Directory(PathBuf),
     Jolt(Jolt),
     Other(PathBuf),
@@ -27,15 +28,29 @@ impl FileBrowserSelection {
             Some(FileBrowserSelection::Directory(path.to_path_buf()))
         } else if path.extension().is_some_and(|e| e == "cue") {
             CueSheet::from_file(path).ok().map(FileBrowserSelection::CueSheet)
+        } else if PlaylistFormat::from_path(path).is_some() {
+            PlaylistFile::from_file(path).ok().map(FileBrowserSelection::Playlist)
         } else {
             Song::from_file(path).ok().map(FileBrowserSelection::Song)
         }
     }
 
+    /// The songs that get queued for this file or directory.
+    pub fn into_songs(self) -> Vec<Song> {
+        match self {
+            FileBrowserSelection::Song(song) => vec![song],
+            FileBrowserSelection::Directory(path) => Song::from_dir(&path),
+            FileBrowserSelection::CueSheet(cue_sheet) => Song::from_cue_sheet(cue_sheet),
+            FileBrowserSelection::Playlist(playlist) => playlist.songs(),
+            FileBrowserSelection::Jolt(_) | FileBrowserSelection::Other(_) => vec![],
+        }
+    }
+
     pub fn to_path(&self) -> PathBuf {
         match self {
             FileBrowserSelection::Song(s) => s.path.clone(),
             FileBrowserSelection::CueSheet(cs) => cs.cue_sheet_file_path(),
+            FileBrowserSelection::Playlist(p) => p.path.clone(),
             FileBrowserSelection::Directory(p) => p.clone(),
             FileBrowserSelection::Jolt(j) => j.path.clone(),
             FileBrowserSelection::Other(p) => p.clone(),
@@ -60,6 +75,10 @@ impl PartialEq for FileBrowserSelection {
                 }
                 _ => false,
             },
+            FileBrowserSelection::Playlist(playlist) => match other {
+                FileBrowserSelection::Playlist(other_playlist) => playlist.path == other_playlist.path,
+                _ => false,
+            },
             FileBrowserSelection::Song(song) => match other {
                 FileBrowserSelection::Song(other_song) => song.path == other_song.path,
                 _ => false,
@@ -107,6 +126,16 @@ impl Ord for FileBrowserSelection {
                     _ => Ordering::Less,
                 }
             }
+            FileBrowserSelection::Playlist(playlist) => {
+                // then playlist files
+                match other {
+                    FileBrowserSelection::Directory(_) => Ordering::Greater,
+                    FileBrowserSelection::Jolt(_) => Ordering::Greater,
+                    FileBrowserSelection::CueSheet(_) => Ordering::Greater,
+                    FileBrowserSelection::Playlist(other_playlist) => playlist.path.cmp(&other_playlist.path),
+                    _ => Ordering::Less,
+                }
+            }
             FileBrowserSelection::Song(song) => {
                 // then songs
                 match other {
@@ -133,7 +162,7 @@ fn dir_entry_to_file_browser_selection(entry: &DirEntry) -> Option<FileBrowserSe
         match Song::from_file(&entry.path()).map(FileBrowserSelection::Song) {
             Ok(a) => Some(a),
             Err(err) => {
-                log::warn!("dir_entry_to_file_browser_selection {:#?} {:#?}", &entry.path(), err);
+                log::warn!("dir_entry_to_file_browser_selection {:#?} {:#?}", entry.path(), err);
                 None
             }
         }
@@ -141,6 +170,14 @@ fn dir_entry_to_file_browser_selection(entry: &DirEntry) -> Option<FileBrowserSe
         Some(FileBrowserSelection::CueSheet(
             CueSheet::from_file(&entry.path()).unwrap(),
         ))
+    } else if dir_entry_is_playlist(entry) {
+        match PlaylistFile::from_file(&entry.path()) {
+            Ok(playlist) => Some(FileBrowserSelection::Playlist(playlist)),
+            Err(err) => {
+                log::warn!("Could not read playlist file {:?}: {err}", entry.path());
+                Some(FileBrowserSelection::Other(entry.path()))
+            }
+        }
     } else if dir_entry_is_jolt_file(entry) {
         match Jolt::from_path(entry.path()) {
             Ok(jolt) => Some(FileBrowserSelection::Jolt(jolt)),
@@ -223,3 +260,7 @@ pub fn dir_entry_is_jolt_file(dir_entry: &DirEntry) -> bool {
 pub fn dir_entry_is_cue(dir_entry: &DirEntry) -> bool {
     dir_entry_is_file(dir_entry) && dir_entry_has_cue_extension(dir_entry)
 }
+
+pub fn dir_entry_is_playlist(dir_entry: &DirEntry) -> bool {
+    dir_entry_is_file(dir_entry) && PlaylistFormat::from_path(&dir_entry.path()).is_some()
+}
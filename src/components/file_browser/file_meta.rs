@@ -1,13 +1,17 @@
 use std::cell::RefCell;
 
-use ratatui::{buffer::Buffer, layout::Rect, widgets::WidgetRef};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    widgets::WidgetRef,
+};
 
 use crate::{
     actions::{Action, OnAction},
-    components::{FileBrowserSelection, List},
+    components::{AlbumArt, FileBrowserSelection, List},
     cue::CueSheet,
     duration::duration_to_string,
-    structs::{Jolt, Song},
+    structs::{Jolt, PlaylistFile, Song},
     theme::Theme,
     ui::Focusable,
 };
@@ -15,13 +19,15 @@ use crate::{
 pub struct FileMeta<'a> {
     file: RefCell<Option<FileBrowserSelection>>,
     list: List<'a, String>,
+    album_art: AlbumArt,
 }
 
 impl FileMeta<'_> {
-    pub fn new(theme: Theme) -> Self {
+    pub fn new(theme: Theme, album_art: AlbumArt) -> Self {
         Self {
             file: RefCell::new(None),
             list: List::new(theme, vec![]),
+            album_art,
         }
     }
 
@@ -29,14 +35,26 @@ impl FileMeta<'_> {
         match file {
             FileBrowserSelection::Song(ref song) => {
                 self.set_song(song);
+                self.album_art.set_path(Some(&song.path));
             }
             FileBrowserSelection::CueSheet(ref cue) => {
                 self.set_cue(cue);
+                // The picture embedded in the first file, or the one next to the cue sheet.
+                let cue_path = cue.cue_sheet_file_path();
+                let file_name = cue.files().first().map(|file| file.name()).unwrap_or_default();
+                self.album_art
+                    .set_path(cue_path.parent().map(|dir| dir.join(file_name)).as_deref());
+            }
+            FileBrowserSelection::Playlist(ref playlist) => {
+                self.set_playlist(playlist);
+                self.album_art.set_path(None);
             }
             FileBrowserSelection::Jolt(ref jolt) => {
                 self.set_jolt(jolt);
+                self.album_art.set_path(None);
             }
             FileBrowserSelection::Other(ref path) => {
+                self.album_art.set_path(None);
                 self.list
                     .set_items(vec!["File:".to_string(), format!("  {}", path.to_string_lossy())]);
                 if let Ok(meta) = path.metadata() {
@@ -57,6 +75,7 @@ impl FileMeta<'_> {
                 }
             }
             FileBrowserSelection::Directory(ref path) => {
+                self.album_art.set_path(None);
                 self.list
                     .set_items(vec!["Folder:".to_string(), format!("  {}", path.to_string_lossy())]);
                 if let Ok(children) = path.read_dir() {
@@ -143,6 +162,33 @@ impl FileMeta<'_> {
         self.list.set_items(items);
     }
 
+    pub fn set_playlist(&self, playlist: &PlaylistFile) {
+        let mut items: Vec<String> = vec![];
+
+        items.push(format!("Playlist: {}", playlist.name));
+        items.push(format!("Format: {}", playlist.format.to_string().to_uppercase()));
+
+        items.push(" ".to_string());
+        items.push(format!("Songs: {}", playlist.entries.len()));
+        for entry in &playlist.entries {
+            let file_name = entry.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let title = match (&entry.artist, &entry.title) {
+                (Some(artist), Some(title)) => format!("{artist} - {title}"),
+                (None, Some(title)) => title.clone(),
+                _ => file_name,
+            };
+            match entry.length {
+                Some(length) => items.push(format!("  {title} ({})", duration_to_string(length))),
+                None => items.push(format!("  {title}")),
+            }
+            if !entry.path.exists() {
+                items.push(format!("    Missing: {}", entry.path.to_string_lossy()));
+            }
+        }
+
+        self.list.set_items(items);
+    }
+
     pub fn set_jolt(&self, jolt: &Jolt) {
         let mut items: Vec<String> = vec![];
 
@@ -156,6 +202,7 @@ impl FileMeta<'_> {
 
     pub fn clear(&self) {
         self.list.set_items(vec![]);
+        self.album_art.set_path(None);
         let mut s = self.file.borrow_mut();
         *s = None;
     }
@@ -163,7 +210,22 @@ impl FileMeta<'_> {
 
 impl WidgetRef for FileMeta<'_> {
     fn render_ref(&self, area: Rect, buf: &mut Buffer) {
-        self.list.render_ref(area, buf);
+        if !self.album_art.has_picture() {
+            self.list.render_ref(area, buf);
+            return;
+        }
+
+        // Cells are about twice as tall as they are wide, so this is roughly square, unless it'd take most of the area.
+        let album_art_width = (area.height * 2).min(area.width / 2);
+        let [area_list, _, area_album_art] = Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(album_art_width),
+        ])
+        .areas(area);
+
+        self.list.render_ref(area_list, buf);
+        self.album_art.render_ref(area_album_art, buf);
     }
 }
 
@@ -48,6 +48,8 @@ impl WidgetRef for FileBrowser<'_> {
             }
         };
 
+        self.refresh_if_changed();
+
         let [area_top, area_main, _, area_help] = Layout::vertical([
             Constraint::Length(2),
             Constraint::Min(10),
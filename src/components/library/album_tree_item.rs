@@ -13,6 +13,7 @@ pub struct Artist {
 }
 
 impl Artist {
+    #[allow(dead_code)]
     pub fn songs(&self) -> Vec<Song> {
         self.albums.iter().flat_map(|album| album.songs.clone()).collect()
     }
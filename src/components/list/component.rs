@@ -118,6 +118,12 @@ where
         cb(items_inner)
     }
 
+    pub fn with_items_mut<R>(&self, cb: impl FnOnce(Vec<&mut T>) -> R) -> R {
+        let mut items = self.items.borrow_mut();
+        let items_inner = items.iter_mut().map(|a| &mut a.inner).collect();
+        cb(items_inner)
+    }
+
     pub fn with_selected_item<R>(&self, cb: impl FnOnce(&T) -> R) -> R {
         let items = self.items.borrow();
         let i = self.selected_item_index.get();
@@ -35,6 +35,9 @@ impl OnActionMut for Playlists<'_> {
                         self.song_list.set_view_options(pl.view_options);
                     });
                 }
+                PlaylistsAction::NewSmartPlaylist | PlaylistsAction::EditSmartPlaylist | PlaylistsAction::Export => {
+                    // The rules and paths are typed into the command line, which belongs to the root component.
+                }
             },
 
             _ => {
@@ -1,10 +1,12 @@
-use std::rc::Rc;
+use std::{cell::RefCell, rc::Rc};
 
 use chrono::Local;
+use ratatui::style::{Modifier, Style};
 
 use crate::{
-    components::{FocusGroup, List, SongList},
-    structs::{Playlist, Song},
+    components::{FocusGroup, List, SongFilter, SongList},
+    files::SongStats,
+    structs::{Playlist, SmartPlaylist, Song},
     theme::Theme,
     ui::{Component, Focusable},
 };
@@ -16,11 +18,13 @@ pub struct Playlists<'a> {
     pub(super) song_list: Rc<SongList<'a>>,
     pub(super) focus_group: FocusGroup<'a>,
     pub(super) show_deleted_playlists: bool,
+    pub(super) library_songs_fn: Rc<RefCell<Box<dyn Fn() -> Vec<Song> + 'a>>>,
 }
 
 impl<'a> Playlists<'a> {
     pub fn new(theme: Theme) -> Self {
         let playlists_file = crate::files::Playlists::from_file();
+        let library_songs_fn: Rc<RefCell<Box<dyn Fn() -> Vec<Song> + 'a>>> = Rc::new(RefCell::new(Box::new(Vec::new)));
 
         let song_list = Rc::new(SongList::new(
             theme,
@@ -36,12 +40,22 @@ impl<'a> Playlists<'a> {
         }
 
         let mut playlist_list = List::new(theme, playlists_file.playlists);
+        playlist_list.line_style(|pl| {
+            pl.smart
+                .is_some()
+                .then(|| Style::default().add_modifier(Modifier::ITALIC))
+        });
         let deleted_playlist_list = Rc::new(List::new(theme, playlists_file.deleted));
 
         playlist_list.on_select({
             let song_list = song_list.clone();
+            let library_songs_fn = library_songs_fn.clone();
             move |pl| {
-                song_list.set_items(pl.songs.clone());
+                let songs = match &pl.smart {
+                    Some(smart) => smart_playlist_songs(smart, &library_songs_fn),
+                    None => pl.songs.clone(),
+                };
+                song_list.set_items(songs);
                 song_list.set_view_options(pl.view_options);
             }
         });
@@ -88,7 +102,10 @@ impl<'a> Playlists<'a> {
             move |a, b| {
                 log::debug!(target: "::playlists", "on_reorder {a} {b}");
                 playlist_list.with_selected_item_mut(move |pl| {
-                    pl.songs.swap(a, b);
+                    // The songs of smart playlists are sorted by their rules.
+                    if pl.smart.is_none() {
+                        pl.songs.swap(a, b);
+                    }
                 });
                 save(&playlist_list, &deleted_playlist_list);
             }
@@ -100,7 +117,10 @@ impl<'a> Playlists<'a> {
             move |song, index| {
                 log::trace!(target: "::playlists", "on_delete {index} {}", song.title);
                 playlist_list.with_selected_item_mut(move |pl| {
-                    pl.songs.remove(index);
+                    // Songs only leave smart playlists when they stop matching the rules.
+                    if pl.smart.is_none() {
+                        pl.songs.remove(index);
+                    }
                 });
                 save(&playlist_list, &deleted_playlist_list);
             }
@@ -123,9 +143,69 @@ impl<'a> Playlists<'a> {
             song_list,
             focus_group,
             show_deleted_playlists: false,
+            library_songs_fn,
+        }
+    }
+
+    /// The songs smart playlists pick from.
+    pub fn library_songs_fn(&self, f: impl Fn() -> Vec<Song> + 'a) {
+        *self.library_songs_fn.borrow_mut() = Box::new(f);
+        self.refresh_smart_playlists();
+    }
+
+    /// Evaluates the rules of every smart playlist again, like after the library changed.
+    pub fn refresh_smart_playlists(&self) {
+        let library = self.library_songs_fn.borrow()();
+        let stats = SongStats::from_file();
+
+        self.playlist_list.with_items_mut(|pls| {
+            for pl in pls {
+                if let Some(smart) = &pl.smart {
+                    pl.songs = smart.songs(&library, &stats);
+                }
+            }
+        });
+        let selected = self.playlist_list.with_items(|pls| {
+            pls.get(self.playlist_list.selected_index())
+                .filter(|pl| pl.smart.is_some())
+                .map(|pl| pl.songs.clone())
+        });
+        if let Some(songs) = selected {
+            self.song_list.set_items(songs);
         }
     }
 
+    pub fn add_smart_playlist(&self, smart: SmartPlaylist) {
+        let playlist = Playlist::new_smart(
+            format!(
+                "New smart playlist created at {}",
+                Local::now().format("%A %-l:%M:%S%P")
+            ),
+            smart,
+        );
+        self.playlist_list.push_item(playlist);
+        save(&self.playlist_list, &self.deleted_playlist_list);
+        self.refresh_smart_playlists();
+    }
+
+    /// Adds a playlist with `songs`, like the ones of a playlist file.
+    pub fn import_playlist(&self, name: String, songs: Vec<Song>) {
+        let mut playlist = Playlist::new(name);
+        playlist.songs = songs;
+        self.playlist_list.push_item(playlist);
+        save(&self.playlist_list, &self.deleted_playlist_list);
+    }
+
+    /// Replaces the rules of the selected playlist, if it's a smart one.
+    pub fn set_selected_smart_playlist(&self, smart: SmartPlaylist) {
+        self.selected_playlist_mut(|pl| {
+            if pl.smart.is_some() {
+                pl.smart = Some(smart);
+            }
+        });
+        self.refresh_smart_playlists();
+    }
+
     pub fn on_enter_song(&self, cb: impl Fn(Song) + 'a) {
         self.song_list.on_confirm(cb);
     }
@@ -135,7 +215,30 @@ impl<'a> Playlists<'a> {
     }
 
     pub fn on_enter_playlist(&self, cb: impl Fn(Playlist) + 'a) {
-        self.playlist_list.on_confirm(cb);
+        let library_songs_fn = self.library_songs_fn.clone();
+        self.playlist_list.on_confirm(move |mut pl| {
+            if let Some(smart) = &pl.smart {
+                pl.songs = smart_playlist_songs(smart, &library_songs_fn);
+            }
+            cb(pl);
+        });
+    }
+
+    pub fn song_filter(&self) -> Option<SongFilter> {
+        self.song_list.filter()
+    }
+
+    pub fn set_song_filter(&self, filter: Option<SongFilter>) {
+        self.song_list.set_filter(filter);
+    }
+
+    /// The selected song, while the song list is focused, or the listed songs of the selected playlist.
+    pub fn selected_songs(&self) -> Vec<Song> {
+        if self.song_list.is_focused() {
+            self.song_list.selected_song().into_iter().collect()
+        } else {
+            self.song_list.listed_songs()
+        }
     }
 
     pub fn on_request_focus_trap_fn(&self, cb: impl Fn(bool) + 'a) {
@@ -146,6 +249,26 @@ impl<'a> Playlists<'a> {
         self.playlist_list.with_items(f)
     }
 
+    /// The names of the playlists songs can be added to, which smart playlists aren't, and the one to suggest.
+    pub fn playlist_names_to_add_to(&self) -> (Vec<String>, Option<String>) {
+        let names: Vec<String> = self.playlist_list.with_items(|pls| {
+            pls.into_iter()
+                .filter(|pl| pl.smart.is_none())
+                .map(|pl| pl.name.clone())
+                .collect()
+        });
+        let selected = self
+            .playlist_list
+            .with_items(|pls| pls.get(self.playlist_list.selected_index()).map(|pl| pl.name.clone()))
+            .filter(|name| names.contains(name))
+            .or_else(|| names.first().cloned());
+        (names, selected)
+    }
+
+    pub fn selected_playlist_index(&self) -> usize {
+        self.playlist_list.selected_index()
+    }
+
     pub fn selected_playlist<T>(&self, f: impl FnOnce(&Playlist) -> T) -> T {
         self.playlist_list.with_selected_item(f)
     }
@@ -157,9 +280,62 @@ impl<'a> Playlists<'a> {
 
     pub fn add_songs(&self, mut songs: Vec<Song>) {
         self.selected_playlist_mut(move |pl| {
+            if pl.smart.is_some() {
+                log::warn!("Playlists.add_songs: {:?} is a smart playlist", pl.name);
+                return;
+            }
             pl.songs.append(&mut songs);
         });
     }
+
+    /// Calls `f` with every song of every playlist, deleted ones included, and saves them if `f` returned true for any.
+    /// The songs of smart playlists are left alone, since they're picked from the library again.
+    pub fn edit_songs(&self, mut f: impl FnMut(&mut Song) -> bool) {
+        let mut changed = false;
+        for list in [&self.playlist_list, &self.deleted_playlist_list] {
+            list.with_items_mut(|pls| {
+                for pl in pls.into_iter().filter(|pl| pl.smart.is_none()) {
+                    for song in &mut pl.songs {
+                        changed |= f(song);
+                    }
+                }
+            });
+        }
+        if !changed {
+            return;
+        }
+
+        save(&self.playlist_list, &self.deleted_playlist_list);
+        let selected = self.playlist_list.with_items(|pls| {
+            pls.get(self.playlist_list.selected_index())
+                .filter(|pl| pl.smart.is_none())
+                .map(|pl| pl.songs.clone())
+        });
+        if let Some(songs) = selected {
+            self.song_list.set_items(songs);
+        }
+    }
+
+    /// Adds songs to the first playlist called `name`.
+    pub fn add_songs_to(&self, name: &str, mut songs: Vec<Song>) {
+        let was_found = self.playlist_list.with_items_mut(|pls| {
+            pls.into_iter()
+                .find(|pl| pl.name == name && pl.smart.is_none())
+                .map(|pl| pl.songs.append(&mut songs))
+                .is_some()
+        });
+        if !was_found {
+            log::warn!("Playlists.add_songs_to: there is no playlist called {name:?}, or it's a smart playlist");
+            return;
+        }
+
+        save(&self.playlist_list, &self.deleted_playlist_list);
+        self.selected_playlist(|pl| {
+            if pl.name == name {
+                self.song_list.set_items(pl.songs.clone());
+            }
+        });
+    }
 }
 
 impl Drop for Playlists<'_> {
@@ -168,8 +344,24 @@ impl Drop for Playlists<'_> {
     }
 }
 
+fn smart_playlist_songs(
+    smart: &SmartPlaylist,
+    library_songs_fn: &RefCell<Box<dyn Fn() -> Vec<Song> + '_>>,
+) -> Vec<Song> {
+    smart.songs(&library_songs_fn.borrow()(), &SongStats::from_file())
+}
+
+/// The songs of smart playlists are left out, since they're evaluated again when loaded.
 fn clone_vec(v: Vec<&Playlist>) -> Vec<Playlist> {
-    v.into_iter().cloned().collect()
+    v.into_iter()
+        .map(|pl| match pl.smart {
+            Some(_) => Playlist {
+                songs: vec![],
+                ..pl.clone()
+            },
+            None => pl.clone(),
+        })
+        .collect()
 }
 
 fn save(playlist_list: &List<Playlist>, deleted_playlist_list: &List<Playlist>) {
@@ -1,5 +1,7 @@
 mod actions;
 mod component;
+mod filter;
 mod widget;
 
 pub use component::*;
+pub use filter::{FilterError, SongFilter};
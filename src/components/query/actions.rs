@@ -1,6 +1,11 @@
+use std::path::Path;
+
 use crate::{
-    actions::{Action, NavigationAction, OnActionMut},
-    components::query::{CommandLine, Query, QueryAddSongsTarget},
+    actions::{Action, NavigationAction, OnActionMut, TextAction},
+    components::query::{CommandLine, Query, QueryAddSongsTarget, SongFilter},
+    files::expand_home,
+    structs::{SmartPlaylist, export_playlist_file},
+    tag_edit::TagField,
 };
 
 impl OnActionMut for CommandLine<'_> {
@@ -35,6 +40,53 @@ impl OnActionMut for CommandLine<'_> {
                             continue;
                         };
                         match query {
+                            Query::OutputDevice { .. } => {
+                                self.on_confirm_fn.call(query);
+                            }
+                            Query::FilterSongs { ref text, .. } => {
+                                // The error is shown while typing. The filter stays open until it's fixed.
+                                if SongFilter::parse(text).is_ok() {
+                                    self.on_confirm_fn.call(query);
+                                } else {
+                                    self.query = Some(query);
+                                }
+                            }
+                            Query::SmartPlaylist { ref text, .. } => {
+                                if SmartPlaylist::parse(text).is_ok() {
+                                    self.on_confirm_fn.call(query);
+                                } else {
+                                    self.query = Some(query);
+                                }
+                            }
+                            Query::ExportPlaylist {
+                                ref name,
+                                ref songs,
+                                ref text,
+                                relative,
+                            } => {
+                                // Failures are shown right here, so the path can be fixed and tried again.
+                                if let Err(err) =
+                                    export_playlist_file(&expand_home(Path::new(text)), name, songs, relative)
+                                {
+                                    self.query_error = Some(format!("Could not export the playlist! {err}"));
+                                    self.query = Some(query);
+                                }
+                            }
+                            Query::EditTags(mut edit) => {
+                                if let Err(err) = edit.validate() {
+                                    self.query_error = Some(err.to_string());
+                                    self.query = Some(Query::EditTags(edit));
+                                    return;
+                                }
+                                let errors = edit.write();
+                                if let Some(err) = errors.first() {
+                                    self.query_error =
+                                        Some(format!("Could not write the tags of {} song(s)! {err}", errors.len()));
+                                }
+                                if !edit.songs().is_empty() {
+                                    self.on_confirm_fn.call(Query::EditTags(edit));
+                                }
+                            }
                             Query::AddSongs {
                                 songs,
                                 step,
@@ -102,8 +154,53 @@ impl OnActionMut for CommandLine<'_> {
                         }
                         return;
                     }
+                    Action::Text(action) if self.is_typing() => {
+                        let text = match self.query.as_mut() {
+                            Some(
+                                Query::FilterSongs { text, .. }
+                                | Query::SmartPlaylist { text, .. }
+                                | Query::ExportPlaylist { text, .. },
+                            ) => text,
+                            Some(Query::EditTags(edit)) => edit.text_mut(),
+                            _ => continue,
+                        };
+                        match action {
+                            TextAction::Char(c) => text.push(c),
+                            TextAction::DeleteBack => {
+                                text.pop();
+                            }
+                            TextAction::Delete => text.clear(),
+                        }
+                        return;
+                    }
+                    Action::Navigation(NavigationAction::FocusNext) => {
+                        match self.query.as_mut() {
+                            Some(Query::ExportPlaylist { relative, .. }) => *relative = !*relative,
+                            Some(Query::EditTags(edit))
+                                if edit.selected_field().field == TagField::Track && edit.songs().len() > 1 =>
+                            {
+                                edit.toggle_number_tracks();
+                            }
+                            _ => {}
+                        }
+                        return;
+                    }
+                    Action::Navigation(NavigationAction::Up) => {
+                        if let Some(Query::EditTags(edit)) = self.query.as_mut() {
+                            edit.select_previous();
+                        }
+                        return;
+                    }
+                    Action::Navigation(NavigationAction::Down) => {
+                        if let Some(Query::EditTags(edit)) = self.query.as_mut() {
+                            edit.select_next();
+                        }
+                        return;
+                    }
                     Action::Navigation(NavigationAction::Right) => {
-                        if let Some(Query::AddSongs {
+                        if let Some(Query::OutputDevice { devices, selected }) = self.query.as_mut() {
+                            *selected = (*selected + 1).min(devices.len().saturating_sub(1));
+                        } else if let Some(Query::AddSongs {
                             step,
                             target,
                             target_name,
@@ -113,9 +210,9 @@ impl OnActionMut for CommandLine<'_> {
                         {
                             if *step == 0 {
                                 *target = target.next();
-                            } else if *step == 1 && *target == QueryAddSongsTarget::Playlist {
+                            } else if *step == 1 && *target == QueryAddSongsTarget::Playlist && !playlists.is_empty() {
                                 let tn = target_name.clone().unwrap_or_default();
-                                let i = playlists.iter().position(|pl| *pl == tn).unwrap();
+                                let i = playlists.iter().position(|pl| *pl == tn).unwrap_or(0);
                                 let i = if i + 1 < playlists.len() {
                                     i + 1
                                 } else {
@@ -128,7 +225,9 @@ impl OnActionMut for CommandLine<'_> {
                         return;
                     }
                     Action::Navigation(NavigationAction::Left) => {
-                        if let Some(Query::AddSongs {
+                        if let Some(Query::OutputDevice { selected, .. }) = self.query.as_mut() {
+                            *selected = selected.saturating_sub(1);
+                        } else if let Some(Query::AddSongs {
                             step,
                             target,
                             target_name,
@@ -138,9 +237,9 @@ impl OnActionMut for CommandLine<'_> {
                         {
                             if *step == 0 {
                                 *target = target.prev();
-                            } else if *step == 1 && *target == QueryAddSongsTarget::Playlist {
+                            } else if *step == 1 && *target == QueryAddSongsTarget::Playlist && !playlists.is_empty() {
                                 let tn = target_name.clone().unwrap_or_default();
-                                let i = playlists.iter().position(|pl| *pl == tn).unwrap();
+                                let i = playlists.iter().position(|pl| *pl == tn).unwrap_or(0);
                                 let i = if i > 0 { i - 1 } else { 0 };
                                 let tn = playlists.get(i).unwrap().clone();
                                 *target_name = Some(tn);
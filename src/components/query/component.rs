@@ -1,6 +1,6 @@
 use strum::Display;
 
-use crate::{components::Callback, structs::Song, theme::Theme};
+use crate::{components::Callback, structs::Song, tag_edit::TagEdit, theme::Theme};
 
 pub struct CommandLine<'a> {
     pub(super) theme: Theme,
@@ -27,6 +27,28 @@ impl<'a> CommandLine<'a> {
         self.query = query
     }
 
+    /// Whether keys that type characters should go to the query, rather than be taken as actions.
+    pub fn is_typing(&self) -> bool {
+        matches!(
+            self.query,
+            Some(
+                Query::FilterSongs { .. }
+                    | Query::SmartPlaylist { .. }
+                    | Query::ExportPlaylist { .. }
+                    | Query::EditTags(_)
+            )
+        )
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.query_error.as_deref()
+    }
+
+    /// Shows an error until it's dismissed, with or without a query.
+    pub fn set_error(&mut self, error: Option<String>) {
+        self.query_error = error
+    }
+
     pub fn on_confirm(&self, f: impl Fn(Query) + 'a) {
         self.on_confirm_fn.set(f);
     }
@@ -41,6 +63,36 @@ pub enum Query {
         target_name: Option<String>,
         playlists: Vec<String>,
     },
+    OutputDevice {
+        /// `None` stands for the system default.
+        devices: Vec<Option<String>>,
+        selected: usize,
+    },
+    /// A `SongFilter` expression, for the song list of `target`.
+    FilterSongs {
+        target: QueryFilterSongsTarget,
+        text: String,
+    },
+    /// The rules of a new smart playlist, or of the selected one.
+    SmartPlaylist { is_new: bool, text: String },
+    /// The path of a playlist file to write the songs of a playlist to.
+    ExportPlaylist {
+        name: String,
+        songs: Vec<Song>,
+        text: String,
+        /// Whether the paths of songs are written relative to the playlist file.
+        relative: bool,
+    },
+    /// Changes to the tags of songs. The command line writes them, and confirms them with the songs read back from
+    /// the files that were written.
+    EditTags(TagEdit),
+}
+
+#[derive(Debug, Display, Clone, Copy, PartialEq, Eq)]
+pub enum QueryFilterSongsTarget {
+    Library,
+    Soundtracks,
+    Playlists,
 }
 #[derive(Debug, Display, Clone, PartialEq, Eq)]
 pub enum QueryAddSongsTarget {
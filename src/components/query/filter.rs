@@ -0,0 +1,478 @@
+use std::{
+    fmt::{Display, Formatter},
+    ops::RangeInclusive,
+    time::SystemTime,
+};
+
+use crate::{
+    files::{SongStat, SongStats},
+    structs::Song,
+};
+
+/// Songs that match every term of a filter expression, like `artist:"King Crimson" year:1969..1975 -title:live
+/// length:>10m`.
+///
+/// - Words without a field match the title, artist, album, soundtrack subject or year.
+/// - Text fields match when they contain the value, ignoring case. Values with spaces go in double quotes.
+/// - Number fields take a number (`year:1969`), a range (`year:1969..1975`, `year:1990..`, `year:..1980`) or a
+///   comparison (`track:>10`, `year:<=1970`). Lengths take seconds, `m:ss` or units, like `10m`, `3:30` or `1m30s`.
+/// - `plays` is how many times a library song was played, and `added` and `played` are how many days ago it was added to
+///   the library and last played, in days or units, like `2w` or `1y`. Songs that were never played have no `played`.
+/// - A `-` in front of a term leaves out the songs that match it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SongFilter {
+    text: String,
+    terms: Vec<Term>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Term {
+    is_negated: bool,
+    condition: Condition,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Condition {
+    Words(String),
+    Text(TextField, String),
+    Number(NumberField, RangeInclusive<u64>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TextField {
+    Artist,
+    Album,
+    Title,
+    Subject,
+    Path,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum NumberField {
+    Year,
+    Track,
+    Disc,
+    /// In seconds.
+    Length,
+    Plays,
+    /// In days ago.
+    Added,
+    /// In days ago.
+    Played,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FilterError {
+    UnterminatedQuote,
+    MissingValue(String),
+    UnknownField(String),
+    InvalidValue { field: String, value: String },
+}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnterminatedQuote => write!(f, "A quote is missing its closing quote."),
+            Self::MissingValue(term) => write!(f, "'{term}' is missing a value."),
+            Self::UnknownField(field) => write!(
+                f,
+                "There is no '{field}' field. Fields are artist, album, title, subject, path, year, track, disc, length, plays, added and played."
+            ),
+            Self::InvalidValue { field, value } => write!(f, "'{value}' is not a valid {field}."),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// Splits `text` at the spaces that aren't quoted. Each token comes with where its first unquoted `:` was, if it had
+/// one, and whether it started with an unquoted `-`. Quotes are left out.
+fn tokenize(text: &str) -> Result<Vec<(String, Option<usize>, bool)>, FilterError> {
+    let mut tokens = vec![];
+    let mut chars = text.chars().peekable();
+
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            return Ok(tokens);
+        }
+
+        let is_negated = chars.next_if_eq(&'-').is_some();
+        let mut token = String::new();
+        let mut colon = None;
+        let mut is_quoted = false;
+
+        while let Some(c) = chars.next_if(|c| is_quoted || !c.is_whitespace()) {
+            match c {
+                '"' => is_quoted = !is_quoted,
+                ':' if !is_quoted && colon.is_none() => {
+                    colon = Some(token.len());
+                    token.push(c);
+                }
+                c => token.push(c),
+            }
+        }
+
+        if is_quoted {
+            return Err(FilterError::UnterminatedQuote);
+        }
+        tokens.push((token, colon, is_negated));
+    }
+}
+
+/// A plain number, or numbers followed by one of `units`, like `1m30s`.
+fn parse_units(value: &str, units: &[(char, u64)]) -> Option<u64> {
+    if let Ok(number) = value.parse() {
+        return Some(number);
+    }
+
+    let mut total = 0;
+    let mut number = String::new();
+    for c in value.chars() {
+        match units.iter().find(|(unit, _)| *unit == c) {
+            _ if c.is_ascii_digit() => number.push(c),
+            Some((_, size)) if !number.is_empty() => {
+                total = number.parse::<u64>().ok()?.checked_mul(*size)?.checked_add(total)?;
+                number.clear();
+            }
+            _ => return None,
+        }
+    }
+    number.is_empty().then_some(total)
+}
+
+/// Seconds, `m:ss`, `h:mm:ss`, or numbers followed by `h`, `m` or `s`, like `1m30s`.
+fn parse_length(value: &str) -> Option<u64> {
+    if value.contains(':') {
+        return value.split(':').try_fold(0u64, |total, part| {
+            total.checked_mul(60)?.checked_add(part.parse().ok()?)
+        });
+    }
+    parse_units(value, &[('h', 3600), ('m', 60), ('s', 1)])
+}
+
+/// Days, or numbers followed by `d`, `w` or `y`, like `2w`.
+fn parse_days(value: &str) -> Option<u64> {
+    parse_units(value, &[('y', 365), ('w', 7), ('d', 1)])
+}
+
+fn parse_range(field: NumberField, value: &str) -> Option<RangeInclusive<u64>> {
+    let parse = |value: &str| match field {
+        NumberField::Length => parse_length(value),
+        NumberField::Added | NumberField::Played => parse_days(value),
+        _ => value.parse().ok(),
+    };
+
+    if let Some((start, end)) = value.split_once("..") {
+        let start = if start.is_empty() { 0 } else { parse(start)? };
+        let end = if end.is_empty() { u64::MAX } else { parse(end)? };
+        return Some(start..=end);
+    }
+
+    if let Some(value) = value.strip_prefix(">=") {
+        Some(parse(value)?..=u64::MAX)
+    } else if let Some(value) = value.strip_prefix("<=") {
+        Some(0..=parse(value)?)
+    } else if let Some(value) = value.strip_prefix('>') {
+        Some(parse(value)?.checked_add(1)?..=u64::MAX)
+    } else if let Some(value) = value.strip_prefix('<') {
+        Some(0..=parse(value)?.checked_sub(1)?)
+    } else {
+        let value = parse(value.strip_prefix('=').unwrap_or(value))?;
+        Some(value..=value)
+    }
+}
+
+fn parse_condition(token: &str, colon: Option<usize>) -> Result<Condition, FilterError> {
+    let Some(colon) = colon else {
+        return Ok(Condition::Words(token.to_lowercase()));
+    };
+
+    let (field, value) = (&token[..colon], &token[colon + 1..]);
+    if value.is_empty() {
+        return Err(FilterError::MissingValue(token.to_string()));
+    }
+
+    let text_field = match field.to_lowercase().as_str() {
+        "artist" => Some(TextField::Artist),
+        "album" => Some(TextField::Album),
+        "title" => Some(TextField::Title),
+        "subject" | "soundtrack" => Some(TextField::Subject),
+        "path" => Some(TextField::Path),
+        _ => None,
+    };
+    if let Some(text_field) = text_field {
+        return Ok(Condition::Text(text_field, value.to_lowercase()));
+    }
+
+    let number_field = match field.to_lowercase().as_str() {
+        "year" => NumberField::Year,
+        "track" => NumberField::Track,
+        "disc" => NumberField::Disc,
+        "length" => NumberField::Length,
+        "plays" => NumberField::Plays,
+        "added" => NumberField::Added,
+        "played" => NumberField::Played,
+        _ => return Err(FilterError::UnknownField(field.to_string())),
+    };
+    parse_range(number_field, value)
+        .map(|range| Condition::Number(number_field, range))
+        .ok_or_else(|| FilterError::InvalidValue {
+            field: field.to_lowercase(),
+            value: value.to_string(),
+        })
+}
+
+impl SongFilter {
+    pub fn parse(text: &str) -> Result<Self, FilterError> {
+        let terms = tokenize(text)?
+            .into_iter()
+            // Empty quotes.
+            .filter(|(token, _, is_negated)| !token.is_empty() || *is_negated)
+            .map(|(token, colon, is_negated)| {
+                if token.is_empty() {
+                    return Err(FilterError::MissingValue("-".to_string()));
+                }
+                Ok(Term {
+                    is_negated,
+                    condition: parse_condition(&token, colon)?,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            text: text.trim().to_string(),
+            terms,
+        })
+    }
+
+    /// The expression the filter was parsed from.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// The words the filter has without a field, and without a `-`, lowercase. Songs that match them are found through
+    /// them, so they're what results are ranked by.
+    pub fn words(&self) -> Vec<&str> {
+        self.terms
+            .iter()
+            .filter(|term| !term.is_negated)
+            .filter_map(|term| match &term.condition {
+                Condition::Words(words) => Some(words.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the filter has terms about `SongStats`. Filters that don't can be matched without reading them.
+    pub fn uses_stats(&self) -> bool {
+        self.terms.iter().any(|term| {
+            matches!(
+                term.condition,
+                Condition::Number(NumberField::Plays | NumberField::Added | NumberField::Played, _)
+            )
+        })
+    }
+
+    pub fn matches(&self, song: &Song, stats: &SongStats) -> bool {
+        let stat = stats.get(song);
+        let now = SystemTime::now();
+        self.terms
+            .iter()
+            .all(|term| term.condition.matches(song, stat, now) != term.is_negated)
+    }
+}
+
+fn days_ago(time: Option<SystemTime>, now: SystemTime) -> Option<u64> {
+    time.map(|time| now.duration_since(time).unwrap_or_default().as_secs() / (24 * 60 * 60))
+}
+
+impl Condition {
+    fn matches(&self, song: &Song, stat: Option<&SongStat>, now: SystemTime) -> bool {
+        let contains = |text: Option<&str>, value: &str| text.is_some_and(|text| text.to_lowercase().contains(value));
+
+        match self {
+            Self::Words(words) => {
+                let year = song.year.map(|year| year.to_string());
+                [
+                    Some(song.title.as_str()),
+                    song.artist.as_deref(),
+                    song.album.as_deref(),
+                    song.soundtrack_subject.as_deref(),
+                    year.as_deref(),
+                ]
+                .into_iter()
+                .any(|text| contains(text, words))
+            }
+            Self::Text(field, value) => {
+                let text = match field {
+                    TextField::Artist => song.artist.as_deref(),
+                    TextField::Album => song.album.as_deref(),
+                    TextField::Title => Some(song.title.as_str()),
+                    TextField::Subject => song.soundtrack_subject.as_deref(),
+                    TextField::Path => song.path.to_str(),
+                };
+                contains(text, value)
+            }
+            Self::Number(field, range) => {
+                let number = match field {
+                    NumberField::Year => song.year.map(u64::from),
+                    NumberField::Track => song.track.map(u64::from),
+                    NumberField::Disc => song.disc_number.map(u64::from),
+                    NumberField::Length => Some(song.length.as_secs()),
+                    NumberField::Plays => Some(stat.map_or(0, |stat| u64::from(stat.plays))),
+                    NumberField::Added => days_ago(stat.and_then(|stat| stat.added), now),
+                    NumberField::Played => days_ago(stat.and_then(|stat| stat.last_played), now),
+                };
+                number.is_some_and(|number| range.contains(&number))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::Duration};
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn song(title: &str, artist: &str, year: u32, seconds: u64) -> Song {
+        Song {
+            library_id: Some(Uuid::new_v4()),
+            path: PathBuf::from(format!("/music/{artist}/{title}.flac")),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(seconds),
+            title: title.to_string(),
+            artist: Some(artist.to_string()),
+            album: None,
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: Some(1),
+            year: Some(year),
+            replay_gain: Default::default(),
+        }
+    }
+
+    #[test]
+    fn filters_songs() {
+        let songs = [
+            song("Starless", "King Crimson", 1974, 738),
+            song("Starless (Live)", "King Crimson", 1974, 800),
+            song("Epitaph", "King Crimson", 1969, 529),
+            song("Fracture", "King Crimson", 1974, 665),
+            song("Roundabout", "Yes", 1971, 510),
+        ];
+        let titles = |text: &str| -> Vec<&str> {
+            let filter = SongFilter::parse(text).unwrap();
+            songs
+                .iter()
+                .filter(|song| filter.matches(song, &SongStats::default()))
+                .map(|song| song.title.as_str())
+                .collect()
+        };
+
+        assert_eq!(
+            titles(r#"artist:"King Crimson" year:1969..1975 -title:live length:>10m"#),
+            vec!["Starless", "Fracture"]
+        );
+        assert_eq!(titles("year:..1970"), vec!["Epitaph"]);
+        assert_eq!(titles("year:>=1971 length:<8:40"), vec!["Roundabout"]);
+        assert_eq!(titles("length:8m49s"), vec!["Epitaph"]);
+        assert_eq!(titles("crimson 1969"), vec!["Epitaph"]);
+        assert_eq!(titles(r#"-"king crimson""#), vec!["Roundabout"]);
+        assert_eq!(
+            titles(""),
+            songs.iter().map(|song| song.title.as_str()).collect::<Vec<_>>()
+        );
+
+        let filter = SongFilter::parse(r#" Crimson -live title:"star" "#).unwrap();
+        assert_eq!(filter.words(), vec!["crimson"]);
+        assert_eq!(filter.text(), r#"Crimson -live title:"star""#);
+    }
+
+    #[test]
+    fn filters_songs_by_stats() {
+        let songs = [
+            song("Starless", "King Crimson", 1974, 738),
+            song("Epitaph", "King Crimson", 1969, 529),
+            song("Roundabout", "Yes", 1971, 510),
+        ];
+        let days_ago = |days: u64| SystemTime::now() - Duration::from_secs(days * 24 * 60 * 60 + 60);
+
+        let mut stats = SongStats::default();
+        stats.record_added(&songs, |song| match song.title.as_str() {
+            "Starless" => days_ago(400),
+            _ => days_ago(3),
+        });
+        stats.record_play(&songs[0], days_ago(40));
+        stats.record_play(&songs[0], days_ago(10));
+        stats.record_play(&songs[1], days_ago(100));
+
+        let titles = |text: &str| -> Vec<&str> {
+            let filter = SongFilter::parse(text).unwrap();
+            songs
+                .iter()
+                .filter(|song| filter.matches(song, &stats))
+                .map(|song| song.title.as_str())
+                .collect()
+        };
+
+        assert_eq!(titles("plays:0"), vec!["Roundabout"]);
+        assert_eq!(titles("plays:>=1"), vec!["Starless", "Epitaph"]);
+        assert_eq!(titles("added:<1w"), vec!["Epitaph", "Roundabout"]);
+        assert_eq!(titles("added:>1y"), vec!["Starless"]);
+        assert_eq!(titles("played:..30"), vec!["Starless"]);
+        // Songs that were never played weren't played recently either.
+        assert_eq!(titles("-played:..30"), vec!["Epitaph", "Roundabout"]);
+
+        assert!(SongFilter::parse("plays:0").unwrap().uses_stats());
+        assert!(!SongFilter::parse("year:1970").unwrap().uses_stats());
+    }
+
+    #[test]
+    fn reports_errors() {
+        assert_eq!(
+            SongFilter::parse(r#"artist:"King Crimson"#),
+            Err(FilterError::UnterminatedQuote)
+        );
+        assert_eq!(
+            SongFilter::parse("genre:prog"),
+            Err(FilterError::UnknownField("genre".to_string()))
+        );
+        assert_eq!(
+            SongFilter::parse("year:late"),
+            Err(FilterError::InvalidValue {
+                field: "year".to_string(),
+                value: "late".to_string()
+            })
+        );
+        assert_eq!(
+            SongFilter::parse("length:10x"),
+            Err(FilterError::InvalidValue {
+                field: "length".to_string(),
+                value: "10x".to_string()
+            })
+        );
+        for value in ["99999999999999999999m", "9999999999999999999h", "999999999999:0:0:0:0:0"] {
+            assert_eq!(
+                SongFilter::parse(&format!("length:{value}")),
+                Err(FilterError::InvalidValue {
+                    field: "length".to_string(),
+                    value: value.to_string()
+                })
+            );
+        }
+        assert!(SongFilter::parse("added:<99999999999999999y").is_err());
+        assert_eq!(
+            SongFilter::parse("title:"),
+            Err(FilterError::MissingValue("title:".to_string()))
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::path::Path;
+
 use ratatui::{
     buffer::Buffer,
     layout::{Offset, Rect},
@@ -5,15 +7,149 @@ use ratatui::{
     style::Modifier,
     text::{Line, Span},
 };
+use strum::VariantNames;
 
-use super::{CommandLine, Query, QueryAddSongsTarget};
+use super::{CommandLine, Query, QueryAddSongsTarget, SongFilter};
+use crate::{
+    structs::{PlaylistFormat, SmartPlaylist, SmartPlaylistSort},
+    tag_edit::TagField,
+};
 
 impl Widget for &CommandLine<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let Some(query) = self.query.as_ref() else {
+            if let Some(error) = self.query_error.as_ref() {
+                Line::from(vec![
+                    Span::from(error.as_str()).style(Style::default().fg(self.theme.search)),
+                    Span::from(" "),
+                    Span::from("Enter or Esc to dismiss").style(Style::default().add_modifier(Modifier::DIM)),
+                ])
+                .render(area, buf);
+            }
             return;
         };
         let line = match query {
+            Query::OutputDevice { devices, selected } => {
+                let device = match devices.get(*selected) {
+                    Some(Some(device)) => device.as_str(),
+                    Some(None) => "System default",
+                    None => "",
+                };
+                Line::from(vec![
+                    Span::from("Play through"),
+                    Span::from(" "),
+                    Span::from(device).style(Style::default().bg(self.theme.background_selected)),
+                    Span::from("?"),
+                    Span::from(" "),
+                    Span::from("Enter to confirm, Left/Right Arrows to change selection, Esc to cancel")
+                        .style(Style::default().add_modifier(Modifier::DIM)),
+                ])
+            }
+            Query::FilterSongs { target, text } => {
+                let mut spans = vec![
+                    Span::from(format!("Filter {target} songs:")),
+                    Span::from(" "),
+                    Span::from(text.as_str()),
+                    Span::from(" ").style(Style::default().bg(self.theme.search)),
+                    Span::from(" "),
+                ];
+                match SongFilter::parse(text) {
+                    Ok(_) => spans.push(
+                        Span::from("Enter to confirm, an empty filter shows every song, Esc to cancel")
+                            .style(Style::default().add_modifier(Modifier::DIM)),
+                    ),
+                    Err(err) => spans.push(Span::from(err.to_string()).style(Style::default().fg(self.theme.search))),
+                }
+                Line::from(spans)
+            }
+            Query::SmartPlaylist { is_new, text } => {
+                let mut spans = vec![
+                    Span::from(if *is_new {
+                        "New smart playlist:"
+                    } else {
+                        "Smart playlist:"
+                    }),
+                    Span::from(" "),
+                    Span::from(text.as_str()),
+                    Span::from(" ").style(Style::default().bg(self.theme.search)),
+                    Span::from(" "),
+                ];
+                match SmartPlaylist::parse(text) {
+                    Ok(_) => spans.push(
+                        Span::from(format!(
+                            "Filter, then sort:{} and limit:N. Enter to confirm, Esc to cancel",
+                            SmartPlaylistSort::VARIANTS.join("|")
+                        ))
+                        .style(Style::default().add_modifier(Modifier::DIM)),
+                    ),
+                    Err(err) => spans.push(Span::from(err.to_string()).style(Style::default().fg(self.theme.search))),
+                }
+                Line::from(spans)
+            }
+            Query::ExportPlaylist {
+                name, text, relative, ..
+            } => {
+                let mut spans = vec![
+                    Span::from(format!("Export {name} to:")),
+                    Span::from(" "),
+                    Span::from(text.as_str()),
+                    Span::from(" ").style(Style::default().bg(self.theme.search)),
+                    Span::from(" "),
+                    Span::from(if *relative { "relative paths" } else { "absolute paths" })
+                        .style(Style::default().bg(self.theme.background_selected)),
+                    Span::from(" "),
+                ];
+                if PlaylistFormat::from_path(Path::new(text)).is_some() {
+                    spans.push(
+                        Span::from("Enter to confirm, Tab to change paths, Esc to cancel")
+                            .style(Style::default().add_modifier(Modifier::DIM)),
+                    );
+                } else {
+                    spans.push(
+                        Span::from(format!(
+                            "The file must end in .{}",
+                            PlaylistFormat::VARIANTS.join(", .")
+                        ))
+                        .style(Style::default().fg(self.theme.search)),
+                    );
+                }
+                Line::from(spans)
+            }
+            Query::EditTags(edit) => {
+                let count = edit.songs().len();
+                let field = edit.selected_field();
+                let mut spans = vec![
+                    Span::from(format!("Edit tags of {count} song(s):")),
+                    Span::from(" "),
+                    Span::from(field.field.to_string()).style(Style::default().bg(self.theme.background_selected)),
+                    Span::from(" "),
+                ];
+                if field.field == TagField::Track && edit.number_tracks() {
+                    spans.push(Span::from(format!("1 to {count}, in the order they're listed")));
+                } else {
+                    let text = field.value.as_deref().or(field.original.as_deref()).unwrap_or_default();
+                    spans.push(Span::from(text));
+                    spans.push(Span::from(" ").style(Style::default().bg(self.theme.search)));
+                    let hint = match (field.value.as_deref(), field.original.as_deref()) {
+                        (None, None) => Some(" (various)"),
+                        (Some(""), original) if original != Some("") => Some(" (removed)"),
+                        _ => None,
+                    };
+                    if let Some(hint) = hint {
+                        spans.push(Span::from(hint).style(Style::default().add_modifier(Modifier::DIM)));
+                    }
+                }
+                spans.push(Span::from(" "));
+                spans.push(
+                    Span::from(if field.field == TagField::Track && count > 1 {
+                        "Up/Down Arrows to change tag, Tab to number the songs, Enter to save, Esc to cancel"
+                    } else {
+                        "Up/Down Arrows to change tag, Enter to save, Esc to cancel"
+                    })
+                    .style(Style::default().add_modifier(Modifier::DIM)),
+                );
+                Line::from(spans)
+            }
             Query::AddSongs {
                 songs,
                 step,
@@ -55,7 +191,7 @@ impl Widget for &CommandLine<'_> {
         line.render(area, buf);
         if let Some(error) = self.query_error.as_ref() {
             let area = area.offset(Offset::new(0, 1));
-            Line::from(error.as_ref())
+            Line::from(error.as_str())
                 .style(Style::default().fg(self.theme.search))
                 .render(area, buf);
         }
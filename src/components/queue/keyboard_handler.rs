@@ -1,8 +1,12 @@
 use super::Queue;
-use crate::actions::{Action, OnAction, OnActionMut};
+use crate::actions::{Action, OnAction, OnActionMut, QueueAction};
 
 impl OnActionMut for Queue<'_> {
     fn on_action(&mut self, actions: Vec<Action>) {
+        if actions.contains(&Action::Queue(QueueAction::Promote)) {
+            self.promote_selected();
+            return;
+        }
         self.song_list.on_action(actions);
     }
 }
@@ -1,20 +1,48 @@
-use std::{cell::Cell, time::Duration};
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+    time::Duration,
+};
 
-use crate::{components::List, structs::Song, theme::Theme, ui::Focusable};
+use ratatui::style::{Modifier, Style};
+
+use crate::{
+    components::{Callback, List},
+    structs::Song,
+    theme::Theme,
+    ui::Focusable,
+};
 
 pub struct Queue<'a> {
     pub(super) song_list: List<'a, Song>,
     duration: Cell<Duration>,
+    /// Songs radio mode put in the queue. They're shown apart from the rest.
+    suggested: Rc<RefCell<Vec<Song>>>,
+    pub(super) on_promote_fn: Callback<'a, usize>,
 }
 
 impl<'a> Queue<'a> {
     pub fn new(songs: Vec<Song>, theme: Theme) -> Self {
-        let song_list = List::new(theme, songs);
+        let mut song_list = List::new(theme, songs);
         song_list.set_is_focused(true);
 
+        let suggested = Rc::new(RefCell::new(Vec::<Song>::new()));
+        song_list.line_style({
+            let suggested = Rc::clone(&suggested);
+            move |song| {
+                suggested.borrow().contains(song).then(|| {
+                    Style::default()
+                        .fg(theme.foreground_secondary)
+                        .add_modifier(Modifier::ITALIC)
+                })
+            }
+        });
+
         Self {
             song_list,
             duration: Cell::new(Duration::default()),
+            suggested,
+            on_promote_fn: Callback::default(),
         }
     }
 
@@ -36,6 +64,10 @@ impl<'a> Queue<'a> {
         self.refresh_duration();
     }
 
+    pub fn set_suggested(&self, songs: Vec<Song>) {
+        *self.suggested.borrow_mut() = songs;
+    }
+
     pub fn append(&self, songs: Vec<Song>) {
         self.song_list.append_items(songs);
         self.refresh_duration();
@@ -49,6 +81,23 @@ impl<'a> Queue<'a> {
         self.song_list.on_delete(cb);
     }
 
+    /// Called with the index of a suggested song, to keep it in the queue.
+    pub fn on_promote(&self, cb: impl Fn(usize) + 'a) {
+        self.on_promote_fn.set(cb);
+    }
+
+    pub(super) fn promote_selected(&self) {
+        let index = self.song_list.selected_index();
+        let is_suggested = self.song_list.with_items(|items| {
+            items
+                .get(index)
+                .is_some_and(|song| self.suggested.borrow().contains(song))
+        });
+        if is_suggested {
+            self.on_promote_fn.call(index);
+        }
+    }
+
     pub fn with_items<R>(&self, cb: impl FnOnce(Vec<&Song>) -> R) -> R {
         self.song_list.with_items(cb)
     }
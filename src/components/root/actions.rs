@@ -1,34 +1,58 @@
 use super::Root;
-use crate::actions::{Action, OnActionMut, ScreenAction};
+use crate::actions::{Action, ListAction, OnActionMut, PlayerAction, PlaylistsAction, ScreenAction};
 
 impl OnActionMut for Root<'_> {
     fn on_action(&mut self, actions: Vec<Action>) {
-        if self.command_line.borrow().query().is_some() {
+        let is_command_line_open = {
+            let command_line = self.command_line.borrow();
+            command_line.query().is_some() || command_line.error().is_some()
+        };
+        if is_command_line_open {
             self.command_line.borrow_mut().on_action(actions);
         } else {
             match actions[0] {
-                Action::Screen(action) if !self.is_focus_trapped.get() => match action {
-                    ScreenAction::Next => {
-                        if self.focused_screen < 5 {
-                            self.focused_screen += 1;
-                        } else {
-                            self.focused_screen = 0;
+                Action::Player(PlayerAction::SelectOutputDevice) => self.select_output_device(),
+                Action::ListAction(ListAction::FilterSongs) if !self.is_focus_trapped.get() => self.filter_songs(),
+                Action::ListAction(ListAction::EditTags) if !self.is_focus_trapped.get() => self.edit_tags(),
+                Action::Playlists(PlaylistsAction::NewSmartPlaylist) if !self.is_focus_trapped.get() => {
+                    self.edit_smart_playlist(true)
+                }
+                Action::Playlists(PlaylistsAction::EditSmartPlaylist) if !self.is_focus_trapped.get() => {
+                    self.edit_smart_playlist(false)
+                }
+                Action::Playlists(PlaylistsAction::Export) if !self.is_focus_trapped.get() => self.export_playlist(),
+                Action::Screen(action) if !self.is_focus_trapped.get() => {
+                    match action {
+                        ScreenAction::Next => {
+                            if self.focused_screen < self.screens.len() - 1 {
+                                self.focused_screen += 1;
+                            } else {
+                                self.focused_screen = 0;
+                            }
                         }
-                    }
-                    ScreenAction::Previous => {
-                        if self.focused_screen > 0 {
-                            self.focused_screen -= 1;
-                        } else {
-                            self.focused_screen = 5;
+                        ScreenAction::Previous => {
+                            if self.focused_screen > 0 {
+                                self.focused_screen -= 1;
+                            } else {
+                                self.focused_screen = self.screens.len() - 1;
+                            }
                         }
+                        ScreenAction::Library => self.focused_screen = 0,
+                        ScreenAction::Soundtracks => self.focused_screen = 1,
+                        ScreenAction::Playlists => self.focused_screen = 2,
+                        ScreenAction::Queue => self.focused_screen = 3,
+                        ScreenAction::FileBrowser => self.focused_screen = 4,
+                        ScreenAction::Help => self.focused_screen = 5,
+                        ScreenAction::Search => {
+                            self.focused_screen = 6;
+                            self.search.borrow().start_typing();
+                        }
+                        ScreenAction::Stats => self.focused_screen = 7,
+                    }
+                    if self.focused_screen == 7 {
+                        self.stats.borrow().refresh();
                     }
-                    ScreenAction::Library => self.focused_screen = 0,
-                    ScreenAction::Soundtracks => self.focused_screen = 1,
-                    ScreenAction::Playlists => self.focused_screen = 2,
-                    ScreenAction::Queue => self.focused_screen = 3,
-                    ScreenAction::FileBrowser => self.focused_screen = 4,
-                    ScreenAction::Help => self.focused_screen = 5,
-                },
+                }
                 _ => {
                     let mut c = self.screens[self.focused_screen].1.borrow_mut();
                     c.on_action(actions);
@@ -1,26 +1,39 @@
 use std::{
     cell::{Cell, RefCell},
+    collections::{HashMap, HashSet},
     env,
-    path::PathBuf,
+    path::{Path, PathBuf},
     rc::Rc,
     sync::Weak,
+    time::{Duration, SystemTime},
 };
 
+use ratatui_image::picker::Picker;
+use uuid::Uuid;
+
 use crate::{
     actions::Actions,
     components::{
+        AlbumArt,
         FileBrowser,
         Help,
         Library,
         Playlists,
         Queue as QueueScreen,
+        Search,
+        SearchSource,
+        SongFilter,
         Soundtracks,
-        query::{CommandLine, Query, QueryAddSongsTarget},
+        Stats,
+        query::{CommandLine, Query, QueryAddSongsTarget, QueryFilterSongsTarget},
     },
+    files::{LibraryChanges, SongStats},
     main_player::MainPlayer,
+    output::output_devices,
     settings::Settings,
     state::State,
-    structs::Song,
+    structs::{SmartPlaylist, Song},
+    tag_edit::{TagEdit, TagField, copy_tags},
     theme::Theme,
     ui::ComponentMut,
 };
@@ -64,13 +77,25 @@ pub struct Root<'a> {
     pub(super) command_line: Rc<RefCell<CommandLine<'a>>>,
 
     pub(super) queue_screen: Rc<RefCell<QueueScreen<'a>>>,
+    pub(super) album_art: AlbumArt,
     browser_screen: Rc<RefCell<FileBrowser<'a>>>,
+    library: Rc<RefCell<Library<'a>>>,
+    soundtracks: Rc<RefCell<Soundtracks<'a>>>,
+    playlists: Rc<RefCell<Playlists<'a>>>,
+    pub(super) search: Rc<RefCell<Search<'a>>>,
+    pub(super) stats: Rc<RefCell<Stats<'a>>>,
 
     on_queue_changed_fn: Rc<Callback<'a, QueueChange>>,
 }
 
 impl<'a> Root<'a> {
-    pub fn new(actions: &'a Actions, settings: Settings, theme: Theme, player: Weak<MainPlayer>) -> Self {
+    pub fn new(
+        actions: &'a Actions,
+        settings: Settings,
+        theme: Theme,
+        player: Weak<MainPlayer>,
+        picker: Option<Picker>,
+    ) -> Self {
         let state = State::from_file();
 
         let current_directory = match &state.last_visited_path {
@@ -84,7 +109,12 @@ impl<'a> Root<'a> {
         let library = Rc::new(RefCell::new(Library::new(theme)));
         let soundtracks = Rc::new(RefCell::new(Soundtracks::new(theme)));
         let playlist = Rc::new(RefCell::new(Playlists::new(theme)));
-        let browser = Rc::new(RefCell::new(FileBrowser::new(actions, theme, current_directory)));
+        let browser = Rc::new(RefCell::new(FileBrowser::new(
+            actions,
+            theme,
+            current_directory,
+            AlbumArt::new(picker.clone()),
+        )));
         let command_line = Rc::new(RefCell::new(CommandLine::new(theme)));
 
         let on_queue_changed_fn = Rc::new(Callback::default());
@@ -203,6 +233,12 @@ impl<'a> Root<'a> {
                     on_queue_changed_fn.call(QueueChange::Remove(index));
                 }
             });
+            queue_screen.on_promote({
+                let player = player.clone();
+                move |index| {
+                    player.upgrade().inspect(|p| p.promote(index));
+                }
+            });
         }
 
         {
@@ -222,43 +258,203 @@ impl<'a> Root<'a> {
                 let command_line = Rc::clone(&command_line);
                 let playlist = Rc::clone(&playlist);
                 move |songs| {
-                    let pl = playlist.borrow().selected_playlist(|pl| pl.name.clone());
-                    let pls = playlist
-                        .borrow()
-                        .playlists(|pls| pls.iter().map(|pl| pl.name.clone()).collect());
+                    let (pls, pl) = playlist.borrow().playlist_names_to_add_to();
                     command_line.borrow_mut().set_query(Some(Query::AddSongs {
                         songs,
                         step: 0,
                         target: QueryAddSongsTarget::Library,
-                        target_name: Some(pl),
+                        target_name: pl,
                         playlists: pls,
                     }));
                 }
             });
+            browser.on_import_playlist({
+                let playlist = Rc::clone(&playlist);
+                move |name, songs| {
+                    playlist.borrow().import_playlist(name, songs);
+                }
+            });
+        }
+
+        {
+            // Songs that were in the library before dates were recorded get the time their file last changed.
+            let mut songs = library.borrow().songs();
+            songs.extend(soundtracks.borrow().songs());
+            SongStats::update(|stats| {
+                stats.record_added(&songs, |song| {
+                    song.path
+                        .metadata()
+                        .and_then(|metadata| metadata.modified())
+                        .unwrap_or_else(|_| SystemTime::now())
+                })
+            });
         }
 
-        let help = Rc::new(RefCell::new(Help::new(actions, settings, theme)));
+        playlist.borrow().library_songs_fn({
+            let library = Rc::clone(&library);
+            let soundtracks = Rc::clone(&soundtracks);
+            move || {
+                let mut songs = library.borrow().songs();
+                songs.extend(soundtracks.borrow().songs());
+                songs
+            }
+        });
+
+        let help = Rc::new(RefCell::new(Help::new(actions, settings.clone(), theme)));
+
+        let search = Rc::new(RefCell::new(Search::new(theme)));
+
+        let stats = Rc::new(RefCell::new(Stats::new(theme)));
+        stats.borrow().library_songs_fn({
+            let library = Rc::clone(&library);
+            let soundtracks = Rc::clone(&soundtracks);
+            move || {
+                let mut songs = library.borrow().songs();
+                songs.extend(soundtracks.borrow().songs());
+                songs
+            }
+        });
+
+        {
+            let search = search.borrow();
+            search.sources_fn({
+                let library = Rc::clone(&library);
+                let soundtracks = Rc::clone(&soundtracks);
+                let playlist = Rc::clone(&playlist);
+                let queue_screen = Rc::clone(&queue_screen);
+                move || {
+                    let mut sources = vec![
+                        SearchSource {
+                            name: "Library".to_string(),
+                            songs: library.borrow().songs(),
+                        },
+                        SearchSource {
+                            name: "Soundtracks".to_string(),
+                            songs: soundtracks.borrow().songs(),
+                        },
+                    ];
+                    playlist.borrow().playlists(|pls| {
+                        sources.extend(pls.into_iter().map(|pl| SearchSource {
+                            name: format!("Playlist: {}", pl.name),
+                            songs: pl.songs.clone(),
+                        }))
+                    });
+                    sources.push(SearchSource {
+                        name: "Queue".to_string(),
+                        songs: queue_screen
+                            .borrow()
+                            .with_items(|songs| songs.into_iter().cloned().collect()),
+                    });
+                    sources
+                }
+            });
+            search.on_enqueue({
+                let queue_screen = queue_screen.clone();
+                let on_queue_changed_fn = on_queue_changed_fn.clone();
+                move |songs| {
+                    queue_screen.borrow_mut().append(songs.clone());
+                    on_queue_changed_fn.call(QueueChange::Append(songs));
+                }
+            });
+            search.on_play({
+                let player = player.clone();
+                move |song| {
+                    player.upgrade().inspect(|p| p.play(song));
+                }
+            });
+            search.on_add_to_playlist({
+                let command_line = Rc::clone(&command_line);
+                let playlist = Rc::clone(&playlist);
+                move |songs| {
+                    let (pls, pl) = playlist.borrow().playlist_names_to_add_to();
+                    if pls.is_empty() {
+                        command_line
+                            .borrow_mut()
+                            .set_error(Some("There are no playlists to add songs to.".to_string()));
+                        return;
+                    }
+                    command_line.borrow_mut().set_query(Some(Query::AddSongs {
+                        songs,
+                        step: 1,
+                        target: QueryAddSongsTarget::Playlist,
+                        target_name: pl,
+                        playlists: pls,
+                    }));
+                }
+            });
+            search.on_request_focus_trap_fn({
+                let is_focus_trapped = is_focus_trapped.clone();
+                move |v| {
+                    is_focus_trapped.set(v);
+                }
+            });
+        }
 
         {
             let command_line = command_line.borrow();
             let library = Rc::clone(&library);
             let soundtracks = Rc::clone(&soundtracks);
             let playlist = Rc::clone(&playlist);
+            let player = player.clone();
 
             command_line.on_confirm({
                 move |query| match query {
-                    Query::AddSongs { songs, target, .. } => match target {
+                    Query::OutputDevice { mut devices, selected } => {
+                        if selected < devices.len() {
+                            let device = devices.swap_remove(selected);
+                            player.upgrade().inspect(|p| p.set_output_device(device));
+                        }
+                    }
+                    Query::FilterSongs { target, text } => {
+                        let filter = SongFilter::parse(&text).ok().filter(|filter| !filter.is_empty());
+                        match target {
+                            QueryFilterSongsTarget::Library => library.borrow().set_song_filter(filter),
+                            QueryFilterSongsTarget::Soundtracks => soundtracks.borrow().set_song_filter(filter),
+                            QueryFilterSongsTarget::Playlists => playlist.borrow().set_song_filter(filter),
+                        }
+                    }
+                    Query::SmartPlaylist { is_new, text } => {
+                        let Ok(smart) = SmartPlaylist::parse(&text) else {
+                            return;
+                        };
+                        if is_new {
+                            playlist.borrow().add_smart_playlist(smart);
+                        } else {
+                            playlist.borrow().set_selected_smart_playlist(smart);
+                        }
+                    }
+                    Query::ExportPlaylist { .. } => {
+                        // Written by the command line itself, which shows whatever goes wrong.
+                    }
+                    Query::EditTags(edit) => {
+                        apply_tag_edits(
+                            &library.borrow(),
+                            &soundtracks.borrow(),
+                            &playlist.borrow(),
+                            player.upgrade().as_deref(),
+                            edit.songs(),
+                        );
+                    }
+                    Query::AddSongs {
+                        songs,
+                        target,
+                        target_name,
+                        ..
+                    } => match target {
                         QueryAddSongsTarget::Library => {
-                            let library = library.borrow_mut();
-                            library.add_songs(songs);
+                            library.borrow().add_songs(songs);
+                            playlist.borrow().refresh_smart_playlists();
                         }
                         QueryAddSongsTarget::Soundtracks => {
-                            let soundtracks = soundtracks.borrow_mut();
-                            soundtracks.add_songs(songs);
+                            soundtracks.borrow().add_songs(songs);
+                            playlist.borrow().refresh_smart_playlists();
                         }
                         QueryAddSongsTarget::Playlist => {
                             let playlist = playlist.borrow_mut();
-                            playlist.add_songs(songs);
+                            match target_name {
+                                Some(name) => playlist.add_songs_to(&name, songs),
+                                None => playlist.add_songs(songs),
+                            }
                         }
                     },
                 }
@@ -277,6 +473,8 @@ impl<'a> Root<'a> {
                 ("Queue".to_string(), queue_screen.clone()),
                 ("File Browser".to_string(), browser.clone()),
                 ("Help".to_string(), help.clone()),
+                ("Search".to_string(), search.clone()),
+                ("Stats".to_string(), stats.clone()),
             ],
             focused_screen: 0,
             is_focus_trapped,
@@ -285,7 +483,13 @@ impl<'a> Root<'a> {
             player,
 
             queue_screen,
+            album_art: AlbumArt::new(picker),
             browser_screen: browser,
+            library,
+            soundtracks,
+            playlists: playlist,
+            search,
+            stats,
 
             on_queue_changed_fn,
         }
@@ -299,8 +503,242 @@ impl<'a> Root<'a> {
         self.on_queue_changed_fn.set(f);
     }
 
-    pub fn set_queue(&self, songs: Vec<Song>) {
-        self.queue_screen.borrow_mut().set_items(songs);
+    /// `suggested` are the songs radio mode added, which the Queue screen tells apart.
+    pub fn set_queue(&self, songs: Vec<Song>, suggested: Vec<Song>) {
+        let queue_screen = self.queue_screen.borrow_mut();
+        queue_screen.set_suggested(suggested);
+        queue_screen.set_items(songs);
+    }
+
+    /// Every song in the Library and Soundtracks.
+    pub fn library_songs(&self) -> Vec<Song> {
+        let mut songs = self.library.borrow().songs();
+        songs.extend(self.soundtracks.borrow().songs());
+        songs
+    }
+
+    /// Brings the Library and Soundtracks up to date with what the library scan found.
+    /// Songs added by hand from the library folders are replaced by the ones the scan found, so they aren't listed twice.
+    pub fn apply_library_changes(&self, changes: LibraryChanges) {
+        let outdated_ids: HashSet<Uuid> = changes
+            .removed
+            .iter()
+            .copied()
+            .chain(changes.modified.iter().filter_map(|song| song.library_id))
+            .collect();
+        let added: HashSet<(&Path, Duration)> = changes
+            .added
+            .iter()
+            .map(|song| (song.path.as_path(), song.start_time))
+            .collect();
+        let is_outdated = |song: &Song| {
+            song.library_id.is_some_and(|id| outdated_ids.contains(&id))
+                || added.contains(&(song.path.as_path(), song.start_time))
+        };
+
+        let library = self.library.borrow();
+        let soundtracks = self.soundtracks.borrow();
+
+        let mut removed = library.remove_songs(is_outdated);
+        removed.extend(soundtracks.remove_songs(is_outdated));
+
+        // Modified songs only go back where they were. Songs deleted from the Library by hand stay deleted.
+        let removed_ids: HashSet<Uuid> = removed.iter().filter_map(|song| song.library_id).collect();
+        let modified = changes
+            .modified
+            .into_iter()
+            .filter(|song| song.library_id.is_some_and(|id| removed_ids.contains(&id)));
+
+        let (soundtrack_songs, library_songs): (Vec<Song>, Vec<Song>) = changes
+            .added
+            .into_iter()
+            .chain(modified)
+            .partition(|song| song.soundtrack_subject.is_some());
+
+        library.insert_songs(library_songs);
+        soundtracks.insert_songs(soundtrack_songs);
+
+        // Songs whose files were deleted stay listed, marked as missing, so they can be relinked.
+        if !changes.missing.is_empty() || !changes.found.is_empty() {
+            library.refresh_songs();
+            soundtracks.refresh_songs();
+        }
+        drop((library, soundtracks));
+
+        self.playlists.borrow().refresh_smart_playlists();
+    }
+
+    pub fn show_error(&self, error: String) {
+        self.command_line.borrow_mut().set_error(Some(error));
+    }
+
+    /// Whether keys that type characters are typed into something, rather than taken as player actions.
+    pub fn is_typing(&self) -> bool {
+        self.is_focus_trapped.get() || self.command_line.borrow().is_typing()
+    }
+
+    /// Asks for a filter for the song list of the focused screen, starting from the one it has.
+    pub(super) fn filter_songs(&self) {
+        let (target, filter) = match self.focused_screen {
+            0 => (QueryFilterSongsTarget::Library, self.library.borrow().song_filter()),
+            1 => (
+                QueryFilterSongsTarget::Soundtracks,
+                self.soundtracks.borrow().song_filter(),
+            ),
+            2 => (QueryFilterSongsTarget::Playlists, self.playlists.borrow().song_filter()),
+            _ => return,
+        };
+        self.command_line.borrow_mut().set_query(Some(Query::FilterSongs {
+            target,
+            text: filter.map(|filter| filter.text().to_string()).unwrap_or_default(),
+        }));
+    }
+
+    /// Asks for the rules of a new smart playlist, or for new rules for the selected one.
+    pub(super) fn edit_smart_playlist(&self, is_new: bool) {
+        let text = if is_new {
+            String::new()
+        } else {
+            let playlists = self.playlists.borrow();
+            let smart = playlists.playlists(|pls| {
+                pls.get(playlists.selected_playlist_index())
+                    .and_then(|pl| pl.smart.as_ref())
+                    .map(SmartPlaylist::text)
+            });
+            let Some(text) = smart else {
+                drop(playlists);
+                self.show_error("The selected playlist is not a smart playlist.".to_string());
+                return;
+            };
+            text
+        };
+        self.command_line
+            .borrow_mut()
+            .set_query(Some(Query::SmartPlaylist { is_new, text }));
+    }
+
+    /// Asks where to write the selected playlist to, as a playlist file other players can read.
+    pub(super) fn export_playlist(&self) {
+        let playlists = self.playlists.borrow();
+        let Some((name, songs)) = playlists.playlists(|pls| {
+            pls.get(playlists.selected_playlist_index())
+                .map(|pl| (pl.name.clone(), pl.songs.clone()))
+        }) else {
+            return;
+        };
+        drop(playlists);
+        let file_name = format!("{}.m3u8", name.replace('/', "-"));
+        let text = self
+            .browser_screen
+            .borrow()
+            .current_directory()
+            .join(file_name)
+            .to_string_lossy()
+            .to_string();
+        self.command_line.borrow_mut().set_query(Some(Query::ExportPlaylist {
+            name,
+            songs,
+            text,
+            relative: false,
+        }));
+    }
+
+    /// Asks for new tags for the selected songs of the focused screen.
+    pub(super) fn edit_tags(&self) {
+        let songs = match self.focused_screen {
+            0 => self.library.borrow().selected_songs(),
+            1 => self.soundtracks.borrow().selected_songs(),
+            2 => self.playlists.borrow().selected_songs(),
+            4 => self.browser_screen.borrow().selected_songs(),
+            _ => {
+                self.show_error(
+                    "Tags can be edited from the Library, Soundtracks, Playlists and File Browser.".to_string(),
+                );
+                return;
+            }
+        };
+        if songs.is_empty() {
+            self.show_error("There are no songs selected to edit the tags of.".to_string());
+            return;
+        }
+        // Songs left without the fields the Library and Soundtracks list them by would drop out of them.
+        let is_listed = |listed: Vec<Song>| {
+            let listed: HashSet<(PathBuf, Duration)> =
+                listed.into_iter().map(|song| (song.path, song.start_time)).collect();
+            songs
+                .iter()
+                .any(|song| listed.contains(&(song.path.clone(), song.start_time)))
+        };
+        let mut required = Vec::new();
+        if is_listed(self.library.borrow().songs()) {
+            required.extend([TagField::Artist, TagField::Album]);
+        } else if is_listed(self.soundtracks.borrow().songs()) {
+            required.push(TagField::Album);
+        }
+
+        match TagEdit::new(songs) {
+            Ok(mut edit) => {
+                edit.set_required(required);
+                self.command_line.borrow_mut().set_query(Some(Query::EditTags(edit)));
+            }
+            Err(err) => self.show_error(format!("Could not edit the tags! {err}")),
+        }
+    }
+
+    pub(super) fn select_output_device(&self) {
+        let Some(player) = self.player.upgrade() else {
+            return;
+        };
+        let devices: Vec<Option<String>> = std::iter::once(None)
+            .chain(output_devices().into_iter().map(Some))
+            .collect();
+        let current = player.output_device();
+        let selected = devices.iter().position(|device| *device == current).unwrap_or(0);
+        self.command_line
+            .borrow_mut()
+            .set_query(Some(Query::OutputDevice { devices, selected }));
+    }
+}
+
+/// Gives the copies of `edited` in the Library, Soundtracks, playlists and queue their new tags.
+/// Songs move to the artist and album they have now. Clearing those is refused beforehand, since the Library and
+/// Soundtracks can't list songs without them.
+fn apply_tag_edits(
+    library: &Library,
+    soundtracks: &Soundtracks,
+    playlists: &Playlists,
+    player: Option<&MainPlayer>,
+    edited: &[Song],
+) {
+    let edited: HashMap<(&Path, Duration), &Song> = edited
+        .iter()
+        .map(|song| ((song.path.as_path(), song.start_time), song))
+        .collect();
+    let find = |song: &Song| edited.get(&(song.path.as_path(), song.start_time)).copied();
+    let edit = |song: &mut Song| {
+        let Some(edited) = find(song) else {
+            return false;
+        };
+        copy_tags(edited, song);
+        true
+    };
+
+    // Songs that somehow lost what they're listed by anyway keep the tags they had there, rather than drop out.
+    let is_listable = |song: &Song| song.artist.is_some() && song.album.is_some();
+    let mut songs = library.remove_songs(|song| find(song).is_some_and(is_listable));
+    songs.iter_mut().for_each(|song| copy_tags(find(song).unwrap(), song));
+    library.insert_songs(songs);
+
+    let is_listable = |song: &Song| song.album.is_some();
+    let mut songs = soundtracks.remove_songs(|song| find(song).is_some_and(is_listable));
+    songs.iter_mut().for_each(|song| copy_tags(find(song).unwrap(), song));
+    soundtracks.insert_songs(songs);
+
+    playlists.edit_songs(edit);
+    playlists.refresh_smart_playlists();
+
+    if let Some(player) = player {
+        player.edit_songs(edit);
     }
 }
 
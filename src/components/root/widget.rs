@@ -24,11 +24,14 @@ impl Widget for &mut Root<'_> {
 
         let screen_titles: Vec<&str> = self.screens.iter().map(|screen| screen.0.as_str()).collect();
 
+        let equalizer_preset = self.player.upgrade().and_then(|player| player.equalizer_preset());
+
         let top_bar = TopBar::new(
-            self.settings,
+            &self.settings,
             self.theme,
             &screen_titles,
             self.focused_screen,
+            equalizer_preset.as_deref(),
             self.frame,
         );
         top_bar.render(area_top, buf);
@@ -44,7 +47,11 @@ impl Widget for &mut Root<'_> {
         //     .style(Style::new().fg(self.theme.foreground))
         //     .render(area_noti, buf);
 
-        if self.command_line.borrow().query().is_some() {
+        let is_command_line_open = {
+            let command_line = self.command_line.borrow();
+            command_line.query().is_some() || command_line.error().is_some()
+        };
+        if is_command_line_open {
             let area = area_player.inner(Margin::new(1, 1));
             self.command_line.borrow().render(area, buf);
         } else {
@@ -69,8 +76,12 @@ impl Widget for &mut Root<'_> {
                 self.queue_screen.borrow().len(),
                 is_paused,
                 repeat_mode,
+                player.shuffle_mode(),
+                player.is_radio_on(),
                 player.volume(),
+                player.speed(),
                 self.frame,
+                &self.album_art,
             )
             .render(area_player, buf);
         }
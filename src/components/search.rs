@@ -0,0 +1,7 @@
+mod component;
+mod keyboard_handler;
+mod results;
+mod widget;
+
+pub use component::*;
+pub use results::SearchSource;
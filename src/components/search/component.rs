@@ -0,0 +1,131 @@
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use ratatui::style::{Modifier, Style};
+
+use super::results::{SearchResult, SearchSource, search};
+use crate::{
+    components::{Callback, List, SongFilter},
+    structs::Song,
+    theme::Theme,
+    ui::Focusable,
+};
+
+pub struct Search<'a> {
+    pub(super) theme: Theme,
+
+    pub(super) query: RefCell<String>,
+    /// Why the query isn't a valid `SongFilter`, if it isn't.
+    pub(super) error: RefCell<Option<String>>,
+    /// Whether key presses go to the query, rather than to the results.
+    pub(super) is_typing: Cell<bool>,
+    pub(super) results: Rc<List<'a, SearchResult>>,
+
+    pub(super) sources_fn: RefCell<Box<dyn Fn() -> Vec<SearchSource> + 'a>>,
+    pub(super) on_add_to_playlist_fn: Callback<'a, Vec<Song>>,
+    pub(super) on_request_focus_trap_fn: Callback<'a, bool>,
+}
+
+impl<'a> Search<'a> {
+    pub fn new(theme: Theme) -> Self {
+        let mut results = List::new(theme, vec![]);
+        results.line_style(|result| match result {
+            SearchResult::Group { .. } => Some(Style::default().add_modifier(Modifier::BOLD)),
+            _ => None,
+        });
+        results.set_is_focused(true);
+
+        Self {
+            theme,
+
+            query: RefCell::new(String::new()),
+            error: RefCell::new(None),
+            is_typing: Cell::new(false),
+            results: Rc::new(results),
+
+            sources_fn: RefCell::new(Box::new(Vec::new)),
+            on_add_to_playlist_fn: Callback::default(),
+            on_request_focus_trap_fn: Callback::default(),
+        }
+    }
+
+    /// Where to search. Called every time the query changes, so results are never stale.
+    pub fn sources_fn(&self, f: impl Fn() -> Vec<SearchSource> + 'a) {
+        *self.sources_fn.borrow_mut() = Box::new(f);
+    }
+
+    /// Called with the songs of a result, when it's confirmed.
+    pub fn on_enqueue(&self, f: impl Fn(Vec<Song>) + 'a) {
+        self.results.on_confirm(move |result| {
+            let songs = result.songs();
+            if !songs.is_empty() {
+                f(songs);
+            }
+        });
+    }
+
+    /// Called with a song, when a song result is confirmed with the alternative key.
+    pub fn on_play(&self, f: impl Fn(Song) + 'a) {
+        self.results.on_confirm_alt(move |result| {
+            if let SearchResult::Song { song, .. } = result {
+                f(song);
+            }
+        });
+    }
+
+    pub fn on_add_to_playlist(&self, f: impl Fn(Vec<Song>) + 'a) {
+        self.on_add_to_playlist_fn.set(f);
+    }
+
+    pub fn on_request_focus_trap_fn(&self, f: impl Fn(bool) + 'a) {
+        self.on_request_focus_trap_fn.set(f);
+    }
+
+    /// Sends key presses to the query. Screen switching keys, like the numbers, are typed too, until typing stops.
+    pub fn start_typing(&self) {
+        self.is_typing.set(true);
+        self.results.set_is_focused(false);
+        self.on_request_focus_trap_fn.call(true);
+    }
+
+    pub(super) fn stop_typing(&self) {
+        self.is_typing.set(false);
+        self.results.set_is_focused(true);
+        self.on_request_focus_trap_fn.call(false);
+    }
+
+    pub(super) fn edit_query(&self, f: impl FnOnce(&mut String)) {
+        f(&mut self.query.borrow_mut());
+
+        let filter = match SongFilter::parse(self.query.borrow().as_str()) {
+            Ok(filter) => filter,
+            Err(err) => {
+                // The results of the last valid query stay, until this one is fixed.
+                *self.error.borrow_mut() = Some(err.to_string());
+                return;
+            }
+        };
+        *self.error.borrow_mut() = None;
+
+        let results = search(self.sources_fn.borrow()(), &filter);
+        // Skip the first group's title.
+        let selected = usize::from(!results.is_empty());
+        self.results.set_items_s(results, selected, 0);
+    }
+
+    pub(super) fn add_selected_to_playlist(&self) {
+        let songs = self.results.with_items(|items| {
+            items
+                .get(self.results.selected_index())
+                .map(|result| result.songs())
+                .unwrap_or_default()
+        });
+        if !songs.is_empty() {
+            self.on_add_to_playlist_fn.call(songs);
+        }
+    }
+}
+
+impl Focusable for Search<'_> {}
@@ -0,0 +1,50 @@
+use super::component::Search;
+use crate::actions::{Action, NavigationAction, OnAction, OnActionMut, SearchAction, TextAction};
+
+impl OnActionMut for Search<'_> {
+    fn on_action(&mut self, actions: Vec<Action>) {
+        if let Action::Search(SearchAction::AddToPlaylist) = actions[0] {
+            self.add_selected_to_playlist();
+            return;
+        }
+
+        if self.is_typing.get() {
+            match actions[0] {
+                Action::Confirm
+                | Action::Cancel
+                | Action::Navigation(NavigationAction::Down | NavigationAction::FocusNext) => self.stop_typing(),
+                Action::Text(TextAction::DeleteBack) => self.edit_query(|query| {
+                    query.pop();
+                }),
+                Action::Text(TextAction::Delete) => self.edit_query(String::clear),
+                _ => {
+                    // Keys that type a character may be bound to other actions too, like the numbers switching screens.
+                    let typed = actions.iter().find_map(|action| match action {
+                        Action::Text(TextAction::Char(c)) => Some(*c),
+                        _ => None,
+                    });
+                    if let Some(c) = typed {
+                        self.edit_query(|query| query.push(c));
+                    }
+                }
+            }
+        } else {
+            match actions[0] {
+                Action::Navigation(NavigationAction::FocusNext | NavigationAction::FocusPrevious) => {
+                    self.start_typing()
+                }
+                Action::Text(TextAction::Char(c)) => {
+                    self.start_typing();
+                    self.edit_query(|query| query.push(c));
+                }
+                Action::Text(TextAction::DeleteBack) => {
+                    self.start_typing();
+                    self.edit_query(|query| {
+                        query.pop();
+                    });
+                }
+                _ => self.results.on_action(actions),
+            }
+        }
+    }
+}
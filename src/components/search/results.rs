@@ -0,0 +1,404 @@
+use std::{
+    collections::{BTreeMap, HashMap, hash_map::Entry},
+    fmt::{Display, Formatter},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{
+    components::SongFilter,
+    files::SongStats,
+    structs::{AlbumOwner, Song},
+};
+
+/// Results past this many, in each group, are left out. Typing more of the query narrows them down.
+const MAX_RESULTS_PER_GROUP: usize = 100;
+
+/// Songs to search in, and where they are.
+pub struct SearchSource {
+    pub name: String,
+    pub songs: Vec<Song>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SearchGroup {
+    Artists,
+    Albums,
+    Soundtracks,
+    Songs,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchResult {
+    Group {
+        group: SearchGroup,
+        count: usize,
+    },
+    Artist {
+        name: String,
+        songs: Vec<Song>,
+    },
+    Album {
+        artist: String,
+        name: String,
+        year: Option<u32>,
+        songs: Vec<Song>,
+    },
+    Soundtrack {
+        subject: String,
+        songs: Vec<Song>,
+    },
+    Song {
+        song: Song,
+        /// The names of the sources the song was found in.
+        sources: Vec<String>,
+    },
+}
+
+impl SearchResult {
+    pub fn songs(&self) -> Vec<Song> {
+        match self {
+            Self::Group { .. } => vec![],
+            Self::Artist { songs, .. } | Self::Album { songs, .. } | Self::Soundtrack { songs, .. } => songs.clone(),
+            Self::Song { song, .. } => vec![song.clone()],
+        }
+    }
+}
+
+impl Display for SearchResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Group { group, count } if *count > MAX_RESULTS_PER_GROUP => {
+                write!(f, "{group:?} ({count}, showing the first {MAX_RESULTS_PER_GROUP})")
+            }
+            Self::Group { group, count } => write!(f, "{group:?} ({count})"),
+            Self::Artist { name, songs } => write!(f, "  {name} - {} song(s)", songs.len()),
+            Self::Album { artist, name, year, .. } => match year {
+                Some(year) => write!(f, "  {year} - {name} - {artist}"),
+                None => write!(f, "  {name} - {artist}"),
+            },
+            Self::Soundtrack { subject, songs } => write!(f, "  {subject} - {} song(s)", songs.len()),
+            Self::Song { song, sources } => {
+                write!(f, "  {}", song.title)?;
+                for part in [&song.artist, &song.album].into_iter().flatten() {
+                    write!(f, " - {part}")?;
+                }
+                write!(f, " ({})", sources.join(", "))
+            }
+        }
+    }
+}
+
+/// How well `term` matches `text`, both lowercase: 4 if it's all of it, 3 if it's the start of it, 2 if it's the start
+/// of one of its words, 1 if it's anywhere in it, and 0 if it isn't in it.
+fn rank(text: &str, term: &str) -> u32 {
+    if text == term {
+        4
+    } else if text.starts_with(term) {
+        3
+    } else if text
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.starts_with(term))
+    {
+        2
+    } else if text.contains(term) {
+        1
+    } else {
+        0
+    }
+}
+
+/// How well all of `terms` match `fields`, or `None` if any term matches none of them.
+fn score(fields: &[Option<String>], terms: &[&str]) -> Option<u32> {
+    let fields: Vec<String> = fields.iter().flatten().map(|field| field.to_lowercase()).collect();
+
+    terms.iter().try_fold(0, |score, term| {
+        let best = fields.iter().map(|field| rank(field, term)).max().unwrap_or(0);
+        (best > 0).then_some(score + best)
+    })
+}
+
+fn push_group(results: &mut Vec<SearchResult>, group: SearchGroup, mut found: Vec<(u32, SearchResult)>) {
+    if found.is_empty() {
+        return;
+    }
+    // The sort is stable, so results that match equally well stay in the order they were found in.
+    found.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    results.push(SearchResult::Group {
+        group,
+        count: found.len(),
+    });
+    results.extend(found.into_iter().take(MAX_RESULTS_PER_GROUP).map(|(_, result)| result));
+}
+
+/// Finds the artists, albums, soundtracks and songs that match `filter`, grouped by what they are, and the best matches
+/// first. Artists, albums and soundtracks are made of the songs that match, and the words of the filter without a field
+/// must be in their names.
+pub fn search(sources: Vec<SearchSource>, filter: &SongFilter) -> Vec<SearchResult> {
+    if filter.is_empty() {
+        return vec![];
+    }
+    let terms = filter.words();
+    let stats = if filter.uses_stats() {
+        SongStats::from_file()
+    } else {
+        SongStats::default()
+    };
+
+    // The same song is often in the library, a playlist and the queue. It's listed once.
+    let mut songs: Vec<(Song, Vec<String>)> = vec![];
+    let mut positions: HashMap<(PathBuf, Duration), usize> = HashMap::new();
+    for source in sources {
+        for song in source.songs {
+            if !filter.matches(&song, &stats) {
+                continue;
+            }
+            match positions.entry((song.path.clone(), song.start_time)) {
+                Entry::Occupied(entry) => {
+                    let sources = &mut songs[*entry.get()].1;
+                    if !sources.contains(&source.name) {
+                        sources.push(source.name.clone());
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(songs.len());
+                    songs.push((song, vec![source.name.clone()]));
+                }
+            }
+        }
+    }
+    songs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut artists: BTreeMap<&str, Vec<Song>> = BTreeMap::new();
+    let mut albums: BTreeMap<(&str, AlbumOwner), Vec<Song>> = BTreeMap::new();
+    let mut soundtracks: BTreeMap<&str, Vec<Song>> = BTreeMap::new();
+    for (song, _) in &songs {
+        if let Some(artist) = &song.artist {
+            artists.entry(artist).or_default().push(song.clone());
+        }
+        if let Some(album) = song.album_key() {
+            albums.entry(album).or_default().push(song.clone());
+        }
+        if let Some(subject) = &song.soundtrack_subject {
+            soundtracks.entry(subject).or_default().push(song.clone());
+        }
+    }
+
+    let mut results = vec![];
+
+    let found = artists
+        .into_iter()
+        .filter_map(|(name, songs)| {
+            let score = score(&[Some(name.to_string())], &terms)?;
+            Some((score, SearchResult::Artist {
+                name: name.to_string(),
+                songs,
+            }))
+        })
+        .collect();
+    push_group(&mut results, SearchGroup::Artists, found);
+
+    let mut albums: Vec<(String, &str, Vec<Song>)> = albums
+        .into_iter()
+        .map(|((name, _), songs)| (Song::album_artist_of(&songs).to_string(), name, songs))
+        .collect();
+    // Albums that match equally well are listed by artist, like the artists themselves.
+    albums.sort_by(|(a_artist, a_name, _), (b_artist, b_name, _)| (a_artist, a_name).cmp(&(b_artist, b_name)));
+    let found = albums
+        .into_iter()
+        .filter_map(|(artist, name, songs)| {
+            let year = songs.iter().find_map(|song| song.year);
+            let score = score(
+                &[
+                    Some(name.to_string()),
+                    Some(artist.clone()),
+                    year.map(|year| year.to_string()),
+                ],
+                &terms,
+            )?;
+            Some((score, SearchResult::Album {
+                artist,
+                name: name.to_string(),
+                year,
+                songs,
+            }))
+        })
+        .collect();
+    push_group(&mut results, SearchGroup::Albums, found);
+
+    let found = soundtracks
+        .into_iter()
+        .filter_map(|(subject, songs)| {
+            let score = score(&[Some(subject.to_string())], &terms)?;
+            Some((score, SearchResult::Soundtrack {
+                subject: subject.to_string(),
+                songs,
+            }))
+        })
+        .collect();
+    push_group(&mut results, SearchGroup::Soundtracks, found);
+
+    let found = songs
+        .into_iter()
+        .filter_map(|(song, sources)| {
+            let score = score(
+                &[
+                    Some(song.title.clone()),
+                    song.artist.clone(),
+                    song.album.clone(),
+                    song.year.map(|year| year.to_string()),
+                    song.soundtrack_subject.clone(),
+                ],
+                &terms,
+            )?;
+            Some((score, SearchResult::Song { song, sources }))
+        })
+        .collect();
+    push_group(&mut results, SearchGroup::Songs, found);
+
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(title: &str, artist: &str, album: &str, year: u32) -> Song {
+        Song {
+            library_id: None,
+            path: PathBuf::from(format!("/music/{artist}/{album}/{title}.flac")),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(60),
+            title: title.to_string(),
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: Some(year),
+            replay_gain: Default::default(),
+        }
+    }
+
+    fn titles(results: &[SearchResult]) -> Vec<String> {
+        results.iter().map(|result| result.to_string()).collect()
+    }
+
+    fn search(sources: Vec<SearchSource>, text: &str) -> Vec<SearchResult> {
+        super::search(sources, &SongFilter::parse(text).unwrap())
+    }
+
+    #[test]
+    fn ranks_and_groups_results() {
+        let court = song("Epitaph", "King Crimson", "In the Court of the Crimson King", 1969);
+        let red = song("Starless", "King Crimson", "Red", 1974);
+        let mut interstellar = song("Cornfield Chase", "Hans Zimmer", "Interstellar", 2014);
+        interstellar.soundtrack_subject = Some("Interstellar".to_string());
+        let king = song("King", "Someone Else", "Crowns", 2001);
+
+        let sources = || {
+            vec![
+                SearchSource {
+                    name: "Library".to_string(),
+                    songs: vec![court.clone(), red.clone(), king.clone()],
+                },
+                SearchSource {
+                    name: "Soundtracks".to_string(),
+                    songs: vec![interstellar.clone()],
+                },
+                SearchSource {
+                    name: "Playlist: Prog".to_string(),
+                    songs: vec![red.clone()],
+                },
+            ]
+        };
+
+        assert!(search(sources(), "  ").is_empty());
+
+        // Exact matches first, then the ones that start with the query, then the ones with a word that does.
+        assert_eq!(titles(&search(sources(), "king")), vec![
+            "Artists (1)",
+            "  King Crimson - 2 song(s)",
+            "Albums (2)",
+            "  1969 - In the Court of the Crimson King - King Crimson",
+            "  1974 - Red - King Crimson",
+            "Songs (3)",
+            "  King - Someone Else - Crowns (Library)",
+            "  Epitaph - King Crimson - In the Court of the Crimson King (Library)",
+            "  Starless - King Crimson - Red (Library, Playlist: Prog)",
+        ]);
+
+        // Every word must match, in any field.
+        assert_eq!(titles(&search(sources(), "crimson 1974")), vec![
+            "Albums (1)",
+            "  1974 - Red - King Crimson",
+            "Songs (1)",
+            "  Starless - King Crimson - Red (Library, Playlist: Prog)",
+        ]);
+
+        // Fields narrow down the songs, and the artists and albums made of them.
+        assert_eq!(titles(&search(sources(), "crimson -title:starless")), vec![
+            "Artists (1)",
+            "  King Crimson - 1 song(s)",
+            "Albums (1)",
+            "  1969 - In the Court of the Crimson King - King Crimson",
+            "Songs (1)",
+            "  Epitaph - King Crimson - In the Court of the Crimson King (Library)",
+        ]);
+        assert_eq!(titles(&search(sources(), "year:2000..")), vec![
+            "Artists (2)",
+            "  Hans Zimmer - 1 song(s)",
+            "  Someone Else - 1 song(s)",
+            "Albums (2)",
+            "  2014 - Interstellar - Hans Zimmer",
+            "  2001 - Crowns - Someone Else",
+            "Soundtracks (1)",
+            "  Interstellar - 1 song(s)",
+            "Songs (2)",
+            "  King - Someone Else - Crowns (Library)",
+            "  Cornfield Chase - Hans Zimmer - Interstellar (Soundtracks)",
+        ]);
+
+        let results = search(sources(), "interstellar");
+        assert_eq!(results[0], SearchResult::Group {
+            group: SearchGroup::Albums,
+            count: 1
+        });
+        assert_eq!(results[2], SearchResult::Group {
+            group: SearchGroup::Soundtracks,
+            count: 1
+        });
+        assert_eq!(results[3].songs(), vec![interstellar.clone()]);
+    }
+
+    #[test]
+    fn groups_compilations_by_album_artist_or_directory() {
+        let compilation = |title: &str, artist: &str| {
+            let mut song = song(title, artist, "Pulp Fiction", 1994);
+            song.path = PathBuf::from(format!("/music/Pulp Fiction/{title}.flac"));
+            song
+        };
+        let misirlou = compilation("Misirlou", "Dick Dale");
+        let jungle_boogie = compilation("Jungle Boogie", "Kool & the Gang");
+        let mut tagged = song("Misirlou", "Dick Dale", "Pulp Fiction", 1994);
+        tagged.path = PathBuf::from("/other/01.flac");
+        tagged.album_artist = Some("Various Artists".to_string());
+        let mut other_tagged = song("Jungle Boogie", "Kool & the Gang", "Pulp Fiction", 1994);
+        other_tagged.path = PathBuf::from("/elsewhere/02.flac");
+        other_tagged.album_artist = Some("Various Artists".to_string());
+        let sources = vec![SearchSource {
+            name: "Library".to_string(),
+            songs: vec![misirlou, jungle_boogie, tagged, other_tagged],
+        }];
+
+        let results = search(sources, "pulp");
+        assert_eq!(titles(&results[..3]), vec![
+            "Albums (2)",
+            "  1994 - Pulp Fiction - Various Artists",
+            "  1994 - Pulp Fiction - Various Artists",
+        ]);
+        assert!(results[1..3].iter().all(|album| album.songs().len() == 2));
+    }
+}
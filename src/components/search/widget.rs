@@ -0,0 +1,54 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    prelude::Widget,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::WidgetRef,
+};
+
+use super::component::Search;
+
+impl WidgetRef for Search<'_> {
+    fn render_ref(&self, area: Rect, buf: &mut Buffer) {
+        let [area_query, area_hint, _, area_results] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ])
+        .horizontal_margin(2)
+        .areas(area);
+
+        let query = self.query.borrow();
+        let mut spans = vec![
+            Span::from("Search: ").style(Style::default().fg(self.theme.foreground_secondary)),
+            Span::from(query.as_str()).style(Style::default().fg(self.theme.search)),
+        ];
+        if self.is_typing.get() {
+            spans.push(Span::from(" ").style(Style::default().bg(self.theme.search)));
+        }
+        Line::from(spans).render(area_query, buf);
+
+        if let Some(error) = self.error.borrow().as_ref() {
+            Line::from(error.as_str())
+                .style(Style::default().fg(self.theme.search))
+                .render(area_hint, buf);
+        } else {
+            let hint = if self.is_typing.get() {
+                "Artist, album, title, year or soundtrack, or fields like artist:\"King Crimson\" year:1969..1975 -title:live length:>10m. Enter or Esc to go to the results"
+            } else {
+                "Enter to add to the queue, Alt+Enter to play a song, Ctrl+A to add to a playlist, Tab to edit the search"
+            };
+            Line::from(hint)
+                .style(
+                    Style::default()
+                        .fg(self.theme.foreground_secondary)
+                        .add_modifier(Modifier::DIM),
+                )
+                .render(area_hint, buf);
+        }
+
+        self.results.render_ref(area_results, buf);
+    }
+}
@@ -1,15 +1,27 @@
+use std::{cell::RefCell, collections::HashSet, path::PathBuf, rc::Rc};
+
+use ratatui::style::{Modifier, Style};
 use serde::{Deserialize, Serialize};
 use serde_default::DefaultFromSerde;
 
 use crate::{
-    components::List,
+    components::{List, SongFilter},
+    files::SongStats,
     structs::{Direction, Song},
     theme::Theme,
     ui::Focusable,
 };
 
 pub struct SongList<'a> {
+    pub(super) theme: Theme,
     pub(super) list: List<'a, Song>,
+    /// Files of the listed songs that don't exist, checked whenever the songs are set.
+    pub(super) missing_files: Rc<RefCell<HashSet<PathBuf>>>,
+    /// Every song, including the ones the filter leaves out of `list`.
+    pub(super) songs: Rc<RefCell<Vec<Song>>>,
+    pub(super) filter: RefCell<Option<SongFilter>>,
+    /// Where each song in `list` is in `songs`.
+    pub(super) positions: Rc<RefCell<Vec<usize>>>,
 }
 
 #[serde_inline_default::serde_inline_default]
@@ -54,8 +66,17 @@ impl SongListViewOptions {
 
 impl<'a> SongList<'a> {
     pub fn new(theme: Theme, songs: Vec<Song>) -> Self {
-        let list = List::new(theme, songs);
-        let mut song_list = Self { list };
+        let missing_files = Rc::new(RefCell::new(missing_files(&songs)));
+        let positions = Rc::new(RefCell::new((0..songs.len()).collect()));
+        let list = List::new(theme, songs.clone());
+        let mut song_list = Self {
+            theme,
+            list,
+            missing_files,
+            songs: Rc::new(RefCell::new(songs)),
+            filter: RefCell::new(None),
+            positions,
+        };
 
         song_list.configure();
         song_list
@@ -95,6 +116,16 @@ impl<'a> SongList<'a> {
 
         self.list
             .on_select(move |song| log::debug!("SongList: selected song {song:#?}"));
+
+        self.list.line_style({
+            let missing_files = self.missing_files.clone();
+            move |song| {
+                missing_files
+                    .borrow()
+                    .contains(&song.path)
+                    .then(|| Style::new().add_modifier(Modifier::DIM | Modifier::CROSSED_OUT))
+            }
+        });
     }
 
     pub fn set_view_options_short(&self) {
@@ -106,11 +137,62 @@ impl<'a> SongList<'a> {
     }
 
     pub fn set_view_options(&self, parts: SongListViewOptions) {
-        self.list.render_fn(move |song| render_song_with_parts(song, parts));
+        let missing_files = self.missing_files.clone();
+        self.list.render_fn(move |song| {
+            let line = render_song_with_parts(song, parts);
+            if missing_files.borrow().contains(&song.path) {
+                format!("{line} (missing)")
+            } else {
+                line
+            }
+        });
     }
 
     pub fn set_items(&self, songs: Vec<Song>) {
-        self.list.set_items(songs);
+        *self.missing_files.borrow_mut() = missing_files(&songs);
+        *self.songs.borrow_mut() = songs;
+        self.refresh_filtered_items();
+    }
+
+    pub fn filter(&self) -> Option<SongFilter> {
+        self.filter.borrow().clone()
+    }
+
+    /// Lists only the songs that match `filter`, from now on, until it's set to `None`.
+    pub fn set_filter(&self, filter: Option<SongFilter>) {
+        *self.filter.borrow_mut() = filter;
+        self.refresh_filtered_items();
+    }
+
+    fn refresh_filtered_items(&self) {
+        let songs = self.songs.borrow();
+        let filter = self.filter.borrow();
+        let stats = match filter.as_ref() {
+            Some(filter) if filter.uses_stats() => SongStats::from_file(),
+            _ => SongStats::default(),
+        };
+
+        let positions: Vec<usize> = (0..songs.len())
+            .filter(|i| filter.as_ref().is_none_or(|filter| filter.matches(&songs[*i], &stats)))
+            .collect();
+        self.list
+            .set_items(positions.iter().map(|i| songs[*i].clone()).collect());
+        *self.positions.borrow_mut() = positions;
+    }
+
+    pub fn selected_song(&self) -> Option<Song> {
+        self.list
+            .with_items(|songs| songs.get(self.list.selected_index()).map(|song| (*song).clone()))
+    }
+
+    /// The songs the filter lets through.
+    pub fn listed_songs(&self) -> Vec<Song> {
+        self.list.with_items(|songs| songs.into_iter().cloned().collect())
+    }
+
+    /// How many songs are listed, and how many there are, counting the ones the filter leaves out.
+    pub(super) fn count(&self) -> (usize, usize) {
+        (self.positions.borrow().len(), self.songs.borrow().len())
     }
 
     pub fn on_confirm(&self, cb: impl Fn(Song) + 'a) {
@@ -121,15 +203,44 @@ impl<'a> SongList<'a> {
         self.list.on_confirm_alt(cb);
     }
 
+    /// Called with the index of the song among all of them, whether it was filtered or not.
     pub fn on_delete(&self, cb: impl Fn(Song, usize) + 'a) {
-        self.list.on_delete(cb);
+        let songs = self.songs.clone();
+        let positions = self.positions.clone();
+        self.list.on_delete(move |song, i| {
+            let mut positions = positions.borrow_mut();
+            let position = positions.remove(i);
+            for p in positions.iter_mut().filter(|p| **p > position) {
+                *p -= 1;
+            }
+            drop(positions);
+
+            songs.borrow_mut().remove(position);
+            cb(song, position);
+        });
     }
 
+    /// Called with the indexes of the songs among all of them, whether it was filtered or not.
+    /// While filtered, the swapped songs may not be next to each other.
     pub fn on_reorder(&self, cb: impl Fn(usize, usize) + 'a) {
-        self.list.on_reorder(cb);
+        let songs = self.songs.clone();
+        let positions = self.positions.clone();
+        self.list.on_reorder(move |a, b| {
+            let (a, b) = {
+                let positions = positions.borrow();
+                (positions[a], positions[b])
+            };
+            songs.borrow_mut().swap(a, b);
+            cb(a, b);
+        });
     }
 }
 
+fn missing_files(songs: &[Song]) -> HashSet<PathBuf> {
+    let paths: HashSet<&PathBuf> = songs.iter().map(|song| &song.path).collect();
+    paths.into_iter().filter(|path| !path.exists()).cloned().collect()
+}
+
 fn render_song_with_parts(song: &Song, parts: SongListViewOptions) -> String {
     let mut pieces = Vec::new();
 
@@ -1,9 +1,29 @@
-use ratatui::{buffer::Buffer, layout::Rect, widgets::WidgetRef};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Widget, WidgetRef},
+};
 
 use super::SongList;
 
 impl WidgetRef for SongList<'_> {
     fn render_ref(&self, area: Rect, buf: &mut Buffer) {
-        self.list.render_ref(area, buf);
+        let Some(filter) = self.filter.borrow().clone() else {
+            self.list.render_ref(area, buf);
+            return;
+        };
+
+        let [area_filter, area_list] = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).areas(area);
+
+        let (listed, total) = self.count();
+        Line::from(vec![
+            Span::from(filter.text().to_string()).style(Style::default().fg(self.theme.search)),
+            Span::from(format!(" {listed} of {total} songs")).style(Style::default().add_modifier(Modifier::DIM)),
+        ])
+        .render(area_filter, buf);
+
+        self.list.render_ref(area_list, buf);
     }
 }
@@ -13,6 +13,7 @@ pub struct Work {
 }
 
 impl Work {
+    #[allow(dead_code)]
     pub fn songs(&self) -> Vec<Song> {
         self.albums.iter().flat_map(|album| album.songs.clone()).collect()
     }
@@ -3,13 +3,15 @@ use std::{
     collections::HashMap,
     fs::{read_to_string, write},
     rc::Rc,
+    time::SystemTime,
 };
 
 use uuid::Uuid;
 
 use super::album_tree_item::{Album, AlbumTreeItem, Work};
 use crate::{
-    components::{FocusGroup, SongList, Tree, TreeNode},
+    components::{FocusGroup, SongFilter, SongList, Tree, TreeNode},
+    files::SongStats,
     structs::Song,
     theme::Theme,
     ui::{Component, Focusable},
@@ -187,6 +189,23 @@ impl<'a> Soundtracks<'a> {
         self.song_list.on_confirm_alt(cb);
     }
 
+    pub fn song_filter(&self) -> Option<SongFilter> {
+        self.song_list.filter()
+    }
+
+    pub fn set_song_filter(&self, filter: Option<SongFilter>) {
+        self.song_list.set_filter(filter);
+    }
+
+    /// The selected song, while the song list is focused, or the listed songs of the selected work or album.
+    pub fn selected_songs(&self) -> Vec<Song> {
+        if self.song_list.is_focused() {
+            self.song_list.selected_song().into_iter().collect()
+        } else {
+            self.song_list.listed_songs()
+        }
+    }
+
     pub fn on_select_songs_fn(&self, cb: impl FnMut(Vec<&Song>) + 'a) {
         *self.on_select_songs_fn.borrow_mut() = Box::new(cb);
     }
@@ -200,6 +219,16 @@ impl<'a> Soundtracks<'a> {
         for song in &mut songs {
             song.library_id = Some(Uuid::new_v4());
         }
+        self.insert_songs(songs);
+    }
+
+    /// Adds songs that already have a `library_id`, like the ones found in the library folders.
+    pub fn insert_songs(&self, mut songs: Vec<Song>) {
+        if songs.is_empty() {
+            return;
+        }
+        SongStats::update(|stats| stats.record_added(&songs, |_| SystemTime::now()));
+        songs.sort();
         let songs = song_vec_to_map(songs);
 
         self.album_tree.borrow_mut().with_nodes_mut(|artist_nodes| {
@@ -213,6 +242,56 @@ impl<'a> Soundtracks<'a> {
 
             save_lib(artist_nodes);
         });
+        self.album_tree.borrow().refresh_selection();
+    }
+
+    /// Lists the songs of the selected node again, marking the ones whose files went missing or came back.
+    pub fn refresh_songs(&self) {
+        self.album_tree.borrow().refresh_selection();
+    }
+
+    /// Every song in the soundtracks.
+    pub fn songs(&self) -> Vec<Song> {
+        self.album_tree.borrow().with_nodes(|nodes| {
+            nodes
+                .iter()
+                .flat_map(|node| &node.children)
+                .flat_map(|child| child.inner.songs())
+                .collect()
+        })
+    }
+
+    /// Removes the songs `f` returns true for, and the albums and works they leave empty.
+    pub fn remove_songs(&self, f: impl Fn(&Song) -> bool) -> Vec<Song> {
+        let mut removed = Vec::new();
+
+        self.album_tree.borrow_mut().with_nodes_mut(|artist_nodes| {
+            for artist_node in artist_nodes.iter_mut() {
+                for album_node in artist_node.children.iter_mut() {
+                    if let AlbumTreeItem::Album(album) = &mut album_node.inner {
+                        let (gone, kept): (Vec<Song>, Vec<Song>) =
+                            std::mem::take(&mut album.songs).into_iter().partition(&f);
+                        album.songs = kept;
+                        removed.extend(gone);
+                    }
+                }
+                artist_node.children.retain(
+                    |album_node| !matches!(&album_node.inner, AlbumTreeItem::Album(album) if album.songs.is_empty()),
+                );
+            }
+            artist_nodes.retain(|artist_node| {
+                !matches!(artist_node.inner, AlbumTreeItem::Work(_)) || !artist_node.children.is_empty()
+            });
+
+            if !removed.is_empty() {
+                save_lib(artist_nodes);
+            }
+        });
+
+        if !removed.is_empty() {
+            self.album_tree.borrow().refresh_selection();
+        }
+        removed
     }
 }
 
@@ -302,6 +381,7 @@ fn add_album_nodes(artist_node: &mut TreeNode<AlbumTreeItem>, artist_name: Strin
     for (album_name, songs) in albums.into_iter() {
         if let Some(album) = find_album(artist_node, album_name.as_str()) {
             album.songs.extend(songs);
+            album.songs.sort();
         } else {
             artist_node.children.push(TreeNode::new(AlbumTreeItem::Album(Album {
                 id: Some(Uuid::new_v4()),
@@ -316,6 +396,36 @@ fn add_album_nodes(artist_node: &mut TreeNode<AlbumTreeItem>, artist_name: Strin
 
 impl Focusable for Soundtracks<'_> {}
 
+/// Calls `f` with every song in `soundtracks.json`, and saves it if `f` returned true for any of them.
+/// Only for when Jolteon isn't running, since it saves its own copy when it closes.
+pub fn edit_saved_soundtrack_songs(mut f: impl FnMut(&mut Song) -> bool) {
+    let mut nodes = load_lib();
+    let mut changed = false;
+
+    for node in &mut nodes {
+        for child in &mut node.children {
+            if let AlbumTreeItem::Album(album) = &mut child.inner {
+                for song in &mut album.songs {
+                    changed |= f(song);
+                }
+            }
+        }
+    }
+
+    if changed {
+        save_lib(&nodes);
+    }
+}
+
+/// Every song in `soundtracks.json`, as it was last saved. Songs are saved as soon as they're added or removed.
+pub fn saved_soundtrack_songs() -> Vec<Song> {
+    load_lib()
+        .iter()
+        .flat_map(|node| &node.children)
+        .flat_map(|child| child.inner.songs())
+        .collect()
+}
+
 fn load_lib() -> Vec<TreeNode<AlbumTreeItem>> {
     let path = home::home_dir()
         .map(|path| path.as_path().join(".config/jolteon/soundtracks.json"))
@@ -0,0 +1,6 @@
+mod component;
+mod keyboard_handler;
+mod listening;
+mod widget;
+
+pub use component::*;
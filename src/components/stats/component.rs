@@ -0,0 +1,56 @@
+use std::{
+    cell::{Cell, RefCell},
+    time::SystemTime,
+};
+
+use super::listening::{ListeningStats, StatsPeriod};
+use crate::{files::PlayHistory, structs::Song, theme::Theme, ui::Focusable};
+
+pub struct Stats<'a> {
+    pub(super) theme: Theme,
+
+    pub(super) period: Cell<StatsPeriod>,
+    pub(super) history: RefCell<PlayHistory>,
+    pub(super) stats: RefCell<ListeningStats>,
+
+    library_songs_fn: RefCell<Box<dyn Fn() -> Vec<Song> + 'a>>,
+}
+
+impl<'a> Stats<'a> {
+    pub fn new(theme: Theme) -> Self {
+        Self {
+            theme,
+
+            period: Cell::new(StatsPeriod::default()),
+            history: RefCell::new(PlayHistory::default()),
+            stats: RefCell::new(ListeningStats::default()),
+
+            library_songs_fn: RefCell::new(Box::new(Vec::new)),
+        }
+    }
+
+    /// The songs plays are looked up in. Plays of songs that left the library aren't counted.
+    pub fn library_songs_fn(&self, f: impl Fn() -> Vec<Song> + 'a) {
+        *self.library_songs_fn.borrow_mut() = Box::new(f);
+    }
+
+    /// Reads the play history again. The player adds to it as songs play, so this is called whenever the screen
+    /// is shown.
+    pub fn refresh(&self) {
+        *self.history.borrow_mut() = PlayHistory::from_file();
+        self.update_stats();
+    }
+
+    pub(super) fn set_period(&self, period: StatsPeriod) {
+        self.period.set(period);
+        self.update_stats();
+    }
+
+    fn update_stats(&self) {
+        let library = self.library_songs_fn.borrow()();
+        *self.stats.borrow_mut() =
+            ListeningStats::new(&self.history.borrow(), &library, self.period.get(), SystemTime::now());
+    }
+}
+
+impl Focusable for Stats<'_> {}
@@ -0,0 +1,16 @@
+use super::component::Stats;
+use crate::actions::{Action, NavigationAction, OnActionMut};
+
+impl OnActionMut for Stats<'_> {
+    fn on_action(&mut self, actions: Vec<Action>) {
+        match actions[0] {
+            Action::Navigation(NavigationAction::Right | NavigationAction::FocusNext) => {
+                self.set_period(self.period.get().next())
+            }
+            Action::Navigation(NavigationAction::Left | NavigationAction::FocusPrevious) => {
+                self.set_period(self.period.get().previous())
+            }
+            _ => {}
+        }
+    }
+}
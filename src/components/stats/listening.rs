@@ -0,0 +1,254 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use strum::Display;
+use uuid::Uuid;
+
+use crate::{
+    files::PlayHistory,
+    structs::{AlbumOwner, Song},
+};
+
+/// How many artists, albums and tracks the top lists have.
+const TOP: usize = 10;
+
+/// How many songs the recently played list has.
+const RECENT: usize = 30;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Display)]
+pub enum StatsPeriod {
+    #[default]
+    #[strum(to_string = "Last 7 days")]
+    Week,
+    #[strum(to_string = "Last 30 days")]
+    Month,
+    #[strum(to_string = "Last 365 days")]
+    Year,
+    #[strum(to_string = "All time")]
+    AllTime,
+}
+
+impl StatsPeriod {
+    pub fn next(&self) -> Self {
+        match self {
+            StatsPeriod::Week => StatsPeriod::Month,
+            StatsPeriod::Month => StatsPeriod::Year,
+            StatsPeriod::Year => StatsPeriod::AllTime,
+            StatsPeriod::AllTime => StatsPeriod::Week,
+        }
+    }
+
+    pub fn previous(&self) -> Self {
+        match self {
+            StatsPeriod::Week => StatsPeriod::AllTime,
+            StatsPeriod::Month => StatsPeriod::Week,
+            StatsPeriod::Year => StatsPeriod::Month,
+            StatsPeriod::AllTime => StatsPeriod::Year,
+        }
+    }
+
+    fn length(&self) -> Option<Duration> {
+        let days = match self {
+            StatsPeriod::Week => 7,
+            StatsPeriod::Month => 30,
+            StatsPeriod::Year => 365,
+            StatsPeriod::AllTime => return None,
+        };
+        Some(Duration::from_secs(days * 24 * 60 * 60))
+    }
+}
+
+/// What the play history says about a period, for the songs that are still in the library.
+#[derive(Debug, Default, PartialEq)]
+pub struct ListeningStats {
+    pub plays: usize,
+    pub skips: usize,
+    pub listening_time: Duration,
+    pub top_artists: Vec<(String, usize)>,
+    /// Albums, as "Album (Artist)".
+    pub top_albums: Vec<(String, usize)>,
+    pub top_tracks: Vec<(Song, usize)>,
+    /// Newest first, whatever the period, along with when each one stopped playing and whether it was skipped.
+    pub recently_played: Vec<(SystemTime, Song, bool)>,
+}
+
+impl ListeningStats {
+    pub fn new(history: &PlayHistory, library: &[Song], period: StatsPeriod, now: SystemTime) -> Self {
+        let songs: HashMap<Uuid, &Song> = library
+            .iter()
+            .filter_map(|song| song.library_id.map(|id| (id, song)))
+            .collect();
+        let since = period.length().and_then(|length| now.checked_sub(length));
+
+        let mut stats = Self::default();
+        let mut artists: HashMap<String, usize> = HashMap::new();
+        let mut albums: HashMap<(&str, AlbumOwner), Vec<&Song>> = HashMap::new();
+        let mut tracks: HashMap<Uuid, usize> = HashMap::new();
+
+        for event in &history.events {
+            let Some(song) = songs.get(&event.library_id) else {
+                continue;
+            };
+            if since.is_some_and(|since| event.time < since) {
+                continue;
+            }
+
+            stats.listening_time += song.length.mul_f32(event.completion);
+            if event.skipped {
+                stats.skips += 1;
+            }
+            if !event.is_counted() {
+                continue;
+            }
+            stats.plays += 1;
+
+            if let Some(artist) = &song.artist {
+                *artists.entry(artist.clone()).or_default() += 1;
+            }
+            if let Some(album) = song.album_key() {
+                albums.entry(album).or_default().push(song);
+            }
+            *tracks.entry(event.library_id).or_default() += 1;
+        }
+
+        stats.top_artists = top(artists);
+        stats.top_albums = top(albums.into_iter().map(|((album, _), songs)| {
+            let artist = Song::album_artist_of(songs.iter().copied());
+            (format!("{album} ({artist})"), songs.len())
+        }));
+        stats.top_tracks = top(tracks.into_iter().map(|(id, count)| (songs[&id].clone(), count)));
+
+        stats.recently_played = history
+            .events
+            .iter()
+            .rev()
+            .filter_map(|event| {
+                songs
+                    .get(&event.library_id)
+                    .map(|song| (event.time, (*song).clone(), event.skipped))
+            })
+            .take(RECENT)
+            .collect();
+
+        stats
+    }
+}
+
+/// The most played first. Ties are broken by name, so the lists don't shuffle around between refreshes.
+fn top<T: Ord>(counts: impl IntoIterator<Item = (T, usize)>) -> Vec<(T, usize)> {
+    let mut counts: Vec<(T, usize)> = counts.into_iter().collect();
+    counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+    counts.truncate(TOP);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::files::PlayEvent;
+
+    fn song(title: &str, artist: &str, album: &str) -> Song {
+        Song {
+            library_id: Some(Uuid::new_v4()),
+            path: PathBuf::from(format!("/music/{artist}/{album}/{title}.flac")),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(100),
+            title: title.to_string(),
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: None,
+            replay_gain: Default::default(),
+        }
+    }
+
+    #[test]
+    fn aggregates_plays_by_period() {
+        let library = vec![
+            song("Red", "King Crimson", "Red"),
+            song("Starless", "King Crimson", "Red"),
+            song("Roundabout", "Yes", "Fragile"),
+        ];
+        let now = SystemTime::now();
+        let days_ago = |days: u64| now - Duration::from_secs(days * 24 * 60 * 60);
+        let event = |song: &Song, completion: f32, skipped: bool, time: SystemTime| PlayEvent {
+            time,
+            library_id: song.library_id.unwrap(),
+            completion,
+            skipped,
+        };
+
+        let removed = song("Gone", "Nobody", "Nothing");
+        let history = PlayHistory {
+            events: vec![
+                event(&library[2], 1.0, false, days_ago(100)),
+                event(&library[2], 1.0, false, days_ago(20)),
+                event(&library[2], 1.0, false, days_ago(20)),
+                event(&library[0], 1.0, false, days_ago(3)),
+                event(&library[1], 0.1, true, days_ago(2)),
+                event(&removed, 1.0, false, days_ago(2)),
+                event(&library[1], 0.75, true, days_ago(1)),
+            ],
+        };
+
+        let week = ListeningStats::new(&history, &library, StatsPeriod::Week, now);
+        assert_eq!(week.plays, 2);
+        assert_eq!(week.skips, 2);
+        assert_eq!(week.listening_time.as_secs_f32().round(), 185.0);
+        assert_eq!(week.top_artists, vec![("King Crimson".to_string(), 2)]);
+        assert_eq!(week.top_albums, vec![("Red (King Crimson)".to_string(), 2)]);
+        assert_eq!(week.top_tracks, vec![(library[0].clone(), 1), (library[1].clone(), 1)]);
+
+        let all_time = ListeningStats::new(&history, &library, StatsPeriod::AllTime, now);
+        assert_eq!(all_time.plays, 5);
+        assert_eq!(all_time.top_artists, vec![
+            ("Yes".to_string(), 3),
+            ("King Crimson".to_string(), 2)
+        ]);
+        assert_eq!(all_time.top_tracks[0], (library[2].clone(), 3));
+
+        let recent: Vec<(&str, bool)> = all_time
+            .recently_played
+            .iter()
+            .map(|(_, song, skipped)| (song.title.as_str(), *skipped))
+            .collect();
+        assert_eq!(recent[..3], [("Starless", true), ("Starless", true), ("Red", false)]);
+        assert_eq!(recent.len(), 6);
+    }
+
+    #[test]
+    fn counts_compilations_as_one_album() {
+        let compilation = |title: &str, artist: &str| Song {
+            path: PathBuf::from(format!("/music/Pulp Fiction/{title}.flac")),
+            ..song(title, artist, "Pulp Fiction")
+        };
+        let library = vec![
+            compilation("Misirlou", "Dick Dale"),
+            compilation("Jungle Boogie", "Kool & the Gang"),
+        ];
+        let history = PlayHistory {
+            events: library
+                .iter()
+                .map(|song| PlayEvent {
+                    time: SystemTime::now(),
+                    library_id: song.library_id.unwrap(),
+                    completion: 1.0,
+                    skipped: false,
+                })
+                .collect(),
+        };
+
+        let stats = ListeningStats::new(&history, &library, StatsPeriod::AllTime, SystemTime::now());
+        assert_eq!(stats.top_albums, vec![(
+            "Pulp Fiction (Various Artists)".to_string(),
+            2
+        )]);
+    }
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Local};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    prelude::Widget,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::WidgetRef,
+};
+
+use super::component::Stats;
+use crate::duration::duration_to_string;
+
+impl WidgetRef for Stats<'_> {
+    fn render_ref(&self, area: Rect, buf: &mut Buffer) {
+        let stats = self.stats.borrow();
+        let top_height = stats
+            .top_artists
+            .len()
+            .max(stats.top_albums.len())
+            .max(stats.top_tracks.len()) as u16
+            + 1;
+
+        let [area_summary, area_hint, _, area_top, _, area_recent] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(top_height),
+            Constraint::Length(1),
+            Constraint::Min(0),
+        ])
+        .horizontal_margin(2)
+        .areas(area);
+
+        let secondary = Style::default().fg(self.theme.foreground_secondary);
+        let title = Style::default().fg(self.theme.foreground).add_modifier(Modifier::BOLD);
+
+        Line::from(vec![
+            Span::from(format!("{}: ", self.period.get())).style(secondary),
+            Span::from(format!(
+                "{} listened, {} plays, {} skipped",
+                duration_to_string(stats.listening_time),
+                stats.plays,
+                stats.skips
+            ))
+            .style(Style::default().fg(self.theme.foreground)),
+        ])
+        .render(area_summary, buf);
+
+        Line::from("Left and Right to change the period. Plays are counted when at least half the song played.")
+            .style(secondary.add_modifier(Modifier::DIM))
+            .render(area_hint, buf);
+
+        let columns = Layout::horizontal([Constraint::Ratio(1, 3); 3])
+            .spacing(2)
+            .split(area_top);
+        let tracks: Vec<(String, usize)> = stats
+            .top_tracks
+            .iter()
+            .map(|(song, count)| {
+                let name = match &song.artist {
+                    Some(artist) => format!("{} - {artist}", song.title),
+                    None => song.title.clone(),
+                };
+                (name, *count)
+            })
+            .collect();
+        for (column, (name, top)) in columns.iter().zip([
+            ("Top Artists", &stats.top_artists),
+            ("Top Albums", &stats.top_albums),
+            ("Top Tracks", &tracks),
+        ]) {
+            let mut lines = vec![Line::from(name).style(title)];
+            lines.extend(top.iter().map(|(name, count)| {
+                Line::from(vec![
+                    Span::from(format!("{count:>4}  ")).style(secondary),
+                    Span::from(name.as_str()).style(Style::default().fg(self.theme.foreground)),
+                ])
+            }));
+            for (line, area) in lines.into_iter().zip(column.rows()) {
+                line.render(area, buf);
+            }
+        }
+
+        let mut lines = vec![Line::from("Recently Played").style(title)];
+        if stats.recently_played.is_empty() {
+            lines.push(
+                Line::from("Nothing yet. Songs from the Library and Soundtracks show up here once they've played.")
+                    .style(secondary),
+            );
+        }
+        lines.extend(stats.recently_played.iter().map(|(time, song, skipped)| {
+            let time = DateTime::<Local>::from(*time).format("%Y-%m-%d %H:%M").to_string();
+            let mut spans = vec![
+                Span::from(format!("{time}  ")).style(secondary),
+                Span::from(song.title.as_str()).style(Style::default().fg(self.theme.foreground)),
+            ];
+            if let Some(artist) = &song.artist {
+                spans.push(Span::from(format!(" - {artist}")).style(secondary));
+            }
+            if *skipped {
+                spans.push(Span::from(" (skipped)").style(secondary.add_modifier(Modifier::DIM)));
+            }
+            Line::from(spans)
+        }));
+        for (line, area) in lines.into_iter().zip(area_recent.rows()) {
+            line.render(area, buf);
+        }
+    }
+}
@@ -75,6 +75,11 @@ where
         }
     }
 
+    pub fn with_nodes<R>(&self, cb: impl FnOnce(&[TreeNode<T>]) -> R) -> R {
+        let items = self.items.borrow();
+        cb(&items)
+    }
+
     pub fn with_nodes_mut(&mut self, cb: impl FnOnce(&mut Vec<TreeNode<T>>)) {
         let mut items = self.items.borrow_mut();
         cb(&mut *items)
@@ -162,6 +167,26 @@ where
         self.set_items_s(items, TreeNodePath::zero(), 0);
     }
 
+    /// Resets the selection if the items changed under it, and lets `on_select` know about the node that's selected now.
+    pub fn refresh_selection(&self) {
+        let items = self.items.borrow();
+        let mut selected_item_path = self.selected_item_path.borrow_mut();
+
+        if TreeNode::get_node_at_path(&selected_item_path, &items).is_none() {
+            *selected_item_path = TreeNodePath::zero();
+            self.offset.set(0);
+        }
+
+        let node = TreeNode::get_node_at_path(&selected_item_path, &items);
+        drop(selected_item_path);
+
+        if let Some(node) = node
+            && let Some(on_select_fn) = &self.on_select_fn
+        {
+            on_select_fn(node);
+        }
+    }
+
     // /// Sets the list of items but tries to conserve selection and scroll
     // pub fn set_items_k(&self, new_items: Vec<TreeNode<T>>) {
     //     let mut items = self.items.borrow_mut();
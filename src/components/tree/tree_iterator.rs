@@ -63,17 +63,14 @@ impl<'a, T> Iterator for TreeNodeListIterator<'a, T> {
 impl<T> DoubleEndedIterator for TreeNodeListIterator<'_, T> {
     fn next_back(&mut self) -> Option<Self::Item> {
         if self.child_iter.is_none() {
-            if let Some(root_node) = self.root_iter.next_back() {
-                self.root_index += 1;
-                self.root_node = Some(root_node);
-                self.child_iter = if self.pick_locks || root_node.is_open {
-                    Some(root_node.iter())
-                } else {
-                    None
-                };
+            let root_node = self.root_iter.next_back()?;
+            self.root_index += 1;
+            self.root_node = Some(root_node);
+            self.child_iter = if self.pick_locks || root_node.is_open {
+                Some(root_node.iter())
             } else {
-                return None;
-            }
+                None
+            };
         }
 
         if let Some((path, node)) = self.child_iter.as_mut().and_then(|ci| ci.next_back()) {
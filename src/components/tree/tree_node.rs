@@ -105,10 +105,10 @@ impl<T> TreeNode<T> {
         let index = path.first();
 
         if path.len() == 1 {
-            Some(&nodes[index])
+            nodes.get(index)
         } else {
             let p = TreeNodePath::from_vec(path.as_slice()[1..].to_vec());
-            nodes[index].get_child(&p)
+            nodes.get(index)?.get_child(&p)
         }
     }
 
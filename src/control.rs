@@ -0,0 +1,463 @@
+use std::{
+    env,
+    fs,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Weak,
+    thread,
+    time::Duration,
+};
+
+use clap::Subcommand;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{OnAction, PlayerAction},
+    main_player::{MainPlayer, RepeatMode, ShuffleMode},
+    structs::Song,
+};
+
+/// A command sent to a running instance of Jolteon, through its control socket.
+///
+/// The protocol is one JSON object per line, in both directions, like `{"command":"seek","seconds":-10}`.
+/// Every request gets exactly one `Response`.
+#[derive(Subcommand, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    /// Resume playback.
+    Play,
+    /// Pause playback.
+    Pause,
+    /// Toggle between playing and paused.
+    PlayPause,
+    /// Skip to the next song in the queue.
+    Next,
+    /// Seek by a number of seconds. Negative numbers seek backwards.
+    Seek {
+        #[arg(allow_negative_numbers = true)]
+        seconds: i32,
+    },
+    /// Seek to a position of the playing song, in seconds.
+    SeekTo { seconds: f64 },
+    /// Set the volume, from 0 to 100.
+    Volume { percent: u32 },
+    /// Add a file, cue sheet or directory to the queue.
+    Enqueue {
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+
+        /// Add the songs to the front of the queue, rather than the back.
+        #[arg(short, long, default_value_t = false)]
+        #[serde(default)]
+        front: bool,
+
+        /// Add the songs to the front of the queue, and skip to the first one.
+        #[arg(short, long, default_value_t = false)]
+        #[serde(default)]
+        play_now: bool,
+    },
+    /// Remove the song at an index of the queue, starting at 0.
+    Remove { index: usize },
+    /// List the songs in the queue.
+    Queue,
+    /// Show what's playing, and how.
+    Status,
+    /// Run a player action, by the name used in the key bindings file, like `VolumeUp` or `RepeatToggle`.
+    Action { action: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    Error(String),
+    Status(Box<Status>),
+    Queue(Vec<Song>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Status {
+    pub song: Option<Song>,
+    /// In seconds, relative to the start of the song.
+    pub position: f64,
+    pub is_paused: bool,
+    pub volume: u32,
+    pub speed: u32,
+    pub repeat_mode: RepeatMode,
+    #[serde(default)]
+    pub shuffle_mode: ShuffleMode,
+    #[serde(default)]
+    pub radio: bool,
+    pub queue_length: usize,
+    /// In seconds.
+    pub queue_duration: f64,
+}
+
+/// `$XDG_RUNTIME_DIR/jolteon.sock`. Without `XDG_RUNTIME_DIR`, it goes in the temporary directory, named after the user.
+pub fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("jolteon.sock"),
+        _ => {
+            let user = env::var("USER").unwrap_or_default();
+            env::temp_dir().join(format!("jolteon-{user}.sock"))
+        }
+    }
+}
+
+fn handle(request: Request, player: &MainPlayer) -> Response {
+    let single_track_player = player.single_track_player();
+
+    match request {
+        Request::Play => {
+            single_track_player.set_is_paused(false);
+        }
+        Request::Pause => {
+            single_track_player.set_is_paused(true);
+        }
+        Request::PlayPause => {
+            single_track_player.toggle_is_paused();
+        }
+        Request::Next => {
+            player.stop();
+        }
+        Request::Seek { seconds } => {
+            if seconds != 0 {
+                single_track_player.seek(seconds);
+            }
+        }
+        Request::SeekTo { seconds } => {
+            if seconds.is_nan() || seconds < 0.0 {
+                return Response::Error("The position can't be negative".to_string());
+            }
+            single_track_player.seek_to(Duration::from_secs_f64(seconds));
+        }
+        Request::Volume { percent } => {
+            single_track_player.set_volume(percent.min(100) as f32 / 100.);
+        }
+        Request::Enqueue { path, front, play_now } => {
+            let songs = match Song::from_path(&path) {
+                Ok(songs) if songs.is_empty() => return Response::Error(format!("No songs found at {path:?}")),
+                Ok(songs) => songs,
+                Err(err) => return Response::Error(format!("{path:?}: {err}")),
+            };
+            if front || play_now {
+                player.prepend(songs);
+            } else {
+                player.append(&mut songs.into());
+            }
+            // Without a song playing, the player picks up the queue by itself.
+            if play_now && player.playing_song().is_some() {
+                player.stop();
+            }
+        }
+        Request::Remove { index } => {
+            let length = player.queue().songs().len();
+            if index >= length {
+                return Response::Error(format!(
+                    "There's no song at index {index}. The queue has {length} songs."
+                ));
+            }
+            player.remove(index);
+        }
+        Request::Queue => {
+            return Response::Queue(player.queue().songs().iter().cloned().collect());
+        }
+        Request::Status => {
+            let queue = player.queue();
+            let (queue_length, queue_duration) = {
+                let songs = queue.songs();
+                (songs.len(), songs.iter().map(|song| song.length).sum::<Duration>())
+            };
+            return Response::Status(Box::new(Status {
+                song: player.playing_song(),
+                position: player.playing_position().as_secs_f64(),
+                is_paused: player.is_paused(),
+                volume: player.volume(),
+                speed: player.speed(),
+                repeat_mode: player.repeat_mode(),
+                shuffle_mode: player.shuffle_mode(),
+                radio: player.is_radio_on(),
+                queue_length,
+                queue_duration: queue_duration.as_secs_f64(),
+            }));
+        }
+        Request::Action { action } => {
+            let action = match PlayerAction::from_str(&action) {
+                // Picking an output device needs the TUI.
+                Ok(PlayerAction::SelectOutputDevice) | Err(_) => {
+                    return Response::Error(format!("Unknown player action {action:?}"));
+                }
+                Ok(action) => action,
+            };
+            player.on_action(vec![action]);
+            single_track_player.on_action(vec![action]);
+        }
+    }
+
+    Response::Ok
+}
+
+fn serve_connection(stream: UnixStream, player: &Weak<MainPlayer>) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match (serde_json::from_str::<Request>(&line), player.upgrade()) {
+            (Ok(request), Some(player)) => {
+                log::debug!("control socket request {request:?}");
+                handle(request, &player)
+            }
+            (Ok(_), None) => Response::Error("The player is shutting down".to_string()),
+            (Err(err), _) => Response::Error(format!("Invalid request: {err}")),
+        };
+
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+    }
+
+    Ok(())
+}
+
+/// Listens for `Request`s on a Unix domain socket, and runs them against the player, for as long as it's alive.
+/// The socket file is removed when this is dropped.
+pub struct ControlServer {
+    path: PathBuf,
+}
+
+impl ControlServer {
+    /// Fails if another instance is already listening on `path`. Sockets left behind by instances that are gone are replaced.
+    pub fn start(path: PathBuf, player: Weak<MainPlayer>) -> std::io::Result<Self> {
+        if UnixStream::connect(&path).is_ok() {
+            return Err(std::io::Error::new(
+                ErrorKind::AddrInUse,
+                format!("another instance is listening on {path:?}"),
+            ));
+        }
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+
+        let listener = UnixListener::bind(&path)?;
+        // Anyone who can connect can control the player, and read the queue.
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+        thread::Builder::new().name("control".to_string()).spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        log::error!("control socket: could not accept connection: {err}");
+                        continue;
+                    }
+                };
+                let player = player.clone();
+                let spawned = thread::Builder::new()
+                    .name("control_client".to_string())
+                    .spawn(move || {
+                        if let Err(err) = serve_connection(stream, &player) {
+                            log::warn!("control socket: connection error: {err}");
+                        }
+                    });
+                if let Err(err) = spawned {
+                    log::error!("control socket: could not spawn connection thread: {err}");
+                }
+            }
+        })?;
+
+        log::debug!("control socket listening on {path:?}");
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            log::warn!("could not remove control socket {:?}: {err}", self.path);
+        }
+    }
+}
+
+/// Sends a request to the instance listening on `path`, and waits for its response.
+pub fn send(path: &Path, request: &Request) -> std::io::Result<Response> {
+    let mut stream = UnixStream::connect(path)?;
+    serde_json::to_writer(&mut stream, request)?;
+    stream.write_all(b"\n")?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, mpsc::channel};
+
+    use super::*;
+    use crate::{
+        main_player::Session,
+        output::OutputTarget,
+        settings::Settings,
+        test_fixtures::{TempDir, write_wav},
+    };
+
+    const PACE: f32 = 20.0;
+
+    #[test]
+    fn requests_are_json_lines() {
+        let request: Request = serde_json::from_str(r#"{"command":"seek","seconds":-10}"#).unwrap();
+        assert_eq!(request, Request::Seek { seconds: -10 });
+
+        let request = Request::Enqueue {
+            path: PathBuf::from("/music/album"),
+            front: true,
+            play_now: false,
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"command":"enqueue","path":"/music/album","front":true,"play_now":false}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Request>(r#"{"command":"enqueue","path":"/music/album"}"#).unwrap(),
+            Request::Enqueue {
+                path: PathBuf::from("/music/album"),
+                front: false,
+                play_now: false,
+            }
+        );
+
+        assert_eq!(serde_json::to_string(&Response::Ok).unwrap(), r#""ok""#);
+        assert_eq!(
+            serde_json::to_string(&Response::Error("nope".to_string())).unwrap(),
+            r#"{"error":"nope"}"#
+        );
+    }
+
+    #[test]
+    fn serves_requests() {
+        let path = env::temp_dir().join(format!("jolteon-control-{}.sock", std::process::id()));
+        let player = Arc::new(MainPlayer::spawn(
+            None,
+            vec![],
+            Settings::default(),
+            OutputTarget::Null { pace: PACE },
+            Session::Standalone,
+        ));
+        let server = ControlServer::start(path.clone(), Arc::downgrade(&player)).unwrap();
+
+        assert!(ControlServer::start(path.clone(), Arc::downgrade(&player)).is_err());
+
+        assert_eq!(send(&path, &Request::Volume { percent: 30 }).unwrap(), Response::Ok);
+        assert_eq!(player.volume(), 30);
+
+        assert_eq!(
+            send(&path, &Request::Action {
+                action: "VolumeUp".to_string()
+            })
+            .unwrap(),
+            Response::Ok
+        );
+        assert_eq!(player.volume(), 35);
+
+        assert!(matches!(
+            send(&path, &Request::Action {
+                action: "Dance".to_string()
+            })
+            .unwrap(),
+            Response::Error(_)
+        ));
+        assert!(matches!(
+            send(&path, &Request::Remove { index: 0 }).unwrap(),
+            Response::Error(_)
+        ));
+
+        match send(&path, &Request::Status).unwrap() {
+            Response::Status(status) => {
+                assert_eq!(status.song, None);
+                assert_eq!(status.volume, 35);
+                assert_eq!(status.queue_length, 0);
+            }
+            response => panic!("unexpected response {response:?}"),
+        }
+
+        assert_eq!(send(&path, &Request::Queue).unwrap(), Response::Queue(vec![]));
+
+        // Invalid requests get an error, and the connection stays usable.
+        let mut stream = UnixStream::connect(&path).unwrap();
+        stream
+            .write_all(b"{\"command\":\"dance\"}\n{\"command\":\"status\"}\n")
+            .unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        assert!(lines.next().unwrap().unwrap().starts_with(r#"{"error":"#));
+        assert!(lines.next().unwrap().unwrap().starts_with(r#"{"status":"#));
+
+        drop(server);
+        assert!(!path.exists());
+        Arc::into_inner(player).unwrap().quit();
+    }
+
+    #[test]
+    fn enqueues_and_plays_now() {
+        let directory = TempDir::new("control-enqueue");
+        let playing = write_wav(&directory.join("playing.wav"), 30);
+        write_wav(&directory.join("album/1.wav"), 30);
+        write_wav(&directory.join("album/2.wav"), 30);
+
+        let path = directory.join("jolteon.sock");
+        let player = Arc::new(MainPlayer::spawn(
+            None,
+            vec![playing.clone()],
+            Settings::default(),
+            OutputTarget::Null { pace: PACE },
+            Session::Standalone,
+        ));
+        let server = ControlServer::start(path.clone(), Arc::downgrade(&player)).unwrap();
+
+        let (changed, queue_changed) = channel();
+        player.on_queue_changed(move || {
+            let _ = changed.send(());
+        });
+        let wait_for_queue = |expected: &[PathBuf]| {
+            loop {
+                let queue: Vec<PathBuf> = player.queue().songs().iter().map(|song| song.path.clone()).collect();
+                if queue == expected {
+                    break;
+                }
+                queue_changed
+                    .recv_timeout(Duration::from_secs(10))
+                    .unwrap_or_else(|_| panic!("the queue stayed at {queue:?} instead of {expected:?}"));
+            }
+        };
+        // Once its only song is taken from the queue, it's playing (or has already been played).
+        wait_for_queue(&[]);
+
+        let enqueue = Request::Enqueue {
+            path: directory.join("album"),
+            front: false,
+            play_now: true,
+        };
+        assert_eq!(send(&path, &enqueue).unwrap(), Response::Ok);
+        // 1.wav was taken from the queue to play now, rather than after the song that was playing.
+        wait_for_queue(&[directory.join("album/2.wav")]);
+
+        let missing = Request::Enqueue {
+            path: directory.join("missing.wav"),
+            front: true,
+            play_now: false,
+        };
+        assert!(matches!(send(&path, &missing).unwrap(), Response::Error(_)));
+
+        drop(server);
+        Arc::into_inner(player).unwrap().quit();
+    }
+}
@@ -0,0 +1,238 @@
+use std::time::Duration;
+
+use rodio::{ChannelCount, SampleRate, Source, source::SeekError};
+use serde::{Deserialize, Serialize};
+
+use crate::biquad::Biquad;
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BandKind {
+    #[default]
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+#[serde_inline_default::serde_inline_default]
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct EqualizerBand {
+    #[serde(default)]
+    pub kind: BandKind,
+    /// Center frequency for peaks, corner frequency for shelves, in Hz.
+    pub frequency: f32,
+    /// In dB.
+    pub gain: f32,
+    #[serde_inline_default(std::f32::consts::FRAC_1_SQRT_2)]
+    pub q: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EqualizerPreset {
+    pub name: String,
+    /// Gain applied before the bands, in dB. Usually negative, to make room for boosted bands.
+    #[serde(default)]
+    pub preamp: f32,
+    pub bands: Vec<EqualizerBand>,
+}
+
+impl EqualizerPreset {
+    fn filters(&self, sample_rate: u32) -> Vec<Biquad> {
+        self.bands
+            .iter()
+            .map(|band| {
+                let filter = match band.kind {
+                    BandKind::Peak => Biquad::peaking,
+                    BandKind::LowShelf => Biquad::low_shelf,
+                    BandKind::HighShelf => Biquad::high_shelf,
+                };
+                filter(sample_rate, band.frequency as f64, band.q as f64, band.gain as f64)
+            })
+            .collect()
+    }
+}
+
+/// Runs every channel of a source through the bands of an `EqualizerPreset`.
+pub struct Equalizer<I> {
+    input: I,
+    preset: Option<EqualizerPreset>,
+    preamp: f64,
+    /// One set of filters per channel, since they hold state.
+    filters: Vec<Vec<Biquad>>,
+    sample_rate: u32,
+    channels: usize,
+    channel: usize,
+}
+
+impl<I: Source> Equalizer<I> {
+    pub fn new(input: I) -> Self {
+        Self {
+            input,
+            preset: None,
+            preamp: 1.0,
+            filters: Vec::new(),
+            sample_rate: 0,
+            channels: 0,
+            channel: 0,
+        }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &I {
+        &self.input
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    /// Cheap to call repeatedly. Filters are only updated when the preset actually changes.
+    pub fn set_preset(&mut self, preset: Option<&EqualizerPreset>) {
+        if self.preset.as_ref() == preset {
+            return;
+        }
+        self.preset = preset.cloned();
+        self.update_filters();
+    }
+
+    fn update_filters(&mut self) {
+        self.sample_rate = self.input.sample_rate().get();
+        self.channels = self.input.channels().get() as usize;
+
+        let Some(preset) = &self.preset else {
+            self.filters.clear();
+            self.preamp = 1.0;
+            return;
+        };
+
+        self.preamp = 10f64.powf(preset.preamp as f64 / 20.0);
+
+        let filters = preset.filters(self.sample_rate);
+
+        if self.filters.len() == self.channels && self.filters.iter().all(|f| f.len() == filters.len()) {
+            for channel_filters in &mut self.filters {
+                for (filter, new) in channel_filters.iter_mut().zip(&filters) {
+                    filter.set_coefficients(new);
+                }
+            }
+        } else {
+            self.filters = vec![filters; self.channels];
+        }
+    }
+}
+
+impl<I: Source> Iterator for Equalizer<I> {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        // The sample rate changes along with the playback speed, and the filters depend on it.
+        if self.channel == 0
+            && (self.input.sample_rate().get() != self.sample_rate
+                || self.input.channels().get() as usize != self.channels)
+        {
+            self.update_filters();
+        }
+
+        let sample = self.input.next()?;
+
+        let channel = self.channel;
+        self.channel = (channel + 1) % self.channels.max(1);
+
+        let Some(filters) = self.filters.get_mut(channel) else {
+            return Some(sample);
+        };
+
+        let sample = filters
+            .iter_mut()
+            .fold(sample as f64 * self.preamp, |x, filter| filter.process(x));
+
+        Some(sample as f32)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.input.size_hint()
+    }
+}
+
+impl<I: Source> Source for Equalizer<I> {
+    #[inline]
+    fn current_span_len(&self) -> Option<usize> {
+        self.input.current_span_len()
+    }
+
+    #[inline]
+    fn channels(&self) -> ChannelCount {
+        self.input.channels()
+    }
+
+    #[inline]
+    fn sample_rate(&self) -> SampleRate {
+        self.input.sample_rate()
+    }
+
+    #[inline]
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+
+    #[inline]
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.channel = 0;
+        self.input.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::{buffer::SamplesBuffer, math::nz};
+
+    use super::*;
+
+    fn sine_rms(preset: Option<&EqualizerPreset>, frequency: f32) -> f32 {
+        let sample_rate = 48_000;
+        let samples: Vec<f32> = (0..sample_rate)
+            .map(|i| (2.0 * std::f32::consts::PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect();
+        let mut equalizer = Equalizer::new(SamplesBuffer::new(nz!(1), nz!(48_000), samples));
+        equalizer.set_preset(preset);
+        // Skip the first half, while the filters settle.
+        let output: Vec<f32> = equalizer.skip(sample_rate as usize / 2).collect();
+        (output.iter().map(|s| s * s).sum::<f32>() / output.len() as f32).sqrt()
+    }
+
+    #[test]
+    fn bands() {
+        let preset = EqualizerPreset {
+            name: "Test".to_string(),
+            preamp: 0.0,
+            bands: vec![EqualizerBand {
+                kind: BandKind::Peak,
+                frequency: 1000.0,
+                gain: 6.0,
+                q: 1.0,
+            }],
+        };
+
+        let flat = sine_rms(None, 1000.0);
+        assert!((flat - std::f32::consts::FRAC_1_SQRT_2).abs() < 0.01);
+
+        // +6dB doubles the amplitude at the center frequency, and leaves frequencies far from it alone.
+        assert!((sine_rms(Some(&preset), 1000.0) / flat - 2.0).abs() < 0.02);
+        assert!((sine_rms(Some(&preset), 50.0) / flat - 1.0).abs() < 0.02);
+
+        let preset = EqualizerPreset {
+            preamp: -6.0,
+            bands: vec![EqualizerBand {
+                kind: BandKind::LowShelf,
+                frequency: 200.0,
+                gain: 6.0,
+                q: std::f32::consts::FRAC_1_SQRT_2,
+            }],
+            ..preset
+        };
+        assert!((sine_rms(Some(&preset), 50.0) / flat - 1.0).abs() < 0.02);
+        assert!((sine_rms(Some(&preset), 10_000.0) / flat - 0.5).abs() < 0.02);
+    }
+}
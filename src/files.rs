@@ -1,3 +1,13 @@
+mod library_index;
+mod loudness;
+mod play_history;
 mod playlists;
+mod relink;
+mod song_stats;
 
+pub use library_index::{LibraryChanges, LibraryIndex, expand_home};
+pub use loudness::{CachedLoudness, Loudness, TrackLoudness};
+pub use play_history::{PlayEvent, PlayHistory};
 pub use playlists::Playlists;
+pub use relink::{Relinker, edit_stored_songs, relink_stored_songs};
+pub use song_stats::{SongStat, SongStats};
@@ -0,0 +1,494 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    components::{VALID_EXTENSIONS, path_is_not_hidden},
+    cue::CueSheet,
+    structs::Song,
+};
+
+/// The songs found in the library folders, keyed by `Song::library_id`, along with the size and modification time of
+/// the files they were read from, so rescans only read the files that changed.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct LibraryIndex {
+    pub songs: BTreeMap<Uuid, Song>,
+    pub files: BTreeMap<PathBuf, IndexedFile>,
+    /// Ids for songs not found in the library folders before, by path and start time. See [`LibraryIndex::seed_ids`].
+    #[serde(skip)]
+    known_ids: HashMap<(PathBuf, Duration), Uuid>,
+}
+
+/// A song file or cue sheet, as it was when it was last read.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexedFile {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    /// Files that can't be read have no songs, and are only read again once they change.
+    pub song_ids: Vec<Uuid>,
+    /// Files that were deleted keep their songs, so they can be relinked, or found again if the file comes back.
+    #[serde(default)]
+    pub missing: bool,
+}
+
+/// What a rescan found. Songs that were read again keep their `library_id`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct LibraryChanges {
+    pub added: Vec<Song>,
+    pub modified: Vec<Song>,
+    pub removed: Vec<Uuid>,
+    /// Songs whose files were deleted. They stay in the library, shown as missing.
+    pub missing: Vec<Uuid>,
+    /// Songs whose files came back without having changed.
+    pub found: Vec<Uuid>,
+}
+
+impl LibraryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.modified.is_empty()
+            && self.removed.is_empty()
+            && self.missing.is_empty()
+            && self.found.is_empty()
+    }
+}
+
+fn index_file_path() -> Option<PathBuf> {
+    home::home_dir().map(|path| path.join(".config/jolteon/library_index.json"))
+}
+
+/// Library folders, with a leading `~` standing for the home directory.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), home::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+pub(super) fn is_cue_sheet(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("cue"))
+}
+
+fn is_song(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| VALID_EXTENSIONS.iter().any(|ve| e.eq_ignore_ascii_case(ve)))
+}
+
+/// Song files and cue sheets under `directory`, skipping hidden files and directories.
+pub(super) fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) {
+    let entries = match directory.read_dir() {
+        Ok(entries) => entries,
+        Err(err) => {
+            log::warn!("library scan: could not read {directory:?}: {err}");
+            return;
+        }
+    };
+
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if !path_is_not_hidden(&path) {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else if is_song(&path) || is_cue_sheet(&path) {
+            files.push(path);
+        }
+    }
+}
+
+fn read_songs(path: &Path) -> Vec<Song> {
+    let songs = if is_cue_sheet(path) {
+        CueSheet::from_file(path)
+            .map(Song::from_cue_sheet)
+            .map_err(|err| err.to_string())
+    } else {
+        Song::from_file(path)
+            .map(|song| vec![song])
+            .map_err(|err| err.to_string())
+    };
+
+    songs.unwrap_or_else(|err| {
+        log::warn!("library scan: could not read {path:?}: {err}");
+        Vec::new()
+    })
+}
+
+impl LibraryIndex {
+    pub fn from_file() -> Self {
+        let Some(path) = index_file_path() else {
+            return Self::default();
+        };
+        match read_to_string(&path) {
+            Ok(string) => serde_json::from_str(&string).unwrap_or_else(|err| {
+                log::error!("Error in the library index. It will be rebuilt. Error was: {err:?}");
+                Self::default()
+            }),
+            Err(err) => {
+                log::debug!("Could not read the library index {path:?}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn to_file(&self) -> std::io::Result<()> {
+        let path = index_file_path().ok_or(std::io::ErrorKind::NotFound)?;
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(path, serde_json::to_string(self)?)
+    }
+
+    /// Looks for files that were added, changed or removed in `roots` since the last scan, and reads the ones that are
+    /// new or changed. Files in roots that can't be read, like unmounted drives, are left alone.
+    pub fn rescan(&mut self, roots: &[PathBuf]) -> LibraryChanges {
+        let mut changes = LibraryChanges::default();
+
+        let mut found = Vec::new();
+        let mut scanned_roots = Vec::new();
+        for root in roots {
+            if root.is_dir() {
+                collect_files(root, &mut found);
+                scanned_roots.push(root);
+            } else {
+                log::warn!("library scan: {root:?} is not a directory. Songs in it are kept as they were.");
+            }
+        }
+
+        // Cue sheets go first, so the files they point to are known, and left out as songs of their own.
+        found.sort_by_key(|path| !is_cue_sheet(path));
+
+        let mut covered_by_cue_sheets = HashSet::new();
+        let mut seen = HashSet::new();
+
+        for path in found {
+            if covered_by_cue_sheets.contains(&path) {
+                continue;
+            }
+            let Ok(metadata) = path.metadata() else {
+                continue;
+            };
+            let (size, modified) = (metadata.len(), metadata.modified().ok());
+
+            let is_unchanged = match self.files.get_mut(&path) {
+                Some(file) if file.size == size && file.modified == modified => {
+                    if file.missing {
+                        file.missing = false;
+                        changes.found.extend(&file.song_ids);
+                    }
+                    true
+                }
+                _ => false,
+            };
+
+            if !is_unchanged {
+                let songs = read_songs(&path);
+                let song_ids = self.replace_songs(&path, songs, &mut changes);
+                self.files.insert(path.clone(), IndexedFile {
+                    size,
+                    modified,
+                    song_ids,
+                    missing: false,
+                });
+            }
+
+            if is_cue_sheet(&path) {
+                covered_by_cue_sheets.extend(
+                    self.files[&path]
+                        .song_ids
+                        .iter()
+                        .filter_map(|id| self.songs.get(id))
+                        .map(|song| song.path.clone()),
+                );
+            }
+            seen.insert(path);
+        }
+
+        // Files of roots that are no longer configured, and files that are now covered by a cue sheet, are gone for
+        // good. Files that were deleted from a root are kept, as missing.
+        let gone: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| !seen.contains(*path))
+            .filter(|path| covered_by_cue_sheets.contains(*path) || !roots.iter().any(|root| path.starts_with(root)))
+            .cloned()
+            .collect();
+
+        for path in gone {
+            if let Some(file) = self.files.remove(&path) {
+                for id in file.song_ids {
+                    self.songs.remove(&id);
+                    changes.removed.push(id);
+                }
+            }
+        }
+
+        for (path, file) in &mut self.files {
+            if !file.missing && !seen.contains(path) && scanned_roots.iter().any(|root| path.starts_with(root)) {
+                file.missing = true;
+                changes.missing.extend(&file.song_ids);
+            }
+        }
+
+        changes
+    }
+
+    /// Has songs that are found for the first time take the id of the one of `songs` at the same path and start time,
+    /// like songs that were added to the Library by hand before their folder was a library folder. Their play counts
+    /// and history stay theirs.
+    pub fn seed_ids<'a>(&mut self, songs: impl IntoIterator<Item = &'a Song>) {
+        let indexed: HashSet<Uuid> = self.songs.keys().copied().collect();
+        self.known_ids = songs
+            .into_iter()
+            .filter_map(|song| Some(((song.path.clone(), song.start_time), song.library_id?)))
+            .filter(|(_, id)| !indexed.contains(id))
+            .collect();
+    }
+
+    /// Points the files and songs at the new paths in `moves`, so they keep their ids, and aren't read again, in the next
+    /// scan. A cue sheet follows the files it points to when it is next to them in their new folder. Returns whether
+    /// anything changed.
+    pub fn relink(&mut self, moves: &HashMap<PathBuf, PathBuf>) -> bool {
+        let mut changed = false;
+
+        for song in self.songs.values_mut() {
+            if let Some(path) = moves.get(&song.path) {
+                song.path = path.clone();
+                changed = true;
+            }
+        }
+
+        let files = std::mem::take(&mut self.files);
+        for (path, file) in files {
+            let moved_cue_sheet = || {
+                let song = file.song_ids.iter().find_map(|id| self.songs.get(id))?;
+                let moved = moves.values().any(|to| *to == song.path);
+                let cue_sheet = song.path.parent()?.join(path.file_name()?);
+                (moved && cue_sheet != path && cue_sheet.exists()).then_some(cue_sheet)
+            };
+            let new_path = match moves.get(&path) {
+                Some(new_path) => Some(new_path.clone()),
+                None if is_cue_sheet(&path) => moved_cue_sheet(),
+                None => None,
+            };
+            changed |= new_path.is_some();
+            self.files.insert(new_path.unwrap_or(path), file);
+        }
+
+        changed
+    }
+
+    /// Replaces the songs read from `path` with `songs`. Songs that were already there, at the same start time, keep
+    /// their id.
+    fn replace_songs(&mut self, path: &Path, songs: Vec<Song>, changes: &mut LibraryChanges) -> Vec<Uuid> {
+        let mut previous: HashMap<Duration, Uuid> = self
+            .files
+            .get(path)
+            .map(|file| {
+                file.song_ids
+                    .iter()
+                    .filter_map(|id| self.songs.get(id))
+                    .filter_map(|song| Some((song.start_time, song.library_id?)))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut song_ids = Vec::with_capacity(songs.len());
+
+        for mut song in songs {
+            match previous.remove(&song.start_time) {
+                Some(id) => {
+                    song.library_id = Some(id);
+                    changes.modified.push(song.clone());
+                    self.songs.insert(id, song);
+                    song_ids.push(id);
+                }
+                None => {
+                    let id = self
+                        .known_ids
+                        .remove(&(song.path.clone(), song.start_time))
+                        .unwrap_or_else(Uuid::new_v4);
+                    song.library_id = Some(id);
+                    changes.added.push(song.clone());
+                    self.songs.insert(id, song);
+                    song_ids.push(id);
+                }
+            }
+        }
+
+        for id in previous.into_values() {
+            self.songs.remove(&id);
+            changes.removed.push(id);
+        }
+
+        song_ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread};
+
+    use super::*;
+    use crate::test_fixtures::{TempDir, write_wav};
+
+    #[test]
+    fn rescans_incrementally() {
+        let dir = TempDir::new("library-index");
+        let root = dir.path().to_path_buf();
+        write_wav(&root.join("album/1.wav"), 1);
+        write_wav(&root.join("album/2.wav"), 1);
+        write_wav(&root.join(".hidden/3.wav"), 1);
+        fs::write(root.join("album/notes.txt"), "not a song").unwrap();
+
+        let mut index = LibraryIndex::default();
+        let changes = index.rescan(std::slice::from_ref(&root));
+        assert_eq!(changes.added.len(), 2);
+        assert!(changes.modified.is_empty() && changes.removed.is_empty());
+        assert_eq!(index.songs.len(), 2);
+        assert!(index.songs.iter().all(|(id, song)| song.library_id == Some(*id)));
+
+        // Nothing changed, so nothing is read again.
+        assert!(index.rescan(std::slice::from_ref(&root)).is_empty());
+
+        let id_of = |index: &LibraryIndex, name: &str| index.files[&root.join(name)].song_ids[0];
+        let id_1 = id_of(&index, "album/1.wav");
+        let id_2 = id_of(&index, "album/2.wav");
+
+        // Files that grew are read again, and keep their id.
+        thread::sleep(Duration::from_millis(10));
+        write_wav(&root.join("album/1.wav"), 2);
+        fs::remove_file(root.join("album/2.wav")).unwrap();
+        write_wav(&root.join("album/4.wav"), 1);
+
+        let changes = index.rescan(std::slice::from_ref(&root));
+        assert_eq!(changes.modified.len(), 1);
+        assert_eq!(changes.modified[0].library_id, Some(id_1));
+        assert_eq!(changes.modified[0].length, Duration::from_secs(2));
+        assert!(changes.removed.is_empty());
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.added[0].path, root.join("album/4.wav"));
+        assert_eq!(index.songs[&id_1].length, Duration::from_secs(2));
+
+        // Deleted files keep their songs, as missing, until they come back.
+        assert_eq!(changes.missing, vec![id_2]);
+        assert!(index.files[&root.join("album/2.wav")].missing);
+        assert!(index.songs.contains_key(&id_2));
+        assert!(index.rescan(std::slice::from_ref(&root)).is_empty());
+        write_wav(&root.join("album/2.wav"), 1);
+        let changes = index.rescan(std::slice::from_ref(&root));
+        assert_eq!(changes.modified.len(), 1);
+        assert_eq!(changes.modified[0].library_id, Some(id_2));
+        assert!(!index.files[&root.join("album/2.wav")].missing);
+
+        // Files that come back as they were aren't read again.
+        let away = root.with_extension("away.wav");
+        fs::rename(root.join("album/2.wav"), &away).unwrap();
+        assert_eq!(index.rescan(std::slice::from_ref(&root)).missing, vec![id_2]);
+        fs::rename(&away, root.join("album/2.wav")).unwrap();
+        let changes = index.rescan(std::slice::from_ref(&root));
+        assert_eq!(changes.found, vec![id_2]);
+        assert!(changes.modified.is_empty());
+
+        // Roots that can't be read keep their songs. Roots that are no longer configured lose them.
+        let missing = root.with_extension("unmounted");
+        index.files.insert(missing.join("5.wav"), IndexedFile {
+            size: 0,
+            modified: None,
+            song_ids: vec![],
+            missing: false,
+        });
+        assert!(index.rescan(&[root.clone(), missing.clone()]).is_empty());
+        assert!(index.files.contains_key(&missing.join("5.wav")));
+
+        let changes = index.rescan(&[]);
+        assert_eq!(changes.removed.len(), 3);
+        assert!(index.songs.is_empty() && index.files.is_empty());
+    }
+
+    #[test]
+    fn prefers_cue_sheets() {
+        let dir = TempDir::new("library-index-cue");
+        let root = dir.path().to_path_buf();
+        write_wav(&root.join("album.wav"), 4);
+        fs::write(
+            root.join("album.cue"),
+            "PERFORMER \"Artist\"\nTITLE \"Album\"\nFILE \"album.wav\" WAVE\n  TRACK 01 AUDIO\n    TITLE \"One\"\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    TITLE \"Two\"\n    INDEX 01 00:02:00\n",
+        )
+        .unwrap();
+
+        let mut index = LibraryIndex::default();
+        let changes = index.rescan(std::slice::from_ref(&root));
+        let mut titles: Vec<&str> = changes.added.iter().map(|song| song.title.as_str()).collect();
+        titles.sort();
+        assert_eq!(titles, vec!["One", "Two"]);
+        assert!(!index.files.contains_key(&root.join("album.wav")));
+    }
+
+    #[test]
+    fn relinks_moved_files() {
+        let dir = TempDir::new("library-index-relink");
+        let root = dir.path().to_path_buf();
+        let (old, new) = (root.join("old"), root.join("new"));
+        write_wav(&old.join("1.wav"), 1);
+        write_wav(&old.join("album.wav"), 4);
+        fs::write(
+            old.join("album.cue"),
+            "TITLE \"Album\"\nFILE \"album.wav\" WAVE\n  TRACK 01 AUDIO\n    TITLE \"One\"\n    INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+
+        let mut index = LibraryIndex::default();
+        index.rescan(std::slice::from_ref(&root));
+        let ids: BTreeMap<Uuid, Duration> = index.songs.iter().map(|(id, song)| (*id, song.length)).collect();
+
+        fs::rename(&old, &new).unwrap();
+        let moves = HashMap::from([
+            (old.join("1.wav"), new.join("1.wav")),
+            (old.join("album.wav"), new.join("album.wav")),
+        ]);
+        assert!(index.relink(&moves));
+        assert!(!index.relink(&moves));
+
+        let mut paths: Vec<&PathBuf> = index.files.keys().collect();
+        paths.sort();
+        assert_eq!(paths, vec![&new.join("1.wav"), &new.join("album.cue")]);
+        assert!(index.songs.values().all(|song| song.path.starts_with(&new)));
+
+        // The moved files are known, so nothing is read again, and the songs keep their ids.
+        assert!(index.rescan(std::slice::from_ref(&root)).is_empty());
+        assert_eq!(
+            index
+                .songs
+                .iter()
+                .map(|(id, song)| (*id, song.length))
+                .collect::<BTreeMap<_, _>>(),
+            ids
+        );
+    }
+
+    #[test]
+    fn keeps_the_ids_of_songs_added_by_hand() {
+        let dir = TempDir::new("library-index-seed");
+        let root = dir.path().to_path_buf();
+        let mut added_by_hand = write_wav(&root.join("1.wav"), 1);
+        write_wav(&root.join("2.wav"), 1);
+
+        let id = Uuid::new_v4();
+        added_by_hand.library_id = Some(id);
+
+        let mut index = LibraryIndex::default();
+        index.seed_ids([&added_by_hand]);
+        let changes = index.rescan(std::slice::from_ref(&root));
+
+        assert_eq!(changes.added.len(), 2);
+        assert_eq!(index.files[&root.join("1.wav")].song_ids, vec![id]);
+        assert_ne!(index.files[&root.join("2.wav")].song_ids, vec![id]);
+        assert_eq!(index.songs[&id].path, root.join("1.wav"));
+    }
+}
@@ -0,0 +1,87 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    loudness::REFERENCE_LOUDNESS,
+    structs::{ReplayGain, Song},
+    toml::{TomlFileError, get_config_file_path, read_toml_file_or_default, write_toml_file},
+};
+
+/// Results of `jolteon analyze`, for songs that have no ReplayGain tags of their own.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Loudness {
+    pub tracks: Vec<TrackLoudness>,
+}
+
+/// `Loudness`, read again only when its file changed, like when `jolteon analyze` measured more songs.
+#[derive(Debug, Default)]
+pub struct CachedLoudness {
+    loudness: Loudness,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TrackLoudness {
+    pub path: PathBuf,
+    pub start_time: Duration,
+    /// Integrated loudness, in LUFS.
+    pub loudness: Option<f64>,
+    /// Linear true peak.
+    pub peak: f64,
+    pub album_loudness: Option<f64>,
+    pub album_peak: Option<f64>,
+}
+
+impl Loudness {
+    pub fn from_file() -> Self {
+        read_toml_file_or_default("loudness")
+    }
+
+    pub fn to_file(&self) -> Result<(), TomlFileError> {
+        write_toml_file("loudness", self)
+    }
+
+    /// Adds or replaces the results for the same tracks.
+    pub fn merge(&mut self, tracks: Vec<TrackLoudness>) {
+        self.tracks
+            .retain(|t| !tracks.iter().any(|n| n.path == t.path && n.start_time == t.start_time));
+        self.tracks.extend(tracks);
+    }
+
+    pub fn replay_gain(&self, song: &Song) -> Option<ReplayGain> {
+        self.tracks
+            .iter()
+            .find(|t| t.path == song.path && t.start_time == song.start_time)
+            .map(TrackLoudness::replay_gain)
+    }
+}
+
+impl CachedLoudness {
+    pub fn get(&mut self) -> &Loudness {
+        let modified = get_config_file_path("loudness")
+            .ok()
+            .and_then(|path| path.metadata().ok())
+            .and_then(|metadata| metadata.modified().ok());
+        if modified != self.modified {
+            self.loudness = Loudness::from_file();
+            self.modified = modified;
+        }
+        &self.loudness
+    }
+}
+
+impl TrackLoudness {
+    pub fn replay_gain(&self) -> ReplayGain {
+        let gain = |loudness: f64| (REFERENCE_LOUDNESS - loudness) as f32;
+        ReplayGain {
+            track_gain: self.loudness.map(gain),
+            track_peak: Some(self.peak as f32),
+            album_gain: self.album_loudness.map(gain),
+            album_peak: self.album_peak.map(|p| p as f32),
+        }
+    }
+}
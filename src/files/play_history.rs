@@ -0,0 +1,168 @@
+use std::{
+    fs::{OpenOptions, create_dir_all, read_to_string},
+    io::Write,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::structs::Song;
+
+/// Songs skipped before this much of them played don't count as played.
+const COUNTED_COMPLETION: f32 = 0.5;
+
+/// One song stopping, whether it played to its end or not.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayEvent {
+    /// When the song stopped playing.
+    pub time: SystemTime,
+    pub library_id: Uuid,
+    /// How much of the song played, from 0 to 1.
+    pub completion: f32,
+    /// Whether it was stopped before its end, by moving on to another song or stopping playback.
+    pub skipped: bool,
+}
+
+impl PlayEvent {
+    /// `None` for songs that aren't in the library, or that didn't play at all.
+    pub fn new(song: &Song, played: Duration, finished: bool, time: SystemTime) -> Option<Self> {
+        let library_id = song.library_id?;
+        if played.is_zero() && !finished {
+            return None;
+        }
+        let completion = if finished || song.length.is_zero() {
+            1.0
+        } else {
+            (played.as_secs_f32() / song.length.as_secs_f32()).min(1.0)
+        };
+        Some(Self {
+            time,
+            library_id,
+            completion,
+            skipped: !finished,
+        })
+    }
+
+    /// Whether the song counts as played, rather than skipped past.
+    pub fn is_counted(&self) -> bool {
+        !self.skipped || self.completion >= COUNTED_COMPLETION
+    }
+}
+
+/// Every play of a library song, oldest first, stored one JSON object per line in
+/// `~/.config/jolteon/play_history.jsonl`. Events are only ever appended, so the file is safe to write to
+/// from the player thread while the UI reads it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PlayHistory {
+    pub events: Vec<PlayEvent>,
+}
+
+fn history_file_path() -> Option<PathBuf> {
+    home::home_dir().map(|path| path.join(".config/jolteon/play_history.jsonl"))
+}
+
+impl PlayHistory {
+    pub fn from_file() -> Self {
+        let Some(path) = history_file_path() else {
+            return Self::default();
+        };
+        match read_to_string(&path) {
+            Ok(string) => Self::parse(&string),
+            Err(err) => {
+                log::debug!("Could not read the play history {path:?}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    /// Lines that can't be read, like one cut short by a crash, are left out.
+    fn parse(string: &str) -> Self {
+        let events = string
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                serde_json::from_str(line)
+                    .inspect_err(|err| log::warn!("Skipping play history line {line:?}: {err}"))
+                    .ok()
+            })
+            .collect();
+        Self { events }
+    }
+
+    pub fn append(event: &PlayEvent) {
+        let result = (|| {
+            let path = history_file_path().ok_or(std::io::ErrorKind::NotFound)?;
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent)?;
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", serde_json::to_string(event)?)
+        })();
+        if let Err(err) = result {
+            log::error!("Could not save the play history! {err:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(length: u64) -> Song {
+        Song {
+            library_id: Some(Uuid::new_v4()),
+            path: PathBuf::from("/music/song.flac"),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(length),
+            title: "Song".to_string(),
+            artist: None,
+            album: None,
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: None,
+            replay_gain: Default::default(),
+        }
+    }
+
+    #[test]
+    fn records_completion_and_skips() {
+        let now = SystemTime::now();
+        let song = song(200);
+
+        let event = PlayEvent::new(&song, Duration::from_secs(50), false, now).unwrap();
+        assert_eq!(event.completion, 0.25);
+        assert!(event.skipped);
+        assert!(!event.is_counted());
+        assert!(
+            PlayEvent::new(&song, Duration::from_secs(100), false, now)
+                .unwrap()
+                .is_counted()
+        );
+
+        let event = PlayEvent::new(&song, Duration::from_secs(199), true, now).unwrap();
+        assert_eq!(event.completion, 1.0);
+        assert!(!event.skipped);
+
+        assert_eq!(PlayEvent::new(&song, Duration::ZERO, false, now), None);
+        let outside_library = Song {
+            library_id: None,
+            ..song.clone()
+        };
+        assert_eq!(
+            PlayEvent::new(&outside_library, Duration::from_secs(50), true, now),
+            None
+        );
+    }
+
+    #[test]
+    fn skips_unreadable_lines() {
+        let event = PlayEvent::new(&song(100), Duration::from_secs(100), true, SystemTime::now()).unwrap();
+        let line = serde_json::to_string(&event).unwrap();
+        let history = PlayHistory::parse(&format!("{line}\n{{\"time\":\n\n{line}\n"));
+        assert_eq!(history.events, vec![event.clone(), event]);
+    }
+}
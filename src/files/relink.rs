@@ -0,0 +1,278 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    LibraryIndex,
+    Loudness,
+    Playlists,
+    library_index::{collect_files, is_cue_sheet},
+};
+use crate::{
+    components::{edit_saved_library_songs, edit_saved_soundtrack_songs},
+    state::State,
+    structs::Song,
+    toml::TomlFileError,
+};
+
+/// How many path components, counting the file name, a missing file and a file in a search folder must share to be
+/// taken as the same file without looking at their tags. Two is the album folder and the file name.
+const MIN_SHARED_SUFFIX: usize = 2;
+
+/// Finds where the files of missing songs went.
+#[derive(Debug, Default)]
+pub struct Relinker {
+    /// A folder that was moved or renamed, and where it is now.
+    pub moved: Option<(PathBuf, PathBuf)>,
+    /// Folders to look for missing files in.
+    pub search: Vec<PathBuf>,
+}
+
+/// What a song is, as far as its tags can tell.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Fingerprint {
+    artist: Option<String>,
+    album: Option<String>,
+    title: String,
+    track: Option<u32>,
+    seconds: u64,
+}
+
+impl From<&Song> for Fingerprint {
+    fn from(song: &Song) -> Self {
+        Self {
+            artist: song.artist.clone(),
+            album: song.album.clone(),
+            title: song.title.clone(),
+            track: song.track,
+            seconds: song.length.as_secs(),
+        }
+    }
+}
+
+fn shared_suffix_len(a: &Path, b: &Path) -> usize {
+    a.components()
+        .rev()
+        .zip(b.components().rev())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+impl Relinker {
+    /// New paths for the files of `songs` that are missing, keyed by their old paths. Files are looked for in the moved
+    /// folder first, then in the search folders, by path, and then by their tags. Matches that aren't unique are left
+    /// out.
+    ///
+    /// Tracks of cue sheets can only be found by path, since their tags come from the cue sheet.
+    pub fn find(&self, songs: &[Song]) -> HashMap<PathBuf, PathBuf> {
+        let mut missing: BTreeMap<&Path, &Song> = BTreeMap::new();
+        for song in songs.iter().filter(|song| !song.path.exists()) {
+            missing.entry(song.path.as_path()).or_insert(song);
+        }
+
+        let mut found = HashMap::new();
+
+        if let Some((from, to)) = &self.moved {
+            for path in missing.keys() {
+                if let Ok(rest) = path.strip_prefix(from)
+                    && to.join(rest).exists()
+                {
+                    found.insert(path.to_path_buf(), to.join(rest));
+                }
+            }
+        }
+        missing.retain(|path, _| !found.contains_key(*path));
+
+        if missing.is_empty() || self.search.is_empty() {
+            return found;
+        }
+
+        let mut candidates = Vec::new();
+        for folder in &self.search {
+            collect_files(folder, &mut candidates);
+        }
+        candidates.retain(|path| !is_cue_sheet(path));
+
+        for path in missing.keys() {
+            let mut best: Option<(usize, &PathBuf)> = None;
+            let mut is_unique = false;
+
+            for candidate in &candidates {
+                let len = shared_suffix_len(path, candidate);
+                match best {
+                    Some((best_len, _)) if len < best_len => {}
+                    Some((best_len, _)) if len == best_len => is_unique = false,
+                    _ => {
+                        best = Some((len, candidate));
+                        is_unique = true;
+                    }
+                }
+            }
+
+            if let Some((len, candidate)) = best
+                && len >= MIN_SHARED_SUFFIX
+                && is_unique
+            {
+                found.insert(path.to_path_buf(), candidate.clone());
+            }
+        }
+        missing.retain(|path, _| !found.contains_key(*path));
+
+        if missing.is_empty() {
+            return found;
+        }
+
+        let mut fingerprints: HashMap<Fingerprint, Vec<&PathBuf>> = HashMap::new();
+        for candidate in &candidates {
+            match Song::from_file(candidate) {
+                Ok(song) => fingerprints
+                    .entry(Fingerprint::from(&song))
+                    .or_default()
+                    .push(candidate),
+                Err(err) => log::debug!("relink: could not read {candidate:?}: {err}"),
+            }
+        }
+
+        for (path, song) in missing {
+            if let Some([candidate]) = fingerprints.get(&Fingerprint::from(song)).map(Vec::as_slice) {
+                found.insert(path.to_path_buf(), (*candidate).clone());
+            }
+        }
+
+        found
+    }
+}
+
+/// Calls `f` with every song in the library, soundtracks, playlists and the saved queue, and saves each of them where
+/// `f` returned true for at least one song.
+pub fn edit_stored_songs(mut f: impl FnMut(&mut Song) -> bool) -> Result<(), TomlFileError> {
+    edit_saved_library_songs(&mut f);
+    edit_saved_soundtrack_songs(&mut f);
+
+    let mut playlists = Playlists::from_file();
+    let mut changed = false;
+    for playlist in playlists.playlists.iter_mut().chain(playlists.deleted.iter_mut()) {
+        for song in &mut playlist.songs {
+            changed |= f(song);
+        }
+    }
+    if changed {
+        playlists.to_file()?;
+    }
+
+    let mut state = State::from_file();
+    let mut changed = false;
+    for song in &mut state.queue_items {
+        changed |= f(song);
+    }
+    if changed {
+        state.to_file()?;
+    }
+
+    Ok(())
+}
+
+/// Points every stored song, the library index, and the stored loudness of every track, at the new paths in `moves`.
+/// Returns how many songs were changed.
+pub fn relink_stored_songs(moves: &HashMap<PathBuf, PathBuf>) -> Result<usize, TomlFileError> {
+    let mut count = 0;
+
+    edit_stored_songs(|song| match moves.get(&song.path) {
+        Some(path) => {
+            song.path = path.clone();
+            count += 1;
+            true
+        }
+        None => false,
+    })?;
+
+    let mut loudness = Loudness::from_file();
+    let mut changed = false;
+    for track in &mut loudness.tracks {
+        if let Some(path) = moves.get(&track.path) {
+            track.path = path.clone();
+            changed = true;
+        }
+    }
+    if changed {
+        loudness.to_file()?;
+    }
+
+    let mut index = LibraryIndex::from_file();
+    if index.relink(moves) {
+        index.to_file()?;
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_fixtures::{TempDir, write_wav};
+
+    fn song(path: PathBuf, secs: u64) -> Song {
+        Song {
+            library_id: None,
+            title: path.file_name().unwrap().to_string_lossy().to_string(),
+            path,
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(secs),
+            artist: None,
+            album: None,
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: None,
+            replay_gain: Default::default(),
+        }
+    }
+
+    #[test]
+    fn finds_moved_files() {
+        let root = TempDir::new("relink");
+        let old = root.join("old");
+
+        // The whole music folder moved.
+        write_wav(&root.join("new/artist/album/1.wav"), 1);
+        // An album moved somewhere else, and is found by its folder and file name.
+        write_wav(&root.join("elsewhere/other album/2.wav"), 1);
+        // A file was renamed. Its tags are the same, and only one file has them.
+        write_wav(&root.join("elsewhere/renamed/3.wav"), 3);
+        write_wav(&root.join("elsewhere/decoy/3.wav"), 4);
+        // Two files with the same tags can't tell which one is the right one.
+        write_wav(&root.join("elsewhere/a/4.wav"), 5);
+        write_wav(&root.join("elsewhere/b/4.wav"), 5);
+
+        let present = root.join("elsewhere/a/4.wav");
+        let songs = vec![
+            song(old.join("artist/album/1.wav"), 1),
+            song(old.join("other album/2.wav"), 1),
+            song(old.join("gone/3.wav"), 3),
+            song(old.join("gone/4.wav"), 5),
+            song(present.clone(), 5),
+        ];
+
+        let relinker = Relinker {
+            moved: Some((old.clone(), root.join("new"))),
+            search: vec![root.join("elsewhere")],
+        };
+        let found = relinker.find(&songs);
+
+        assert_eq!(found.len(), 3, "{found:#?}");
+        assert_eq!(
+            found[&old.join("artist/album/1.wav")],
+            root.join("new/artist/album/1.wav")
+        );
+        assert_eq!(
+            found[&old.join("other album/2.wav")],
+            root.join("elsewhere/other album/2.wav")
+        );
+        assert_eq!(found[&old.join("gone/3.wav")], root.join("elsewhere/renamed/3.wav"));
+        assert!(!found.contains_key(&present));
+    }
+}
@@ -0,0 +1,158 @@
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read_to_string, rename, write},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::structs::Song;
+
+/// When library songs were added, and how often and when they were last played, keyed by `Song::library_id`.
+/// Songs that aren't in the library have no stats.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SongStats {
+    pub songs: BTreeMap<Uuid, SongStat>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SongStat {
+    pub added: Option<SystemTime>,
+    #[serde(default)]
+    pub plays: u32,
+    pub last_played: Option<SystemTime>,
+}
+
+/// Held while the stats are read, changed and saved. Both the UI and the player thread update them, and would
+/// otherwise undo each other's changes.
+static UPDATE_LOCK: Mutex<()> = Mutex::new(());
+
+fn stats_file_path() -> Option<PathBuf> {
+    home::home_dir().map(|path| path.join(".config/jolteon/song_stats.json"))
+}
+
+impl SongStats {
+    pub fn from_file() -> Self {
+        let Some(path) = stats_file_path() else {
+            return Self::default();
+        };
+        Self::read(&path).unwrap_or_else(|err| {
+            log::error!("Could not read the song stats {path:?}: {err}");
+            Self::default()
+        })
+    }
+
+    /// No file counts as no stats, rather than an error.
+    fn read(path: &Path) -> Result<Self, String> {
+        match read_to_string(path) {
+            Ok(string) => serde_json::from_str(&string).map_err(|err| err.to_string()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    /// Writes a file next to `path` first, and moves it over `path`, so `path` is never left half written.
+    fn write(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        let temporary = path.with_extension("json.tmp");
+        write(&temporary, serde_json::to_string(self)?)?;
+        rename(temporary, path)
+    }
+
+    /// Reads the stats, lets `f` change them, and saves them.
+    pub fn update(f: impl FnOnce(&mut Self)) {
+        if let Some(path) = stats_file_path() {
+            Self::update_file(&path, f);
+        }
+    }
+
+    /// Stats that can't be read are left alone, rather than replaced by the few that `f` would leave.
+    fn update_file(path: &Path, f: impl FnOnce(&mut Self)) {
+        let _lock = UPDATE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut stats = match Self::read(path) {
+            Ok(stats) => stats,
+            Err(err) => {
+                log::error!("Could not read the song stats {path:?}, so they won't be changed: {err}");
+                return;
+            }
+        };
+        let before = stats.clone();
+        f(&mut stats);
+        if stats != before
+            && let Err(err) = stats.write(path)
+        {
+            log::error!("Could not save the song stats! {err:?}");
+        }
+    }
+
+    pub fn get(&self, song: &Song) -> Option<&SongStat> {
+        song.library_id.and_then(|id| self.songs.get(&id))
+    }
+
+    /// Remembers `added(song)` as when the songs were added to the library, unless they already have a date.
+    pub fn record_added(&mut self, songs: &[Song], added: impl Fn(&Song) -> SystemTime) {
+        for song in songs {
+            if let Some(id) = song.library_id {
+                let stat = self.songs.entry(id).or_default();
+                stat.added.get_or_insert_with(|| added(song));
+            }
+        }
+    }
+
+    /// Counts a play of `song`. The player does so for the events of the `PlayHistory` that count as plays.
+    pub fn record_play(&mut self, song: &Song, when: SystemTime) {
+        if let Some(id) = song.library_id {
+            let stat = self.songs.entry(id).or_default();
+            stat.plays += 1;
+            stat.last_played = Some(when);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn song() -> Song {
+        Song {
+            library_id: Some(Uuid::new_v4()),
+            path: PathBuf::from("/music/song.flac"),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(200),
+            title: "Song".to_string(),
+            artist: None,
+            album: None,
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: None,
+            replay_gain: Default::default(),
+        }
+    }
+
+    #[test]
+    fn leaves_unreadable_stats_alone() {
+        let path = std::env::temp_dir().join(format!("jolteon-song-stats-{}.json", std::process::id()));
+        let song = song();
+
+        SongStats::update_file(&path, |stats| stats.record_play(&song, SystemTime::now()));
+        assert_eq!(SongStats::read(&path).unwrap().get(&song).unwrap().plays, 1);
+
+        // Like a file cut short by a crash.
+        write(&path, "{\"songs\":{").unwrap();
+        SongStats::update_file(&path, |stats| stats.record_play(&song, SystemTime::now()));
+        assert_eq!(read_to_string(&path).unwrap(), "{\"songs\":{");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,300 @@
+use std::{f64::consts::PI, fs::File, io::BufReader, time::Duration};
+
+use rodio::{Decoder, Source};
+
+use crate::{biquad::Biquad, structs::Song};
+
+/// Loudness the ReplayGain 2.0 gain of a song brings it to, in LUFS.
+pub const REFERENCE_LOUDNESS: f64 = -18.0;
+
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+/// Taps of the interpolation filter used to find true peaks.
+const INTERPOLATION_TAPS: usize = 49;
+
+/// The two stages of the K-weighting filter: a high shelf modelling the head, followed by a high pass.
+/// Coefficients are derived for the actual sample rate, rather than using the 48kHz ones of the spec.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let rate = sample_rate as f64;
+
+    let f0 = 1681.974450955533;
+    let gain = 3.999843853973347;
+    let q = 0.7071752369554196;
+    let k = (PI * f0 / rate).tan();
+    let vh = 10f64.powf(gain / 20.0);
+    let vb = vh.powf(0.4996667741545416);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad::new(
+        [
+            (vh + vb * k / q + k * k) / a0,
+            2.0 * (k * k - vh) / a0,
+            (vh - vb * k / q + k * k) / a0,
+        ],
+        [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+    );
+
+    let f0 = 38.13547087602444;
+    let q = 0.5003270373238773;
+    let k = (PI * f0 / rate).tan();
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::new([1.0, -2.0, 1.0], [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0]);
+
+    [shelf, high_pass]
+}
+
+/// Oversamples a channel with a windowed sinc filter, to find peaks between samples.
+#[derive(Debug, Clone)]
+struct TruePeak {
+    factor: usize,
+    coefficients: Vec<f64>,
+    history: Vec<f64>,
+    peak: f64,
+}
+
+impl TruePeak {
+    fn new(sample_rate: u32) -> Self {
+        let factor = match sample_rate {
+            0..96_000 => 4,
+            96_000..192_000 => 2,
+            _ => 1,
+        };
+
+        let coefficients = (0..INTERPOLATION_TAPS)
+            .map(|j| {
+                let m = j as f64 - (INTERPOLATION_TAPS - 1) as f64 / 2.0;
+                let sinc = if m.abs() < 1e-6 {
+                    1.0
+                } else {
+                    (m * PI / factor as f64).sin() / (m * PI / factor as f64)
+                };
+                let window = 0.5 * (1.0 - (2.0 * PI * j as f64 / (INTERPOLATION_TAPS - 1) as f64).cos());
+                sinc * window
+            })
+            .collect();
+
+        Self {
+            factor,
+            coefficients,
+            history: vec![0.0; INTERPOLATION_TAPS.div_ceil(factor)],
+            peak: 0.0,
+        }
+    }
+
+    fn process(&mut self, x: f64) {
+        self.peak = self.peak.max(x.abs());
+
+        if self.factor == 1 {
+            return;
+        }
+
+        self.history.rotate_right(1);
+        self.history[0] = x;
+
+        for phase in 0..self.factor {
+            let y: f64 = self
+                .coefficients
+                .iter()
+                .skip(phase)
+                .step_by(self.factor)
+                .zip(&self.history)
+                .map(|(c, x)| c * x)
+                .sum();
+            self.peak = self.peak.max(y.abs());
+        }
+    }
+}
+
+/// Measures the loudness and true peak of interleaved samples, as specified by EBU R128 / ITU-R BS.1770.
+#[derive(Debug, Clone)]
+pub struct LoudnessMeter {
+    channels: usize,
+    weights: Vec<f64>,
+    filters: Vec<[Biquad; 2]>,
+    true_peaks: Vec<TruePeak>,
+
+    /// Frames per 100ms. Gating blocks are 400ms long and overlap by 75%, so they're built from these.
+    step_frames: usize,
+    step_position: usize,
+    step_energy: f64,
+    steps: Vec<f64>,
+
+    frame: Vec<f32>,
+    blocks: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    pub fn new(channels: u16, sample_rate: u32) -> Self {
+        let channels = channels.max(1) as usize;
+
+        // For 5.1, the LFE channel is ignored and the surround channels are boosted.
+        let weights = if channels == 6 {
+            vec![1.0, 1.0, 1.0, 0.0, 1.41, 1.41]
+        } else {
+            vec![1.0; channels]
+        };
+
+        Self {
+            channels,
+            weights,
+            filters: vec![k_weighting(sample_rate); channels],
+            true_peaks: vec![TruePeak::new(sample_rate); channels],
+            step_frames: (sample_rate as usize / 10).max(1),
+            step_position: 0,
+            step_energy: 0.0,
+            steps: Vec::new(),
+            frame: Vec::with_capacity(channels),
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.frame.push(sample);
+
+        if self.frame.len() < self.channels {
+            return;
+        }
+
+        for (channel, &sample) in self.frame.iter().enumerate() {
+            let sample = sample as f64;
+            self.true_peaks[channel].process(sample);
+            let [shelf, high_pass] = &mut self.filters[channel];
+            let weighted = high_pass.process(shelf.process(sample));
+            self.step_energy += self.weights[channel] * weighted * weighted;
+        }
+        self.frame.clear();
+
+        self.step_position += 1;
+        if self.step_position < self.step_frames {
+            return;
+        }
+
+        self.steps.push(self.step_energy / self.step_frames as f64);
+        self.step_position = 0;
+        self.step_energy = 0.0;
+
+        if let Some(last_four) = self.steps.last_chunk::<4>() {
+            self.blocks.push(last_four.iter().sum::<f64>() / 4.0);
+        }
+    }
+
+    /// Mean square energy of each 400ms gating block. Blocks of several meters can be concatenated to get the
+    /// integrated loudness of all of them together, as is done for albums.
+    pub fn blocks(&self) -> &[f64] {
+        &self.blocks
+    }
+
+    pub fn true_peak(&self) -> f64 {
+        self.true_peaks.iter().map(|p| p.peak).fold(0.0, f64::max)
+    }
+
+    pub fn integrated_loudness(&self) -> Option<f64> {
+        integrated_loudness(&self.blocks)
+    }
+}
+
+/// Decodes the part of the file a song covers, the same way `Source` does for playback, and measures it.
+pub fn measure_song(song: &Song) -> Result<LoudnessMeter, String> {
+    let file = BufReader::new(File::open(&song.path).map_err(|e| e.to_string())?);
+    let mut decoder = Decoder::new(file).map_err(|e| e.to_string())?;
+
+    if song.start_time > Duration::ZERO {
+        decoder.try_seek(song.start_time).map_err(|e| format!("{e:?}"))?;
+    }
+
+    let channels = decoder.channels().get();
+    let sample_rate = decoder.sample_rate().get();
+    let mut meter = LoudnessMeter::new(channels, sample_rate);
+
+    let samples = if song.length.is_zero() {
+        usize::MAX
+    } else {
+        (song.length.as_secs_f64() * sample_rate as f64) as usize * channels as usize
+    };
+
+    decoder.take(samples).for_each(|sample| meter.push(sample));
+
+    Ok(meter)
+}
+
+fn energy_to_loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+/// Gated integrated loudness, in LUFS. `None` if everything is below the absolute gate, such as silence.
+pub fn integrated_loudness(blocks: &[f64]) -> Option<f64> {
+    let mean = |blocks: &mut dyn Iterator<Item = f64>| {
+        let (sum, count) = blocks.fold((0.0, 0), |(sum, count), e| (sum + e, count + 1));
+        (count > 0).then(|| sum / count as f64)
+    };
+
+    let above_absolute = || {
+        blocks
+            .iter()
+            .copied()
+            .filter(|&e| energy_to_loudness(e) > ABSOLUTE_GATE)
+    };
+
+    let relative_gate = energy_to_loudness(mean(&mut above_absolute())?) + RELATIVE_GATE;
+
+    mean(&mut above_absolute().filter(|&e| energy_to_loudness(e) > relative_gate)).map(energy_to_loudness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(meter: &mut LoudnessMeter, channels: u16, sample_rate: u32, frequency: f64, amplitude: f64, secs: f64) {
+        let frames = (sample_rate as f64 * secs) as usize;
+        for i in 0..frames {
+            let sample = amplitude * (2.0 * PI * frequency * i as f64 / sample_rate as f64).sin();
+            for _ in 0..channels {
+                meter.push(sample as f32);
+            }
+        }
+    }
+
+    #[test]
+    fn full_scale_sine() {
+        // The spec's reference: a 1kHz, 0dBFS sine on a single channel measures -3.01 LUFS.
+        let mut meter = LoudnessMeter::new(1, 48_000);
+        sine(&mut meter, 1, 48_000, 1000.0, 1.0, 5.0);
+        let loudness = meter.integrated_loudness().unwrap();
+        assert!((loudness - -3.01).abs() < 0.05, "{loudness}");
+
+        let mut meter = LoudnessMeter::new(2, 44_100);
+        sine(&mut meter, 2, 44_100, 1000.0, 0.5, 5.0);
+        let loudness = meter.integrated_loudness().unwrap();
+        assert!((loudness - -6.02).abs() < 0.05, "{loudness}");
+    }
+
+    #[test]
+    fn true_peak() {
+        // Sampled at a quarter of the sample rate, 45° off phase, no sample lands on the actual peak.
+        let mut meter = LoudnessMeter::new(1, 48_000);
+        for i in 0..48_000 {
+            let sample = (PI / 2.0 * i as f64 + PI / 4.0).sin() * 0.5;
+            meter.push(sample as f32);
+        }
+        let peak = meter.true_peak();
+        assert!((peak - 0.5).abs() < 0.01, "{peak}");
+    }
+
+    #[test]
+    fn gating() {
+        let mut meter = LoudnessMeter::new(1, 48_000);
+        sine(&mut meter, 1, 48_000, 1000.0, 0.0, 2.0);
+        assert_eq!(meter.integrated_loudness(), None);
+
+        // Quiet passages more than 10 LU below the rest don't count.
+        let mut loud = LoudnessMeter::new(1, 48_000);
+        sine(&mut loud, 1, 48_000, 1000.0, 0.5, 4.0);
+        let mut both = loud.blocks().to_vec();
+        let mut quiet = LoudnessMeter::new(1, 48_000);
+        sine(&mut quiet, 1, 48_000, 1000.0, 0.01, 20.0);
+        both.extend_from_slice(quiet.blocks());
+        let expected = loud.integrated_loudness().unwrap();
+        let loudness = integrated_loudness(&both).unwrap();
+        assert!((loudness - expected).abs() < 0.01, "{loudness} {expected}");
+    }
+}
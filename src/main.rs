@@ -11,22 +11,33 @@
 mod actions;
 mod app;
 mod auto_update;
+mod biquad;
 mod bye;
 mod cli;
 mod components;
 mod constants;
+#[cfg(unix)]
+mod control;
 mod cue;
 mod duration;
+mod equalizer;
 mod files;
+mod loudness;
 mod main_player;
 mod mpris;
+mod output;
 mod player;
+mod radio;
+mod scrobble;
 mod settings;
 mod source;
 mod spawn_terminal;
 mod state;
 mod structs;
+mod tag_edit;
 mod term;
+#[cfg(test)]
+mod test_fixtures;
 mod theme;
 mod toml;
 mod ui;
@@ -1,30 +1,55 @@
 use std::{
+    cell::RefCell,
     collections::VecDeque,
+    path::Path,
     sync::{
         Arc,
         Mutex,
+        atomic::{AtomicBool, Ordering},
         mpsc::{Sender, channel},
     },
     thread,
     thread::JoinHandle,
-    time::Duration,
+    time::{Duration, SystemTime},
 };
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
     actions::{OnAction, PlayerAction},
+    files::{PlayEvent, PlayHistory, SongStats},
     mpris::Mpris,
+    output::OutputTarget,
     player::SingleTrackPlayer,
+    radio,
+    scrobble::ScrobbleService,
+    settings::Settings,
     structs::{Queue, Song},
 };
 
+/// How many of the last played artists shuffling avoids, when `Settings::shuffle_avoid_recent_artists` is on.
+const RECENT_ARTISTS: usize = 3;
+
+/// How many of the songs played in this session going back can return to.
+const HISTORY_LENGTH: usize = 100;
+
+/// Going back this far into a song restarts it, rather than going back to the one before it.
+const RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+
 #[derive(Debug)]
 enum MainPlayerCommand {
     Quit,
+    /// Turns shuffling on or off, the only way MPRIS clients know it.
+    SetShuffle(bool),
+    /// Goes back to the song played before the current one, or restarts the current one.
+    Previous,
 }
 
 #[derive(Debug)]
 enum MainPlayerEvent {
-    PlaybackEnded(Song),
+    /// The song, how far into it playback got, and whether it played to its end.
+    PlaybackEnded(Box<Song>, Duration, bool),
+    NextSongStarted(Box<Song>),
     QueueChanged,
 }
 
@@ -35,7 +60,7 @@ enum MainPlayerMessage {
     Command(MainPlayerCommand),
 }
 
-#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum RepeatMode {
     Off,
     One,
@@ -52,45 +77,279 @@ impl RepeatMode {
     }
 }
 
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum ShuffleMode {
+    #[default]
+    Off,
+    /// Any song in the queue can play next.
+    Tracks,
+    /// Albums play in order, and the album that plays next is picked at random.
+    Albums,
+}
+
+impl ShuffleMode {
+    pub fn toggle(&self) -> Self {
+        match self {
+            ShuffleMode::Off => ShuffleMode::Tracks,
+            ShuffleMode::Tracks => ShuffleMode::Albums,
+            ShuffleMode::Albums => ShuffleMode::Off,
+        }
+    }
+}
+
+/// Where a `MainPlayer` plays. Radio mode, scrobbling and recording plays are only for the app, where there's someone
+/// listening to their library. Rendering to a file and playing files from the command line leave no trace, and end
+/// along with their queue.
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum Session {
+    App,
+    Standalone,
+}
+
+/// Changes the queue on behalf of something other than the UI, such as MPRIS clients,
+/// and lets both the UI and the main player thread know about it.
+#[derive(Clone)]
+struct ExternalQueueEditor {
+    player: Arc<SingleTrackPlayer>,
+    queue: Arc<Queue>,
+    sender: Sender<MainPlayerMessage>,
+    on_queue_changed: Arc<Mutex<Option<Box<dyn Fn() + Send + 'static>>>>,
+    on_error: Arc<Mutex<Option<Box<dyn Fn(String) + Send + 'static>>>>,
+}
+
+impl ExternalQueueEditor {
+    /// Songs at a file, cue sheet or directory. Errors are reported, and result in no songs.
+    fn songs_at(&self, path: &Path) -> Vec<Song> {
+        Song::from_path(path).unwrap_or_else(|err| {
+            self.on_error
+                .lock()
+                .unwrap()
+                .as_ref()
+                .inspect(|f| f(format!("{path:?}: {err}")));
+            Vec::new()
+        })
+    }
+
+    /// If `edit` returns an index, the song at that index moves to the front of the queue, and playback skips to it.
+    fn edit(&self, edit: impl FnOnce(&mut VecDeque<Song>) -> Option<usize>) {
+        // Checked before the main player thread hears about the change, and possibly starts playing something.
+        let is_playing = self.player.playing_song().lock().unwrap().is_some();
+
+        let skip_to = {
+            let mut songs = self.queue.songs();
+            let skip_to = edit(&mut songs);
+            if let Some(song) = skip_to.and_then(|index| songs.remove(index)) {
+                songs.push_front(song);
+            }
+            skip_to
+        };
+
+        let _ = self
+            .sender
+            .send(MainPlayerMessage::Event(MainPlayerEvent::QueueChanged));
+        self.on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+
+        // Once the current song stops, the one at the front of the queue starts.
+        // If nothing is playing, the main player thread starts it when it hears about the change.
+        if skip_to.is_some() && is_playing {
+            self.player.stop();
+        }
+    }
+}
+
 pub struct MainPlayer {
     thread: JoinHandle<()>,
     sender: Sender<MainPlayerMessage>,
     player: Arc<SingleTrackPlayer>,
     queue: Arc<Queue>,
     on_queue_changed: Arc<Mutex<Option<Box<dyn Fn() + Send + 'static>>>>,
+    on_queue_ended: Arc<Mutex<Option<Box<dyn Fn() + Send + 'static>>>>,
     on_error: Arc<Mutex<Option<Box<dyn Fn(String) + Send + 'static>>>>,
     repeat_mode: Arc<Mutex<RepeatMode>>,
+    shuffle_mode: Arc<Mutex<ShuffleMode>>,
+    is_radio_on: Arc<AtomicBool>,
+    /// Songs in the queue that radio mode put there, rather than the user.
+    suggested: Arc<Mutex<Vec<Song>>>,
 }
 
 impl MainPlayer {
-    pub fn spawn(mpris: Option<Mpris>, queue_songs: Vec<Song>) -> Self {
+    pub fn spawn(
+        mpris: Option<Mpris>,
+        queue_songs: Vec<Song>,
+        settings: Settings,
+        output_target: OutputTarget,
+        session: Session,
+    ) -> Self {
         let (tx, rx) = channel::<MainPlayerMessage>();
 
         let mpris = mpris.map(Arc::new);
-        let player = Arc::new(SingleTrackPlayer::spawn(mpris.clone()));
+        let avoid_recent_artists = settings.shuffle_avoid_recent_artists;
+        let radio_engine = settings.radio_engine;
+        let is_radio_on = Arc::new(AtomicBool::new(session == Session::App && settings.radio));
+        let suggested = Arc::new(Mutex::new(Vec::<Song>::new()));
+        let scrobbling = settings.scrobbling.clone();
+        let player = Arc::new(SingleTrackPlayer::spawn(mpris.clone(), settings, output_target));
+        let scrobbles = if session == Session::App {
+            ScrobbleService::from_settings(&scrobbling, {
+                let player = player.clone();
+                move || player.is_paused()
+            })
+        } else {
+            None
+        };
         let queue = Arc::new(Queue::new(queue_songs));
         let on_error = Arc::new(Mutex::new(None::<Box<dyn Fn(String) + Send + 'static>>));
 
+        let on_queue_changed = Arc::new(Mutex::new(None::<Box<dyn Fn() + Send + 'static>>));
+
         if let Some(mpris) = &mpris {
+            let edit_queue = ExternalQueueEditor {
+                player: player.clone(),
+                queue: queue.clone(),
+                sender: tx.clone(),
+                on_queue_changed: on_queue_changed.clone(),
+                on_error: on_error.clone(),
+            };
+
             mpris.on_play_pause({
                 let player = player.clone();
                 move || {
                     player.toggle_is_paused();
                 }
             });
+            mpris.on_play({
+                let player = player.clone();
+                move || {
+                    player.set_is_paused(false);
+                }
+            });
+            mpris.on_pause({
+                let player = player.clone();
+                move || {
+                    player.set_is_paused(true);
+                }
+            });
             mpris.on_stop({
                 let player = player.clone();
                 move || {
                     player.stop();
                 }
             });
+            mpris.on_next({
+                // Once the current song stops, the next one in the queue starts.
+                let player = player.clone();
+                move || {
+                    player.stop();
+                }
+            });
+            mpris.on_previous({
+                let tx = tx.clone();
+                move || {
+                    let _ = tx.send(MainPlayerMessage::Command(MainPlayerCommand::Previous));
+                }
+            });
+            mpris.on_seek({
+                let player = player.clone();
+                move |position| {
+                    player.seek_to(position);
+                }
+            });
+            mpris.on_set_volume({
+                let player = player.clone();
+                move |volume| {
+                    player.set_volume(volume as f32);
+                }
+            });
+            mpris.on_set_rate({
+                let player = player.clone();
+                move |rate| {
+                    player.set_speed((rate * 100.).round() as u32);
+                }
+            });
+            mpris.on_set_repeat_mode({
+                let tx = tx.clone();
+                move |repeat_mode| {
+                    let action = match repeat_mode {
+                        RepeatMode::Off => PlayerAction::RepeatNone,
+                        RepeatMode::One => PlayerAction::RepeatOne,
+                        RepeatMode::Queue => PlayerAction::RepeatQueue,
+                    };
+                    let _ = tx.send(MainPlayerMessage::Action(action));
+                }
+            });
+            mpris.on_set_shuffle({
+                let tx = tx.clone();
+                move |shuffle| {
+                    let _ = tx.send(MainPlayerMessage::Command(MainPlayerCommand::SetShuffle(shuffle)));
+                }
+            });
+            mpris.on_open({
+                // The songs go to the front of the queue, and playback skips to them right away.
+                let edit_queue = edit_queue.clone();
+                move |path| {
+                    let songs = edit_queue.songs_at(&path);
+                    edit_queue.edit(|queue| {
+                        let has_songs = !songs.is_empty();
+                        for song in songs.into_iter().rev() {
+                            queue.push_front(song);
+                        }
+                        has_songs.then_some(0)
+                    });
+                }
+            });
+            mpris.on_add_to_queue({
+                let edit_queue = edit_queue.clone();
+                move |path, index, play_now| {
+                    let songs = edit_queue.songs_at(&path);
+                    edit_queue.edit(|queue| {
+                        let index = index.min(queue.len());
+                        let has_songs = !songs.is_empty();
+                        for song in songs.into_iter().rev() {
+                            queue.insert(index, song);
+                        }
+                        (play_now && has_songs).then_some(index)
+                    });
+                }
+            });
+            mpris.on_remove_from_queue({
+                let edit_queue = edit_queue.clone();
+                move |index| {
+                    edit_queue.edit(|queue| {
+                        queue.remove(index);
+                        None
+                    });
+                }
+            });
+            mpris.on_go_to({
+                let edit_queue = edit_queue.clone();
+                move |index| {
+                    edit_queue.edit(|queue| (index < queue.len()).then_some(index));
+                }
+            });
+            mpris.set_position_source({
+                let player = player.clone();
+                move || player.playing_position()
+            });
         }
 
         player.on_playback_end({
             let tx = tx.clone();
-            move |song| {
-                tx.send(MainPlayerMessage::Event(MainPlayerEvent::PlaybackEnded(song)))
-                    .unwrap();
+            move |song, played, finished| {
+                // Fails if the main player quit first, in which case nobody cares anymore.
+                let _ = tx.send(MainPlayerMessage::Event(MainPlayerEvent::PlaybackEnded(
+                    Box::new(song),
+                    played,
+                    finished,
+                )));
+            }
+        });
+
+        player.on_gapless_transition({
+            let tx = tx.clone();
+            move |_previous, next| {
+                let _ = tx.send(MainPlayerMessage::Event(MainPlayerEvent::NextSongStarted(Box::new(
+                    next,
+                ))));
             }
         });
 
@@ -102,8 +361,9 @@ impl MainPlayer {
             }
         });
 
-        let on_queue_changed = Arc::new(Mutex::new(None));
+        let on_queue_ended = Arc::new(Mutex::new(None::<Box<dyn Fn() + Send + 'static>>));
         let repeat_mode = Arc::new(Mutex::new(RepeatMode::Off));
+        let shuffle_mode = Arc::new(Mutex::new(ShuffleMode::Off));
 
         let t = thread::Builder::new()
             .name("main_player".to_string())
@@ -111,66 +371,312 @@ impl MainPlayer {
                 let player = player.clone();
                 let queue = queue.clone();
                 let on_queue_changed = on_queue_changed.clone();
+                let on_queue_ended = on_queue_ended.clone();
                 let repeat_mode = Arc::clone(&repeat_mode);
+                let shuffle_mode = Arc::clone(&shuffle_mode);
+                let is_radio_on = Arc::clone(&is_radio_on);
+                let suggested = Arc::clone(&suggested);
+                let mpris = mpris.clone();
 
                 move || {
+                    // MPRIS clients see the queue as a track list.
+                    let publish_queue = || {
+                        if let Some(mpris) = &mpris {
+                            mpris.set_queue(queue.songs().iter().cloned().collect());
+                        }
+                    };
+
+                    let set_repeat_mode = |mode: RepeatMode| {
+                        *repeat_mode.lock().unwrap() = mode;
+                        if let Some(mpris) = &mpris {
+                            mpris.set_repeat_mode(mode);
+                        }
+                    };
+
+                    let set_shuffle_mode = |mode: ShuffleMode| {
+                        *shuffle_mode.lock().unwrap() = mode;
+                        if let Some(mpris) = &mpris {
+                            mpris.set_shuffle_mode(mode);
+                        }
+                    };
+
+                    let shuffle = RefCell::new(Shuffle {
+                        rng: fastrand::Rng::new(),
+                        picked: None,
+                        recent_artists: VecDeque::new(),
+                    });
+
+                    // With shuffle on, the song picked to play next goes to the front of the queue, so everything that
+                    // takes songs from the front, like gapless playback, works the same.
+                    let shuffle_queue = |current: &Option<Song>| {
+                        let mode = *shuffle_mode.lock().unwrap();
+                        if mode == ShuffleMode::Off || *repeat_mode.lock().unwrap() == RepeatMode::One {
+                            return;
+                        }
+
+                        let moved = {
+                            let mut shuffle = shuffle.borrow_mut();
+                            let mut songs = queue.songs();
+                            if songs.front().is_some() && songs.front() == shuffle.picked.as_ref() {
+                                return;
+                            }
+                            let Shuffle {
+                                rng, recent_artists, ..
+                            } = &mut *shuffle;
+                            let Some(index) = pick_shuffled(&songs, mode, current.as_ref(), recent_artists, rng) else {
+                                return;
+                            };
+                            let picked = songs.remove(index).unwrap();
+                            shuffle.picked = Some(picked.clone());
+                            songs.push_front(picked);
+                            index != 0
+                        };
+
+                        if moved {
+                            on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+                            publish_queue();
+                        }
+                    };
+
+                    let remember_artist = |song: &Song| {
+                        let mut shuffle = shuffle.borrow_mut();
+                        if avoid_recent_artists && let Some(artist) = &song.artist {
+                            shuffle.recent_artists.push_back(artist.clone());
+                            if shuffle.recent_artists.len() > RECENT_ARTISTS {
+                                shuffle.recent_artists.pop_front();
+                            }
+                        }
+                    };
+
+                    // With radio mode on, a song is suggested as soon as the queue runs out, so it's lined up
+                    // while the last song plays, like any other.
+                    let suggest_next = |current: &Option<Song>| {
+                        let Some(current) = current else {
+                            return;
+                        };
+                        if !is_radio_on.load(Ordering::Acquire)
+                            || *repeat_mode.lock().unwrap() == RepeatMode::One
+                            || !queue.songs().is_empty()
+                        {
+                            return;
+                        }
+                        let Some(suggestion) =
+                            radio::suggest_from_saved(radio_engine, current, &mut fastrand::Rng::new())
+                        else {
+                            return;
+                        };
+                        log::debug!("radio suggested {:?}", suggestion.title);
+                        suggested.lock().unwrap().push(suggestion.clone());
+                        queue.add_back(suggestion);
+                        on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+                        publish_queue();
+                    };
+
+                    let record_play = |song: &Song, played: Duration, finished: bool| {
+                        if session == Session::App
+                            && let Some(event) = PlayEvent::new(song, played, finished, SystemTime::now())
+                        {
+                            PlayHistory::append(&event);
+                            if event.is_counted() {
+                                SongStats::update(|stats| stats.record_play(song, event.time));
+                            }
+                        }
+                        scrobbles.as_ref().inspect(|scrobbles| scrobbles.stopped());
+                    };
+
+                    let record_start = |song: &Song| {
+                        scrobbles.as_ref().inspect(|scrobbles| scrobbles.now_playing(song));
+                    };
+
                     let mut song: Option<Song> = None;
 
+                    // Songs played in this session, the last one most recently.
+                    let mut history: VecDeque<Song> = VecDeque::new();
+                    let remember_played = |history: &mut VecDeque<Song>, song: Song| {
+                        history.push_back(song);
+                        if history.len() > HISTORY_LENGTH {
+                            history.pop_front();
+                        }
+                    };
+                    // Whether the song that's ending was stopped to go back to the one before it.
+                    let mut is_going_back = false;
+
                     loop {
+                        shuffle_queue(&song);
+
                         let repeat_mode_lock = repeat_mode.lock().unwrap();
 
-                        if *repeat_mode_lock == RepeatMode::One && song.is_some() {
+                        if *repeat_mode_lock == RepeatMode::One
+                            && let Some(song) = &song
+                        {
                             player.set_is_paused(false); // added by IA to fix an issue. not sue if it fixes anything.
-                            player.play_song(song.clone().unwrap());
+                            player.play_song(song.clone());
+                            record_start(song);
                         } else {
                             song = queue.pop();
                             if let Some(ref song) = song {
+                                unmark_suggested(&suggested, song);
+                                remember_artist(song);
                                 if *repeat_mode_lock == RepeatMode::Queue {
                                     queue.add_back(song.clone());
                                 }
                                 log::debug!("song_player grabbed song from queue {song:?}");
                                 player.set_is_paused(false); // added by IA to fix an issue. not sue if it fixes anything.
                                 player.play_song(song.clone());
+                                record_start(song);
                                 on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+                                publish_queue();
                             } else {
                                 log::debug!("song_player queue was empty. will wait for changes.");
                                 player.set_is_paused(false);
+                                on_queue_ended.lock().unwrap().as_ref().inspect(|f| f());
                             }
                         }
 
                         drop(repeat_mode_lock);
 
+                        let set_next_song = |song: &Option<Song>| {
+                            if song.is_some() {
+                                suggest_next(song);
+                                shuffle_queue(song);
+                                player.set_next_song(next_song(&queue, *repeat_mode.lock().unwrap(), song));
+                            }
+                        };
+
+                        set_next_song(&song);
+
                         loop {
                             match rx.recv().unwrap() {
                                 MainPlayerMessage::Command(MainPlayerCommand::Quit) => {
+                                    // The song playing counts as played as far as it got, as if it were stopped.
+                                    let playing = player.playing_song().lock().unwrap().clone();
+                                    if let Some(playing) = playing {
+                                        record_play(&playing, player.playing_position(), false);
+                                    }
+                                    if let Some(scrobbles) = scrobbles {
+                                        scrobbles.quit();
+                                    }
                                     return;
                                 }
-                                MainPlayerMessage::Event(MainPlayerEvent::PlaybackEnded(song)) => {
-                                    log::debug!("playback ended {song:?}");
+                                MainPlayerMessage::Command(MainPlayerCommand::SetShuffle(on)) => {
+                                    // Album shuffling counts as on, and stays on if it already is.
+                                    let mode = match (on, *shuffle_mode.lock().unwrap()) {
+                                        (false, _) => ShuffleMode::Off,
+                                        (true, ShuffleMode::Off) => ShuffleMode::Tracks,
+                                        (true, mode) => mode,
+                                    };
+                                    log::debug!("shuffle mode {mode:?}");
+                                    set_shuffle_mode(mode);
+                                    shuffle.borrow_mut().picked = None;
+                                    set_next_song(&song);
+                                }
+                                MainPlayerMessage::Action(PlayerAction::ShuffleToggle) => {
+                                    let toggled = shuffle_mode.lock().unwrap().toggle();
+                                    log::debug!("shuffle mode {toggled:?}");
+                                    set_shuffle_mode(toggled);
+                                    shuffle.borrow_mut().picked = None;
+                                    set_next_song(&song);
+                                }
+                                MainPlayerMessage::Action(PlayerAction::RadioToggle) => {
+                                    let is_on = !is_radio_on.fetch_xor(true, Ordering::AcqRel);
+                                    log::debug!("radio mode {is_on}");
+                                    // Suggestions only make sense while radio mode is on.
+                                    if !is_on && drop_suggestions(&queue, &suggested) {
+                                        on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+                                        publish_queue();
+                                    }
+                                    set_next_song(&song);
+                                }
+                                MainPlayerMessage::Command(MainPlayerCommand::Previous) => {
+                                    let Some(current) = &song else {
+                                        continue;
+                                    };
+                                    if player.playing_position() >= RESTART_THRESHOLD || history.is_empty() {
+                                        player.seek_to(Duration::ZERO);
+                                        continue;
+                                    }
+                                    let previous = history.pop_back().unwrap();
+                                    log::debug!("going back to {previous:?}");
+                                    requeue_previous(
+                                        &mut queue.songs(),
+                                        previous.clone(),
+                                        current.clone(),
+                                        *repeat_mode.lock().unwrap(),
+                                    );
+                                    // Shuffling leaves the front of the queue alone while it's the song it picked.
+                                    shuffle.borrow_mut().picked = Some(previous);
+                                    is_going_back = true;
+                                    on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+                                    publish_queue();
+                                    // Once the current song stops, the previous one starts from the front of the queue.
+                                    player.stop();
+                                }
+                                MainPlayerMessage::Event(MainPlayerEvent::PlaybackEnded(ended, played, finished)) => {
+                                    log::debug!("playback ended {ended:?}");
+                                    record_play(&ended, played, finished);
+                                    if is_going_back {
+                                        is_going_back = false;
+                                        // Otherwise, RepeatMode::One would play the current song again.
+                                        song = None;
+                                    } else {
+                                        remember_played(&mut history, *ended);
+                                    }
                                     break;
                                 }
+                                MainPlayerMessage::Event(MainPlayerEvent::NextSongStarted(next)) => {
+                                    let next = *next;
+                                    log::debug!("next song started without a gap {next:?}");
+                                    let repeat_mode = *repeat_mode.lock().unwrap();
+
+                                    // With RepeatMode::One, the "next" song is the same one, which is not taken from the queue.
+                                    // Otherwise, it's the front of the queue, unless it changed while the transition happened.
+                                    if repeat_mode != RepeatMode::One && queue.peek().as_ref() == Some(&next) {
+                                        queue.pop();
+                                        unmark_suggested(&suggested, &next);
+                                        if repeat_mode == RepeatMode::Queue {
+                                            queue.add_back(next.clone());
+                                        }
+                                        on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+                                        publish_queue();
+                                    }
+
+                                    if let Some(previous) = song.take() {
+                                        record_play(&previous, previous.length, true);
+                                        remember_played(&mut history, previous);
+                                    }
+                                    remember_artist(&next);
+                                    record_start(&next);
+                                    song = Some(next);
+                                    set_next_song(&song);
+                                }
                                 MainPlayerMessage::Event(MainPlayerEvent::QueueChanged) => {
+                                    publish_queue();
                                     if player.playing_song().lock().unwrap().is_none() {
                                         log::debug!("MainPlayerEvent::QueueChanged");
                                         break;
                                     }
+                                    set_next_song(&song);
                                 }
                                 MainPlayerMessage::Action(PlayerAction::RepeatOne) => {
                                     log::debug!("will repeat one song");
-                                    *repeat_mode.lock().unwrap() = RepeatMode::One;
+                                    set_repeat_mode(RepeatMode::One);
+                                    set_next_song(&song);
                                 }
                                 MainPlayerMessage::Action(PlayerAction::RepeatNone) => {
                                     log::debug!("will not repeat");
-                                    *repeat_mode.lock().unwrap() = RepeatMode::Off;
+                                    set_repeat_mode(RepeatMode::Off);
+                                    set_next_song(&song);
                                 }
                                 MainPlayerMessage::Action(PlayerAction::RepeatQueue) => {
                                     log::debug!("will repeat entire queue");
-                                    *repeat_mode.lock().unwrap() = RepeatMode::Queue;
+                                    set_repeat_mode(RepeatMode::Queue);
+                                    set_next_song(&song);
                                 }
                                 MainPlayerMessage::Action(PlayerAction::RepeatToggle) => {
                                     log::debug!("will repeat entire queue");
-                                    let mut repeat_mode = repeat_mode.lock().unwrap();
-                                    *repeat_mode = repeat_mode.toggle();
+                                    let toggled = repeat_mode.lock().unwrap().toggle();
+                                    set_repeat_mode(toggled);
+                                    set_next_song(&song);
                                 }
                                 m => {
                                     log::warn!("MainPlayer received unknown message {m:?}");
@@ -191,25 +697,30 @@ impl MainPlayer {
             sender: tx,
             player,
             on_queue_changed,
+            on_queue_ended,
             on_error,
             queue,
             repeat_mode,
+            shuffle_mode,
+            is_radio_on,
+            suggested,
         }
     }
 
     pub fn quit(self) {
-        if let Some(player) = Arc::into_inner(self.player) {
-            player.quit();
-        } else {
-            log::error!("Dangling references to player! Could not quit it gracefully.")
-        }
-
+        // The main_player thread holds a reference to the player, so it has to go first.
         self.sender
             .send(MainPlayerMessage::Command(MainPlayerCommand::Quit))
             .unwrap();
         if let Err(err) = self.thread.join() {
             log::error!("error joining player thread {err:?}");
         };
+
+        if let Some(player) = Arc::into_inner(self.player) {
+            player.quit();
+        } else {
+            log::error!("Dangling references to player! Could not quit it gracefully.")
+        }
     }
 
     pub fn single_track_player(&self) -> Arc<SingleTrackPlayer> {
@@ -224,6 +735,22 @@ impl MainPlayer {
         self.single_track_player().get_volume()
     }
 
+    pub fn speed(&self) -> u32 {
+        self.single_track_player().get_speed()
+    }
+
+    pub fn equalizer_preset(&self) -> Option<String> {
+        self.single_track_player().equalizer_preset()
+    }
+
+    pub fn output_device(&self) -> Option<String> {
+        self.single_track_player().output_device()
+    }
+
+    pub fn set_output_device(&self, device: Option<String>) {
+        self.single_track_player().set_output_device(device);
+    }
+
     //// Playback Management
 
     pub fn playing_song(&self) -> Option<Song> {
@@ -247,6 +774,29 @@ impl MainPlayer {
         *self.repeat_mode.lock().unwrap()
     }
 
+    pub fn shuffle_mode(&self) -> ShuffleMode {
+        *self.shuffle_mode.lock().unwrap()
+    }
+
+    pub fn is_radio_on(&self) -> bool {
+        self.is_radio_on.load(Ordering::Acquire)
+    }
+
+    /// Songs in the queue that radio mode suggested, that haven't been promoted.
+    pub fn suggested_songs(&self) -> Vec<Song> {
+        self.suggested.lock().unwrap().clone()
+    }
+
+    /// Keeps the suggested song at `index` of the queue, as if it had been added by hand.
+    pub fn promote(&self, index: usize) {
+        let Some(song) = self.queue.songs().get(index).cloned() else {
+            return;
+        };
+        if unmark_suggested(&self.suggested, &song) {
+            self.on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+        }
+    }
+
     pub fn play(&self, song: Song) {
         self.single_track_player().play_song(song);
     }
@@ -261,6 +811,11 @@ impl MainPlayer {
         *self.on_queue_changed.lock().unwrap() = Some(Box::new(f));
     }
 
+    /// Called whenever the player runs out of songs to play, once the last one ends.
+    pub fn on_queue_ended(&self, f: impl Fn() + Send + 'static) {
+        *self.on_queue_ended.lock().unwrap() = Some(Box::new(f));
+    }
+
     pub fn on_error(&self, f: impl Fn(String) + Send + 'static) {
         *self.on_error.lock().unwrap() = Some(Box::new(f));
     }
@@ -271,25 +826,169 @@ impl MainPlayer {
             .unwrap();
     }
 
+    /// Songs added by hand take the place of the ones radio mode suggested.
+    fn drop_suggestions(&self) {
+        if drop_suggestions(&self.queue, &self.suggested) {
+            self.on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+        }
+    }
+
     pub fn add_front(&self, song: Song) {
+        self.drop_suggestions();
         self.queue.add_front(song);
         self.notify_queue_changed();
     }
 
+    /// Puts `songs` at the front of the queue, in order, all at once. Added one by one, an idle player would start
+    /// playing the first one added before the rest arrive.
+    pub fn prepend(&self, songs: Vec<Song>) {
+        self.drop_suggestions();
+        {
+            let mut queue = self.queue.songs();
+            for song in songs.into_iter().rev() {
+                queue.push_front(song);
+            }
+        }
+        self.notify_queue_changed();
+    }
+
     pub fn add_back(&self, song: Song) {
+        self.drop_suggestions();
         self.queue.add_back(song);
         self.notify_queue_changed();
     }
 
     pub fn append(&self, songs: &mut VecDeque<Song>) {
+        self.drop_suggestions();
         self.queue.append(songs);
         self.notify_queue_changed();
     }
 
     pub fn remove(&self, index: usize) {
+        if let Some(song) = self.queue.songs().get(index) {
+            unmark_suggested(&self.suggested, song);
+        }
         self.queue.remove(index);
         self.notify_queue_changed();
     }
+
+    /// Calls `f` with every song in the queue, like to give them new tags, and lets everyone know if `f` returned true
+    /// for any.
+    pub fn edit_songs(&self, mut f: impl FnMut(&mut Song) -> bool) {
+        let mut changed = false;
+        for song in self.queue.songs().iter_mut() {
+            changed |= f(song);
+        }
+        if !changed {
+            return;
+        }
+        // Suggestions are told apart by being equal to songs in the queue.
+        for song in self.suggested.lock().unwrap().iter_mut() {
+            f(song);
+        }
+        self.notify_queue_changed();
+        self.on_queue_changed.lock().unwrap().as_ref().inspect(|f| f());
+    }
+}
+
+/// Forgets that `song` was suggested, once it leaves the queue or is promoted. Returns whether it was.
+fn unmark_suggested(suggested: &Mutex<Vec<Song>>, song: &Song) -> bool {
+    let mut suggested = suggested.lock().unwrap();
+    let index = suggested.iter().position(|suggestion| suggestion == song);
+    index.inspect(|index| {
+        suggested.remove(*index);
+    });
+    index.is_some()
+}
+
+/// Takes the suggested songs out of the queue. Returns whether there were any.
+fn drop_suggestions(queue: &Queue, suggested: &Mutex<Vec<Song>>) -> bool {
+    let mut suggested = suggested.lock().unwrap();
+    if suggested.is_empty() {
+        return false;
+    }
+    let mut songs = queue.songs();
+    for suggestion in suggested.drain(..) {
+        if let Some(index) = songs.iter().rposition(|song| *song == suggestion) {
+            songs.remove(index);
+        }
+    }
+    true
+}
+
+struct Shuffle {
+    rng: fastrand::Rng,
+    /// The song last moved to the front of the queue. Another one is picked only once it's gone from the front.
+    picked: Option<Song>,
+    recent_artists: VecDeque<String>,
+}
+
+/// The index of the song in `queue` that should play after `current`, when shuffling.
+/// With `ShuffleMode::Albums`, the album of `current` plays on while the queue has more of it, in order.
+/// Songs by `recent_artists` are only picked when there's nothing else.
+fn pick_shuffled(
+    queue: &VecDeque<Song>,
+    mode: ShuffleMode,
+    current: Option<&Song>,
+    recent_artists: &VecDeque<String>,
+    rng: &mut fastrand::Rng,
+) -> Option<usize> {
+    // The first song of the album of `song` in the queue, after `after`, if given.
+    let first_of_album = |song: &Song, after: Option<&Song>| {
+        queue
+            .iter()
+            .enumerate()
+            .filter(|(_, other)| song.is_same_album(other) && after.is_none_or(|after| *other > after))
+            .min_by(|(_, a), (_, b)| a.cmp(b))
+            .map(|(index, _)| index)
+    };
+
+    let candidates: Vec<usize> = match mode {
+        ShuffleMode::Off => return (!queue.is_empty()).then_some(0),
+        ShuffleMode::Tracks => (0..queue.len()).collect(),
+        ShuffleMode::Albums => {
+            if let Some(next) = current.and_then(|current| first_of_album(current, Some(current))) {
+                return Some(next);
+            }
+            // One song per album, the one it starts with. Songs without an album stand on their own.
+            (0..queue.len())
+                .filter(|index| first_of_album(&queue[*index], None).is_none_or(|first| first == *index))
+                .collect()
+        }
+    };
+
+    let is_recent = |index: &usize| {
+        queue[*index]
+            .artist
+            .as_ref()
+            .is_some_and(|artist| recent_artists.contains(artist))
+    };
+    let fresh: Vec<usize> = candidates.iter().copied().filter(|index| !is_recent(index)).collect();
+    let candidates = if fresh.is_empty() { candidates } else { fresh };
+
+    (!candidates.is_empty()).then(|| candidates[rng.usize(..candidates.len())])
+}
+
+/// Puts `previous` and then `current` at the front of the queue, so going back plays them again in order.
+/// With `RepeatMode::Queue`, both were put at the back of the queue when they started, and are moved from there.
+fn requeue_previous(queue: &mut VecDeque<Song>, previous: Song, current: Song, repeat_mode: RepeatMode) {
+    if repeat_mode == RepeatMode::Queue {
+        for song in [&current, &previous] {
+            if let Some(index) = queue.iter().rposition(|queued| queued == song) {
+                queue.remove(index);
+            }
+        }
+    }
+    queue.push_front(current);
+    queue.push_front(previous);
+}
+
+/// The song that should play after `song` finishes, without altering the queue.
+fn next_song(queue: &Queue, repeat_mode: RepeatMode, song: &Option<Song>) -> Option<Song> {
+    match repeat_mode {
+        RepeatMode::One => song.clone(),
+        RepeatMode::Off | RepeatMode::Queue => queue.peek(),
+    }
 }
 
 impl OnAction<PlayerAction> for MainPlayer {
@@ -298,10 +997,321 @@ impl OnAction<PlayerAction> for MainPlayer {
             PlayerAction::RepeatNone
             | PlayerAction::RepeatOne
             | PlayerAction::RepeatQueue
-            | PlayerAction::RepeatToggle => {
+            | PlayerAction::RepeatToggle
+            | PlayerAction::ShuffleToggle
+            | PlayerAction::RadioToggle => {
                 self.sender.send(MainPlayerMessage::Action(action[0])).unwrap();
             }
             _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        sync::mpsc::Receiver,
+        time::{Duration, Instant},
+    };
+
+    use super::*;
+    use crate::test_fixtures::{TempDir, write_wav};
+
+    const PACE: f32 = 20.0;
+
+    fn spawn(songs: Vec<Song>) -> (MainPlayer, Receiver<()>) {
+        let player = MainPlayer::spawn(
+            None,
+            songs,
+            Settings::default(),
+            OutputTarget::Null { pace: PACE },
+            Session::Standalone,
+        );
+        let (tx, rx) = channel();
+        player.on_queue_ended(move || {
+            let _ = tx.send(());
+        });
+        (player, rx)
+    }
+
+    fn wait_for(what: &str, mut condition: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !condition() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "timed out waiting for {what}"
+            );
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn plays_through_queue() {
+        let dir = TempDir::new("main-player-queue");
+        let songs = vec![
+            write_wav(&dir.join("queue-a.wav"), 2),
+            write_wav(&dir.join("queue-b.wav"), 2),
+            write_wav(&dir.join("queue-c.wav"), 2),
+        ];
+        let (player, queue_ended) = spawn(songs.clone());
+
+        let mut played = vec![];
+        wait_for("the queue to end", || {
+            if let Some(song) = player.playing_song()
+                && played.last() != Some(&song)
+            {
+                played.push(song);
+            }
+            played.len() == songs.len() && queue_ended.try_recv().is_ok()
+        });
+
+        assert_eq!(played, songs);
+        assert!(player.queue().songs().is_empty());
+
+        player.quit();
+    }
+
+    #[test]
+    fn prepends_songs_to_an_idle_player() {
+        let dir = TempDir::new("main-player-prepend");
+        let songs = vec![
+            write_wav(&dir.join("prepend-a.wav"), 1),
+            write_wav(&dir.join("prepend-b.wav"), 1),
+            write_wav(&dir.join("prepend-c.wav"), 1),
+        ];
+        let (player, queue_ended) = spawn(vec![]);
+        player.prepend(songs.clone());
+
+        // The queue started out empty, which may be reported late, so its end only counts once every song played.
+        let mut played = vec![];
+        wait_for("the queue to end", || {
+            if let Some(song) = player.playing_song()
+                && played.last() != Some(&song)
+            {
+                played.push(song);
+            }
+            played.len() == songs.len() && queue_ended.try_recv().is_ok()
+        });
+        assert_eq!(played, songs);
+
+        player.quit();
+    }
+
+    #[test]
+    fn repeats_one_song() {
+        let dir = TempDir::new("main-player-repeat-one");
+        let song = write_wav(&dir.join("repeat-one.wav"), 1);
+        let (player, queue_ended) = spawn(vec![song.clone()]);
+        player.on_action(vec![PlayerAction::RepeatOne]);
+
+        // Long enough for the song to play several times over.
+        assert!(queue_ended.recv_timeout(Duration::from_secs_f32(4.0 / PACE)).is_err());
+        assert_eq!(player.playing_song(), Some(song.clone()));
+        assert_eq!(player.repeat_mode(), RepeatMode::One);
+
+        player.on_action(vec![PlayerAction::RepeatNone]);
+        wait_for("the queue to end", || queue_ended.try_recv().is_ok());
+
+        player.quit();
+    }
+
+    #[test]
+    fn repeats_queue() {
+        let dir = TempDir::new("main-player-repeat-queue");
+        let songs = vec![
+            write_wav(&dir.join("repeat-queue-a.wav"), 1),
+            write_wav(&dir.join("repeat-queue-b.wav"), 1),
+        ];
+        // Songs taken from the queue before repeating is turned on would not be put back.
+        let (player, _) = spawn(vec![]);
+        player.on_action(vec![PlayerAction::RepeatQueue]);
+        wait_for("repeating to be turned on", || {
+            player.repeat_mode() == RepeatMode::Queue
+        });
+        player.append(&mut songs.clone().into());
+
+        let mut played = vec![];
+        wait_for("both songs to play twice", || {
+            if let Some(song) = player.playing_song()
+                && played.last() != Some(&song)
+            {
+                played.push(song);
+            }
+            played.len() >= 4
+        });
+
+        assert_eq!(played[..4], [songs.clone(), songs.clone()].concat());
+        assert_eq!(player.queue().songs().len(), 2);
+
+        player.quit();
+    }
+
+    #[test]
+    fn keeps_album_shuffling_when_clients_turn_shuffle_on() {
+        let (player, _queue_ended) = spawn(vec![]);
+        let set_shuffle = |on| {
+            player
+                .sender
+                .send(MainPlayerMessage::Command(MainPlayerCommand::SetShuffle(on)))
+                .unwrap();
+        };
+
+        set_shuffle(true);
+        wait_for("shuffle to be turned on", || {
+            player.shuffle_mode() == ShuffleMode::Tracks
+        });
+        player.on_action(vec![PlayerAction::ShuffleToggle]);
+        wait_for("album shuffle", || player.shuffle_mode() == ShuffleMode::Albums);
+        set_shuffle(true);
+        // Messages are handled in order, so once repeat is set, shuffle has been turned on again.
+        player.on_action(vec![PlayerAction::RepeatQueue]);
+        wait_for("repeat to be set", || player.repeat_mode() == RepeatMode::Queue);
+        assert_eq!(player.shuffle_mode(), ShuffleMode::Albums);
+
+        set_shuffle(false);
+        wait_for("shuffle to be turned off", || player.shuffle_mode() == ShuffleMode::Off);
+
+        player.quit();
+    }
+
+    #[test]
+    fn shuffles_queue() {
+        let dir = TempDir::new("main-player-shuffle");
+        let songs = vec![
+            write_wav(&dir.join("shuffle-a.wav"), 1),
+            write_wav(&dir.join("shuffle-b.wav"), 1),
+            write_wav(&dir.join("shuffle-c.wav"), 1),
+        ];
+        let (player, queue_ended) = spawn(vec![]);
+        player.on_action(vec![PlayerAction::ShuffleToggle]);
+        wait_for("shuffle to be turned on", || {
+            player.shuffle_mode() == ShuffleMode::Tracks
+        });
+        // The queue started out empty, which may be reported late, so its end only counts once every song played.
+        player.append(&mut songs.clone().into());
+
+        let mut played = vec![];
+        wait_for("the queue to end", || {
+            if let Some(song) = player.playing_song()
+                && played.last() != Some(&song)
+            {
+                played.push(song);
+            }
+            played.len() == songs.len() && queue_ended.try_recv().is_ok()
+        });
+
+        played.sort();
+        let mut expected = songs.clone();
+        expected.sort();
+        assert_eq!(played, expected);
+
+        player.quit();
+    }
+
+    fn album_song(artist: &str, album: Option<&str>, track: u32) -> Song {
+        Song {
+            library_id: None,
+            path: PathBuf::from(format!("/music/{artist}/{}/{track}.flac", album.unwrap_or("-"))),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(200),
+            title: format!("{artist} {track}"),
+            artist: Some(artist.to_string()),
+            album: album.map(str::to_string),
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: Some(track),
+            year: None,
+            replay_gain: Default::default(),
+        }
+    }
+
+    #[test]
+    fn picks_shuffled_songs() {
+        let queue: VecDeque<Song> = vec![
+            album_song("Yes", Some("Fragile"), 2),
+            album_song("Yes", Some("Fragile"), 1),
+            album_song("Camel", Some("Moonmadness"), 1),
+            album_song("Yes", Some("Fragile"), 3),
+            album_song("Camel", None, 4),
+        ]
+        .into();
+        let none = VecDeque::new();
+        let mut rng = fastrand::Rng::with_seed(7);
+        let mut pick = |mode, current: Option<&Song>, recent: &VecDeque<String>| {
+            (0..50)
+                .map(|_| pick_shuffled(&queue, mode, current, recent, &mut rng).unwrap())
+                .collect::<std::collections::BTreeSet<usize>>()
+        };
+
+        assert_eq!(pick(ShuffleMode::Off, None, &none), [0].into());
+        assert_eq!(pick(ShuffleMode::Tracks, None, &none), [0, 1, 2, 3, 4].into());
+        // Albums start at their first track, and songs without an album count on their own.
+        assert_eq!(pick(ShuffleMode::Albums, None, &none), [1, 2, 4].into());
+        // The album that's playing plays on, in order.
+        assert_eq!(pick(ShuffleMode::Albums, Some(&queue[1]), &none), [0].into());
+        assert_eq!(pick(ShuffleMode::Albums, Some(&queue[3]), &none), [1, 2, 4].into());
+
+        let recent = VecDeque::from(["Yes".to_string()]);
+        assert_eq!(pick(ShuffleMode::Tracks, None, &recent), [2, 4].into());
+        let recent = VecDeque::from(["Yes".to_string(), "Camel".to_string()]);
+        assert_eq!(pick(ShuffleMode::Tracks, None, &recent), [0, 1, 2, 3, 4].into());
+
+        assert_eq!(
+            pick_shuffled(&VecDeque::new(), ShuffleMode::Tracks, None, &none, &mut rng),
+            None
+        );
+    }
+
+    #[test]
+    fn requeues_previous_song() {
+        let [a, b, c] = [1, 2, 3].map(|track| album_song("Yes", Some("Fragile"), track));
+
+        let mut queue = VecDeque::from([c.clone()]);
+        requeue_previous(&mut queue, a.clone(), b.clone(), RepeatMode::Off);
+        assert_eq!(queue, [a.clone(), b.clone(), c.clone()]);
+
+        // Both went to the back of the queue when they started.
+        let mut queue = VecDeque::from([c.clone(), a.clone(), b.clone()]);
+        requeue_previous(&mut queue, a.clone(), b.clone(), RepeatMode::Queue);
+        assert_eq!(queue, [a, b, c]);
+    }
+
+    #[test]
+    fn seeks() {
+        let dir = TempDir::new("main-player-seek");
+        let long = write_wav(&dir.join("seek-long.wav"), 60);
+        let short = write_wav(&dir.join("seek-short.wav"), 1);
+        let (player, queue_ended) = spawn(vec![long.clone(), short.clone()]);
+
+        wait_for("the first song to start", || {
+            player.playing_song() == Some(long.clone())
+        });
+        player.single_track_player().seek(30);
+        wait_for("the seek to land", || {
+            player.playing_position() >= Duration::from_secs(30)
+        });
+        assert!(
+            player.playing_position() < Duration::from_secs(45),
+            "{:?}",
+            player.playing_position()
+        );
+        assert_eq!(player.playing_song(), Some(long.clone()));
+
+        player.single_track_player().seek_to(Duration::from_secs(10));
+        wait_for("the seek to land", || {
+            player.playing_position() < Duration::from_secs(30)
+        });
+        assert!(player.playing_position() >= Duration::from_secs(10));
+
+        // Seeking past the end moves on to the next song.
+        player.single_track_player().seek(60);
+        wait_for("the next song to start", || {
+            player.playing_song() == Some(short.clone())
+        });
+        wait_for("the queue to end", || queue_ended.try_recv().is_ok());
+
+        player.quit();
+    }
+}
@@ -1,4 +1,15 @@
-use std::{error::Error, sync::Arc, thread};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+        mpsc::{Sender, channel},
+    },
+    thread,
+    time::Duration,
+};
 
 use mpris_server::{
     LoopStatus,
@@ -9,28 +20,203 @@ use mpris_server::{
     Property,
     RootInterface,
     Server,
+    Signal,
     Time,
     TrackId,
+    TrackListInterface,
+    TrackListSignal,
     Volume,
     zbus,
 };
 use tokio::sync::Mutex;
+use url::Url;
+
+use crate::{
+    main_player::{RepeatMode, ShuffleMode},
+    player::{MAX_SPEED, MIN_SPEED},
+    structs::Song,
+};
 
-use crate::structs::Song;
+type Callback<F> = Arc<Mutex<Option<Box<F>>>>;
+
+/// What the player last reported, served to clients that read properties.
+struct Status {
+    playback_status: PlaybackStatus,
+    loop_status: LoopStatus,
+    shuffle: bool,
+    volume: Volume,
+    rate: PlaybackRate,
+    metadata: Metadata,
+    /// The songs in the queue, after the current one, each with the id it's known by in the track list.
+    queue: Vec<(TrackId, Song)>,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self {
+            playback_status: PlaybackStatus::Stopped,
+            loop_status: LoopStatus::None,
+            shuffle: false,
+            volume: 1.0,
+            rate: 1.0,
+            metadata: Metadata::new(),
+            queue: Vec::new(),
+        }
+    }
+}
+
+impl Status {
+    fn has_song(&self) -> bool {
+        self.metadata.trackid().is_some()
+    }
+
+    fn current_track(&self) -> TrackId {
+        self.metadata.trackid().unwrap_or(TrackId::NO_TRACK)
+    }
+
+    /// The current song, followed by the queue.
+    fn tracks(&self) -> Vec<TrackId> {
+        self.metadata
+            .trackid()
+            .into_iter()
+            .chain(self.queue.iter().map(|(id, _)| id.clone()))
+            .collect()
+    }
+
+    fn queue_index(&self, track_id: &TrackId) -> Option<usize> {
+        self.queue.iter().position(|(id, _)| id == track_id)
+    }
+}
+
+fn song_metadata(song: &Song, track_id: TrackId) -> Metadata {
+    let mut metadata = Metadata::new();
+    metadata.set_trackid(Some(track_id));
+    metadata.set_title(Some(song.title.clone()));
+    metadata.set_artist(song.artist.clone().map(|a| vec![a]));
+    metadata.set_album(song.album.clone());
+    metadata.set_track_number(song.track.map(|track| track as i32));
+    metadata.set_disc_number(song.disc_number.map(|disc| disc as i32));
+    metadata.set_length(Some(time_from_duration(song.length)));
+    metadata.set_url(Url::from_file_path(&song.path).ok().map(String::from));
+    metadata
+}
+
+fn path_from_uri(uri: &str) -> zbus::fdo::Result<PathBuf> {
+    Url::parse(uri)
+        .ok()
+        .filter(|url| url.scheme() == "file")
+        .and_then(|url| url.to_file_path().ok())
+        .ok_or_else(|| zbus::fdo::Error::InvalidArgs(format!("Not a file URI: {uri}")))
+}
+
+impl From<RepeatMode> for LoopStatus {
+    fn from(repeat_mode: RepeatMode) -> Self {
+        match repeat_mode {
+            RepeatMode::Off => LoopStatus::None,
+            RepeatMode::One => LoopStatus::Track,
+            RepeatMode::Queue => LoopStatus::Playlist,
+        }
+    }
+}
+
+impl From<LoopStatus> for RepeatMode {
+    fn from(loop_status: LoopStatus) -> Self {
+        match loop_status {
+            LoopStatus::None => RepeatMode::Off,
+            LoopStatus::Track => RepeatMode::One,
+            LoopStatus::Playlist => RepeatMode::Queue,
+        }
+    }
+}
+
+fn time_from_duration(duration: Duration) -> Time {
+    Time::from_micros(duration.as_micros() as i64)
+}
 
 #[derive(Default)]
 pub struct MprisState {
-    on_play_pause: Arc<Mutex<Option<Box<dyn Fn() + Send + Sync + 'static>>>>,
-    on_stop: Arc<Mutex<Option<Box<dyn Fn() + Send + Sync + 'static>>>>,
+    status: Arc<Mutex<Status>>,
+    position: Callback<dyn Fn() -> Duration + Send + Sync + 'static>,
+    on_play_pause: Callback<dyn Fn() + Send + Sync + 'static>,
+    on_play: Callback<dyn Fn() + Send + Sync + 'static>,
+    on_pause: Callback<dyn Fn() + Send + Sync + 'static>,
+    on_stop: Callback<dyn Fn() + Send + Sync + 'static>,
+    on_next: Callback<dyn Fn() + Send + Sync + 'static>,
+    on_previous: Callback<dyn Fn() + Send + Sync + 'static>,
+    on_seek: Callback<dyn Fn(Duration) + Send + Sync + 'static>,
+    on_set_volume: Callback<dyn Fn(f64) + Send + Sync + 'static>,
+    on_set_rate: Callback<dyn Fn(f64) + Send + Sync + 'static>,
+    on_set_repeat_mode: Callback<dyn Fn(RepeatMode) + Send + Sync + 'static>,
+    on_set_shuffle: Callback<dyn Fn(bool) + Send + Sync + 'static>,
+    on_open: Callback<dyn Fn(PathBuf) + Send + Sync + 'static>,
+    on_add_to_queue: Callback<dyn Fn(PathBuf, usize, bool) + Send + Sync + 'static>,
+    on_remove_from_queue: Callback<dyn Fn(usize) + Send + Sync + 'static>,
+    on_go_to: Callback<dyn Fn(usize) + Send + Sync + 'static>,
 }
 
 impl MprisState {
     pub fn on_play_pause(&self, f: impl Fn() + Send + Sync + 'static) {
         *self.on_play_pause.blocking_lock() = Some(Box::new(f));
     }
+    pub fn on_play(&self, f: impl Fn() + Send + Sync + 'static) {
+        *self.on_play.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_pause(&self, f: impl Fn() + Send + Sync + 'static) {
+        *self.on_pause.blocking_lock() = Some(Box::new(f));
+    }
     pub fn on_stop(&self, f: impl Fn() + Send + Sync + 'static) {
         *self.on_stop.blocking_lock() = Some(Box::new(f));
     }
+    pub fn on_next(&self, f: impl Fn() + Send + Sync + 'static) {
+        *self.on_next.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_previous(&self, f: impl Fn() + Send + Sync + 'static) {
+        *self.on_previous.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_seek(&self, f: impl Fn(Duration) + Send + Sync + 'static) {
+        *self.on_seek.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_set_volume(&self, f: impl Fn(f64) + Send + Sync + 'static) {
+        *self.on_set_volume.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_set_rate(&self, f: impl Fn(f64) + Send + Sync + 'static) {
+        *self.on_set_rate.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_set_repeat_mode(&self, f: impl Fn(RepeatMode) + Send + Sync + 'static) {
+        *self.on_set_repeat_mode.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_set_shuffle(&self, f: impl Fn(bool) + Send + Sync + 'static) {
+        *self.on_set_shuffle.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_open(&self, f: impl Fn(PathBuf) + Send + Sync + 'static) {
+        *self.on_open.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_add_to_queue(&self, f: impl Fn(PathBuf, usize, bool) + Send + Sync + 'static) {
+        *self.on_add_to_queue.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_remove_from_queue(&self, f: impl Fn(usize) + Send + Sync + 'static) {
+        *self.on_remove_from_queue.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn on_go_to(&self, f: impl Fn(usize) + Send + Sync + 'static) {
+        *self.on_go_to.blocking_lock() = Some(Box::new(f));
+    }
+    pub fn set_position_source(&self, f: impl Fn() -> Duration + Send + Sync + 'static) {
+        *self.position.blocking_lock() = Some(Box::new(f));
+    }
+
+    async fn current_position(&self) -> Duration {
+        match &*self.position.lock().await {
+            Some(position) => position(),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Seeks within the current song. Positions past its end move on to the next one.
+    async fn seek_to(&self, position: Duration) {
+        if let Some(on_seek) = &*self.on_seek.lock().await {
+            on_seek(position);
+        }
+    }
 }
 
 #[allow(unused)]
@@ -64,7 +250,7 @@ impl RootInterface for MprisState {
     }
 
     async fn has_track_list(&self) -> zbus::fdo::Result<bool> {
-        Ok(false)
+        Ok(true)
     }
 
     async fn identity(&self) -> zbus::fdo::Result<String> {
@@ -96,24 +282,23 @@ impl RootInterface for MprisState {
 #[allow(unused)]
 impl PlayerInterface for MprisState {
     async fn next(&self) -> zbus::fdo::Result<()> {
-        let on_stop = self.on_stop.lock().await;
-        let Some(on_stop) = &*on_stop else {
-            return Ok(());
-        };
-        on_stop();
+        if let Some(on_next) = &*self.on_next.lock().await {
+            on_next();
+        }
         Ok(())
     }
 
     async fn previous(&self) -> zbus::fdo::Result<()> {
+        if let Some(on_previous) = &*self.on_previous.lock().await {
+            on_previous();
+        }
         Ok(())
     }
 
     async fn pause(&self) -> zbus::fdo::Result<()> {
-        let on_play_pause = self.on_play_pause.lock().await;
-        let Some(on_play_pause) = &*on_play_pause else {
-            return Ok(());
-        };
-        on_play_pause();
+        if let Some(on_pause) = &*self.on_pause.lock().await {
+            on_pause();
+        }
         Ok(())
     }
 
@@ -136,96 +321,132 @@ impl PlayerInterface for MprisState {
     }
 
     async fn play(&self) -> zbus::fdo::Result<()> {
-        let on_play_pause = self.on_play_pause.lock().await;
-        let Some(on_play_pause) = &*on_play_pause else {
-            return Ok(());
-        };
-        on_play_pause();
+        if let Some(on_play) = &*self.on_play.lock().await {
+            on_play();
+        }
         Ok(())
     }
 
     async fn seek(&self, offset: Time) -> zbus::fdo::Result<()> {
+        if !self.status.lock().await.has_song() {
+            return Ok(());
+        }
+        let position = time_from_duration(self.current_position().await).saturating_add(offset);
+        // Seeking before the start of the song goes to its start.
+        let position = Duration::from_micros(position.as_micros().max(0) as u64);
+        self.seek_to(position).await;
         Ok(())
     }
 
     async fn set_position(&self, track_id: TrackId, position: Time) -> zbus::fdo::Result<()> {
+        // Requests meant for a song that isn't playing anymore, or for positions outside the song, are ignored.
+        let status = self.status.lock().await;
+        if status.metadata.trackid().as_ref() != Some(&track_id)
+            || position.is_negative()
+            || status.metadata.length().is_some_and(|length| position > length)
+        {
+            return Ok(());
+        }
+        drop(status);
+
+        self.seek_to(Duration::from_micros(position.as_micros() as u64)).await;
         Ok(())
     }
 
     async fn open_uri(&self, uri: String) -> zbus::fdo::Result<()> {
+        let path = path_from_uri(&uri)?;
+        if let Some(on_open) = &*self.on_open.lock().await {
+            on_open(path);
+        }
         Ok(())
     }
 
     async fn playback_status(&self) -> zbus::fdo::Result<PlaybackStatus> {
-        Ok(PlaybackStatus::Stopped)
+        Ok(self.status.lock().await.playback_status)
     }
 
     async fn loop_status(&self) -> zbus::fdo::Result<LoopStatus> {
-        Ok(LoopStatus::None)
+        Ok(self.status.lock().await.loop_status)
     }
 
     async fn set_loop_status(&self, loop_status: LoopStatus) -> zbus::Result<()> {
+        if let Some(on_set_repeat_mode) = &*self.on_set_repeat_mode.lock().await {
+            on_set_repeat_mode(loop_status.into());
+        }
         Ok(())
     }
 
     async fn rate(&self) -> zbus::fdo::Result<PlaybackRate> {
-        Ok(1.0)
+        Ok(self.status.lock().await.rate)
     }
 
     async fn set_rate(&self, rate: PlaybackRate) -> zbus::Result<()> {
+        // Clients aren't supposed to set a rate of 0. If they do, it's treated as a pause.
+        if rate == 0.0 {
+            return self.pause().await.map_err(zbus::Error::from);
+        }
+        if let Some(on_set_rate) = &*self.on_set_rate.lock().await {
+            on_set_rate(rate);
+        }
         Ok(())
     }
 
     async fn shuffle(&self) -> zbus::fdo::Result<bool> {
-        Ok(true)
+        Ok(self.status.lock().await.shuffle)
     }
 
     async fn set_shuffle(&self, shuffle: bool) -> zbus::Result<()> {
+        if let Some(on_set_shuffle) = &*self.on_set_shuffle.lock().await {
+            on_set_shuffle(shuffle);
+        }
         Ok(())
     }
 
     async fn metadata(&self) -> zbus::fdo::Result<Metadata> {
-        Ok(Metadata::new())
+        Ok(self.status.lock().await.metadata.clone())
     }
 
     async fn volume(&self) -> zbus::fdo::Result<Volume> {
-        Ok(1.0)
+        Ok(self.status.lock().await.volume)
     }
 
     async fn set_volume(&self, volume: Volume) -> zbus::Result<()> {
+        if let Some(on_set_volume) = &*self.on_set_volume.lock().await {
+            on_set_volume(volume.clamp(0.0, 1.0));
+        }
         Ok(())
     }
 
     async fn position(&self) -> zbus::fdo::Result<Time> {
-        Ok(Time::ZERO)
+        Ok(time_from_duration(self.current_position().await))
     }
 
     async fn minimum_rate(&self) -> zbus::fdo::Result<PlaybackRate> {
-        Ok(1.0)
+        Ok(MIN_SPEED as f64 / 100.)
     }
 
     async fn maximum_rate(&self) -> zbus::fdo::Result<PlaybackRate> {
-        Ok(1.0)
+        Ok(MAX_SPEED as f64 / 100.)
     }
 
     async fn can_go_next(&self) -> zbus::fdo::Result<bool> {
-        Ok(false)
+        Ok(self.status.lock().await.has_song())
     }
 
     async fn can_go_previous(&self) -> zbus::fdo::Result<bool> {
-        Ok(false)
+        Ok(self.status.lock().await.has_song())
     }
 
     async fn can_play(&self) -> zbus::fdo::Result<bool> {
-        Ok(false)
+        Ok(self.status.lock().await.has_song())
     }
 
     async fn can_pause(&self) -> zbus::fdo::Result<bool> {
-        Ok(false)
+        Ok(self.status.lock().await.has_song())
     }
 
     async fn can_seek(&self) -> zbus::fdo::Result<bool> {
-        Ok(false)
+        Ok(self.status.lock().await.has_song())
     }
 
     async fn can_control(&self) -> zbus::fdo::Result<bool> {
@@ -233,16 +454,154 @@ impl PlayerInterface for MprisState {
     }
 }
 
+/// Matches the songs of a new queue with the ones of the old queue, so songs that stayed keep their ids.
+/// Returns the new queue, the indices in it of the songs that weren't in the old one,
+/// and the ids of the songs that aren't in the new one.
+fn reconcile_queue(
+    old: Vec<(TrackId, Song)>,
+    songs: Vec<Song>,
+    mut new_track_id: impl FnMut() -> TrackId,
+) -> (Vec<(TrackId, Song)>, Vec<usize>, Vec<TrackId>) {
+    let mut old = VecDeque::from(old);
+    let mut queue = Vec::with_capacity(songs.len());
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+
+    for song in songs {
+        match old.iter().position(|(_, old_song)| *old_song == song) {
+            Some(index) => {
+                // Songs before it in the old queue are not in the new one.
+                removed.extend(old.drain(..index).map(|(id, _)| id));
+                queue.push(old.pop_front().unwrap());
+            }
+            None => {
+                added.push(queue.len());
+                queue.push((new_track_id(), song));
+            }
+        }
+    }
+    removed.extend(old.into_iter().map(|(id, _)| id));
+
+    (queue, added, removed)
+}
+
+/// The track list is the current song followed by the queue.
+/// Tracks are only ever added to and removed from the queue. The current song can't be removed.
+impl TrackListInterface for MprisState {
+    async fn get_tracks_metadata(&self, track_ids: Vec<TrackId>) -> zbus::fdo::Result<Vec<Metadata>> {
+        let status = self.status.lock().await;
+        Ok(track_ids
+            .iter()
+            .filter_map(|track_id| {
+                if status.metadata.trackid().as_ref() == Some(track_id) {
+                    Some(status.metadata.clone())
+                } else {
+                    let index = status.queue_index(track_id)?;
+                    let (id, song) = &status.queue[index];
+                    Some(song_metadata(song, id.clone()))
+                }
+            })
+            .collect())
+    }
+
+    async fn add_track(&self, uri: String, after_track: TrackId, set_as_current: bool) -> zbus::fdo::Result<()> {
+        let path = path_from_uri(&uri)?;
+
+        let index = {
+            let status = self.status.lock().await;
+            if after_track == TrackId::NO_TRACK || Some(&after_track) == status.metadata.trackid().as_ref() {
+                0
+            } else {
+                match status.queue_index(&after_track) {
+                    Some(index) => index + 1,
+                    None => return Err(zbus::fdo::Error::InvalidArgs(format!("Unknown track: {after_track}"))),
+                }
+            }
+        };
+
+        if let Some(on_add_to_queue) = &*self.on_add_to_queue.lock().await {
+            on_add_to_queue(path, index, set_as_current);
+        }
+        Ok(())
+    }
+
+    async fn remove_track(&self, track_id: TrackId) -> zbus::fdo::Result<()> {
+        let Some(index) = self.status.lock().await.queue_index(&track_id) else {
+            return Ok(());
+        };
+        if let Some(on_remove_from_queue) = &*self.on_remove_from_queue.lock().await {
+            on_remove_from_queue(index);
+        }
+        Ok(())
+    }
+
+    async fn go_to(&self, track_id: TrackId) -> zbus::fdo::Result<()> {
+        let Some(index) = self.status.lock().await.queue_index(&track_id) else {
+            return Ok(());
+        };
+        if let Some(on_go_to) = &*self.on_go_to.lock().await {
+            on_go_to(index);
+        }
+        Ok(())
+    }
+
+    async fn tracks(&self) -> zbus::fdo::Result<Vec<TrackId>> {
+        Ok(self.status.lock().await.tracks())
+    }
+
+    async fn can_edit_tracks(&self) -> zbus::fdo::Result<bool> {
+        Ok(true)
+    }
+}
+
+enum Emission {
+    PropertiesChanged(Vec<Property>),
+    Player(Signal),
+    TrackList(TrackListSignal),
+}
+
 pub struct Mpris {
     server: Arc<Mutex<Server<MprisState>>>,
+    status: Arc<Mutex<Status>>,
+    next_track_id: AtomicU64,
+    emitter: Sender<Emission>,
 }
 
 impl Mpris {
     pub async fn new() -> Result<Self, Box<dyn Error>> {
-        let server = Server::new("jolteon", MprisState::default()).await?;
+        let status = Arc::new(Mutex::new(Status::default()));
+        let state = MprisState {
+            status: status.clone(),
+            ..Default::default()
+        };
+        let server = Arc::new(Mutex::new(Server::new_with_track_list("jolteon", state).await?));
+
+        // Signals are sent from a single thread, in the order they happen, so clients can make sense of them.
+        let (emitter, emissions) = channel::<Emission>();
+        thread::Builder::new().name("mpris".to_string()).spawn({
+            let server = server.clone();
+            move || {
+                for emission in emissions {
+                    futures::executor::block_on(async {
+                        let server = server.lock().await;
+                        let result = match emission {
+                            Emission::PropertiesChanged(properties) => server.properties_changed(properties).await,
+                            Emission::Player(signal) => server.emit(signal).await,
+                            Emission::TrackList(signal) => server.track_list_emit(signal).await,
+                        };
+                        if let Err(err) = result {
+                            log::error!("could not emit MPRIS signal: {err}");
+                        }
+                    });
+                }
+            }
+        })?;
 
         Ok(Self {
-            server: Arc::new(Mutex::new(server)),
+            server,
+            status,
+            next_track_id: AtomicU64::new(0),
+            emitter,
         })
     }
 
@@ -251,52 +610,296 @@ impl Mpris {
         s.imp().on_play_pause(f);
     }
 
+    pub fn on_play(&self, f: impl Fn() + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_play(f);
+    }
+
+    pub fn on_pause(&self, f: impl Fn() + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_pause(f);
+    }
+
     pub fn on_stop(&self, f: impl Fn() + Send + Sync + 'static) {
         let s = self.server.blocking_lock();
         s.imp().on_stop(f);
     }
 
-    fn emit_properties_changed(&self, properties: impl IntoIterator<Item = Property> + Send + Sync + 'static) {
-        let server = self.server.clone();
-        thread::spawn(move || {
-            futures::executor::block_on(async move {
-                let server = server.lock().await;
-                server.properties_changed(properties).await.unwrap();
-            });
-        });
+    pub fn on_next(&self, f: impl Fn() + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_next(f);
+    }
+
+    pub fn on_previous(&self, f: impl Fn() + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_previous(f);
+    }
+
+    /// Receives a position of the current song, relative to its start.
+    pub fn on_seek(&self, f: impl Fn(Duration) + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_seek(f);
+    }
+
+    /// Receives a volume between 0 and 1.
+    pub fn on_set_volume(&self, f: impl Fn(f64) + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_set_volume(f);
+    }
+
+    /// Receives a playback speed, where 1 is normal speed.
+    pub fn on_set_rate(&self, f: impl Fn(f64) + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_set_rate(f);
+    }
+
+    pub fn on_set_repeat_mode(&self, f: impl Fn(RepeatMode) + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_set_repeat_mode(f);
+    }
+
+    pub fn on_set_shuffle(&self, f: impl Fn(bool) + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_set_shuffle(f);
+    }
+
+    /// Receives the path of a file clients asked to play.
+    pub fn on_open(&self, f: impl Fn(PathBuf) + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_open(f);
+    }
+
+    /// Receives a file or directory to add to the queue, the index to add it at,
+    /// and whether playback should skip to it right away.
+    pub fn on_add_to_queue(&self, f: impl Fn(PathBuf, usize, bool) + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_add_to_queue(f);
+    }
+
+    /// Receives the index in the queue of the song to remove.
+    pub fn on_remove_from_queue(&self, f: impl Fn(usize) + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_remove_from_queue(f);
+    }
+
+    /// Receives the index in the queue of the song to skip to.
+    pub fn on_go_to(&self, f: impl Fn(usize) + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().on_go_to(f);
+    }
+
+    /// Clients poll the position rather than being told about it, so it's read from `f` when they do.
+    pub fn set_position_source(&self, f: impl Fn() -> Duration + Send + Sync + 'static) {
+        let s = self.server.blocking_lock();
+        s.imp().set_position_source(f);
+    }
+
+    fn emit(&self, emission: Emission) {
+        if let Err(err) = self.emitter.send(emission) {
+            log::error!("MPRIS emitter thread is gone: {err}");
+        }
+    }
+
+    fn emit_properties_changed(&self, properties: Vec<Property>) {
+        self.emit(Emission::PropertiesChanged(properties));
+    }
+
+    fn emit_track_list_signals(&self, signals: Vec<TrackListSignal>) {
+        for signal in signals {
+            self.emit(Emission::TrackList(signal));
+        }
+    }
+
+    fn new_track_id(&self) -> TrackId {
+        let id = self.next_track_id.fetch_add(1, Ordering::Relaxed);
+        TrackId::try_from(format!("/org/jolteon/track/{id}")).unwrap()
+    }
+
+    fn track_list_replaced(status: &Status) -> TrackListSignal {
+        TrackListSignal::TrackListReplaced {
+            tracks: status.tracks(),
+            current_track: status.current_track(),
+        }
     }
 
     pub fn set_song(&self, song: Song) {
-        let mut metadata = Metadata::new();
-        metadata.set_title(Some(song.title));
-        metadata.set_artist(song.artist.map(|a| vec![a]));
-        metadata.set_album(song.album);
-        metadata.set_length(Some(Time::from_secs(song.length.as_secs() as i64)));
+        let metadata = song_metadata(&song, self.new_track_id());
+
+        let track_list_replaced = {
+            let mut status = self.status.blocking_lock();
+            status.metadata = metadata.clone();
+            Self::track_list_replaced(&status)
+        };
+        self.emit_track_list_signals(vec![track_list_replaced]);
 
         self.emit_properties_changed(vec![
             Property::Metadata(metadata),
             Property::CanPlay(true),
             Property::CanPause(true),
             Property::CanGoNext(true),
+            Property::CanGoPrevious(true),
             Property::CanSeek(true),
         ]);
     }
 
     pub fn clear_song(&self) {
+        let track_list_replaced = {
+            let mut status = self.status.blocking_lock();
+            status.metadata = Metadata::new();
+            status.playback_status = PlaybackStatus::Stopped;
+            Self::track_list_replaced(&status)
+        };
+        self.emit_track_list_signals(vec![track_list_replaced]);
+
         self.emit_properties_changed(vec![
             Property::Metadata(Metadata::new()),
             Property::PlaybackStatus(PlaybackStatus::Stopped),
             Property::CanPlay(false),
+            Property::CanPause(false),
             Property::CanGoNext(false),
+            Property::CanGoPrevious(false),
             Property::CanSeek(false),
         ]);
     }
 
     pub fn play(&self) {
+        self.status.blocking_lock().playback_status = PlaybackStatus::Playing;
         self.emit_properties_changed(vec![Property::PlaybackStatus(PlaybackStatus::Playing)]);
     }
 
     pub fn pause(&self) {
+        self.status.blocking_lock().playback_status = PlaybackStatus::Paused;
         self.emit_properties_changed(vec![Property::PlaybackStatus(PlaybackStatus::Paused)])
     }
+
+    /// Volume between 0 and 1.
+    pub fn set_volume(&self, volume: f64) {
+        self.status.blocking_lock().volume = volume;
+        self.emit_properties_changed(vec![Property::Volume(volume)]);
+    }
+
+    /// Playback speed, where 1 is normal speed.
+    pub fn set_rate(&self, rate: f64) {
+        self.status.blocking_lock().rate = rate;
+        self.emit_properties_changed(vec![Property::Rate(rate)]);
+    }
+
+    pub fn set_repeat_mode(&self, repeat_mode: RepeatMode) {
+        let loop_status = repeat_mode.into();
+        self.status.blocking_lock().loop_status = loop_status;
+        self.emit_properties_changed(vec![Property::LoopStatus(loop_status)]);
+    }
+
+    pub fn set_shuffle_mode(&self, shuffle_mode: ShuffleMode) {
+        let shuffle = shuffle_mode != ShuffleMode::Off;
+        self.status.blocking_lock().shuffle = shuffle;
+        self.emit_properties_changed(vec![Property::Shuffle(shuffle)]);
+    }
+
+    /// Updates the track list to the songs in the queue.
+    /// If songs were only added or only removed, clients are told about each of them.
+    /// Otherwise, they're told the whole list was replaced.
+    pub fn set_queue(&self, songs: Vec<Song>) {
+        let mut status = self.status.blocking_lock();
+
+        let old = std::mem::take(&mut status.queue);
+        let (queue, added, removed) = reconcile_queue(old, songs, || self.new_track_id());
+        status.queue = queue;
+
+        let signals = if added.is_empty() {
+            removed
+                .into_iter()
+                .map(|track_id| TrackListSignal::TrackRemoved { track_id })
+                .collect()
+        } else if removed.is_empty() {
+            added
+                .into_iter()
+                .map(|index| {
+                    let (track_id, song) = &status.queue[index];
+                    TrackListSignal::TrackAdded {
+                        metadata: song_metadata(song, track_id.clone()),
+                        after_track: match index {
+                            0 => status.current_track(),
+                            _ => status.queue[index - 1].0.clone(),
+                        },
+                    }
+                })
+                .collect()
+        } else {
+            vec![Self::track_list_replaced(&status)]
+        };
+
+        drop(status);
+        self.emit_track_list_signals(signals);
+    }
+
+    /// Lets clients know the position jumped, to a position relative to the start of the current song.
+    pub fn seeked(&self, position: Duration) {
+        self.emit(Emission::Player(Signal::Seeked {
+            position: time_from_duration(position),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(title: &str) -> Song {
+        Song {
+            library_id: None,
+            path: PathBuf::from(format!("/music/{title}.flac")),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(60),
+            title: title.to_string(),
+            artist: None,
+            album: None,
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: None,
+            replay_gain: Default::default(),
+        }
+    }
+
+    fn reconcile(old: &[(TrackId, Song)], titles: &[&str]) -> (Vec<(TrackId, Song)>, Vec<usize>, Vec<TrackId>) {
+        let mut next_id = 100;
+        reconcile_queue(old.to_vec(), titles.iter().map(|title| song(title)).collect(), || {
+            next_id += 1;
+            TrackId::try_from(format!("/test/{next_id}")).unwrap()
+        })
+    }
+
+    fn titles(queue: &[(TrackId, Song)]) -> Vec<&str> {
+        queue.iter().map(|(_, song)| song.title.as_str()).collect()
+    }
+
+    #[test]
+    fn reconcile_queue_keeps_ids() {
+        let (queue, added, removed) = reconcile(&[], &["a", "b", "c"]);
+        assert_eq!(titles(&queue), ["a", "b", "c"]);
+        assert_eq!(added, [0, 1, 2]);
+        assert!(removed.is_empty());
+
+        // Playing the first song takes it out of the queue.
+        let (popped, added, removed) = reconcile(&queue, &["b", "c"]);
+        assert_eq!(popped, queue[1..]);
+        assert!(added.is_empty());
+        assert_eq!(removed, [queue[0].0.clone()]);
+
+        // Adding songs in between.
+        let (inserted, added, removed) = reconcile(&queue, &["a", "d", "b", "c", "e"]);
+        assert_eq!(titles(&inserted), ["a", "d", "b", "c", "e"]);
+        assert_eq!(inserted[0], queue[0]);
+        assert_eq!(inserted[2..4], queue[1..3]);
+        assert_eq!(added, [1, 4]);
+        assert!(removed.is_empty());
+
+        // Moving a song to the front.
+        let (moved, added, removed) = reconcile(&queue, &["c", "a", "b"]);
+        assert_eq!(moved[0], queue[2]);
+        assert_eq!(added, [1, 2]);
+        assert_eq!(removed, [queue[0].0.clone(), queue[1].0.clone()]);
+    }
 }
@@ -0,0 +1,352 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use rodio::{
+    ChannelCount,
+    DeviceSinkBuilder,
+    MixerDeviceSink,
+    SampleRate,
+    Source,
+    cpal::{
+        self,
+        traits::{DeviceTrait, HostTrait},
+    },
+    math::nz,
+    mixer::{Mixer, MixerSource, mixer},
+    queue::{SourcesQueueInput, queue},
+};
+
+/// Where the player sends its audio.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputTarget {
+    /// A sound card, by name. `None` is the system default.
+    Device(Option<String>),
+    /// Discards samples. `pace` is how many times faster than real time they're consumed.
+    Null { pace: f32 },
+    /// Writes samples to a WAV file, leaving out leading and trailing silence.
+    /// `pace` is how many times faster than real time they're consumed.
+    Wav { path: PathBuf, pace: f32 },
+}
+
+impl OutputTarget {
+    /// How many times faster than real time samples are consumed.
+    pub fn pace(&self) -> f32 {
+        match self {
+            Self::Device(_) => 1.0,
+            Self::Null { pace } | Self::Wav { pace, .. } => *pace,
+        }
+    }
+}
+
+/// Names of the output devices of the default host, as expected by `OutputTarget::Device`.
+pub fn output_devices() -> Vec<String> {
+    match cpal::default_host().output_devices() {
+        Ok(devices) => devices
+            .filter_map(|device| device.description().ok())
+            .map(|description| description.name().to_string())
+            .collect(),
+        Err(err) => {
+            log::error!("could not list output devices: {err}");
+            Vec::new()
+        }
+    }
+}
+
+fn open_output_stream(device: Option<&str>) -> Result<MixerDeviceSink, String> {
+    let builder = match device {
+        None => DeviceSinkBuilder::from_default_device(),
+        Some(name) => {
+            let device = cpal::default_host()
+                .output_devices()
+                .map_err(|err| err.to_string())?
+                .find(|device| device.description().is_ok_and(|description| description.name() == name))
+                .ok_or_else(|| "device not found".to_string())?;
+            DeviceSinkBuilder::from_device(device)
+        }
+    };
+
+    builder
+        .map_err(|err| err.to_string())?
+        .with_error_callback(|err| log::error!("audio stream error: {err}"))
+        .open_sink_or_fallback()
+        .map(|mut stream| {
+            // Streams are dropped on purpose when switching devices. This would print to stderr, over the TUI.
+            stream.log_on_drop(false);
+            stream
+        })
+        .map_err(|err| err.to_string())
+}
+
+enum Sink {
+    Device(MixerDeviceSink),
+    Renderer(Renderer),
+}
+
+impl Sink {
+    fn mixer(&self) -> &Mixer {
+        match self {
+            Self::Device(stream) => stream.mixer(),
+            Self::Renderer(renderer) => &renderer.mixer,
+        }
+    }
+}
+
+/// The sink samples end up in, and the two queues ("decks") sources go through, both added to its mixer once.
+///
+/// A queue moves on to the next source in the same audio callback the previous one ends in,
+/// which is what makes gapless playback possible.
+/// Crossfading starts the incoming source on the other deck, so both can play at once.
+pub struct Output {
+    /// Without a sink, sources in the decks are never played. Playback stays paused until one can be opened.
+    sink: Option<Sink>,
+    pace: f32,
+    pub decks: [Arc<SourcesQueueInput>; 2],
+}
+
+impl Output {
+    /// Devices fall back to the default one if they can't be opened.
+    pub fn open(target: &OutputTarget, on_error: impl Fn(String)) -> Self {
+        let sink = match target {
+            OutputTarget::Device(device) => open_output_stream(device.as_deref())
+                .or_else(|err| match device {
+                    Some(device) => {
+                        on_error(format!(
+                            "Could not open output device {device:?} ({err}). Using the default device."
+                        ));
+                        open_output_stream(None)
+                    }
+                    None => Err(err),
+                })
+                .map(Sink::Device),
+            OutputTarget::Null { pace } => Ok(Sink::Renderer(Renderer::spawn(None, *pace))),
+            OutputTarget::Wav { path, pace } => {
+                WavWriter::create(path, RENDER_CHANNELS.get(), RENDER_SAMPLE_RATE.get())
+                    .map(|writer| Sink::Renderer(Renderer::spawn(Some(writer), *pace)))
+                    .map_err(|err| format!("{path:?}: {err}"))
+            }
+        }
+        .inspect_err(|err| on_error(format!("Could not open audio output: {err}")))
+        .ok();
+
+        let decks = [queue(true), queue(true)].map(|(deck, deck_output)| {
+            if let Some(sink) = &sink {
+                sink.mixer().add(deck_output);
+            }
+            deck
+        });
+
+        Self {
+            sink,
+            pace: target.pace(),
+            decks,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Stops the output, and drops everything playing through it.
+    pub fn close(&mut self) {
+        self.sink = None;
+    }
+
+    pub fn pace(&self) -> f32 {
+        self.pace
+    }
+}
+
+const RENDER_CHANNELS: ChannelCount = nz!(2);
+const RENDER_SAMPLE_RATE: SampleRate = nz!(44_100);
+/// Frames pulled from the mixer between checks of the clock.
+const RENDER_CHUNK_FRAMES: u64 = 441;
+
+/// Pulls samples out of a mixer on its own thread, as a sound card would, optionally writing them to a file.
+struct Renderer {
+    mixer: Mixer,
+    must_stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Renderer {
+    fn spawn(writer: Option<WavWriter>, pace: f32) -> Self {
+        let (mixer, source) = mixer(RENDER_CHANNELS, RENDER_SAMPLE_RATE);
+        let must_stop = Arc::new(AtomicBool::new(false));
+
+        let thread = thread::Builder::new()
+            .name("renderer".to_string())
+            .spawn({
+                let must_stop = must_stop.clone();
+                move || render(source, writer, pace, &must_stop)
+            })
+            .unwrap();
+
+        Self {
+            mixer,
+            must_stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        self.must_stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            log::error!("renderer thread panicked");
+        }
+    }
+}
+
+fn render(mut source: MixerSource, mut writer: Option<WavWriter>, pace: f32, must_stop: &AtomicBool) {
+    let channels = source.channels().get() as usize;
+    let sample_rate = source.sample_rate().get() as f64;
+    let start = Instant::now();
+    let mut frames: u64 = 0;
+
+    // Silence is only written once something else comes after it.
+    let mut has_started = false;
+    let mut silent_frames: u64 = 0;
+
+    let mut frame = vec![0.0; channels];
+    let silence = vec![0.0; channels];
+
+    while !must_stop.load(Ordering::Acquire) {
+        for _ in 0..RENDER_CHUNK_FRAMES {
+            for sample in frame.iter_mut() {
+                *sample = source.next().unwrap_or(0.0);
+            }
+
+            let Some(writer) = writer.as_mut() else {
+                continue;
+            };
+
+            if frame.iter().all(|sample| *sample == 0.0) {
+                silent_frames += has_started as u64;
+                continue;
+            }
+
+            let result = (0..silent_frames)
+                .try_for_each(|_| writer.write_frame(&silence))
+                .and_then(|_| writer.write_frame(&frame));
+            if let Err(err) = result {
+                log::error!("could not write rendered audio: {err}");
+                return;
+            }
+            has_started = true;
+            silent_frames = 0;
+        }
+
+        frames += RENDER_CHUNK_FRAMES;
+
+        let due = Duration::from_secs_f64(frames as f64 / sample_rate / pace.max(0.01) as f64);
+        if let Some(wait) = due.checked_sub(start.elapsed()) {
+            thread::sleep(wait);
+        }
+    }
+
+    if let Some(writer) = writer
+        && let Err(err) = writer.finish()
+    {
+        log::error!("could not finish writing rendered audio: {err}");
+    }
+}
+
+/// Writes 16 bit PCM WAV files.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    channels: u16,
+    data_len: u32,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, channels: u16, sample_rate: u32) -> std::io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        let block_align = channels * 2;
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?; // patched by `finish`
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?; // PCM
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?; // patched by `finish`
+
+        Ok(Self {
+            file,
+            channels,
+            data_len: 0,
+        })
+    }
+
+    /// One sample per channel.
+    pub fn write_frame(&mut self, frame: &[f32]) -> std::io::Result<()> {
+        debug_assert_eq!(frame.len(), self.channels as usize);
+        for sample in frame {
+            let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len += frame.len() as u32 * 2;
+        Ok(())
+    }
+
+    /// Fills in the lengths the header couldn't know about when it was written.
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_len.to_le_bytes())?;
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use rodio::Decoder;
+
+    use super::*;
+
+    #[test]
+    fn wav_round_trip() {
+        let path = std::env::temp_dir().join(format!("jolteon-wav-round-trip-{}.wav", std::process::id()));
+
+        let mut writer = WavWriter::create(&path, 2, 8_000).unwrap();
+        for i in 0..8_000 {
+            let sample = (i as f32 / 8_000.0) * 2.0 - 1.0;
+            writer.write_frame(&[sample, -sample]).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let decoder = Decoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+        assert_eq!(decoder.channels().get(), 2);
+        assert_eq!(decoder.sample_rate().get(), 8_000);
+        assert_eq!(decoder.total_duration(), Some(Duration::from_secs(1)));
+
+        let samples: Vec<f32> = decoder.collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(samples.len(), 16_000);
+        assert!((samples[0] + 1.0).abs() < 0.001);
+        assert!((samples[1] - 1.0).abs() < 0.001);
+        assert!((samples[8_000] - 0.0).abs() < 0.001);
+    }
+}
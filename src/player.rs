@@ -7,18 +7,30 @@ use std::{
     },
     thread,
     thread::JoinHandle,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use rodio::DeviceSinkBuilder;
-
 use crate::{
     actions::{OnAction, PlayerAction},
+    equalizer::EqualizerPreset,
+    files::CachedLoudness,
     mpris::Mpris,
+    output::{Output, OutputTarget},
+    settings::Settings,
     source::{Controls, Source},
     structs::Song,
 };
 
+/// Bounds of the playback speed, as a percentage.
+pub const MIN_SPEED: u32 = 20;
+pub const MAX_SPEED: u32 = 300;
+
+/// How long a source that should be about to end gets to do so by itself, before it's stopped.
+/// The player thread wakes up when it calculates a song ends, which can be slightly before the source runs out.
+const END_GRACE: Duration = Duration::from_millis(100);
+
+type GaplessTransitionFn = Box<dyn Fn(Song, Song) + Send + 'static>;
+
 pub struct SingleTrackPlayer {
     thread: JoinHandle<()>,
     command_sender: Sender<Command>,
@@ -30,24 +42,100 @@ pub struct SingleTrackPlayer {
     is_paused: Arc<AtomicBool>,
     playing_position: Arc<Mutex<Duration>>,
     volume: Arc<AtomicU32>,
-
-    on_playback_end: Arc<Mutex<Option<Box<dyn Fn(Song) + Send + 'static>>>>,
+    speed: Arc<AtomicU32>,
+    equalizer_presets: Arc<Vec<EqualizerPreset>>,
+    equalizer_preset: Arc<Mutex<Option<usize>>>,
+    output_target: Arc<Mutex<OutputTarget>>,
+    mpris: Option<Arc<Mpris>>,
+
+    on_playback_end: Arc<Mutex<Option<Box<dyn Fn(Song, Duration, bool) + Send + 'static>>>>,
+    on_gapless_transition: Arc<Mutex<Option<GaplessTransitionFn>>>,
     on_error: Arc<Mutex<Option<Box<dyn Fn(String) + Send + 'static>>>>,
 }
 
 #[derive(Debug)]
 enum Command {
     SetSong(Song),
+    SetNextSong(Option<Song>),
     Play,
     Pause,
     Stop,
     Seek(i32),
+    /// Seeks to a position of the current song, relative to its start.
+    SeekTo(Duration),
+    /// Wakes the player thread up, so it recalculates when the current song ends.
+    SpeedChanged,
+    /// Reopens the output on another device. `None` is the system default.
+    SetOutputDevice(Option<String>),
     Quit,
 }
 
+/// Flags shared between the player thread and the periodic access of a single `Source`.
+///
+/// With gapless playback, up to two sources live in the output queue at once: the playing one,
+/// and the one lined up after it. These flags let the player thread address one of them without
+/// affecting the other.
+#[derive(Clone)]
+struct SourceHandle {
+    id: u64,
+    is_current: Arc<AtomicBool>,
+    must_stop: Arc<AtomicBool>,
+    fade: Arc<Mutex<Fade>>,
+}
+
+impl SourceHandle {
+    fn new(id: u64) -> Self {
+        Self {
+            id,
+            is_current: Arc::new(AtomicBool::new(false)),
+            must_stop: Arc::new(AtomicBool::new(false)),
+            fade: Arc::new(Mutex::new(Fade::default())),
+        }
+    }
+}
+
+/// Volume ramps of a source, expressed in terms of its own position, so they follow pauses and seeks.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+struct Fade {
+    /// Position at which the fade-in starts, and its length.
+    fade_in: Option<(Duration, Duration)>,
+    /// Position at which the fade-out ends, and its length.
+    /// The source stops itself once it reaches this position.
+    fade_out: Option<(Duration, Duration)>,
+}
+
+impl Fade {
+    fn gain(&self, pos: Duration) -> f32 {
+        let fade_in = match self.fade_in {
+            Some((start, length)) if !length.is_zero() => {
+                (pos.saturating_sub(start).as_secs_f32() / length.as_secs_f32()).clamp(0.0, 1.0)
+            }
+            _ => 1.0,
+        };
+        let fade_out = match self.fade_out {
+            Some((end, length)) if !length.is_zero() => {
+                (end.saturating_sub(pos).as_secs_f32() / length.as_secs_f32()).clamp(0.0, 1.0)
+            }
+            _ => 1.0,
+        };
+        fade_in * fade_out
+    }
+
+    fn has_faded_out(&self, pos: Duration) -> bool {
+        self.fade_out.is_some_and(|(end, _)| pos >= end)
+    }
+}
+
+/// Whether `next` picks up exactly where `song` ends, in the same file.
+/// This is the case of consecutive tracks of a cue sheet, which can share a single `Source`.
+fn is_contiguous(song: &Song, next: &Song) -> bool {
+    song.path == next.path && song.start_time + song.length == next.start_time
+}
+
 impl SingleTrackPlayer {
-    pub fn spawn(mpris: Option<Arc<Mpris>>) -> Self {
+    pub fn spawn(mpris: Option<Arc<Mpris>>, settings: Settings, output_target: OutputTarget) -> Self {
         let (command_sender, command_receiver) = channel();
+        let crossfade = Duration::from_secs_f32(settings.crossfade_seconds.max(0.0));
 
         let playing_song = Arc::new(Mutex::new(None));
         let playing_song_start_time = Arc::new(AtomicU64::new(0));
@@ -56,29 +144,46 @@ impl SingleTrackPlayer {
         let is_paused = Arc::new(AtomicBool::default());
         let playing_position = Arc::new(Mutex::new(Duration::ZERO));
         let volume = Arc::new(AtomicU32::new(100)); // volume as percentage (0-100)
+        let speed = Arc::new(AtomicU32::new(100)); // playback speed as percentage
+
+        let equalizer_presets = Arc::new(settings.equalizer.presets.clone());
+        let equalizer_preset = Arc::new(Mutex::new(settings.equalizer.preset.as_ref().and_then(|name| {
+            let position = equalizer_presets.iter().position(|preset| &preset.name == name);
+            if position.is_none() {
+                log::warn!("equalizer preset {name:?} not found");
+            }
+            position
+        })));
+        let output_target = Arc::new(Mutex::new(output_target));
 
-        let on_playback_end = Arc::new(Mutex::new(None::<Box<dyn Fn(Song) + Send + 'static>>));
+        let on_playback_end = Arc::new(Mutex::new(None::<Box<dyn Fn(Song, Duration, bool) + Send + 'static>>));
+        let on_gapless_transition = Arc::new(Mutex::new(None::<GaplessTransitionFn>));
         let on_error = Arc::new(Mutex::new(None::<Box<dyn Fn(String) + Send + 'static>>));
 
         let thread = thread::Builder::new()
             .name("single_track_player".to_string())
             .spawn({
                 let on_playback_end = on_playback_end.clone();
+                let on_gapless_transition = on_gapless_transition.clone();
                 let on_error = Arc::clone(&on_error);
                 let mpris = mpris.clone();
                 let currently_playing = playing_song.clone();
                 let currently_playing_start_time = playing_song_start_time.clone();
                 let is_stopped = is_stopped.clone();
                 let volume = volume.clone();
+                let speed = speed.clone();
+                let equalizer_presets = equalizer_presets.clone();
+                let equalizer_preset = equalizer_preset.clone();
+                let output_target = output_target.clone();
                 let pause = is_paused.clone();
                 let position = playing_position.clone();
 
-                let (song_ended_tx, song_ended_rx) = channel::<()>();
-                let must_stop = Arc::new(AtomicBool::new(false));
+                let (song_ended_tx, song_ended_rx) = channel::<u64>();
                 let must_seek = Arc::new(Mutex::new(None));
 
                 let set_currently_playing = {
                     let mpris = mpris.clone();
+                    let pause = pause.clone();
                     move |song: Option<Song>| {
                         let start_time = song
                             .as_ref()
@@ -91,7 +196,11 @@ impl SingleTrackPlayer {
                             match song {
                                 Some(ref song) => {
                                     mpris.set_song(song.clone());
-                                    mpris.play();
+                                    if pause.load(Ordering::SeqCst) {
+                                        mpris.pause();
+                                    } else {
+                                        mpris.play();
+                                    }
                                 }
                                 None => mpris.clear_song(),
                             }
@@ -109,79 +218,177 @@ impl SingleTrackPlayer {
                 };
 
                 move || {
-                    let output_stream = DeviceSinkBuilder::from_default_device()
-                        .unwrap() // TODO: do not unwrap()
-                        .with_error_callback(|err| log::error!("audio stream error: {err}"))
-                        .open_stream()
-                        .unwrap(); // TODO: do not unwrap()
-
-                    let wait_until_song_ends = || {
-                        let target = "::wait_until_song_ends";
-                        log::debug!(target: target, "start");
-                        must_stop.store(true, Ordering::SeqCst);
-
-                        if let Err(err) = song_ended_rx.recv() {
-                            log::error!("ender_recv.recv {err:?}");
-                            return;
+                    let report_error = |error: String| {
+                        on_error.lock().unwrap().as_ref().inspect(|f| f(error));
+                    };
+
+                    // Opened when the first song plays, rather than right away, so errors reach `on_error`.
+                    let mut output: Option<Output> = None;
+                    let mut deck = 0;
+
+                    // Sources get `grace` to end by themselves, and are stopped after that.
+                    let wait_until_source_ends = |handle: &SourceHandle, grace: Duration| {
+                        let target = "::wait_until_source_ends";
+                        log::debug!(target: target, "start {}", handle.id);
+                        let deadline = Instant::now() + grace;
+                        let mut is_stopping = false;
+
+                        loop {
+                            let timeout = deadline.saturating_duration_since(Instant::now());
+                            if !is_stopping && timeout.is_zero() {
+                                handle.must_stop.store(true, Ordering::SeqCst);
+                                is_stopping = true;
+                            }
+
+                            let id = if is_stopping {
+                                song_ended_rx.recv().map_err(RecvTimeoutError::from)
+                            } else {
+                                song_ended_rx.recv_timeout(timeout)
+                            };
+
+                            match id {
+                                Ok(id) if id == handle.id => break,
+                                Ok(id) => {
+                                    // Most likely, a source that faded out.
+                                    log::trace!(target: target, "received end signal of source {id} while waiting for {}", handle.id);
+                                }
+                                Err(RecvTimeoutError::Timeout) => {}
+                                Err(err) => {
+                                    log::error!("song_ended_rx.recv {err:?}");
+                                    return;
+                                }
+                            }
                         }
 
                         log::debug!(target: target, "ender signal received");
 
-                        while song_ended_rx.try_recv().is_ok() {}
-
-                        must_stop.store(false, Ordering::SeqCst);
                         must_seek.lock().unwrap().take();
 
-                        set_currently_playing(None);
-
                         log::debug!(target: target, "done");
                     };
 
-                    let periodic_access = || {
+                    let periodic_access = |handle: &SourceHandle| {
                         let is_stopped = is_stopped.clone();
-                        let must_stop = must_stop.clone();
+                        let is_current = handle.is_current.clone();
+                        let must_stop = handle.must_stop.clone();
+                        let fade = handle.fade.clone();
                         let volume = volume.clone();
+                        let speed = speed.clone();
+                        let equalizer_presets = equalizer_presets.clone();
+                        let equalizer_preset = equalizer_preset.clone();
                         let pause = pause.clone();
+                        let position = position.clone();
                         let must_seek = must_seek.clone();
 
                         move |controls: &mut Controls| {
-                            if must_stop.swap(false, Ordering::SeqCst) {
+                            let is_current = is_current.load(Ordering::Acquire);
+                            let fade = *fade.lock().unwrap();
+                            let pos = controls.pos();
+
+                            if must_stop.swap(false, Ordering::SeqCst) || fade.has_faded_out(pos) {
                                 controls.stop();
                                 controls.skip();
-                                is_stopped.store(true, Ordering::SeqCst);
+                                if is_current {
+                                    *position.lock().unwrap() = Duration::ZERO;
+                                    is_stopped.store(true, Ordering::SeqCst);
+                                }
                                 log::debug!("periodic access stop");
                                 return;
                             }
 
-                            controls.set_volume(volume.load(Ordering::Relaxed) as f32 / 100.);
+                            controls.set_volume(volume.load(Ordering::Relaxed) as f32 / 100. * fade.gain(pos));
                             controls.set_paused(pause.load(Ordering::SeqCst));
+                            controls.set_speed(speed.load(Ordering::Relaxed) as f32 / 100.);
+                            controls.set_equalizer(
+                                equalizer_preset
+                                    .lock()
+                                    .unwrap()
+                                    .and_then(|i| equalizer_presets.get(i)),
+                            );
+
+                            if !is_current {
+                                // This source started playing before the player thread got to promote it to current.
+                                // Leave the shared position and seeks to the source that is still current.
+                                return;
+                            }
 
-                            if let Some(seek) = must_seek.lock().unwrap().take() {
-                                if let Err(err) = controls.seek(seek) {
-                                    log::error!("periodic_access.try_seek() error. {err:?}")
-                                    /*
-                                    TODO(bug):
-                                    `source` drops here, some times. if that happens,
-                                    the song moves to "ended" but the UI isn't updated properly.
-
-                                    Example log:
-                                    ERROR cpal_alsa_out jolteon::player periodic_access.try_seek() error. SymphoniaDecoder(Refining(IoError(Custom { kind: UnexpectedEof, error: "end of stream" })))
-                                    TRACE cpal_alsa_out jolteon::player source.on_playback_ended
-                                    TRACE cpal_alsa_out jolteon::source Source.drop()
-                                    */
-                                }
+                            // While a seek is pending, the position is already where it will land.
+                            let Some(seek) = must_seek.lock().unwrap().take() else {
+                                *position.lock().unwrap() = pos;
+                                return;
+                            };
+
+                            if let Err(err) = controls.seek(seek) {
+                                log::error!("periodic_access.try_seek() error. {err:?}");
+                                /*
+                                TODO(bug):
+                                `source` drops here, some times. if that happens,
+                                the song moves to "ended" but the UI isn't updated properly.
+
+                                Example log:
+                                ERROR cpal_alsa_out jolteon::player periodic_access.try_seek() error. SymphoniaDecoder(Refining(IoError(Custom { kind: UnexpectedEof, error: "end of stream" })))
+                                TRACE cpal_alsa_out jolteon::player source.on_playback_ended
+                                TRACE cpal_alsa_out jolteon::source Source.drop()
+                                */
                             }
                         }
                     };
 
+                    let mut next_source_id: u64 = 0;
+
+                    // How long `song` and `next` should overlap for, if at all. Consecutive tracks of an album are
+                    // never crossfaded: gapless playback matters more for those.
+                    let crossfade_length = |song: &Song, next: &Song| {
+                        if crossfade.is_zero() || is_contiguous(song, next) || song.is_same_album(next) {
+                            None
+                        } else {
+                            Some(crossfade.min(song.length).min(next.length))
+                        }
+                    };
+
+                    let mut loudness = CachedLoudness::default();
+
+                    let mut open_source = |song: &Song| {
+                        let handle = SourceHandle::new(next_source_id);
+                        next_source_id += 1;
+
+                        // Checked for every song, so songs measured by `jolteon analyze` while playing get their gain.
+                        let replay_gain = if song.replay_gain.is_none() {
+                            loudness.get().replay_gain(song).unwrap_or_default()
+                        } else {
+                            song.replay_gain
+                        };
+
+                        let gain = replay_gain.factor(
+                            settings.replay_gain,
+                            settings.replay_gain_preamp,
+                            settings.replay_gain_prevent_clipping,
+                        );
+
+                        let source = Source::from_file(song.path.clone(), gain, periodic_access(&handle), {
+                            let song_ended_tx = song_ended_tx.clone();
+                            let id = handle.id;
+                            move || {
+                                log::trace!("source.on_playback_ended {id}");
+                                let _ = song_ended_tx.send(id);
+                            }
+                        });
+
+                        source.map(|mut source| {
+                            if song.start_time > Duration::ZERO {
+                                log::debug!("start_time > Duration::ZERO, {:?}", song.start_time);
+                                if let Err(err) = source.seek(song.start_time) {
+                                    log::error!("start_time > 0 try_seek() error. {err:?}")
+                                }
+                            }
+                            (source, handle)
+                        })
+                    };
+
                     loop {
-                        let song = loop {
+                        let mut song = loop {
                             match command_receiver.recv() {
                                 Ok(Command::SetSong(song)) => {
-                                    if let Some(mpris) = &mpris {
-                                        mpris.set_song(song.clone());
-                                        mpris.play();
-                                    }
                                     break song;
                                 }
                                 Ok(Command::Quit) => return,
@@ -194,62 +401,111 @@ impl SingleTrackPlayer {
                                     pause.store(false, Ordering::Release);
                                     continue;
                                 }
+                                Ok(Command::SetOutputDevice(device)) => {
+                                    *output_target.lock().unwrap() = OutputTarget::Device(device);
+                                    // Closes the current stream. The next song opens the new one.
+                                    output = None;
+                                    continue;
+                                }
                                 _ => continue,
                             }
                         };
 
-                        let path = song.path.clone();
-                        let start_time = song.start_time;
-                        let length = song.length;
-
-                        let source = Source::from_file(path, periodic_access(), position.clone(), {
-                            let song_ended_tx = song_ended_tx.clone();
-                            move || {
-                                log::trace!("source.on_playback_ended");
-                                let _ = song_ended_tx.send(());
-                            }
-                        });
+                        // Retry opening the output on every song, in case a device came back.
+                        if !output.as_ref().is_some_and(Output::is_open) {
+                            output = Some(Output::open(&output_target.lock().unwrap(), report_error));
+                        }
+                        let output = output.as_mut().unwrap();
+                        if !output.is_open() {
+                            pause.store(true, Ordering::SeqCst);
+                        }
 
-                        let mut source = match source {
+                        let (source, mut handle) = match open_source(&song) {
                             Ok(source) => source,
                             Err(err) => {
-                                on_playback_end.lock().unwrap().as_ref().inspect(|f| f(song));
-                                on_error.lock().unwrap().as_ref().inspect(|f| f(err));
+                                on_playback_end
+                                    .lock()
+                                    .unwrap()
+                                    .as_ref()
+                                    .inspect(|f| f(song, Duration::ZERO, false));
+                                report_error(err);
                                 continue;
                             }
                         };
 
+                        handle.is_current.store(true, Ordering::Release);
                         is_stopped.store(false, Ordering::SeqCst);
 
                         set_currently_playing(Some(song.clone()));
 
-                        if start_time > Duration::ZERO {
-                            log::debug!("start_time > Duration::ZERO, {start_time:?}");
-                            if let Err(err) = source.seek(start_time) {
-                                log::error!("start_time > 0 try_seek() error. {err:?}")
-                            }
-                        }
+                        *position.lock().unwrap() = song.start_time;
+
+                        log::debug!("source_queue.append()");
+                        // The queue is tied to the CPAL thread, which starts consuming the source automatically.
+                        output.decks[deck].clear();
+                        output.decks[deck].append(source);
+
+                        // The song that should play after the current one, as told by whoever owns the queue.
+                        let mut next_song: Option<Song> = None;
 
-                        *position.lock().unwrap() = start_time;
+                        // The source lined up after the current one, if any, and the song it plays.
+                        // Sources that will be crossfaded into are held here until the crossfade starts,
+                        // rather than appended to the current deck.
+                        let mut preloaded: Option<(Song, SourceHandle, Option<Source<_>>)> = None;
 
-                        log::debug!("output_stream.mixer().add()");
-                        // Mixer is tied to the CPAL thread, which starts consuming the source automatically.
-                        output_stream.mixer().add(source);
+                        // The previous source, while it fades out.
+                        let mut fading_out: Option<SourceHandle> = None;
 
                         // Start looping until the current song ends OR something wakes us up.
                         // When woken up, we check whether we need to immediately exit.
                         // If we don't, we recalculate the remaining time until the song ends,
                         // and then go back to bed.
-                        loop {
+                        let has_ended = loop {
+                            if preloaded.is_none()
+                                && let Some(next) = next_song.as_ref()
+                                && !is_contiguous(&song, next)
+                            {
+                                match open_source(next) {
+                                    Ok((source, next_handle)) => {
+                                        log::debug!("preloaded next song {:?}", next.title);
+                                        let source = if crossfade_length(&song, next).is_some() {
+                                            Some(source)
+                                        } else {
+                                            output.decks[deck].append(source);
+                                            None
+                                        };
+                                        preloaded = Some((next.clone(), next_handle, source));
+                                    }
+                                    Err(err) => {
+                                        // Not much to do here. Once the current song ends, the next one will be
+                                        // played the usual way, and the error will be reported then.
+                                        log::warn!("could not preload next song {:?}: {err}", next.path);
+                                        next_song = None;
+                                    }
+                                }
+                            }
+
+                            let start_time = song.start_time;
+                            let length = song.length;
+
+                            // When crossfading, the next song starts this long before the current one ends.
+                            let overlap = match &preloaded {
+                                Some((next, _, Some(_))) => crossfade_length(&song, next).unwrap_or_default(),
+                                _ => Duration::ZERO,
+                            };
+
                             let sleepy_time = if pause.load(Ordering::SeqCst) {
                                 Duration::MAX
                             } else {
                                 let abs_pos = position.lock().unwrap().saturating_sub(start_time);
                                 if abs_pos >= length {
                                     log::debug!("inner loop: pos >= length, {abs_pos:?} > {length:?}");
-                                    break;
+                                    break true;
                                 }
-                                length - abs_pos
+                                // Positions are in terms of the file, so they pass faster or slower than real time.
+                                // So does everything, with outputs that aren't paced in real time.
+                                let speed = speed.load(Ordering::Relaxed) as f32 / 100.;
+                                (length - abs_pos).saturating_sub(overlap).div_f32(speed * output.pace())
                             };
 
                             // log::debug!("inner loop: sleepy_time! {:?}", sleepy_time);
@@ -257,10 +513,24 @@ impl SingleTrackPlayer {
                             match command_receiver.recv_timeout(sleepy_time) {
                                 Ok(command) => {
                                     log::debug!("Player.Command({command:?})");
+                                    let mut reopen_output = false;
+                                    let mut seek_target = None;
                                     match command {
                                         Command::SetSong(song) => {
                                             log::error!("oops! received SetSong while playing! {song:?}");
                                         }
+                                        Command::SetNextSong(song) => {
+                                            if song == next_song {
+                                                continue;
+                                            }
+                                            next_song = song;
+                                            // The preloaded source is behind the current one in the deck, and
+                                            // clearing the deck would drop the current one too. Stopped sources end
+                                            // on their first sample instead.
+                                            if let Some((_, preloaded, None)) = preloaded.take() {
+                                                preloaded.must_stop.store(true, Ordering::SeqCst);
+                                            }
+                                        }
                                         Command::Quit => {
                                             log::trace!("Player: quitting main loop");
                                             return;
@@ -270,6 +540,7 @@ impl SingleTrackPlayer {
                                             if let Some(mpris) = &mpris {
                                                 mpris.play();
                                             }
+                                            reopen_output = !output.is_open();
                                         }
                                         Command::Pause => {
                                             pause.store(true, Ordering::SeqCst);
@@ -278,7 +549,12 @@ impl SingleTrackPlayer {
                                             }
                                         }
                                         Command::Stop => {
-                                            break;
+                                            break false;
+                                        }
+                                        Command::SpeedChanged => {}
+                                        Command::SetOutputDevice(device) => {
+                                            *output_target.lock().unwrap() = OutputTarget::Device(device);
+                                            reopen_output = true;
                                         }
                                         Command::Seek(seek) => {
                                             // NOTE: "intense" seek causes `ALSA lib pcm.c:8740:(snd_pcm_recover) underrun occurred`.
@@ -289,35 +565,78 @@ impl SingleTrackPlayer {
                                                 continue;
                                             }
 
-                                            if is_stopped.load(Ordering::SeqCst) || must_stop.load(Ordering::SeqCst) {
-                                                continue;
-                                            }
-
                                             let seek_abs = Duration::from_secs(seek.unsigned_abs() as u64);
-                                            let mut pos = position.lock().unwrap();
+                                            let pos = *position.lock().unwrap();
 
-                                            let target = if seek > 0 {
+                                            seek_target = Some(if seek > 0 {
                                                 pos.saturating_add(seek_abs)
                                             } else {
                                                 pos.saturating_sub(seek_abs).max(start_time)
-                                            };
+                                            });
+                                        }
+                                        Command::SeekTo(target) => {
+                                            seek_target = Some(start_time + target);
+                                        }
+                                    }
+
+                                    if let Some(target) = seek_target
+                                        && !is_stopped.load(Ordering::SeqCst)
+                                        && !handle.must_stop.load(Ordering::SeqCst)
+                                    {
+                                        // If we'd seek past song end, skip seeking and just move to next song instead.
+                                        if target > length + start_time {
+                                            log::debug!("Seeking past end");
+                                            handle.must_stop.store(true, Ordering::SeqCst);
+                                            break true;
+                                        }
 
-                                            // If we'd seek past song end, skip seeking and just move to next song instead.
-                                            if target > length + start_time {
-                                                log::debug!("Seeking past end");
-                                                break;
+                                        log::debug!("Seek({target:?})");
+                                        *must_seek.lock().unwrap() = Some(target);
+                                        // optimistic update, otherwise sleepy_time will be off
+                                        *position.lock().unwrap() = target;
+
+                                        if let Some(mpris) = &mpris {
+                                            mpris.seeked(target.saturating_sub(start_time));
+                                        }
+                                    }
+
+                                    if reopen_output {
+                                        // Everything playing goes away along with the old stream.
+                                        // The current song is opened again on the new one, where it left off.
+                                        // Some devices can't be opened twice, so the old stream is closed first.
+                                        output.close();
+                                        *output = Output::open(&output_target.lock().unwrap(), report_error);
+                                        deck = 0;
+                                        preloaded = None;
+                                        fading_out = None;
+                                        must_seek.lock().unwrap().take();
+
+                                        let (mut source, new_handle) = match open_source(&song) {
+                                            Ok(source) => source,
+                                            Err(err) => {
+                                                report_error(err);
+                                                // The old source went away with the old stream, and won't signal its end.
+                                                let _ = song_ended_tx.send(handle.id);
+                                                break true;
                                             }
+                                        };
+                                        let pos = *position.lock().unwrap();
+                                        if let Err(err) = source.seek(pos) {
+                                            log::error!("reopen_output try_seek() error. {err:?}")
+                                        }
+                                        new_handle.is_current.store(true, Ordering::Release);
+                                        handle = new_handle;
+                                        output.decks[deck].append(source);
 
-                                            log::debug!("Seek({target:?})");
-                                            *must_seek.lock().unwrap() = Some(target);
-                                            *pos = target; // optimistic update, otherwise sleepy_time will be off
+                                        if !output.is_open() {
+                                            pause.store(true, Ordering::SeqCst);
                                         }
                                     }
+                                    continue;
                                 }
                                 Err(RecvTimeoutError::Timeout) => {
                                     // Playing song reached its end. We want to move on to the next song.
                                     log::trace!("Player Command Timeout");
-                                    break;
                                 }
                                 Err(RecvTimeoutError::Disconnected) => {
                                     // Most of the time, not a real error. This can happen because the command_sender was dropped,
@@ -326,13 +645,86 @@ impl SingleTrackPlayer {
                                     return;
                                 }
                             }
-                        }
+
+                            // The current song reached its end, or the point where the next one should start fading in.
+                            // If we know what comes next, move on to it without stopping the output,
+                            // and go back to waiting.
+                            let next = match (next_song.take(), preloaded.take()) {
+                                (Some(next), _) if is_contiguous(&song, &next) => {
+                                    log::debug!("gapless: {:?} continues in the same source", next.title);
+                                    next
+                                }
+                                (_, Some((next, next_handle, Some(source)))) => {
+                                    log::debug!("crossfade: fading into {:?} for {overlap:?}", next.title);
+                                    handle.fade.lock().unwrap().fade_out = Some((start_time + length, overlap));
+                                    next_handle.fade.lock().unwrap().fade_in = Some((next.start_time, overlap));
+                                    deck = 1 - deck;
+                                    output.decks[deck].append(source);
+                                    handle.is_current.store(false, Ordering::Release);
+                                    next_handle.is_current.store(true, Ordering::Release);
+                                    if let Some(previous) = fading_out.replace(std::mem::replace(&mut handle, next_handle)) {
+                                        previous.must_stop.store(true, Ordering::SeqCst);
+                                    }
+                                    is_stopped.store(false, Ordering::SeqCst);
+                                    *position.lock().unwrap() = next.start_time;
+                                    next
+                                }
+                                (_, Some((next, next_handle, None))) => {
+                                    log::debug!("gapless: moving on to preloaded source of {:?}", next.title);
+                                    wait_until_source_ends(&handle, END_GRACE);
+                                    handle.is_current.store(false, Ordering::Release);
+                                    next_handle.is_current.store(true, Ordering::Release);
+                                    handle = next_handle;
+                                    is_stopped.store(false, Ordering::SeqCst);
+                                    *position.lock().unwrap() = next.start_time;
+                                    next
+                                }
+                                _ => break true,
+                            };
+
+                            let previous = std::mem::replace(&mut song, next);
+                            set_currently_playing(Some(song.clone()));
+                            on_gapless_transition
+                                .lock()
+                                .unwrap()
+                                .as_ref()
+                                .inspect(|f| f(previous, song.clone()));
+                        };
+
+                        log::debug!("playback of {:?} done. has_ended: {has_ended}", song.title);
 
                         // while command_receiver.try_recv().is_ok() {} // "drain" the command queue - dropping everything that might have accumulated.
 
-                        wait_until_song_ends();
+                        // The preloaded source is behind the current one in the deck. Clearing the deck would drop the
+                        // current source too, if the output hasn't started pulling it yet, and its end would never be
+                        // signalled. Stopped sources end on their first sample instead.
+                        if let Some((_, preloaded, None)) = preloaded.take() {
+                            preloaded.must_stop.store(true, Ordering::SeqCst);
+                        }
 
-                        on_playback_end.lock().unwrap().as_ref().inspect(|f| f(song));
+                        if let Some(previous) = fading_out.take() {
+                            previous.must_stop.store(true, Ordering::SeqCst);
+                        }
+
+                        if output.is_open() {
+                            wait_until_source_ends(&handle, if has_ended { END_GRACE } else { Duration::ZERO });
+                        } else {
+                            // Nothing will ever consume the source.
+                            must_seek.lock().unwrap().take();
+                        }
+                        set_currently_playing(None);
+
+                        // The position is only updated every so often, so it can fall a little short of the end.
+                        let played = if has_ended {
+                            song.length
+                        } else {
+                            position.lock().unwrap().saturating_sub(song.start_time).min(song.length)
+                        };
+                        on_playback_end
+                            .lock()
+                            .unwrap()
+                            .as_ref()
+                            .inspect(|f| f(song, played, has_ended));
                     }
                 }
             })
@@ -349,8 +741,14 @@ impl SingleTrackPlayer {
             is_paused,
             playing_position,
             volume,
+            speed,
+            equalizer_presets,
+            equalizer_preset,
+            output_target,
+            mpris,
 
             on_playback_end,
+            on_gapless_transition,
             on_error,
         }
     }
@@ -365,10 +763,18 @@ impl SingleTrackPlayer {
         pos.saturating_sub(Duration::from_secs(start_time))
     }
 
-    pub fn on_playback_end(&self, f: impl Fn(Song) + Send + 'static) {
+    /// Receives the song that stopped playing, how far into it playback got,
+    /// and whether it played to its end, rather than being stopped.
+    pub fn on_playback_end(&self, f: impl Fn(Song, Duration, bool) + Send + 'static) {
         *self.on_playback_end.lock().unwrap() = Some(Box::new(f));
     }
 
+    /// Called when the player moves on to the song set with `set_next_song` by itself, without a gap,
+    /// instead of stopping. Receives the song that ended and the one that started.
+    pub fn on_gapless_transition(&self, f: impl Fn(Song, Song) + Send + 'static) {
+        *self.on_gapless_transition.lock().unwrap() = Some(Box::new(f));
+    }
+
     pub fn on_error(&self, f: impl Fn(String) + Send + 'static) {
         *self.on_error.lock().unwrap() = Some(Box::new(f));
     }
@@ -398,6 +804,12 @@ impl SingleTrackPlayer {
         self.send_command(Command::SetSong(song));
     }
 
+    /// Lets the player know which song comes after the current one, so it can be lined up ahead of time
+    /// and played back without a gap.
+    pub fn set_next_song(&self, song: Option<Song>) {
+        self.send_command(Command::SetNextSong(song));
+    }
+
     pub fn is_paused(&self) -> bool {
         self.is_paused.load(Ordering::Acquire)
     }
@@ -433,10 +845,19 @@ impl SingleTrackPlayer {
         self.seek(-5);
     }
 
+    /// Seeks to a position of the playing song, relative to its start. Positions past its end move on to the next song.
+    pub fn seek_to(&self, position: Duration) {
+        if self.is_stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        self.send_command(Command::SeekTo(position));
+    }
+
     pub fn change_volume(&self, amount: i32) {
         let volume = self.volume.load(Ordering::Relaxed);
         let volume = (volume as i32 + amount).clamp(0, 100) as u32;
         self.volume.store(volume, Ordering::Relaxed);
+        self.notify_volume_changed();
     }
 
     pub fn get_volume(&self) -> u32 {
@@ -445,6 +866,68 @@ impl SingleTrackPlayer {
 
     pub fn set_volume(&self, amount: f32) {
         self.volume.store((amount * 100.) as u32, Ordering::Relaxed);
+        self.notify_volume_changed();
+    }
+
+    fn notify_volume_changed(&self) {
+        if let Some(mpris) = &self.mpris {
+            mpris.set_volume(self.get_volume() as f64 / 100.);
+        }
+    }
+
+    pub fn change_speed(&self, amount: i32) {
+        let speed = self.speed.load(Ordering::Relaxed);
+        let speed = (speed as i32 + amount).clamp(MIN_SPEED as i32, MAX_SPEED as i32) as u32;
+        self.set_speed(speed);
+    }
+
+    /// Playback speed as a percentage. 100 is normal speed.
+    pub fn get_speed(&self) -> u32 {
+        self.speed.load(Ordering::Relaxed)
+    }
+
+    pub fn set_speed(&self, speed: u32) {
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.speed.store(speed, Ordering::Relaxed);
+        self.send_command(Command::SpeedChanged);
+        if let Some(mpris) = &self.mpris {
+            mpris.set_rate(speed as f64 / 100.);
+        }
+    }
+
+    /// Name of the active equalizer preset, if any.
+    pub fn equalizer_preset(&self) -> Option<String> {
+        self.equalizer_preset
+            .lock()
+            .unwrap()
+            .and_then(|i| self.equalizer_presets.get(i))
+            .map(|preset| preset.name.clone())
+    }
+
+    /// Name of the output device in use, as configured. None is the system default, or not a device at all.
+    pub fn output_device(&self) -> Option<String> {
+        match &*self.output_target.lock().unwrap() {
+            OutputTarget::Device(device) => device.clone(),
+            _ => None,
+        }
+    }
+
+    /// Switches to another output device. The current song carries on from where it was.
+    pub fn set_output_device(&self, device: Option<String>) {
+        self.send_command(Command::SetOutputDevice(device));
+    }
+
+    /// Moves through the equalizer presets, in the order they're configured in, with "off" between the last
+    /// and the first one.
+    pub fn cycle_equalizer_preset(&self, forwards: bool) {
+        let count = self.equalizer_presets.len();
+        let mut preset = self.equalizer_preset.lock().unwrap();
+        *preset = match (*preset, forwards) {
+            (None, true) => (count > 0).then_some(0),
+            (None, false) => count.checked_sub(1),
+            (Some(i), true) => (i + 1 < count).then_some(i + 1),
+            (Some(i), false) => i.checked_sub(1),
+        };
     }
 }
 
@@ -469,7 +952,82 @@ impl OnAction<PlayerAction> for SingleTrackPlayer {
             PlayerAction::SeekBackwards => {
                 self.seek_backward();
             }
+            PlayerAction::SpeedUp => {
+                self.change_speed(10);
+            }
+            PlayerAction::SpeedDown => {
+                self.change_speed(-10);
+            }
+            PlayerAction::SpeedReset => {
+                self.set_speed(100);
+            }
+            PlayerAction::EqualizerNext => {
+                self.cycle_equalizer_preset(true);
+            }
+            PlayerAction::EqualizerPrevious => {
+                self.cycle_equalizer_preset(false);
+            }
             _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fade_gain() {
+        let secs = Duration::from_secs;
+        let fade = Fade {
+            fade_in: Some((secs(10), secs(4))),
+            fade_out: None,
+        };
+        assert_eq!(fade.gain(secs(5)), 0.0);
+        assert_eq!(fade.gain(secs(12)), 0.5);
+        assert_eq!(fade.gain(secs(20)), 1.0);
+
+        let fade = Fade {
+            fade_in: None,
+            fade_out: Some((secs(100), secs(10))),
+        };
+        assert_eq!(fade.gain(secs(50)), 1.0);
+        assert_eq!(fade.gain(secs(95)), 0.5);
+        assert_eq!(fade.gain(secs(100)), 0.0);
+        assert!(!fade.has_faded_out(secs(99)));
+        assert!(fade.has_faded_out(secs(100)));
+    }
+
+    #[test]
+    fn same_album_across_track_artists() {
+        let song = |path: &str, artist: &str, album_artist: Option<&str>| Song {
+            library_id: None,
+            path: path.into(),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(180),
+            title: "Title".to_string(),
+            artist: Some(artist.to_string()),
+            album: Some("Pulp Fiction".to_string()),
+            album_artist: album_artist.map(str::to_string),
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: None,
+            replay_gain: Default::default(),
+        };
+
+        let a = song("/music/pf/01.flac", "Dick Dale", Some("Various Artists"));
+        let b = song("/music/pf/02.flac", "Kool & the Gang", Some("Various Artists"));
+        assert!(a.is_same_album(&b));
+
+        let b = song("/music/pf/02.flac", "Kool & the Gang", Some("Someone Else"));
+        assert!(!a.is_same_album(&b));
+
+        let a = song("/music/pf/01.flac", "Dick Dale", None);
+        let b = song("/music/pf/02.flac", "Kool & the Gang", None);
+        assert!(a.is_same_album(&b));
+
+        let b = song("/music/other/02.flac", "Kool & the Gang", None);
+        assert!(!a.is_same_album(&b));
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    components::{saved_library_songs, saved_soundtrack_songs},
+    files::PlayHistory,
+    structs::Song,
+};
+
+/// Songs played this recently aren't suggested, unless there's nothing else.
+const RECENT_PLAYS: usize = 50;
+
+/// Songs whose years are this close count as the same era.
+const ERA_YEARS: u32 = 5;
+
+/// Plays further apart than this are taken as different listening sessions.
+const SESSION_GAP: Duration = Duration::from_secs(30 * 60);
+
+/// How many songs away, in a listening session, two songs still count as played together.
+const CO_LISTENING_WINDOW: usize = 3;
+
+/// How radio mode picks the songs that keep playing after the queue ends.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RadioEngine {
+    /// Any song.
+    Random,
+    /// Songs by the same artist, or from around the same years.
+    Similar,
+    /// Songs that have been played along with the last one before, going by the play history.
+    #[default]
+    CoListening,
+}
+
+/// Picks a song to play after `seed`, out of `candidates`.
+pub trait SuggestionEngine {
+    /// The index of the suggested song in `candidates`, or `None` if the engine has nothing to go on.
+    fn suggest(&self, seed: &Song, candidates: &[&Song], rng: &mut fastrand::Rng) -> Option<usize>;
+}
+
+pub struct RandomSuggestions;
+
+impl SuggestionEngine for RandomSuggestions {
+    fn suggest(&self, _seed: &Song, candidates: &[&Song], rng: &mut fastrand::Rng) -> Option<usize> {
+        (!candidates.is_empty()).then(|| rng.usize(..candidates.len()))
+    }
+}
+
+pub struct SimilarSuggestions;
+
+impl SuggestionEngine for SimilarSuggestions {
+    fn suggest(&self, seed: &Song, candidates: &[&Song], rng: &mut fastrand::Rng) -> Option<usize> {
+        let weights = candidates.iter().map(|song| {
+            let same_artist = seed.artist.is_some() && song.artist == seed.artist;
+            let same_era = seed
+                .year
+                .zip(song.year)
+                .is_some_and(|(a, b)| a.abs_diff(b) <= ERA_YEARS);
+            4.0 * f32::from(u8::from(same_artist)) + f32::from(u8::from(same_era))
+        });
+        pick_weighted(weights, rng)
+    }
+}
+
+/// Songs are close to each other when they tend to be played one shortly after the other.
+#[derive(Debug, Default)]
+pub struct CoListeningSuggestions {
+    proximity: HashMap<Uuid, HashMap<Uuid, f32>>,
+}
+
+impl CoListeningSuggestions {
+    /// Songs skipped before they were half done don't count as listened to.
+    pub fn learn(history: &PlayHistory) -> Self {
+        let mut proximity: HashMap<Uuid, HashMap<Uuid, f32>> = HashMap::new();
+
+        let listened: Vec<_> = history.events.iter().filter(|event| event.is_counted()).collect();
+
+        let mut sessions = vec![];
+        let mut session = vec![];
+        for (index, event) in listened.iter().enumerate() {
+            let is_new_session = index > 0
+                && event
+                    .time
+                    .duration_since(listened[index - 1].time)
+                    .is_ok_and(|gap| gap > SESSION_GAP);
+            if is_new_session {
+                sessions.push(std::mem::take(&mut session));
+            }
+            session.push(event.library_id);
+        }
+        sessions.push(session);
+
+        for session in sessions {
+            for (i, a) in session.iter().enumerate() {
+                for (distance, b) in session.iter().skip(i + 1).take(CO_LISTENING_WINDOW).enumerate() {
+                    if a == b {
+                        continue;
+                    }
+                    let weight = 1.0 / (distance + 1) as f32;
+                    *proximity.entry(*a).or_default().entry(*b).or_default() += weight;
+                    *proximity.entry(*b).or_default().entry(*a).or_default() += weight;
+                }
+            }
+        }
+
+        Self { proximity }
+    }
+}
+
+impl SuggestionEngine for CoListeningSuggestions {
+    fn suggest(&self, seed: &Song, candidates: &[&Song], rng: &mut fastrand::Rng) -> Option<usize> {
+        let neighbors = self.proximity.get(&seed.library_id?)?;
+        let weights = candidates.iter().map(|song| {
+            song.library_id
+                .and_then(|id| neighbors.get(&id))
+                .copied()
+                .unwrap_or_default()
+        });
+        pick_weighted(weights, rng)
+    }
+}
+
+/// A random index, more likely the higher its weight. `None` if no weight is above 0.
+fn pick_weighted(weights: impl Iterator<Item = f32>, rng: &mut fastrand::Rng) -> Option<usize> {
+    let weights: Vec<f32> = weights.collect();
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        return None;
+    }
+    let mut target = rng.f32() * total;
+    for (index, weight) in weights.iter().enumerate() {
+        if *weight > 0.0 && target < *weight {
+            return Some(index);
+        }
+        target -= weight;
+    }
+    weights.iter().rposition(|weight| *weight > 0.0)
+}
+
+/// A song from `library` to play after `seed`, that hasn't been played recently if possible.
+/// Engines that have nothing to go on fall back to simpler ones, down to `RadioEngine::Random`.
+pub fn suggest(
+    engine: RadioEngine,
+    seed: &Song,
+    library: &[Song],
+    history: &PlayHistory,
+    rng: &mut fastrand::Rng,
+) -> Option<Song> {
+    let recent: HashSet<Uuid> = history
+        .events
+        .iter()
+        .rev()
+        .take(RECENT_PLAYS)
+        .map(|event| event.library_id)
+        .collect();
+    let is_recent = |song: &Song| song == seed || song.library_id.is_some_and(|id| recent.contains(&id));
+
+    let mut candidates: Vec<&Song> = library.iter().filter(|song| !is_recent(song)).collect();
+    if candidates.is_empty() {
+        candidates = library.iter().filter(|song| *song != seed).collect();
+    }
+
+    let co_listening;
+    let mut engines: Vec<&dyn SuggestionEngine> = vec![];
+    if engine == RadioEngine::CoListening {
+        co_listening = CoListeningSuggestions::learn(history);
+        engines.push(&co_listening);
+    }
+    if engine != RadioEngine::Random {
+        engines.push(&SimilarSuggestions);
+    }
+    engines.push(&RandomSuggestions);
+
+    engines
+        .into_iter()
+        .find_map(|engine| engine.suggest(seed, &candidates, rng))
+        .map(|index| candidates[index].clone())
+}
+
+/// Like `suggest`, with the Library, Soundtracks and play history as they were last saved.
+pub fn suggest_from_saved(engine: RadioEngine, seed: &Song, rng: &mut fastrand::Rng) -> Option<Song> {
+    let mut library = saved_library_songs();
+    library.extend(saved_soundtrack_songs());
+    suggest(engine, seed, &library, &PlayHistory::from_file(), rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::PathBuf, time::SystemTime};
+
+    use super::*;
+    use crate::files::PlayEvent;
+
+    fn song(title: &str, artist: &str, year: u32) -> Song {
+        Song {
+            library_id: Some(Uuid::new_v4()),
+            path: PathBuf::from(format!("/music/{artist}/{title}.flac")),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(300),
+            title: title.to_string(),
+            artist: Some(artist.to_string()),
+            album: None,
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: Some(year),
+            replay_gain: Default::default(),
+        }
+    }
+
+    fn history(plays: &[(&Song, u64)]) -> PlayHistory {
+        let start = SystemTime::now() - Duration::from_secs(24 * 60 * 60);
+        PlayHistory {
+            events: plays
+                .iter()
+                .map(|(song, minutes)| PlayEvent {
+                    time: start + Duration::from_secs(minutes * 60),
+                    library_id: song.library_id.unwrap(),
+                    completion: 1.0,
+                    skipped: false,
+                })
+                .collect(),
+        }
+    }
+
+    fn suggestions(engine: RadioEngine, seed: &Song, library: &[Song], history: &PlayHistory) -> HashSet<String> {
+        let mut rng = fastrand::Rng::with_seed(3);
+        (0..100)
+            .map(|_| suggest(engine, seed, library, history, &mut rng).unwrap().title)
+            .collect()
+    }
+
+    #[test]
+    fn suggests_similar_songs() {
+        let library = vec![
+            song("Red", "King Crimson", 1974),
+            song("Starless", "King Crimson", 1974),
+            song("Roundabout", "Yes", 1971),
+            song("Teardrop", "Massive Attack", 1998),
+        ];
+        let none = PlayHistory::default();
+
+        let similar = suggestions(RadioEngine::Similar, &library[0], &library, &none);
+        assert_eq!(similar, ["Starless", "Roundabout"].map(String::from).into());
+
+        // Nothing similar to go on.
+        let fallback = suggestions(RadioEngine::Similar, &song("Intro", "Nobody", 1900), &library, &none);
+        assert_eq!(fallback.len(), 4);
+
+        // Recently played songs are left out, while there's anything else.
+        let played = history(&[(&library[1], 0)]);
+        let similar = suggestions(RadioEngine::Similar, &library[0], &library, &played);
+        assert_eq!(similar, ["Roundabout"].map(String::from).into());
+    }
+
+    #[test]
+    fn learns_songs_played_together() {
+        let library: Vec<Song> = (0..6)
+            .map(|i| song(&format!("Song {i}"), &format!("Artist {i}"), 1950))
+            .collect();
+        let played = history(&[
+            (&library[0], 0),
+            (&library[1], 5),
+            // A new session starts after a long break.
+            (&library[2], 100),
+            (&library[3], 105),
+            (&library[0], 110),
+        ]);
+
+        let engine = CoListeningSuggestions::learn(&played);
+        let neighbors = |song: &Song| {
+            let mut neighbors: Vec<String> = engine.proximity[&song.library_id.unwrap()]
+                .keys()
+                .map(|id| {
+                    library
+                        .iter()
+                        .find(|song| song.library_id == Some(*id))
+                        .unwrap()
+                        .title
+                        .clone()
+                })
+                .collect();
+            neighbors.sort();
+            neighbors
+        };
+        assert_eq!(neighbors(&library[0]), ["Song 1", "Song 2", "Song 3"]);
+        assert_eq!(neighbors(&library[1]), ["Song 0"]);
+
+        // Every neighbor was played recently, so there's nothing to suggest but the songs never played.
+        let suggested = suggestions(RadioEngine::CoListening, &library[1], &library, &played);
+        assert_eq!(suggested, ["Song 4", "Song 5"].map(String::from).into());
+
+        let mut rng = fastrand::Rng::with_seed(3);
+        let candidates: Vec<&Song> = library.iter().collect();
+        let picked: HashSet<usize> = (0..100)
+            .filter_map(|_| engine.suggest(&library[3], &candidates, &mut rng))
+            .collect();
+        assert_eq!(picked, [0, 2].into());
+        assert_eq!(engine.suggest(&library[4], &candidates, &mut rng), None);
+    }
+}
@@ -0,0 +1,13 @@
+mod lastfm;
+mod listenbrainz;
+#[cfg(test)]
+mod mock_server;
+mod pending;
+mod scrobbler;
+mod service;
+
+pub use lastfm::*;
+pub use listenbrainz::*;
+pub use pending::*;
+pub use scrobbler::*;
+pub use service::*;
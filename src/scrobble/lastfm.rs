@@ -0,0 +1,295 @@
+use std::time::Duration;
+
+use md5::{Digest, Md5};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{Scrobble, ScrobbleError, Scrobbler};
+
+pub static LASTFM_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+pub static LASTFM_AUTH_URL: &str = "https://www.last.fm/api/auth/";
+
+/// Error codes of failures that may go away on their own, or once the API key, secret or session key are fixed.
+/// See https://www.last.fm/api/errorcodes.
+const RETRY_ERROR_CODES: [u64; 8] = [8, 9, 10, 11, 13, 16, 26, 29];
+
+/// Last.fm, or any service with the same API, like Libre.fm. Needs an API account's key and secret, and a session key
+/// from `jolteon lastfm-login`.
+pub struct LastFm {
+    client: Client,
+    api_url: String,
+    api_key: String,
+    api_secret: String,
+    session_key: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct SessionResponse {
+    session: Session,
+}
+
+#[derive(Deserialize)]
+struct Session {
+    key: String,
+}
+
+/// Every call is signed with the MD5 of its parameters, sorted by name, and the API secret.
+fn signature(params: &[(String, String)], api_secret: &str) -> String {
+    let mut params: Vec<&(String, String)> = params.iter().collect();
+    params.sort();
+    let mut hasher = Md5::new();
+    for (name, value) in params {
+        hasher.update(name);
+        hasher.update(value);
+    }
+    hasher.update(api_secret);
+    hasher.finalize().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Calls `method`, signed, and returns the response's JSON.
+fn call(
+    client: &Client,
+    api_url: &str,
+    api_key: &str,
+    api_secret: &str,
+    method: &str,
+    mut params: Vec<(String, String)>,
+) -> Result<Value, ScrobbleError> {
+    params.push(("method".to_string(), method.to_string()));
+    params.push(("api_key".to_string(), api_key.to_string()));
+    let api_sig = signature(&params, api_secret);
+    params.push(("api_sig".to_string(), api_sig));
+    params.push(("format".to_string(), "json".to_string()));
+
+    let response = client
+        .post(api_url)
+        .header("User-Agent", "jolteon")
+        .timeout(Duration::from_secs(10))
+        .form(&params)
+        .send()?;
+    let status = response.status();
+    let body: Value = match response.json() {
+        Ok(body) => body,
+        Err(err) if status.is_success() => return Err(err.into()),
+        Err(_) => {
+            return Err(ScrobbleError::Service {
+                message: status.to_string(),
+                can_retry: status.is_server_error() || status.as_u16() == 429,
+            });
+        }
+    };
+
+    if let Some(code) = body["error"].as_u64() {
+        return Err(ScrobbleError::Service {
+            message: format!("Error {code}: {}", body["message"].as_str().unwrap_or_default()),
+            can_retry: RETRY_ERROR_CODES.contains(&code),
+        });
+    }
+    Ok(body)
+}
+
+impl LastFm {
+    pub fn new(api_url: &str, api_key: &str, api_secret: &str, session_key: &str) -> Self {
+        Self {
+            client: Client::new(),
+            api_url: api_url.to_string(),
+            api_key: api_key.to_string(),
+            api_secret: api_secret.to_string(),
+            session_key: session_key.to_string(),
+        }
+    }
+
+    fn call(&self, method: &str, mut params: Vec<(String, String)>) -> Result<Value, ScrobbleError> {
+        params.push(("sk".to_string(), self.session_key.clone()));
+        call(
+            &self.client,
+            &self.api_url,
+            &self.api_key,
+            &self.api_secret,
+            method,
+            params,
+        )
+    }
+}
+
+/// The parameters for one track. Scrobbles of several tracks number them, like `artist[0]`, `artist[1]`.
+fn track_params(scrobble: &Scrobble, index: Option<usize>) -> Vec<(String, String)> {
+    let name = |name: &str| match index {
+        Some(index) => format!("{name}[{index}]"),
+        None => name.to_string(),
+    };
+    let mut params = vec![
+        (name("artist"), scrobble.artist.clone()),
+        (name("track"), scrobble.title.clone()),
+        (name("duration"), scrobble.length.as_secs().to_string()),
+    ];
+    if let Some(album) = &scrobble.album {
+        params.push((name("album"), album.clone()));
+    }
+    if let Some(track) = scrobble.track {
+        params.push((name("trackNumber"), track.to_string()));
+    }
+    params
+}
+
+impl Scrobbler for LastFm {
+    fn name(&self) -> &'static str {
+        "lastfm"
+    }
+
+    fn batch_size(&self) -> usize {
+        50
+    }
+
+    fn now_playing(&self, scrobble: &Scrobble) -> Result<(), ScrobbleError> {
+        self.call("track.updateNowPlaying", track_params(scrobble, None))
+            .map(|_| ())
+    }
+
+    fn scrobble(&self, scrobbles: &[Scrobble]) -> Result<(), ScrobbleError> {
+        let params = scrobbles
+            .iter()
+            .enumerate()
+            .flat_map(|(index, scrobble)| {
+                let mut params = track_params(scrobble, Some(index));
+                params.push((format!("timestamp[{index}]"), scrobble.timestamp().to_string()));
+                params
+            })
+            .collect();
+        self.call("track.scrobble", params).map(|_| ())
+    }
+}
+
+/// The first step of authorizing Jolteon: a token, which the user then approves at `lastfm_auth_url`.
+pub fn lastfm_token(api_url: &str, api_key: &str, api_secret: &str) -> Result<String, ScrobbleError> {
+    let body = call(&Client::new(), api_url, api_key, api_secret, "auth.getToken", vec![])?;
+    serde_json::from_value::<TokenResponse>(body)
+        .map(|response| response.token)
+        .map_err(|err| ScrobbleError::Service {
+            message: err.to_string(),
+            can_retry: false,
+        })
+}
+
+pub fn lastfm_auth_url(api_key: &str, token: &str) -> String {
+    format!("{LASTFM_AUTH_URL}?api_key={api_key}&token={token}")
+}
+
+/// The last step of authorizing Jolteon, once the user approved `token`. Session keys don't expire.
+pub fn lastfm_session_key(
+    api_url: &str,
+    api_key: &str,
+    api_secret: &str,
+    token: &str,
+) -> Result<String, ScrobbleError> {
+    let params = vec![("token".to_string(), token.to_string())];
+    let body = call(&Client::new(), api_url, api_key, api_secret, "auth.getSession", params)?;
+    serde_json::from_value::<SessionResponse>(body)
+        .map(|response| response.session.key)
+        .map_err(|err| ScrobbleError::Service {
+            message: err.to_string(),
+            can_retry: false,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::scrobble::mock_server::MockServer;
+
+    fn form(body: &str) -> Vec<(String, String)> {
+        url::form_urlencoded::parse(body.as_bytes()).into_owned().collect()
+    }
+
+    fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+        params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn signs_calls() {
+        let params = [("b", "2"), ("a", "1"), ("method", "auth.getToken")]
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+        // md5("a1b2methodauth.getTokensecret")
+        assert_eq!(signature(&params, "secret"), "8b5276ea27f3b19abf40693c27dca805");
+    }
+
+    #[test]
+    fn scrobbles_tracks() {
+        let server = MockServer::start(vec![
+            (200, r#"{"nowplaying":{}}"#),
+            (200, r#"{"scrobbles":{"@attr":{"accepted":2,"ignored":0}}}"#),
+            (
+                200,
+                r#"{"error":9,"message":"Invalid session key - Please re-authenticate"}"#,
+            ),
+            (400, r#"{"error":6,"message":"Invalid parameters"}"#),
+        ]);
+        let lastfm = LastFm::new(&server.url, "key", "secret", "session");
+        let scrobble = |title: &str, started: u64| Scrobble {
+            artist: "Yes".to_string(),
+            title: title.to_string(),
+            album: None,
+            track: None,
+            length: Duration::from_secs(300),
+            started: UNIX_EPOCH + Duration::from_secs(started),
+        };
+
+        lastfm.now_playing(&scrobble("Roundabout", 0)).unwrap();
+        let scrobbles = [scrobble("Roundabout", 1000), scrobble("South Side of the Sky", 1500)];
+        lastfm.scrobble(&scrobbles).unwrap();
+
+        let err = lastfm.scrobble(&scrobbles).unwrap_err();
+        assert!(err.can_retry());
+        assert_eq!(err.to_string(), "Error 9: Invalid session key - Please re-authenticate");
+        assert!(!lastfm.scrobble(&scrobbles).unwrap_err().can_retry());
+
+        let requests = server.requests();
+        let now_playing = form(&requests[0].body);
+        assert_eq!(param(&now_playing, "method"), Some("track.updateNowPlaying"));
+        assert_eq!(param(&now_playing, "track"), Some("Roundabout"));
+        assert_eq!(param(&now_playing, "sk"), Some("session"));
+
+        let scrobbled = form(&requests[1].body);
+        assert_eq!(param(&scrobbled, "method"), Some("track.scrobble"));
+        assert_eq!(param(&scrobbled, "track[1]"), Some("South Side of the Sky"));
+        assert_eq!(param(&scrobbled, "timestamp[1]"), Some("1500"));
+        assert_eq!(param(&scrobbled, "album[0]"), None);
+
+        let signed: Vec<(String, String)> = scrobbled
+            .iter()
+            .filter(|(name, _)| name != "api_sig" && name != "format")
+            .cloned()
+            .collect();
+        assert_eq!(
+            param(&scrobbled, "api_sig"),
+            Some(signature(&signed, "secret").as_str())
+        );
+    }
+
+    #[test]
+    fn gets_session_keys() {
+        let server = MockServer::start(vec![
+            (200, r#"{"token":"abc"}"#),
+            (200, r#"{"session":{"name":"someone","key":"session","subscriber":0}}"#),
+        ]);
+
+        let token = lastfm_token(&server.url, "key", "secret").unwrap();
+        assert_eq!(token, "abc");
+        assert_eq!(
+            lastfm_session_key(&server.url, "key", "secret", &token).unwrap(),
+            "session"
+        );
+        assert_eq!(param(&form(&server.requests()[1].body), "token"), Some("abc"));
+    }
+}
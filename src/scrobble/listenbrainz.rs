@@ -0,0 +1,183 @@
+use std::time::Duration;
+
+use reqwest::blocking::{Client, Response};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{Scrobble, ScrobbleError, Scrobbler};
+use crate::auto_update::CARGO_PKG_VERSION;
+
+pub static LISTENBRAINZ_API_URL: &str = "https://api.listenbrainz.org";
+
+/// ListenBrainz, or any server that runs it, authenticated with a user token from the ListenBrainz settings page.
+pub struct ListenBrainz {
+    client: Client,
+    api_url: String,
+    token: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+impl ListenBrainz {
+    pub fn new(api_url: &str, token: &str) -> Self {
+        Self {
+            client: Client::new(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        }
+    }
+
+    fn submit(&self, listen_type: &str, payload: Vec<Value>) -> Result<(), ScrobbleError> {
+        let response = self
+            .client
+            .post(format!("{}/1/submit-listens", self.api_url))
+            .header("Authorization", format!("Token {}", self.token))
+            .header("User-Agent", "jolteon")
+            .timeout(Duration::from_secs(10))
+            .json(&json!({
+                "listen_type": listen_type,
+                "payload": payload,
+            }))
+            .send()?;
+        check_response(response)
+    }
+}
+
+fn check_response(response: Response) -> Result<(), ScrobbleError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let message = match response.json::<ErrorResponse>() {
+        Ok(body) => format!("{status}: {}", body.error),
+        Err(_) => status.to_string(),
+    };
+    Err(ScrobbleError::Service {
+        message,
+        // Bad requests are the only ones that'll keep failing no matter what.
+        can_retry: status.as_u16() != 400,
+    })
+}
+
+fn track_metadata(scrobble: &Scrobble) -> Value {
+    let mut additional_info = json!({
+        "media_player": "Jolteon",
+        "submission_client": "Jolteon",
+        "submission_client_version": CARGO_PKG_VERSION,
+        "duration_ms": scrobble.length.as_millis(),
+    });
+    if let Some(track) = scrobble.track {
+        additional_info["tracknumber"] = json!(track);
+    }
+
+    let mut metadata = json!({
+        "artist_name": scrobble.artist,
+        "track_name": scrobble.title,
+        "additional_info": additional_info,
+    });
+    if let Some(album) = &scrobble.album {
+        metadata["release_name"] = json!(album);
+    }
+    metadata
+}
+
+impl Scrobbler for ListenBrainz {
+    fn name(&self) -> &'static str {
+        "listenbrainz"
+    }
+
+    fn batch_size(&self) -> usize {
+        100
+    }
+
+    fn now_playing(&self, scrobble: &Scrobble) -> Result<(), ScrobbleError> {
+        self.submit("playing_now", vec![
+            json!({ "track_metadata": track_metadata(scrobble) }),
+        ])
+    }
+
+    fn scrobble(&self, scrobbles: &[Scrobble]) -> Result<(), ScrobbleError> {
+        let listen_type = if scrobbles.len() == 1 { "single" } else { "import" };
+        let payload = scrobbles
+            .iter()
+            .map(|scrobble| {
+                json!({
+                    "listened_at": scrobble.timestamp(),
+                    "track_metadata": track_metadata(scrobble),
+                })
+            })
+            .collect();
+        self.submit(listen_type, payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use crate::scrobble::mock_server::MockServer;
+
+    fn scrobble(title: &str, started: u64) -> Scrobble {
+        Scrobble {
+            artist: "King Crimson".to_string(),
+            title: title.to_string(),
+            album: Some("Red".to_string()),
+            track: Some(1),
+            length: Duration::from_secs(400),
+            started: UNIX_EPOCH + Duration::from_secs(started),
+        }
+    }
+
+    #[test]
+    fn submits_listens() {
+        let server = MockServer::start(vec![(200, r#"{"status":"ok"}"#)]);
+        let listenbrainz = ListenBrainz::new(&format!("{}/", server.url), "secret");
+
+        listenbrainz.now_playing(&scrobble("Red", 0)).unwrap();
+        listenbrainz
+            .scrobble(&[scrobble("Red", 1000), scrobble("Starless", 1400)])
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.path == "/1/submit-listens"));
+        assert_eq!(requests[0].header("authorization"), Some("Token secret"));
+
+        let playing_now: Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(playing_now["listen_type"], "playing_now");
+        assert_eq!(playing_now["payload"][0].get("listened_at"), None);
+
+        let import: Value = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(import["listen_type"], "import");
+        assert_eq!(import["payload"][1]["listened_at"], 1400);
+        let metadata = &import["payload"][1]["track_metadata"];
+        assert_eq!(metadata["artist_name"], "King Crimson");
+        assert_eq!(metadata["track_name"], "Starless");
+        assert_eq!(metadata["release_name"], "Red");
+        assert_eq!(metadata["additional_info"]["duration_ms"], 400_000);
+    }
+
+    #[test]
+    fn tells_errors_worth_retrying() {
+        let server = MockServer::start(vec![
+            (401, r#"{"code":401,"error":"Invalid authorization token."}"#),
+            (400, r#"{"code":400,"error":"Invalid payload."}"#),
+            (503, ""),
+        ]);
+        let listenbrainz = ListenBrainz::new(&server.url, "secret");
+        let scrobbles = [scrobble("Red", 1000)];
+
+        let err = listenbrainz.scrobble(&scrobbles).unwrap_err();
+        assert!(err.can_retry());
+        assert_eq!(err.to_string(), "401 Unauthorized: Invalid authorization token.");
+        assert!(!listenbrainz.scrobble(&scrobbles).unwrap_err().can_retry());
+        assert!(listenbrainz.scrobble(&scrobbles).unwrap_err().can_retry());
+
+        let offline = ListenBrainz::new("http://127.0.0.1:1", "secret");
+        assert!(offline.scrobble(&scrobbles).unwrap_err().can_retry());
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub path: String,
+    /// Header names are lowercase.
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// An HTTP server on localhost that answers requests with the given status codes and bodies, in order, and keeps
+/// repeating the last one.
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<MockRequest>>>,
+}
+
+impl MockServer {
+    pub fn start(responses: Vec<(u16, &'static str)>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        thread::spawn({
+            let requests = requests.clone();
+            move || {
+                for (index, stream) in listener.incoming().enumerate() {
+                    let Ok(mut stream) = stream else {
+                        return;
+                    };
+                    let mut reader = BufReader::new(&mut stream);
+
+                    let mut request_line = String::new();
+                    reader.read_line(&mut request_line).unwrap();
+                    let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+
+                    let mut headers = vec![];
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        let Some((name, value)) = line.trim_end().split_once(':') else {
+                            break;
+                        };
+                        headers.push((name.to_lowercase(), value.trim().to_string()));
+                    }
+                    let length = headers
+                        .iter()
+                        .find(|(name, _)| name == "content-length")
+                        .map_or(0, |(_, value)| value.parse().unwrap());
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+
+                    requests.lock().unwrap().push(MockRequest {
+                        path,
+                        headers,
+                        body: String::from_utf8(body).unwrap(),
+                    });
+
+                    let (status, body) = responses[index.min(responses.len() - 1)];
+                    let _ = write!(
+                        stream,
+                        "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                }
+            }
+        });
+
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
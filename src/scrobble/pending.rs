@@ -0,0 +1,89 @@
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::Scrobble;
+
+/// How long to wait after the first failed submission.
+const FIRST_RETRY: Duration = Duration::from_secs(30);
+
+/// Retries never wait longer than this, however long the service has been unreachable.
+const MAX_RETRY: Duration = Duration::from_secs(60 * 60);
+
+/// Scrobbles their services haven't taken yet, by `Scrobbler::name`, oldest first.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct PendingScrobbles {
+    pub services: BTreeMap<String, Vec<Scrobble>>,
+}
+
+pub fn pending_scrobbles_path() -> Option<PathBuf> {
+    home::home_dir().map(|path| path.join(".config/jolteon/pending_scrobbles.json"))
+}
+
+impl PendingScrobbles {
+    pub fn from_file(path: &Path) -> Self {
+        match read_to_string(path) {
+            Ok(string) => serde_json::from_str(&string).unwrap_or_else(|err| {
+                log::error!("Error in the pending scrobbles. They will be lost. Error was: {err:?}");
+                Self::default()
+            }),
+            Err(err) => {
+                log::debug!("Could not read the pending scrobbles {path:?}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn to_file(&self, path: &Path) -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+        write(path, serde_json::to_string(self)?)
+    }
+}
+
+/// How long to wait before trying again, after `failures` failed attempts in a row.
+pub fn retry_delay(failures: u32) -> Duration {
+    let doublings = failures.saturating_sub(1).min(16);
+    FIRST_RETRY.saturating_mul(1 << doublings).min(MAX_RETRY)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+
+    #[test]
+    fn backs_off_exponentially() {
+        assert_eq!(retry_delay(1), Duration::from_secs(30));
+        assert_eq!(retry_delay(2), Duration::from_secs(60));
+        assert_eq!(retry_delay(4), Duration::from_secs(240));
+        assert_eq!(retry_delay(8), Duration::from_secs(3600));
+        assert_eq!(retry_delay(u32::MAX), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let path = std::env::temp_dir().join(format!("jolteon-pending-scrobbles-{}.json", std::process::id()));
+        let mut pending = PendingScrobbles::default();
+        pending.services.insert("listenbrainz".to_string(), vec![Scrobble {
+            artist: "Yes".to_string(),
+            title: "Roundabout".to_string(),
+            album: Some("Fragile".to_string()),
+            track: Some(1),
+            length: Duration::from_secs(510),
+            started: SystemTime::UNIX_EPOCH + Duration::from_secs(1000),
+        }]);
+
+        pending.to_file(&path).unwrap();
+        assert_eq!(PendingScrobbles::from_file(&path), pending);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(PendingScrobbles::from_file(&path), PendingScrobbles::default());
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    fmt::{Display, Formatter},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::structs::Song;
+
+/// Songs shorter than this are never scrobbled.
+const MIN_LENGTH: Duration = Duration::from_secs(30);
+
+/// Songs are scrobbled once they've played for half their length, or this long, whichever comes first.
+const MAX_PLAYED: Duration = Duration::from_secs(4 * 60);
+
+/// A song played, the way scrobbling services know it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Scrobble {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub track: Option<u32>,
+    pub length: Duration,
+    /// When the song started playing.
+    pub started: SystemTime,
+}
+
+impl Scrobble {
+    /// `None` for songs with no artist, which no service takes.
+    pub fn new(song: &Song, started: SystemTime) -> Option<Self> {
+        Some(Self {
+            artist: song.artist.clone()?,
+            title: song.title.clone(),
+            album: song.album.clone(),
+            track: song.track,
+            length: song.length,
+            started,
+        })
+    }
+
+    /// Seconds since the Unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.started.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+    }
+}
+
+/// Whether a song of `length` that played for `played` counts as listened to.
+pub fn is_scrobbled(length: Duration, played: Duration) -> bool {
+    length >= MIN_LENGTH && played >= (length / 2).min(MAX_PLAYED)
+}
+
+#[derive(Debug)]
+pub enum ScrobbleError {
+    /// The service couldn't be reached, or didn't answer in time.
+    Http(reqwest::Error),
+    /// The service answered, but didn't take the request.
+    Service {
+        message: String,
+        /// Whether the same request may work later, like when the service is down, or the credentials are wrong
+        /// and get fixed. Otherwise, the request itself is wrong, and there's no point in sending it again.
+        can_retry: bool,
+    },
+}
+
+impl ScrobbleError {
+    pub fn can_retry(&self) -> bool {
+        match self {
+            ScrobbleError::Http(_) => true,
+            ScrobbleError::Service { can_retry, .. } => *can_retry,
+        }
+    }
+}
+
+impl Display for ScrobbleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScrobbleError::Http(err) => write!(f, "{err}"),
+            ScrobbleError::Service { message, .. } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ScrobbleError {
+    fn from(value: reqwest::Error) -> Self {
+        ScrobbleError::Http(value)
+    }
+}
+
+/// A service songs are scrobbled to.
+pub trait Scrobbler: Send {
+    /// Shown in logs. It also keeps each service's pending scrobbles apart, so it shouldn't change.
+    fn name(&self) -> &'static str;
+
+    /// How many scrobbles `scrobble` takes at once, at most.
+    fn batch_size(&self) -> usize;
+
+    fn now_playing(&self, scrobble: &Scrobble) -> Result<(), ScrobbleError>;
+
+    fn scrobble(&self, scrobbles: &[Scrobble]) -> Result<(), ScrobbleError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scrobbles_after_half_or_four_minutes() {
+        let minutes = |minutes: f32| Duration::from_secs_f32(minutes * 60.0);
+
+        assert!(!is_scrobbled(minutes(3.0), minutes(1.4)));
+        assert!(is_scrobbled(minutes(3.0), minutes(1.5)));
+        assert!(!is_scrobbled(minutes(20.0), minutes(3.9)));
+        assert!(is_scrobbled(minutes(20.0), minutes(4.0)));
+        assert!(!is_scrobbled(Duration::from_secs(20), Duration::from_secs(20)));
+    }
+}
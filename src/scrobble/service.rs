@@ -0,0 +1,388 @@
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel},
+    thread,
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{
+    LastFm,
+    ListenBrainz,
+    PendingScrobbles,
+    Scrobble,
+    Scrobbler,
+    is_scrobbled,
+    pending_scrobbles_path,
+    retry_delay,
+};
+use crate::{settings::ScrobblingSettings, structs::Song};
+
+/// How often the time a song has been listened to is brought up to date, while it plays.
+const LISTEN_TICK: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+enum ScrobbleMessage {
+    NowPlaying(Box<Song>),
+    Stopped,
+    Quit,
+}
+
+/// Scrobbles songs to every configured service, from a thread of its own, so slow or unreachable services never hold
+/// up playback. Scrobbles are saved until their service takes them, so songs played offline are sent once it's back.
+pub struct ScrobbleService {
+    sender: Sender<ScrobbleMessage>,
+    thread: JoinHandle<()>,
+}
+
+/// The song playing now, and how long it has actually been listened to. Time spent paused doesn't count, and neither
+/// does whatever seeking skipped over.
+struct Listen {
+    song: Song,
+    started: SystemTime,
+    listened: Duration,
+    /// When `listened` was last brought up to date.
+    counted_until: Instant,
+    is_scrobbled: bool,
+}
+
+impl Listen {
+    fn new(song: Song, now: Instant, started: SystemTime) -> Self {
+        Self {
+            song,
+            started,
+            listened: Duration::ZERO,
+            counted_until: now,
+            is_scrobbled: false,
+        }
+    }
+
+    /// Counts the time since it was last called as listened to, unless playback `is_paused`.
+    /// Returns the scrobble of the song the first time it has been listened to for long enough.
+    fn count(&mut self, now: Instant, is_paused: bool) -> Option<Scrobble> {
+        if !is_paused {
+            self.listened += now.saturating_duration_since(self.counted_until);
+        }
+        self.counted_until = now;
+
+        if self.is_scrobbled || !is_scrobbled(self.song.length, self.listened) {
+            return None;
+        }
+        self.is_scrobbled = true;
+        Scrobble::new(&self.song, self.started)
+    }
+}
+
+struct Retry {
+    failures: u32,
+    at: Instant,
+}
+
+/// The scrobbles each service has yet to take, and when to try sending them again after a failure.
+struct Submissions {
+    scrobblers: Vec<Box<dyn Scrobbler>>,
+    pending: PendingScrobbles,
+    path: Option<PathBuf>,
+    retries: HashMap<&'static str, Retry>,
+}
+
+impl Submissions {
+    fn new(scrobblers: Vec<Box<dyn Scrobbler>>, path: Option<PathBuf>) -> Self {
+        let pending = path.as_deref().map(PendingScrobbles::from_file).unwrap_or_default();
+        Self {
+            scrobblers,
+            pending,
+            path,
+            retries: HashMap::new(),
+        }
+    }
+
+    fn save(&self) {
+        if let Some(path) = &self.path
+            && let Err(err) = self.pending.to_file(path)
+        {
+            log::error!("Could not save the pending scrobbles! {err:?}");
+        }
+    }
+
+    fn add(&mut self, scrobble: Scrobble) {
+        for scrobbler in &self.scrobblers {
+            self.pending
+                .services
+                .entry(scrobbler.name().to_string())
+                .or_default()
+                .push(scrobble.clone());
+        }
+        self.save();
+    }
+
+    /// The earliest time a service that failed should be tried again.
+    fn next_retry(&self) -> Option<Instant> {
+        self.retries.values().map(|retry| retry.at).min()
+    }
+
+    /// Sends pending scrobbles to the services that aren't waiting to retry, or to all of them, with `even_if_waiting`.
+    fn submit(&mut self, now: Instant, even_if_waiting: bool) {
+        let mut changed = false;
+
+        for scrobbler in &self.scrobblers {
+            let name = scrobbler.name();
+            if !even_if_waiting && self.retries.get(name).is_some_and(|retry| retry.at > now) {
+                continue;
+            }
+            let Some(pending) = self.pending.services.get_mut(name) else {
+                self.retries.remove(name);
+                continue;
+            };
+
+            while !pending.is_empty() {
+                let batch = pending.len().min(scrobbler.batch_size());
+                match scrobbler.scrobble(&pending[..batch]) {
+                    Ok(()) => {
+                        log::debug!("{name} took {batch} scrobbles");
+                        self.retries.remove(name);
+                    }
+                    Err(err) if err.can_retry() => {
+                        let failures = self.retries.get(name).map_or(1, |retry| retry.failures + 1);
+                        let delay = retry_delay(failures);
+                        log::warn!("Could not scrobble to {name}. Will try again in {delay:?}. {err}");
+                        self.retries.insert(name, Retry {
+                            failures,
+                            at: now + delay,
+                        });
+                        break;
+                    }
+                    Err(err) => {
+                        log::error!("{name} refused {batch} scrobbles, which won't be sent again. {err}");
+                    }
+                }
+                pending.drain(..batch);
+                changed = true;
+            }
+
+            if pending.is_empty() {
+                self.pending.services.remove(name);
+                self.retries.remove(name);
+            }
+        }
+
+        if changed {
+            self.save();
+        }
+    }
+
+    fn now_playing(&self, scrobble: &Scrobble) {
+        for scrobbler in &self.scrobblers {
+            if let Err(err) = scrobbler.now_playing(scrobble) {
+                log::debug!("Could not update the song playing on {}. {err}", scrobbler.name());
+            }
+        }
+    }
+}
+
+impl ScrobbleService {
+    /// `None` when no service is configured. `settings` is read on the service's thread, where its HTTP clients
+    /// have to be created. `is_paused` tells whether playback is paused, which doesn't count as listening.
+    pub fn from_settings(settings: &ScrobblingSettings, is_paused: impl Fn() -> bool + Send + 'static) -> Option<Self> {
+        if settings.listenbrainz.is_none() && settings.lastfm.is_none() {
+            return None;
+        }
+        let settings = settings.clone();
+        let scrobblers = move || {
+            let mut scrobblers: Vec<Box<dyn Scrobbler>> = vec![];
+            if let Some(listenbrainz) = settings.listenbrainz {
+                scrobblers.push(Box::new(ListenBrainz::new(&listenbrainz.api_url, &listenbrainz.token)));
+            }
+            if let Some(lastfm) = settings.lastfm {
+                scrobblers.push(Box::new(LastFm::new(
+                    &lastfm.api_url,
+                    &lastfm.api_key,
+                    &lastfm.api_secret,
+                    &lastfm.session_key,
+                )));
+            }
+            scrobblers
+        };
+        Some(Self::spawn(scrobblers, pending_scrobbles_path(), is_paused))
+    }
+
+    pub fn spawn(
+        scrobblers: impl FnOnce() -> Vec<Box<dyn Scrobbler>> + Send + 'static,
+        pending_path: Option<PathBuf>,
+        is_paused: impl Fn() -> bool + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = channel();
+        let thread = thread::Builder::new()
+            .name("scrobbler".to_string())
+            .spawn(move || run(Submissions::new(scrobblers(), pending_path), receiver, is_paused))
+            .unwrap();
+        Self { sender, thread }
+    }
+
+    /// Starts counting how long `song` is listened to. It's scrobbled as soon as that's long enough.
+    pub fn now_playing(&self, song: &Song) {
+        let _ = self.sender.send(ScrobbleMessage::NowPlaying(Box::new(song.clone())));
+    }
+
+    /// Stops counting how long the song playing was listened to.
+    pub fn stopped(&self) {
+        let _ = self.sender.send(ScrobbleMessage::Stopped);
+    }
+
+    /// Waits for the song playing to be scrobbled, if it was listened to for long enough. Scrobbles that weren't sent
+    /// yet are sent the next time.
+    pub fn quit(self) {
+        let _ = self.sender.send(ScrobbleMessage::Quit);
+        if let Err(err) = self.thread.join() {
+            log::error!("error joining scrobbler thread {err:?}");
+        }
+    }
+}
+
+/// Runs until the `ScrobbleService` quits, or is dropped.
+fn run(mut submissions: Submissions, receiver: Receiver<ScrobbleMessage>, is_paused: impl Fn() -> bool) {
+    // Whatever was left pending the last time goes first.
+    submissions.submit(Instant::now(), true);
+
+    let mut listen: Option<Listen> = None;
+
+    loop {
+        let wake_up = [
+            submissions.next_retry(),
+            listen.as_ref().map(|_| Instant::now() + LISTEN_TICK),
+        ]
+        .into_iter()
+        .flatten()
+        .min();
+        let message = match wake_up {
+            Some(at) => receiver.recv_timeout(at.saturating_duration_since(Instant::now())),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        // Whatever happened, the time until now was spent listening to the song, or not.
+        let listened = listen
+            .as_mut()
+            .and_then(|listen| listen.count(Instant::now(), is_paused()));
+        if let Some(scrobble) = &listened {
+            log::debug!("scrobbling {:?}", scrobble.title);
+            submissions.add(scrobble.clone());
+        }
+
+        match message {
+            Ok(ScrobbleMessage::NowPlaying(song)) => {
+                let now = SystemTime::now();
+                if let Some(scrobble) = Scrobble::new(&song, now) {
+                    submissions.now_playing(&scrobble);
+                }
+                listen = Some(Listen::new(*song, Instant::now(), now));
+            }
+            Ok(ScrobbleMessage::Stopped) => {
+                listen = None;
+            }
+            Ok(ScrobbleMessage::Quit) | Err(RecvTimeoutError::Disconnected) => {
+                // A scrobble of the last song is saved by now, and waits for the next time.
+                return;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+
+        // A new scrobble is a good time to check whether the service is back.
+        submissions.submit(Instant::now(), listened.is_some());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scrobble::mock_server::MockServer;
+
+    fn scrobble(title: &str) -> Scrobble {
+        Scrobble {
+            artist: "Massive Attack".to_string(),
+            title: title.to_string(),
+            album: Some("Mezzanine".to_string()),
+            track: None,
+            length: Duration::from_secs(300),
+            started: SystemTime::now(),
+        }
+    }
+
+    #[test]
+    fn keeps_scrobbles_until_the_service_takes_them() {
+        let path = std::env::temp_dir().join(format!("jolteon-scrobble-service-{}.json", std::process::id()));
+        let server = MockServer::start(vec![(503, ""), (503, ""), (200, r#"{"status":"ok"}"#)]);
+        let scrobblers = || -> Vec<Box<dyn Scrobbler>> { vec![Box::new(ListenBrainz::new(&server.url, "token"))] };
+        let now = Instant::now();
+
+        let mut submissions = Submissions::new(scrobblers(), Some(path.clone()));
+        submissions.add(scrobble("Angel"));
+        submissions.submit(now, true);
+        assert_eq!(submissions.next_retry(), Some(now + Duration::from_secs(30)));
+
+        // Songs played while offline wait, even after a restart.
+        let mut submissions = Submissions::new(scrobblers(), Some(path.clone()));
+        submissions.add(scrobble("Teardrop"));
+        assert_eq!(submissions.pending.services["listenbrainz"].len(), 2);
+
+        submissions.submit(now, true);
+        assert_eq!(submissions.next_retry(), Some(now + Duration::from_secs(30)));
+        submissions.submit(now + Duration::from_secs(20), false);
+        assert_eq!(submissions.next_retry(), Some(now + Duration::from_secs(30)));
+        assert_eq!(server.requests().len(), 2);
+
+        submissions.submit(now + Duration::from_secs(30), false);
+        assert_eq!(submissions.next_retry(), None);
+        assert_eq!(PendingScrobbles::from_file(&path), PendingScrobbles::default());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2].body.contains("Angel") && requests[2].body.contains("Teardrop"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn counts_listening_without_pauses() {
+        let song = Song {
+            library_id: None,
+            path: PathBuf::from("/music/angel.flac"),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(6 * 60),
+            title: "Angel".to_string(),
+            artist: Some("Massive Attack".to_string()),
+            album: None,
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: None,
+            replay_gain: Default::default(),
+        };
+        let minutes = |minutes: u64| Duration::from_secs(minutes * 60);
+        let now = Instant::now();
+        let started = SystemTime::now();
+
+        let mut listen = Listen::new(song, now, started);
+        assert_eq!(listen.count(now + minutes(2), false), None);
+        // Paused for an hour.
+        assert_eq!(listen.count(now + minutes(62), true), None);
+        assert_eq!(
+            listen.count(now + minutes(63), false).map(|scrobble| scrobble.started),
+            Some(started)
+        );
+        // Scrobbled once, however long it plays on.
+        assert_eq!(listen.count(now + minutes(64), false), None);
+    }
+
+    #[test]
+    fn drops_refused_scrobbles() {
+        let server = MockServer::start(vec![(400, r#"{"code":400,"error":"Invalid payload."}"#)]);
+        let mut submissions = Submissions::new(vec![Box::new(ListenBrainz::new(&server.url, "token"))], None);
+
+        submissions.add(scrobble("Angel"));
+        submissions.submit(Instant::now(), true);
+        assert_eq!(submissions.pending, PendingScrobbles::default());
+        assert_eq!(submissions.next_retry(), None);
+    }
+}
@@ -1,12 +1,22 @@
-use std::fmt::{Display, Formatter};
+use std::{
+    fmt::{Display, Formatter},
+    path::PathBuf,
+};
 
 use serde::{Deserialize, Serialize};
 use serde_default::DefaultFromSerde;
 
-use crate::{theme::BuiltInThemeNames, toml::read_toml_file_or_default};
+use crate::{
+    equalizer::{BandKind, EqualizerBand, EqualizerPreset},
+    radio::RadioEngine,
+    scrobble::{LASTFM_API_URL, LISTENBRAINZ_API_URL},
+    structs::ReplayGainMode,
+    theme::BuiltInThemeNames,
+    toml::read_toml_file_or_default,
+};
 
 #[serde_inline_default::serde_inline_default]
-#[derive(Serialize, Deserialize, Debug, Copy, Clone, DefaultFromSerde)]
+#[derive(Serialize, Deserialize, Debug, Clone, DefaultFromSerde)]
 pub struct Settings {
     #[serde_inline_default(false)]
     pub debug_frame_counter: bool,
@@ -19,6 +29,138 @@ pub struct Settings {
 
     #[serde_inline_default(BuiltInThemeNames::GruvboxDark)]
     pub theme: BuiltInThemeNames,
+
+    /// How long consecutive tracks overlap for, in seconds. 0 disables crossfading.
+    #[serde_inline_default(0.0)]
+    pub crossfade_seconds: f32,
+
+    #[serde_inline_default(ReplayGainMode::Off)]
+    pub replay_gain: ReplayGainMode,
+
+    /// Added to the ReplayGain of every song that has one, in dB.
+    #[serde_inline_default(0.0)]
+    pub replay_gain_preamp: f32,
+
+    /// Lower the gain of songs whose peak would otherwise clip.
+    #[serde_inline_default(true)]
+    pub replay_gain_prevent_clipping: bool,
+
+    #[serde(default)]
+    pub equalizer: EqualizerSettings,
+
+    /// Name of the audio output device, as listed by the output device picker. None uses the system default.
+    #[serde_inline_default(None)]
+    pub output_device: Option<String>,
+
+    /// Show album art of the playing song, and of songs selected in the file browser.
+    #[serde_inline_default(true)]
+    pub album_art: bool,
+
+    /// When shuffling, don't pick songs by the last few artists played, unless the queue has nothing else.
+    #[serde_inline_default(true)]
+    pub shuffle_avoid_recent_artists: bool,
+
+    /// Keep playing songs from the Library and Soundtracks once the queue runs out.
+    #[serde_inline_default(false)]
+    pub radio: bool,
+
+    /// How radio mode picks songs.
+    #[serde_inline_default(RadioEngine::CoListening)]
+    pub radio_engine: RadioEngine,
+
+    #[serde(default)]
+    pub scrobbling: ScrobblingSettings,
+
+    /// Folders scanned for songs on startup. Songs found in them show up in the Library, or in Soundtracks.
+    /// A leading `~` stands for the home directory.
+    #[serde_inline_default(vec![])]
+    pub library_folders: Vec<PathBuf>,
+}
+
+#[serde_inline_default::serde_inline_default]
+#[derive(Serialize, Deserialize, Debug, Clone, DefaultFromSerde)]
+pub struct EqualizerSettings {
+    /// Name of the preset active on startup. None leaves the equalizer off.
+    #[serde_inline_default(None)]
+    pub preset: Option<String>,
+
+    #[serde_inline_default(default_equalizer_presets())]
+    pub presets: Vec<EqualizerPreset>,
+}
+
+/// Songs are scrobbled to every service that's configured here.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ScrobblingSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listenbrainz: Option<ListenBrainzSettings>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lastfm: Option<LastFmSettings>,
+}
+
+#[serde_inline_default::serde_inline_default]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListenBrainzSettings {
+    /// The user token, from the ListenBrainz settings page.
+    pub token: String,
+
+    /// For servers other than listenbrainz.org.
+    #[serde_inline_default(LISTENBRAINZ_API_URL.to_string())]
+    pub api_url: String,
+}
+
+#[serde_inline_default::serde_inline_default]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LastFmSettings {
+    pub api_key: String,
+
+    pub api_secret: String,
+
+    /// From `jolteon lastfm-login`.
+    pub session_key: String,
+
+    /// For services with the same API as Last.fm, like Libre.fm.
+    #[serde_inline_default(LASTFM_API_URL.to_string())]
+    pub api_url: String,
+}
+
+fn default_equalizer_presets() -> Vec<EqualizerPreset> {
+    let band = |kind, frequency, gain, q| EqualizerBand {
+        kind,
+        frequency,
+        gain,
+        q,
+    };
+
+    vec![
+        EqualizerPreset {
+            name: "Bass Boost".to_string(),
+            preamp: -6.0,
+            bands: vec![band(BandKind::LowShelf, 100.0, 6.0, 0.7)],
+        },
+        EqualizerPreset {
+            name: "Treble Boost".to_string(),
+            preamp: -6.0,
+            bands: vec![band(BandKind::HighShelf, 8000.0, 6.0, 0.7)],
+        },
+        EqualizerPreset {
+            name: "Vocal".to_string(),
+            preamp: -3.0,
+            bands: vec![
+                band(BandKind::LowShelf, 150.0, -2.0, 0.7),
+                band(BandKind::Peak, 2500.0, 3.0, 1.0),
+            ],
+        },
+        EqualizerPreset {
+            name: "Headphones".to_string(),
+            preamp: -4.0,
+            bands: vec![
+                band(BandKind::LowShelf, 105.0, 4.0, 0.7),
+                band(BandKind::Peak, 3000.0, -2.0, 1.4),
+                band(BandKind::HighShelf, 10000.0, -2.0, 0.7),
+            ],
+        },
+    ]
 }
 
 impl Settings {
@@ -3,7 +3,6 @@ use std::{
     io::BufReader,
     num::NonZero,
     path::PathBuf,
-    sync::{Arc, Mutex},
     time::Duration,
 };
 
@@ -13,19 +12,22 @@ use rodio::{
     source::{Amplify, Pausable, PeriodicAccess, SeekError, Skippable, Speed, Stoppable, TrackPosition},
 };
 
-type FullRodioSource = Stoppable<Skippable<Amplify<Pausable<TrackPosition<Speed<Decoder<BufReader<File>>>>>>>>;
+use crate::equalizer::{Equalizer, EqualizerPreset};
+
+// `TrackPosition` goes before `Speed`, so positions are always in terms of the file, no matter the playback speed.
+type FullRodioSource =
+    Stoppable<Skippable<Amplify<Pausable<Equalizer<Speed<TrackPosition<Decoder<BufReader<File>>>>>>>>>;
 type PeriodicRodioSource<F> = PeriodicAccess<FullRodioSource, F>;
 
 pub struct Controls<'a> {
     src: &'a mut FullRodioSource,
-    shared_pos: &'a Arc<Mutex<Duration>>,
+    gain: f32,
 }
 
 impl Controls<'_> {
     #[inline]
     pub fn stop(&mut self) {
         self.src.stop();
-        self.set_pos(Duration::ZERO);
     }
 
     #[inline]
@@ -35,22 +37,34 @@ impl Controls<'_> {
 
     #[inline]
     pub fn pos(&self) -> Duration {
-        self.src.inner().inner().inner().inner().get_pos()
+        self.src.inner().inner().inner().inner().inner().inner().get_pos()
     }
 
     #[inline]
-    pub fn set_pos(&self, pos: Duration) {
-        *self.shared_pos.lock().unwrap() = pos;
+    pub fn set_speed(&mut self, factor: f32) {
+        self.src
+            .inner_mut()
+            .inner_mut()
+            .inner_mut()
+            .inner_mut()
+            .inner_mut()
+            .set_factor(factor)
     }
 
     #[inline]
-    pub fn refresh_pos(&self) {
-        self.set_pos(self.pos());
+    pub fn set_equalizer(&mut self, preset: Option<&EqualizerPreset>) {
+        self.src
+            .inner_mut()
+            .inner_mut()
+            .inner_mut()
+            .inner_mut()
+            .set_preset(preset)
     }
 
+    /// Sets the volume of the source. The ReplayGain factor it was created with is applied on top.
     #[inline]
     pub fn set_volume(&mut self, factor: f32) {
-        self.src.inner_mut().inner_mut().set_factor(factor)
+        self.src.inner_mut().inner_mut().set_factor(factor * self.gain)
     }
 
     #[inline]
@@ -58,9 +72,17 @@ impl Controls<'_> {
         self.src.inner_mut().inner_mut().inner_mut().set_paused(paused)
     }
 
+    /// Seeks to a position in the file. Goes around `Speed`, which would scale the position by its factor.
     #[inline]
     pub fn seek(&mut self, position: Duration) -> Result<(), SeekError> {
-        self.src.try_seek(position)
+        self.src
+            .inner_mut()
+            .inner_mut()
+            .inner_mut()
+            .inner_mut()
+            .inner_mut()
+            .inner_mut()
+            .try_seek(position)
     }
 }
 
@@ -72,28 +94,23 @@ pub struct Source<F> {
 impl Source<()> {
     pub fn from_file(
         path: PathBuf,
+        gain: f32,
         periodic_access: impl Fn(&mut Controls) + Send,
-        shared_pos: Arc<Mutex<Duration>>,
         on_playback_end: impl FnOnce() + Send + 'static,
     ) -> Result<Source<Box<impl FnMut(&mut FullRodioSource) + Send>>, String> {
         let periodic_access_inner = {
             Box::new(move |src: &mut FullRodioSource| {
-                let mut controls = Controls {
-                    src,
-                    shared_pos: &shared_pos,
-                };
-                controls.refresh_pos();
+                let mut controls = Controls { src, gain };
                 periodic_access(&mut controls);
             })
         };
 
-        let file = BufReader::new(File::open(path.clone()).map_err(|e| e.to_string())?);
-        let source = Decoder::new(file).map_err(|e| e.to_string())?;
-        let input = source
-            .speed(1.0)
-            .track_position()
+        // Going through `File` tells the decoder the stream is seekable, and how long it is. Seeking backwards needs that.
+        let file = File::open(path.clone()).map_err(|e| e.to_string())?;
+        let source = Decoder::try_from(file).map_err(|e| e.to_string())?;
+        let input = Equalizer::new(source.track_position().speed(1.0))
             .pausable(false)
-            .amplify(1.0)
+            .amplify(gain)
             .skippable()
             .stoppable()
             .periodic_access(Duration::from_millis(5), periodic_access_inner);
@@ -1,11 +1,15 @@
 mod direction;
 mod jolt;
 mod playlist;
+mod playlist_file;
 mod queue;
+mod replay_gain;
 mod song;
 
 pub use direction::Direction;
 pub use jolt::Jolt;
-pub use playlist::Playlist;
+pub use playlist::{Playlist, SmartPlaylist, SmartPlaylistSort};
+pub use playlist_file::{PlaylistFile, PlaylistFormat, export_playlist_file};
 pub use queue::Queue;
-pub use song::Song;
+pub use replay_gain::{ReplayGain, ReplayGainMode};
+pub use song::{AlbumOwner, Song};
@@ -1,14 +1,25 @@
+use std::{cmp::Reverse, str::FromStr};
+
 use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString, VariantNames};
 
-use crate::{components::SongListViewOptions, structs::Song};
+use crate::{
+    components::{FilterError, SongFilter, SongListViewOptions},
+    files::SongStats,
+    structs::Song,
+};
 
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Playlist {
     pub name: String,
+    /// For smart playlists, the songs the rules matched the last time they were evaluated. They aren't saved.
+    #[serde(default)]
     pub songs: Vec<Song>,
     pub is_deleted: bool,
     #[serde(default)]
     pub view_options: SongListViewOptions,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub smart: Option<SmartPlaylist>,
 }
 
 impl Playlist {
@@ -18,6 +29,198 @@ impl Playlist {
             songs: vec![],
             is_deleted: false,
             view_options: SongListViewOptions::default(),
+            smart: None,
+        }
+    }
+
+    pub fn new_smart(name: String, smart: SmartPlaylist) -> Self {
+        Self {
+            smart: Some(smart),
+            ..Self::new(name)
+        }
+    }
+}
+
+/// A playlist whose songs are the library songs that match its rules, rather than songs added to it by hand.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SmartPlaylist {
+    /// A `SongFilter` expression.
+    pub rules: String,
+    #[serde(default)]
+    pub sort: SmartPlaylistSort,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq, Display, EnumString, VariantNames)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SmartPlaylistSort {
+    /// By artist, then the way albums are sorted in the library.
+    #[default]
+    Library,
+    Random,
+    Title,
+    Year,
+    /// Most played first.
+    Plays,
+    /// Most recently played first.
+    Played,
+    /// Most recently added first.
+    Added,
+}
+
+impl SmartPlaylist {
+    /// Parses rules in the `SongFilter` language, along with `sort:` and `limit:` terms, like
+    /// `year:1970..1979 plays:0 sort:random limit:50`.
+    pub fn parse(text: &str) -> Result<Self, FilterError> {
+        let mut sort = SmartPlaylistSort::default();
+        let mut limit = None;
+        let mut rules = vec![];
+
+        for word in text.split_whitespace() {
+            if let Some(value) = word.strip_prefix("sort:") {
+                sort = SmartPlaylistSort::from_str(value).map_err(|_| FilterError::InvalidValue {
+                    field: format!("sort ({})", SmartPlaylistSort::VARIANTS.join(", ")),
+                    value: value.to_string(),
+                })?;
+            } else if let Some(value) = word.strip_prefix("limit:") {
+                limit = Some(value.parse().map_err(|_| FilterError::InvalidValue {
+                    field: "limit".to_string(),
+                    value: value.to_string(),
+                })?);
+            } else {
+                rules.push(word);
+            }
+        }
+
+        let rules = rules.join(" ");
+        SongFilter::parse(&rules)?;
+        Ok(Self { rules, sort, limit })
+    }
+
+    /// The rules, sort and limit, the way `parse` takes them.
+    pub fn text(&self) -> String {
+        let mut text = self.rules.clone();
+        if self.sort != SmartPlaylistSort::default() {
+            text.push_str(&format!(" sort:{}", self.sort));
+        }
+        if let Some(limit) = self.limit {
+            text.push_str(&format!(" limit:{limit}"));
+        }
+        text.trim().to_string()
+    }
+
+    /// The songs of `library` that match the rules, sorted, and up to the limit.
+    pub fn songs(&self, library: &[Song], stats: &SongStats) -> Vec<Song> {
+        let filter = match SongFilter::parse(&self.rules) {
+            Ok(filter) => filter,
+            Err(err) => {
+                log::warn!("Smart playlist rules {:?} are not valid: {err}", self.rules);
+                return vec![];
+            }
+        };
+
+        let mut songs: Vec<Song> = library
+            .iter()
+            .filter(|song| filter.matches(song, stats))
+            .cloned()
+            .collect();
+
+        songs.sort_by(|a, b| a.artist.cmp(&b.artist).then_with(|| a.cmp(b)));
+        let stat = |song: &Song| stats.get(song).cloned().unwrap_or_default();
+        match self.sort {
+            SmartPlaylistSort::Library => {}
+            SmartPlaylistSort::Random => fastrand::shuffle(&mut songs),
+            SmartPlaylistSort::Title => songs.sort_by_key(|song| song.title.to_lowercase()),
+            SmartPlaylistSort::Year => songs.sort_by_key(|song| song.year),
+            SmartPlaylistSort::Plays => songs.sort_by_key(|song| Reverse(stat(song).plays)),
+            SmartPlaylistSort::Played => songs.sort_by_key(|song| Reverse(stat(song).last_played)),
+            SmartPlaylistSort::Added => songs.sort_by_key(|song| Reverse(stat(song).added)),
+        }
+
+        if let Some(limit) = self.limit {
+            songs.truncate(limit);
+        }
+        songs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn song(title: &str, artist: &str, album: &str, year: u32, track: u32) -> Song {
+        Song {
+            library_id: Some(Uuid::new_v4()),
+            path: PathBuf::from(format!("/music/{artist}/{album}/{title}.flac")),
+            start_time: Duration::ZERO,
+            length: Duration::from_secs(300),
+            title: title.to_string(),
+            artist: Some(artist.to_string()),
+            album: Some(album.to_string()),
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: Some(track),
+            year: Some(year),
+            replay_gain: Default::default(),
         }
     }
+
+    fn titles(songs: Vec<Song>) -> Vec<String> {
+        songs.into_iter().map(|song| song.title).collect()
+    }
+
+    #[test]
+    fn parses_rules_sort_and_limit() {
+        let smart = SmartPlaylist::parse(r#"year:1970..1979  sort:plays plays:>0 limit:2"#).unwrap();
+        assert_eq!(smart, SmartPlaylist {
+            rules: "year:1970..1979 plays:>0".to_string(),
+            sort: SmartPlaylistSort::Plays,
+            limit: Some(2),
+        });
+        assert_eq!(smart.text(), "year:1970..1979 plays:>0 sort:plays limit:2");
+        assert_eq!(SmartPlaylist::parse("plays:0").unwrap().text(), "plays:0");
+
+        assert!(SmartPlaylist::parse("sort:loudest").is_err());
+        assert!(SmartPlaylist::parse("limit:-1").is_err());
+        assert!(SmartPlaylist::parse("genre:prog").is_err());
+    }
+
+    #[test]
+    fn evaluates_rules() {
+        let library = vec![
+            song("Roundabout", "Yes", "Fragile", 1971, 1),
+            song("Starless", "King Crimson", "Red", 1974, 7),
+            song("Epitaph", "King Crimson", "In the Court of the Crimson King", 1969, 3),
+            song("Red", "King Crimson", "Red", 1974, 1),
+            song("Heart of the Sunrise", "Yes", "Fragile", 1971, 9),
+        ];
+        let mut stats = SongStats::default();
+        let now = SystemTime::now();
+        stats.record_play(&library[1], now);
+        stats.record_play(&library[1], now);
+        stats.record_play(&library[0], now);
+
+        let songs = |text: &str| titles(SmartPlaylist::parse(text).unwrap().songs(&library, &stats));
+
+        assert_eq!(songs("year:1970..1979"), vec![
+            "Red",
+            "Starless",
+            "Roundabout",
+            "Heart of the Sunrise"
+        ]);
+        assert_eq!(songs("year:1970..1979 plays:0"), vec!["Red", "Heart of the Sunrise"]);
+        assert_eq!(songs("sort:plays limit:2"), vec!["Starless", "Roundabout"]);
+        assert_eq!(songs("crimson sort:title"), vec!["Epitaph", "Red", "Starless"]);
+        assert_eq!(songs("sort:random limit:3").len(), 3);
+    }
 }
@@ -0,0 +1,671 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    fs::{read, read_to_string, write},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use quick_xml::{escape::escape, events::Event, reader::Reader};
+use strum::{Display, EnumString, VariantNames};
+use url::Url;
+
+use crate::{cue::CueSheet, structs::Song};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, EnumString, VariantNames)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum PlaylistFormat {
+    M3u,
+    M3u8,
+    Pls,
+    Xspf,
+}
+
+impl PlaylistFormat {
+    /// The format of a playlist file, going by its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| Self::from_str(extension).ok())
+    }
+
+    /// The contents of a playlist file of this format, holding `songs`. With `relative_to`, the paths of songs inside
+    /// that directory, or near it, are written relative to it, so the playlist keeps working if the whole tree moves.
+    pub fn export(self, name: &str, songs: &[Song], relative_to: Option<&Path>) -> String {
+        match self {
+            Self::M3u | Self::M3u8 => export_m3u(name, songs, relative_to),
+            Self::Pls => export_pls(songs, relative_to),
+            Self::Xspf => export_xspf(name, songs, relative_to),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PlaylistFileError {
+    UnknownFormat(PathBuf),
+    Io(std::io::Error),
+    Xml(quick_xml::Error),
+}
+
+impl Display for PlaylistFileError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownFormat(path) => write!(
+                f,
+                "{path:?} is not a playlist file. Playlist files end in {}.",
+                PlaylistFormat::VARIANTS.join(", ")
+            ),
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Xml(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for PlaylistFileError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<quick_xml::Error> for PlaylistFileError {
+    fn from(value: quick_xml::Error) -> Self {
+        Self::Xml(value)
+    }
+}
+
+/// A song in a playlist file, with whatever the file says about it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlaylistFileEntry {
+    pub path: PathBuf,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub length: Option<Duration>,
+    /// Set for entries that only play part of their file, like a track of a cue sheet.
+    pub start_time: Option<Duration>,
+    pub end_time: Option<Duration>,
+}
+
+/// An M3U, M3U8, PLS or XSPF playlist, from some other player.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlaylistFile {
+    pub path: PathBuf,
+    pub format: PlaylistFormat,
+    /// The name the file gives the playlist, or else the file's own name.
+    pub name: String,
+    pub entries: Vec<PlaylistFileEntry>,
+}
+
+impl PlaylistFile {
+    pub fn from_file(path: &Path) -> Result<Self, PlaylistFileError> {
+        let Some(format) = PlaylistFormat::from_path(path) else {
+            return Err(PlaylistFileError::UnknownFormat(path.to_path_buf()));
+        };
+        let text = match format {
+            // These are older than UTF-8 being everywhere, and are often in the code page of the system that wrote them.
+            PlaylistFormat::M3u | PlaylistFormat::Pls => {
+                String::from_utf8(read(path)?).unwrap_or_else(|err| decode_windows_1252(err.as_bytes()))
+            }
+            PlaylistFormat::M3u8 | PlaylistFormat::Xspf => read_to_string(path)?,
+        };
+        Self::parse(path, format, &text)
+    }
+
+    /// Entries with relative paths are relative to the directory of `path`. Entries that aren't local files, like
+    /// internet radio streams, are left out.
+    pub fn parse(path: &Path, format: PlaylistFormat, text: &str) -> Result<Self, PlaylistFileError> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let text = text.trim_start_matches('\u{feff}');
+        let (name, entries) = match format {
+            PlaylistFormat::M3u | PlaylistFormat::M3u8 => parse_m3u(dir, text),
+            PlaylistFormat::Pls => (None, parse_pls(dir, text)),
+            PlaylistFormat::Xspf => parse_xspf(dir, text)?,
+        };
+        let name = name.filter(|name| !name.is_empty()).unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default()
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            name,
+            entries,
+        })
+    }
+
+    /// Reads the songs of every entry. Entries that can't be read are skipped.
+    pub fn songs(&self) -> Vec<Song> {
+        self.entries.iter().flat_map(PlaylistFileEntry::songs).collect()
+    }
+}
+
+/// Windows-1252 is Latin-1, but for printable characters in place of most of the C1 control codes.
+fn decode_windows_1252(bytes: &[u8]) -> String {
+    const C1: [char; 32] = [
+        '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}', '\u{90}', '‘',
+        '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+    ];
+    bytes
+        .iter()
+        .map(|&byte| match byte {
+            0x80..=0x9f => C1[usize::from(byte - 0x80)],
+            _ => char::from(byte),
+        })
+        .collect()
+}
+
+impl PlaylistFileEntry {
+    fn songs(&self) -> Vec<Song> {
+        if self.path.extension().is_some_and(|e| e == "cue") {
+            return match CueSheet::from_file(&self.path) {
+                Ok(cue_sheet) => Song::from_cue_sheet(cue_sheet),
+                Err(err) => {
+                    log::warn!("Could not read {:?} from a playlist file: {err}", self.path);
+                    vec![]
+                }
+            };
+        }
+
+        let mut song = match Song::from_file(&self.path) {
+            Ok(song) => song,
+            Err(err) => {
+                log::warn!("Could not read {:?} from a playlist file: {err}", self.path);
+                return vec![];
+            }
+        };
+
+        if self.start_time.is_some() || self.end_time.is_some() {
+            // The file's own tags describe the whole of it, not the part this entry plays.
+            let start_time = self.start_time.unwrap_or_default();
+            song.length = match self.end_time {
+                Some(end_time) => end_time.saturating_sub(start_time),
+                None => song.length.saturating_sub(start_time),
+            };
+            song.start_time = start_time;
+            if let Some(title) = &self.title {
+                song.title.clone_from(title);
+            }
+            if self.artist.is_some() {
+                song.artist.clone_from(&self.artist);
+            }
+            if self.album.is_some() {
+                song.album.clone_from(&self.album);
+            }
+        }
+
+        vec![song]
+    }
+}
+
+/// A local file, from a path, relative to `dir` or absolute, or from a `file://` URL.
+fn resolve_location(dir: &Path, location: &str) -> Option<PathBuf> {
+    if location.starts_with("file://") {
+        return Url::parse(location).ok()?.to_file_path().ok();
+    }
+    if location.contains("://") {
+        return None;
+    }
+    // Without the `..`s relative paths come with, songs from playlists are the same as songs from anywhere else.
+    let mut path = PathBuf::new();
+    for component in dir.join(location).components() {
+        match component {
+            Component::ParentDir if path.file_name().is_some() => {
+                path.pop();
+            }
+            Component::CurDir => {}
+            component => path.push(component),
+        }
+    }
+    Some(path)
+}
+
+fn parse_seconds(seconds: &str) -> Option<Duration> {
+    Duration::try_from_secs_f64(seconds.trim().parse().ok()?).ok()
+}
+
+/// Splits titles the way M3U and PLS files usually write them, like `Artist - Title`.
+fn parse_display_title(title: &str) -> (Option<String>, Option<String>) {
+    match title.split_once(" - ") {
+        Some((artist, title)) => (Some(artist.trim().to_string()), Some(title.trim().to_string())),
+        None if title.trim().is_empty() => (None, None),
+        None => (None, Some(title.trim().to_string())),
+    }
+}
+
+fn display_title(song: &Song) -> String {
+    match &song.artist {
+        Some(artist) => format!("{artist} - {}", song.title),
+        None => song.title.clone(),
+    }
+}
+
+/// Parses the `start-time` and `stop-time` options VLC uses for entries that play part of their file.
+fn parse_vlc_option(entry: &mut PlaylistFileEntry, option: &str) {
+    if let Some((name, value)) = option.split_once('=') {
+        match name.trim() {
+            "start-time" => entry.start_time = parse_seconds(value),
+            "stop-time" => entry.end_time = parse_seconds(value),
+            _ => {}
+        }
+    }
+}
+
+fn parse_m3u(dir: &Path, text: &str) -> (Option<String>, Vec<PlaylistFileEntry>) {
+    let mut name = None;
+    let mut entries = vec![];
+    let mut entry = PlaylistFileEntry::default();
+
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            let (length, title) = info.split_once(',').unwrap_or((info, ""));
+            // The length may be followed by attributes, like `#EXTINF:300 tvg-id="x",Title`. -1 means unknown.
+            entry.length = length.split_whitespace().next().and_then(parse_seconds);
+            (entry.artist, entry.title) = parse_display_title(title);
+        } else if let Some(playlist) = line.strip_prefix("#PLAYLIST:") {
+            name = Some(playlist.trim().to_string());
+        } else if let Some(option) = line.strip_prefix("#EXTVLCOPT:") {
+            parse_vlc_option(&mut entry, option);
+        } else if !line.starts_with('#') {
+            let entry = std::mem::take(&mut entry);
+            if let Some(path) = resolve_location(dir, line) {
+                entries.push(PlaylistFileEntry { path, ..entry });
+            }
+        }
+    }
+
+    (name, entries)
+}
+
+fn parse_pls(dir: &Path, text: &str) -> Vec<PlaylistFileEntry> {
+    let mut entries: BTreeMap<usize, (Option<String>, PlaylistFileEntry)> = BTreeMap::new();
+
+    for line in text.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_lowercase();
+        let field = key.trim_end_matches(|c: char| c.is_ascii_digit());
+        let Ok(index) = key[field.len()..].parse() else {
+            continue;
+        };
+        let (location, entry) = entries.entry(index).or_default();
+        match field {
+            "file" => *location = Some(value.trim().to_string()),
+            "title" => (entry.artist, entry.title) = parse_display_title(value),
+            "length" => entry.length = parse_seconds(value),
+            _ => {}
+        }
+    }
+
+    entries
+        .into_values()
+        .filter_map(|(location, entry)| {
+            let path = resolve_location(dir, &location?)?;
+            Some(PlaylistFileEntry { path, ..entry })
+        })
+        .collect()
+}
+
+fn parse_xspf(dir: &Path, text: &str) -> Result<(Option<String>, Vec<PlaylistFileEntry>), PlaylistFileError> {
+    // XSPF locations are URIs, which relative ones are resolved against.
+    let base = std::path::absolute(dir)
+        .ok()
+        .and_then(|dir| Url::from_directory_path(dir).ok());
+
+    let mut reader = Reader::from_str(text);
+    reader.config_mut().trim_text(true);
+
+    let mut elements: Vec<String> = vec![];
+    let mut name = None;
+    let mut entries = vec![];
+    let mut location: Option<String> = None;
+    let mut entry = PlaylistFileEntry::default();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                elements.push(String::from_utf8_lossy(element.local_name().as_ref()).to_string());
+            }
+            Event::End(_) => {
+                if elements.pop().as_deref() == Some("track") {
+                    let entry = std::mem::take(&mut entry);
+                    let path = location.take().and_then(|location| match &base {
+                        Some(base) => base.join(&location).ok()?.to_file_path().ok(),
+                        None => resolve_location(dir, &location),
+                    });
+                    if let Some(path) = path {
+                        entries.push(PlaylistFileEntry { path, ..entry });
+                    }
+                }
+            }
+            Event::Text(text) => {
+                let text = text.unescape()?.to_string();
+                match elements.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+                    ["playlist", "title"] => name = Some(text),
+                    [.., "track", "location"] if location.is_none() => location = Some(text),
+                    [.., "track", "title"] => entry.title = Some(text),
+                    [.., "track", "creator"] => entry.artist = Some(text),
+                    [.., "track", "album"] => entry.album = Some(text),
+                    [.., "track", "duration"] => {
+                        entry.length = text.parse().ok().map(Duration::from_millis);
+                    }
+                    [.., "track", "extension", "option"] => parse_vlc_option(&mut entry, &text),
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok((name, entries))
+}
+
+/// `path`, relative to `dir`, if they have more than the root in common.
+fn relative_path(dir: &Path, path: &Path) -> PathBuf {
+    let dir_components: Vec<Component> = dir.components().collect();
+    let path_components: Vec<Component> = path.components().collect();
+    let common = dir_components
+        .iter()
+        .zip(&path_components)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let shares_root_only = dir_components
+        .iter()
+        .take(common)
+        .all(|component| matches!(component, Component::RootDir | Component::Prefix(_)));
+    if !dir.is_absolute() || !path.is_absolute() || shares_root_only {
+        return path.to_path_buf();
+    }
+
+    let mut relative = PathBuf::new();
+    for _ in common..dir_components.len() {
+        relative.push("..");
+    }
+    for component in &path_components[common..] {
+        relative.push(component);
+    }
+    relative
+}
+
+fn song_location(song: &Song, relative_to: Option<&Path>) -> PathBuf {
+    match relative_to {
+        Some(dir) => relative_path(dir, &song.path),
+        None => song.path.clone(),
+    }
+}
+
+/// Where a song starts and stops within its file, for songs that only play part of it, like tracks of a cue sheet.
+fn song_range(song: &Song, songs: &[Song]) -> Option<(Duration, Duration)> {
+    let shares_file = songs
+        .iter()
+        .any(|other| other.path == song.path && other.start_time != song.start_time);
+    (!song.start_time.is_zero() || shares_file).then(|| (song.start_time, song.start_time + song.length))
+}
+
+fn export_m3u(name: &str, songs: &[Song], relative_to: Option<&Path>) -> String {
+    let mut text = format!("#EXTM3U\n#PLAYLIST:{name}\n");
+    for song in songs {
+        text.push_str(&format!("#EXTINF:{},{}\n", song.length.as_secs(), display_title(song)));
+        if let Some((start, stop)) = song_range(song, songs) {
+            text.push_str(&format!("#EXTVLCOPT:start-time={}\n", start.as_secs_f64()));
+            text.push_str(&format!("#EXTVLCOPT:stop-time={}\n", stop.as_secs_f64()));
+        }
+        text.push_str(&format!("{}\n", song_location(song, relative_to).to_string_lossy()));
+    }
+    text
+}
+
+/// PLS has no way to play part of a file, so tracks of cue sheets play their whole file.
+fn export_pls(songs: &[Song], relative_to: Option<&Path>) -> String {
+    let mut text = "[playlist]\n".to_string();
+    for (i, song) in songs.iter().enumerate() {
+        let n = i + 1;
+        text.push_str(&format!(
+            "File{n}={}\n",
+            song_location(song, relative_to).to_string_lossy()
+        ));
+        text.push_str(&format!("Title{n}={}\n", display_title(song)));
+        text.push_str(&format!("Length{n}={}\n", song.length.as_secs()));
+    }
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", songs.len()));
+    text
+}
+
+fn export_xspf(name: &str, songs: &[Song], relative_to: Option<&Path>) -> String {
+    let mut text = concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\" xmlns:vlc=\"http://www.videolan.org/vlc/playlist/ns/0/\">\n",
+    )
+    .to_string();
+    text.push_str(&format!("  <title>{}</title>\n  <trackList>\n", escape(name)));
+
+    for song in songs {
+        let location = song_location(song, relative_to);
+        let location = if location.is_absolute() {
+            Url::from_file_path(&location).map(String::from).ok()
+        } else {
+            // Relative URIs come out of the URLs of the song and the directory, so they're escaped the same way.
+            relative_to
+                .and_then(|dir| Url::from_directory_path(dir).ok())
+                .zip(Url::from_file_path(&song.path).ok())
+                .and_then(|(dir, song)| dir.make_relative(&song))
+        };
+        let Some(location) = location else {
+            log::warn!("Could not write the location of {:?} to an XSPF playlist.", song.path);
+            continue;
+        };
+
+        text.push_str("    <track>\n");
+        text.push_str(&format!("      <location>{}</location>\n", escape(&location)));
+        text.push_str(&format!("      <title>{}</title>\n", escape(&song.title)));
+        if let Some(artist) = &song.artist {
+            text.push_str(&format!("      <creator>{}</creator>\n", escape(artist)));
+        }
+        if let Some(album) = &song.album {
+            text.push_str(&format!("      <album>{}</album>\n", escape(album)));
+        }
+        if let Some(track) = song.track {
+            text.push_str(&format!("      <trackNum>{track}</trackNum>\n"));
+        }
+        text.push_str(&format!("      <duration>{}</duration>\n", song.length.as_millis()));
+        if let Some((start, stop)) = song_range(song, songs) {
+            text.push_str("      <extension application=\"http://www.videolan.org/vlc/playlist/0\">\n");
+            text.push_str(&format!(
+                "        <vlc:option>start-time={}</vlc:option>\n",
+                start.as_secs_f64()
+            ));
+            text.push_str(&format!(
+                "        <vlc:option>stop-time={}</vlc:option>\n",
+                stop.as_secs_f64()
+            ));
+            text.push_str("      </extension>\n");
+        }
+        text.push_str("    </track>\n");
+    }
+
+    text.push_str("  </trackList>\n</playlist>\n");
+    text
+}
+
+/// Writes `songs` to a playlist file at `path`, in the format its extension names.
+pub fn export_playlist_file(path: &Path, name: &str, songs: &[Song], relative: bool) -> Result<(), PlaylistFileError> {
+    let Some(format) = PlaylistFormat::from_path(path) else {
+        return Err(PlaylistFileError::UnknownFormat(path.to_path_buf()));
+    };
+    let dir = std::path::absolute(path)?.parent().map(Path::to_path_buf);
+    let relative_to = if relative { dir.as_deref() } else { None };
+    write(path, format.export(name, songs, relative_to))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::TempDir;
+
+    fn song(path: &str, title: &str, start_time: u64, length: u64) -> Song {
+        Song {
+            library_id: None,
+            path: PathBuf::from(path),
+            start_time: Duration::from_secs(start_time),
+            length: Duration::from_secs(length),
+            title: title.to_string(),
+            artist: Some("Tim Buckley".to_string()),
+            album: Some("Happy Sad".to_string()),
+            album_artist: None,
+            soundtrack_subject: None,
+            disc_number: None,
+            track: None,
+            year: None,
+            replay_gain: Default::default(),
+        }
+    }
+
+    fn songs() -> Vec<Song> {
+        vec![
+            song("/music/Happy Sad/album.flac", "Strange Feelin'", 0, 458),
+            song("/music/Happy Sad/album.flac", "Buzzin' Fly", 458, 363),
+            song(
+                "/music/Starsailor & More/Song to the Siren.mp3",
+                "Song to the Siren",
+                0,
+                201,
+            ),
+        ]
+    }
+
+    fn round_trip(format: PlaylistFormat, relative_to: Option<&Path>) -> PlaylistFile {
+        let text = format.export("Sad & Happy", &songs(), relative_to);
+        PlaylistFile::parse(Path::new("/music/playlists/list"), format, &text).unwrap()
+    }
+
+    #[test]
+    fn round_trips_m3u() {
+        let playlist = round_trip(PlaylistFormat::M3u8, Some(Path::new("/music/playlists")));
+        assert_eq!(playlist.name, "Sad & Happy");
+        assert_eq!(playlist.entries, vec![
+            PlaylistFileEntry {
+                path: PathBuf::from("/music/Happy Sad/album.flac"),
+                title: Some("Strange Feelin'".to_string()),
+                artist: Some("Tim Buckley".to_string()),
+                album: None,
+                length: Some(Duration::from_secs(458)),
+                start_time: Some(Duration::ZERO),
+                end_time: Some(Duration::from_secs(458)),
+            },
+            PlaylistFileEntry {
+                path: PathBuf::from("/music/Happy Sad/album.flac"),
+                title: Some("Buzzin' Fly".to_string()),
+                artist: Some("Tim Buckley".to_string()),
+                album: None,
+                length: Some(Duration::from_secs(363)),
+                start_time: Some(Duration::from_secs(458)),
+                end_time: Some(Duration::from_secs(821)),
+            },
+            PlaylistFileEntry {
+                path: PathBuf::from("/music/Starsailor & More/Song to the Siren.mp3"),
+                title: Some("Song to the Siren".to_string()),
+                artist: Some("Tim Buckley".to_string()),
+                album: None,
+                length: Some(Duration::from_secs(201)),
+                start_time: None,
+                end_time: None,
+            },
+        ]);
+    }
+
+    #[test]
+    fn round_trips_pls() {
+        let playlist = round_trip(PlaylistFormat::Pls, None);
+        assert_eq!(playlist.name, "list");
+        assert_eq!(playlist.entries.len(), 3);
+        assert_eq!(playlist.entries[1].path, PathBuf::from("/music/Happy Sad/album.flac"));
+        assert_eq!(playlist.entries[1].title.as_deref(), Some("Buzzin' Fly"));
+        assert_eq!(playlist.entries[1].start_time, None);
+        assert_eq!(playlist.entries[2].length, Some(Duration::from_secs(201)));
+    }
+
+    #[test]
+    fn round_trips_xspf() {
+        for relative_to in [None, Some(Path::new("/music/playlists"))] {
+            let playlist = round_trip(PlaylistFormat::Xspf, relative_to);
+            assert_eq!(playlist.name, "Sad & Happy");
+            assert_eq!(playlist.entries.len(), 3);
+            assert_eq!(playlist.entries[1].path, PathBuf::from("/music/Happy Sad/album.flac"));
+            assert_eq!(playlist.entries[1].album.as_deref(), Some("Happy Sad"));
+            assert_eq!(playlist.entries[1].start_time, Some(Duration::from_secs(458)));
+            assert_eq!(playlist.entries[1].end_time, Some(Duration::from_secs(821)));
+            assert_eq!(
+                playlist.entries[2].path,
+                PathBuf::from("/music/Starsailor & More/Song to the Siren.mp3")
+            );
+            assert_eq!(playlist.entries[2].start_time, None);
+        }
+
+        let text = PlaylistFormat::Xspf.export("x", &songs(), Some(Path::new("/music/playlists")));
+        assert!(text.contains("<location>../Starsailor%20&amp;%20More/Song%20to%20the%20Siren.mp3</location>"));
+    }
+
+    #[test]
+    fn reads_other_players_playlists() {
+        let text = "\u{feff}#EXTM3U\r\n#EXTINF:-1 tvg-id=\"x\",Radio\r\nhttp://radio.example/stream\r\n\r\n# a comment\r\nsong.mp3\r\nfile:///music/other%20song.ogg\r\n";
+        let playlist = PlaylistFile::parse(Path::new("/music/list.m3u"), PlaylistFormat::M3u, text).unwrap();
+        assert_eq!(playlist.name, "list");
+        assert_eq!(
+            playlist
+                .entries
+                .iter()
+                .map(|entry| entry.path.clone())
+                .collect::<Vec<_>>(),
+            vec![PathBuf::from("/music/song.mp3"), PathBuf::from("/music/other song.ogg")]
+        );
+        assert_eq!(playlist.entries[0].title, None);
+
+        let text = "[playlist]\nfile2=b.mp3\nFile1=a.mp3\nTitle1=Just a Title\nLength1=-1\nNumberOfEntries=2\n";
+        let playlist = PlaylistFile::parse(Path::new("/music/list.pls"), PlaylistFormat::Pls, text).unwrap();
+        assert_eq!(playlist.entries[0].path, PathBuf::from("/music/a.mp3"));
+        assert_eq!(playlist.entries[0].title.as_deref(), Some("Just a Title"));
+        assert_eq!(playlist.entries[0].artist, None);
+        assert_eq!(playlist.entries[0].length, None);
+        assert_eq!(playlist.entries[1].path, PathBuf::from("/music/b.mp3"));
+    }
+
+    #[test]
+    fn reads_m3u_and_pls_in_windows_1252() {
+        let dir = TempDir::new("playlist-file");
+        let text = b"#EXTM3U\n#EXTINF:100,Beyonc\xe9 \x96 D\xe9j\xe0 Vu\n/music/d\xe9j\xe0 vu.mp3\n";
+
+        std::fs::write(dir.join("list.m3u"), text).unwrap();
+        let playlist = PlaylistFile::from_file(&dir.join("list.m3u")).unwrap();
+        assert_eq!(playlist.entries[0].path, Path::new("/music/déjà vu.mp3"));
+        assert_eq!(playlist.entries[0].title.as_deref(), Some("Beyoncé – Déjà Vu"));
+
+        // M3U8 files are UTF-8 by definition.
+        std::fs::write(dir.join("list.m3u8"), text).unwrap();
+        assert!(PlaylistFile::from_file(&dir.join("list.m3u8")).is_err());
+    }
+
+    #[test]
+    fn writes_relative_paths_when_they_make_sense() {
+        let dir = Path::new("/music/playlists");
+        assert_eq!(
+            relative_path(dir, Path::new("/music/playlists/a.mp3")),
+            PathBuf::from("a.mp3")
+        );
+        assert_eq!(
+            relative_path(dir, Path::new("/music/b/a.mp3")),
+            PathBuf::from("../b/a.mp3")
+        );
+        assert_eq!(relative_path(dir, Path::new("/mnt/a.mp3")), PathBuf::from("/mnt/a.mp3"));
+        assert_eq!(
+            PlaylistFormat::from_path(Path::new("a.M3U8")),
+            Some(PlaylistFormat::M3u8)
+        );
+        assert_eq!(PlaylistFormat::from_path(Path::new("a.txt")), None);
+    }
+}
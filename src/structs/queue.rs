@@ -32,6 +32,10 @@ impl Queue {
         song
     }
 
+    pub fn peek(&self) -> Option<Song> {
+        self.songs().front().cloned()
+    }
+
     pub fn with_items(&self, f: impl FnOnce(&VecDeque<Song>)) {
         let songs = self.songs();
         f(&songs);
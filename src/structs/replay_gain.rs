@@ -0,0 +1,145 @@
+use std::hash::{Hash, Hasher};
+
+use lofty::{prelude::ItemKey, tag::Tag};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReplayGainMode {
+    Track,
+    Album,
+    Off,
+}
+
+/// ReplayGain values of a song, as found in its tags. Gains are in dB, peaks are linear sample values.
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq)]
+pub struct ReplayGain {
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+// Tags never hold NaN, so comparing bit patterns is good enough for `Song` to stay `Eq` and `Hash`.
+impl Eq for ReplayGain {}
+
+impl Hash for ReplayGain {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for value in [self.track_gain, self.track_peak, self.album_gain, self.album_peak] {
+            value.map(f32::to_bits).hash(state);
+        }
+    }
+}
+
+impl ReplayGain {
+    pub fn from_tag(tag: &Tag) -> Self {
+        Self {
+            track_gain: tag.get_string(&ItemKey::ReplayGainTrackGain).and_then(parse_gain),
+            track_peak: tag.get_string(&ItemKey::ReplayGainTrackPeak).and_then(parse_peak),
+            album_gain: tag.get_string(&ItemKey::ReplayGainAlbumGain).and_then(parse_gain),
+            album_peak: tag.get_string(&ItemKey::ReplayGainAlbumPeak).and_then(parse_peak),
+        }
+    }
+
+    /// Reads album values from cue sheet comments, such as `REM REPLAYGAIN_ALBUM_GAIN -7.89 dB`.
+    pub fn from_cue_comments(comments: &[String]) -> Self {
+        let find = |key: &str| {
+            comments
+                .iter()
+                .find_map(|comment| comment.strip_prefix(key)?.strip_prefix(' '))
+        };
+
+        Self {
+            track_gain: None,
+            track_peak: None,
+            album_gain: find("REPLAYGAIN_ALBUM_GAIN").and_then(parse_gain),
+            album_peak: find("REPLAYGAIN_ALBUM_PEAK").and_then(parse_peak),
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The linear factor to amplify the song by.
+    /// Falls back to the values of the other mode when the ones of the chosen mode are missing.
+    pub fn factor(&self, mode: ReplayGainMode, preamp: f32, prevent_clipping: bool) -> f32 {
+        let (gain, peak) = match mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => (self.track_gain.or(self.album_gain), self.track_peak.or(self.album_peak)),
+            ReplayGainMode::Album => (self.album_gain.or(self.track_gain), self.album_peak.or(self.track_peak)),
+        };
+
+        let Some(gain) = gain else {
+            return 1.0;
+        };
+
+        let factor = 10f32.powf((gain + preamp) / 20.0);
+
+        match peak {
+            Some(peak) if prevent_clipping && peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
+
+fn parse_gain(s: &str) -> Option<f32> {
+    let s = s.trim();
+    let s = s.strip_suffix("dB").or(s.strip_suffix("db")).unwrap_or(s);
+    s.trim().parse().ok()
+}
+
+fn parse_peak(s: &str) -> Option<f32> {
+    s.trim().parse().ok().filter(|peak: &f32| *peak >= 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_tag_values() {
+        assert_eq!(parse_gain("-6.54 dB"), Some(-6.54));
+        assert_eq!(parse_gain("+1.20 dB"), Some(1.2));
+        assert_eq!(parse_gain("3.5"), Some(3.5));
+        assert_eq!(parse_gain("loud"), None);
+        assert_eq!(parse_peak("0.988547"), Some(0.988547));
+        assert_eq!(parse_peak("-1"), None);
+    }
+
+    #[test]
+    fn from_cue_comments() {
+        let comments = vec![
+            "DATE 1969".to_string(),
+            "REPLAYGAIN_ALBUM_GAIN -7.89 dB".to_string(),
+            "REPLAYGAIN_ALBUM_PEAK 0.5".to_string(),
+        ];
+        let replay_gain = ReplayGain::from_cue_comments(&comments);
+        assert_eq!(replay_gain.album_gain, Some(-7.89));
+        assert_eq!(replay_gain.album_peak, Some(0.5));
+        assert_eq!(replay_gain.track_gain, None);
+    }
+
+    #[test]
+    fn factor() {
+        let replay_gain = ReplayGain {
+            track_gain: Some(-20.0),
+            track_peak: Some(0.5),
+            album_gain: Some(6.0),
+            album_peak: Some(0.8),
+        };
+
+        assert_eq!(replay_gain.factor(ReplayGainMode::Off, 0.0, true), 1.0);
+        assert!((replay_gain.factor(ReplayGainMode::Track, 0.0, true) - 0.1).abs() < 1e-6);
+        assert!((replay_gain.factor(ReplayGainMode::Track, 20.0, false) - 1.0).abs() < 1e-6);
+        assert!((replay_gain.factor(ReplayGainMode::Track, 40.0, true) - 2.0).abs() < 1e-6);
+        assert!((replay_gain.factor(ReplayGainMode::Album, 0.0, true) - 1.25).abs() < 1e-6);
+        assert!(replay_gain.factor(ReplayGainMode::Album, 0.0, false) > 1.99);
+
+        let album_only = ReplayGain {
+            album_gain: Some(-20.0),
+            ..ReplayGain::default()
+        };
+        assert!((album_only.factor(ReplayGainMode::Track, 0.0, true) - 0.1).abs() < 1e-6);
+        assert_eq!(ReplayGain::default().factor(ReplayGainMode::Track, 6.0, true), 1.0);
+    }
+}
@@ -8,6 +8,7 @@ use std::{
 use lofty::{
     error::LoftyError,
     file::{AudioFile, TaggedFileExt},
+    prelude::ItemKey,
     probe::Probe,
     tag::Accessor,
 };
@@ -17,7 +18,7 @@ use uuid::Uuid;
 use crate::{
     components::{FileBrowserSelection, dir_entry_is_song, directory_to_songs_and_folders},
     cue::{CueFile, CueSheet},
-    structs::Jolt,
+    structs::{Jolt, ReplayGain},
 };
 
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
@@ -29,10 +30,15 @@ pub struct Song {
     pub title: String,
     pub artist: Option<String>,
     pub album: Option<String>,
+    /// The album artist tag, or the performer of a cue sheet. Songs added before it was read don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album_artist: Option<String>,
     pub soundtrack_subject: Option<String>,
     pub disc_number: Option<u32>,
     pub track: Option<u32>,
     pub year: Option<u32>,
+    #[serde(default, skip_serializing_if = "ReplayGain::is_none")]
+    pub replay_gain: ReplayGain,
 }
 
 fn find_closest_jolt(path: &Path) -> Option<Jolt> {
@@ -57,6 +63,12 @@ impl Song {
             _ => (None, None, None, None, None, None),
         };
 
+        let replay_gain = tagged_file.primary_tag().map(ReplayGain::from_tag).unwrap_or_default();
+        let album_artist = tagged_file
+            .primary_tag()
+            .and_then(|tag| tag.get_string(&ItemKey::AlbumArtist))
+            .map(String::from);
+
         Ok(Song {
             library_id: None,
             path: PathBuf::from(path),
@@ -65,10 +77,12 @@ impl Song {
             title: title.unwrap_or(path.file_name().unwrap().to_str().unwrap().to_string()),
             artist: jolt.as_ref().and_then(|j| j.artist.clone()).or(artist),
             album: jolt.as_ref().and_then(|j| j.album.clone()).or(album),
+            album_artist,
             soundtrack_subject: jolt.as_ref().and_then(|j| j.soundtrack_subject.clone()),
             disc_number,
             track,
             year: jolt.as_ref().and_then(|j| j.year).or(year),
+            replay_gain,
         })
     }
 
@@ -157,10 +171,25 @@ impl Song {
 
         log::debug!("DATE from cue sheet: {cue_date:?}");
 
-        let cue_year: Option<u32> = cue_date.unwrap()[5..].parse().ok(); // TODO: 100% not safe lol
+        let cue_year: Option<u32> = cue_date.and_then(|date| date[5..].parse().ok());
 
         log::debug!("DATE from cue sheet: {cue_year:?}");
 
+        // The file behind a cue sheet usually holds the whole album, so its own gain is effectively the album gain.
+        let cue_replay_gain = ReplayGain::from_cue_comments(&cue_sheet.comments());
+        let replay_gain = ReplayGain {
+            track_gain: None,
+            track_peak: None,
+            album_gain: cue_replay_gain
+                .album_gain
+                .or(song.replay_gain.album_gain)
+                .or(song.replay_gain.track_gain),
+            album_peak: cue_replay_gain
+                .album_peak
+                .or(song.replay_gain.album_peak)
+                .or(song.replay_gain.track_peak),
+        };
+
         let mut songs: Vec<Song> = tracks
             .iter()
             .map(|t| Song {
@@ -175,10 +204,12 @@ impl Song {
                 title: t.title(),
                 start_time: t.start_time(),
                 album: jolt.as_ref().and_then(|j| j.album.clone()).or(cue_sheet.title()),
+                album_artist: performer.clone().or(song.album_artist.clone()),
                 soundtrack_subject: jolt.as_ref().and_then(|j| j.soundtrack_subject.clone()),
                 track: t.index().split_whitespace().nth(0).and_then(|i| i.parse().ok()),
                 year: jolt.as_ref().and_then(|j| j.year).or(song.year).or(cue_year),
                 disc_number: jolt.as_ref().and_then(|j| j.disc_number), // There seems to be no standard disc number field for Cue Sheets...
+                replay_gain,
             })
             .collect();
 
@@ -203,12 +234,57 @@ impl Song {
             .collect()
     }
 
+    /// Songs of a single file, a cue sheet, a playlist file or a directory, the same ones the file browser queues.
+    pub fn from_path(path: &Path) -> Result<Vec<Self>, String> {
+        FileBrowserSelection::from_path(path)
+            .map(FileBrowserSelection::into_songs)
+            .ok_or_else(|| "Not a song, cue sheet, playlist file or directory that can be read".to_string())
+    }
+
+    /// The artists of tracks of compilations and soundtracks differ, so albums are told apart by their album artist, or
+    /// by their directory when the tags don't say.
+    pub fn is_same_album(&self, other: &Song) -> bool {
+        let is_same_album_artist = match (&self.album_artist, &other.album_artist) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.path.parent() == other.path.parent(),
+        };
+        self.album.is_some() && self.album == other.album && is_same_album_artist
+    }
+
+    /// A key grouping songs by album the way [`Song::is_same_album`] tells them apart.
+    pub fn album_key(&self) -> Option<(&str, AlbumOwner<'_>)> {
+        let owner = match &self.album_artist {
+            Some(album_artist) => AlbumOwner::AlbumArtist(album_artist),
+            None => AlbumOwner::Directory(self.path.parent()?),
+        };
+        Some((self.album.as_deref()?, owner))
+    }
+
+    /// Who an album is by: its album artist, or the artist of its tracks when the tags don't say and they all share one.
+    pub fn album_artist_of<'a>(songs: impl IntoIterator<Item = &'a Song>) -> &'a str {
+        let mut songs = songs.into_iter().peekable();
+        if let Some(album_artist) = songs.peek().and_then(|song| song.album_artist.as_deref()) {
+            return album_artist;
+        }
+        let mut artists = songs.map(|song| song.artist.as_deref());
+        match artists.next().flatten() {
+            Some(artist) if artists.all(|other| other == Some(artist)) => artist,
+            _ => "Various Artists",
+        }
+    }
+
     pub fn get_tags(&self) -> Vec<lofty::tag::Tag> {
         let tagged_file = Probe::open(&self.path).unwrap().read().unwrap();
         tagged_file.tags().to_vec()
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AlbumOwner<'a> {
+    AlbumArtist(&'a str),
+    Directory(&'a Path),
+}
+
 impl Ord for Song {
     fn cmp(&self, other: &Self) -> Ordering {
         match (&self.album, &other.album) {
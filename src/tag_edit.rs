@@ -0,0 +1,411 @@
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use lofty::{
+    config::WriteOptions,
+    error::LoftyError,
+    file::{AudioFile, TaggedFile, TaggedFileExt},
+    prelude::ItemKey,
+    probe::Probe,
+    tag::{Accessor, Tag},
+};
+use strum::{Display, VariantArray};
+
+use crate::structs::Song;
+
+/// Songs at least this much shorter than their file only play part of it, like the tracks of a cue sheet.
+const PARTIAL_SONG_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// The tags the tag editor can change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, VariantArray)]
+pub enum TagField {
+    Title,
+    Artist,
+    Album,
+    #[strum(serialize = "Album artist")]
+    AlbumArtist,
+    Track,
+    Disc,
+    Year,
+    Genre,
+}
+
+impl TagField {
+    fn is_number(self) -> bool {
+        matches!(self, Self::Track | Self::Disc | Self::Year)
+    }
+
+    fn get(self, tag: &Tag) -> Option<String> {
+        match self {
+            Self::Title => tag.title().map(String::from),
+            Self::Artist => tag.artist().map(String::from),
+            Self::Album => tag.album().map(String::from),
+            Self::AlbumArtist => tag.get_string(&ItemKey::AlbumArtist).map(String::from),
+            Self::Track => tag.track().map(|track| track.to_string()),
+            Self::Disc => tag.disk().map(|disc| disc.to_string()),
+            Self::Year => tag.year().map(|year| year.to_string()),
+            Self::Genre => tag.genre().map(String::from),
+        }
+    }
+
+    /// An empty value removes the tag.
+    fn set(self, tag: &mut Tag, value: &str) -> Result<(), TagEditError> {
+        let value = value.trim();
+        if value.is_empty() {
+            match self {
+                Self::Title => tag.remove_title(),
+                Self::Artist => tag.remove_artist(),
+                Self::Album => tag.remove_album(),
+                Self::AlbumArtist => tag.remove_key(&ItemKey::AlbumArtist),
+                Self::Track => tag.remove_track(),
+                Self::Disc => tag.remove_disk(),
+                Self::Year => tag.remove_year(),
+                Self::Genre => tag.remove_genre(),
+            }
+            return Ok(());
+        }
+        match self {
+            Self::Title => tag.set_title(value.to_string()),
+            Self::Artist => tag.set_artist(value.to_string()),
+            Self::Album => tag.set_album(value.to_string()),
+            Self::AlbumArtist => {
+                tag.insert_text(ItemKey::AlbumArtist, value.to_string());
+            }
+            Self::Track => tag.set_track(self.parse_number(value)?),
+            Self::Disc => tag.set_disk(self.parse_number(value)?),
+            Self::Year => tag.set_year(self.parse_number(value)?),
+            Self::Genre => tag.set_genre(value.to_string()),
+        }
+        Ok(())
+    }
+
+    /// Years are dates in some tags, like ID3v2 ones, and those can't be read back unless the year has 4 digits.
+    fn parse_number(self, value: &str) -> Result<u32, TagEditError> {
+        let invalid = || TagEditError::InvalidNumber {
+            field: self,
+            value: value.to_string(),
+        };
+        let number = value.parse().map_err(|_| invalid())?;
+        if self == Self::Year && !(1000..=9999).contains(&number) {
+            return Err(invalid());
+        }
+        Ok(number)
+    }
+}
+
+#[derive(Debug)]
+pub enum TagEditError {
+    Lofty(PathBuf, LoftyError),
+    /// The song only plays part of its file, so the tags of the file aren't its own.
+    PartialSong(PathBuf),
+    InvalidNumber {
+        field: TagField,
+        value: String,
+    },
+    /// Songs of the Library and Soundtracks are listed by this field, so it can't be cleared.
+    Required(TagField),
+}
+
+impl Display for TagEditError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lofty(path, err) => write!(f, "{path:?}: {err}"),
+            Self::PartialSong(path) => write!(
+                f,
+                "{path:?} holds more than one song, like the tracks of a cue sheet. Its tags can't be edited per song."
+            ),
+            Self::InvalidNumber {
+                field: TagField::Year,
+                value,
+            } => write!(f, "Year must be a number of 4 digits, not {value:?}."),
+            Self::InvalidNumber { field, value } => write!(f, "{field} must be a number, not {value:?}."),
+            Self::Required(field) => write!(
+                f,
+                "{field} can't be cleared. The Library and Soundtracks list their songs by it."
+            ),
+        }
+    }
+}
+
+/// One tag, as the songs being edited have it and as it's being changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagFieldEdit {
+    pub field: TagField,
+    /// `None` when the songs don't all have the same value. An empty string when none of them have it.
+    pub original: Option<String>,
+    /// `None` until the field is changed, which leaves the tag of each song as it is.
+    pub value: Option<String>,
+}
+
+/// Changes to the tags of one or more songs, written back to their files all at once.
+#[derive(Clone, Debug)]
+pub struct TagEdit {
+    songs: Vec<Song>,
+    fields: Vec<TagFieldEdit>,
+    selected: usize,
+    number_tracks: bool,
+    required: Vec<TagField>,
+}
+
+impl TagEdit {
+    /// Songs listed more than once are only edited once.
+    pub fn new(songs: Vec<Song>) -> Result<Self, TagEditError> {
+        let mut paths = HashSet::new();
+        let songs: Vec<Song> = songs
+            .into_iter()
+            .filter(|song| paths.insert(song.path.clone()))
+            .collect();
+
+        let mut tags = Vec::with_capacity(songs.len());
+        for song in &songs {
+            let tagged_file = read(&song.path)?;
+            if song.start_time > Duration::ZERO
+                || song.length + PARTIAL_SONG_TOLERANCE < tagged_file.properties().duration()
+            {
+                return Err(TagEditError::PartialSong(song.path.clone()));
+            }
+            tags.push(tagged_file.primary_tag().cloned());
+        }
+
+        let fields = TagField::VARIANTS
+            .iter()
+            .map(|&field| {
+                let mut values = tags
+                    .iter()
+                    .map(|tag| tag.as_ref().and_then(|tag| field.get(tag)).unwrap_or_default());
+                let first = values.next().unwrap_or_default();
+                let original = values.all(|value| value == first).then_some(first);
+                TagFieldEdit {
+                    field,
+                    original,
+                    value: None,
+                }
+            })
+            .collect();
+
+        Ok(Self {
+            songs,
+            fields,
+            selected: 0,
+            number_tracks: false,
+            required: Vec::new(),
+        })
+    }
+
+    pub fn songs(&self) -> &[Song] {
+        &self.songs
+    }
+
+    pub fn selected_field(&self) -> &TagFieldEdit {
+        &self.fields[self.selected]
+    }
+
+    pub fn select_next(&mut self) {
+        self.selected = (self.selected + 1).min(self.fields.len() - 1);
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// The value of the selected field, starting from the one the songs have, if they all have the same.
+    pub fn text_mut(&mut self) -> &mut String {
+        let field = &mut self.fields[self.selected];
+        field
+            .value
+            .get_or_insert_with(|| field.original.clone().unwrap_or_default())
+    }
+
+    /// Whether the songs get numbered 1, 2, 3… in the order they're listed, rather than get the Track field.
+    pub fn number_tracks(&self) -> bool {
+        self.number_tracks
+    }
+
+    pub fn toggle_number_tracks(&mut self) {
+        self.number_tracks = !self.number_tracks;
+    }
+
+    /// Fields that can't be cleared, because the songs are listed by them.
+    pub fn set_required(&mut self, fields: Vec<TagField>) {
+        self.required = fields;
+    }
+
+    pub fn validate(&self) -> Result<(), TagEditError> {
+        for edit in &self.fields {
+            if self.required.contains(&edit.field) && edit.value.as_deref().is_some_and(|value| value.trim().is_empty())
+            {
+                return Err(TagEditError::Required(edit.field));
+            }
+            if !edit.field.is_number() || (edit.field == TagField::Track && self.number_tracks) {
+                continue;
+            }
+            if let Some(value) = edit.value.as_deref().map(str::trim)
+                && !value.is_empty()
+            {
+                edit.field.parse_number(value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the changes to every file it can. The songs are read back from the files that were written, and
+    /// the ones that weren't are left out.
+    pub fn write(&mut self) -> Vec<TagEditError> {
+        let mut songs = Vec::with_capacity(self.songs.len());
+        let mut errors = Vec::new();
+
+        for (i, song) in self.songs.iter().enumerate() {
+            let written = self
+                .write_song(i, &song.path)
+                .and_then(|()| Song::from_file(&song.path).map_err(|err| TagEditError::Lofty(song.path.clone(), err)));
+            match written {
+                Ok(written) => songs.push(written),
+                Err(err) => errors.push(err),
+            }
+        }
+
+        self.songs = songs;
+        errors
+    }
+
+    fn write_song(&self, index: usize, path: &Path) -> Result<(), TagEditError> {
+        let mut tagged_file = read(path)?;
+        if tagged_file.primary_tag().is_none() {
+            tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+        }
+        let tag = tagged_file.primary_tag_mut().unwrap();
+
+        for edit in &self.fields {
+            if let Some(value) = &edit.value
+                && !(edit.field == TagField::Track && self.number_tracks)
+            {
+                edit.field.set(tag, value)?;
+            }
+        }
+        if self.number_tracks {
+            tag.set_track(index as u32 + 1);
+        }
+
+        tagged_file
+            .save_to_path(path, WriteOptions::default())
+            .map_err(|err| TagEditError::Lofty(path.to_path_buf(), err))
+    }
+}
+
+fn read(path: &Path) -> Result<TaggedFile, TagEditError> {
+    Probe::open(path)
+        .and_then(|probe| probe.read())
+        .map_err(|err| TagEditError::Lofty(path.to_path_buf(), err))
+}
+
+/// Gives `to` the tags of `from`, keeping everything else, like its place in the library.
+pub fn copy_tags(from: &Song, to: &mut Song) {
+    to.title.clone_from(&from.title);
+    to.artist.clone_from(&from.artist);
+    to.album.clone_from(&from.album);
+    to.album_artist.clone_from(&from.album_artist);
+    to.track = from.track;
+    to.disc_number = from.disc_number;
+    to.year = from.year;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{TempDir, write_wav};
+
+    fn set(edit: &mut TagEdit, field: TagField, value: &str) {
+        edit.selected = edit.fields.iter().position(|edit| edit.field == field).unwrap();
+        *edit.text_mut() = value.to_string();
+    }
+
+    #[test]
+    fn writes_tags_of_an_album() {
+        let directory = TempDir::new("tag-edit");
+        let songs = vec![
+            write_wav(&directory.join("b.wav"), 1),
+            write_wav(&directory.join("a.wav"), 1),
+        ];
+
+        let mut edit = TagEdit::new(songs.clone()).unwrap();
+        assert_eq!(edit.selected_field().original, Some(String::new()));
+        set(&mut edit, TagField::Album, "Goodbye and Hello");
+        set(&mut edit, TagField::AlbumArtist, "Tim Buckley");
+        set(&mut edit, TagField::Year, "1967");
+        edit.toggle_number_tracks();
+        edit.validate().unwrap();
+        assert!(edit.write().is_empty());
+
+        let written = edit.songs();
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0].track, Some(1));
+        assert_eq!(written[1].track, Some(2));
+        assert!(
+            written
+                .iter()
+                .all(|song| song.album.as_deref() == Some("Goodbye and Hello"))
+        );
+        assert!(written.iter().all(|song| song.year == Some(1967)));
+
+        let mut edit = TagEdit::new(songs).unwrap();
+        assert_eq!(
+            edit.fields[TagField::AlbumArtist as usize].original.as_deref(),
+            Some("Tim Buckley")
+        );
+        assert_eq!(edit.fields[TagField::Track as usize].original, None);
+        set(&mut edit, TagField::Title, "Pleasant Street");
+        set(&mut edit, TagField::Year, "");
+        assert!(edit.write().is_empty());
+        let written = edit.songs();
+        assert!(
+            written
+                .iter()
+                .all(|song| song.title == "Pleasant Street" && song.year.is_none())
+        );
+        assert_eq!(written[1].track, Some(2));
+    }
+
+    #[test]
+    fn refuses_invalid_numbers_cleared_fields_and_partial_songs() {
+        let directory = TempDir::new("tag-edit-invalid");
+        let song = write_wav(&directory.join("a.wav"), 1);
+
+        let mut edit = TagEdit::new(vec![song.clone()]).unwrap();
+        set(&mut edit, TagField::Disc, "A1");
+        assert!(matches!(
+            edit.validate(),
+            Err(TagEditError::InvalidNumber {
+                field: TagField::Disc,
+                ..
+            })
+        ));
+        set(&mut edit, TagField::Disc, "1");
+        set(&mut edit, TagField::Year, "67");
+        assert!(matches!(
+            edit.validate(),
+            Err(TagEditError::InvalidNumber {
+                field: TagField::Year,
+                ..
+            })
+        ));
+
+        set(&mut edit, TagField::Year, "1967");
+        set(&mut edit, TagField::Artist, " ");
+        assert!(edit.validate().is_ok());
+        edit.set_required(vec![TagField::Artist, TagField::Album]);
+        assert!(matches!(edit.validate(), Err(TagEditError::Required(TagField::Artist))));
+        set(&mut edit, TagField::Artist, "Tim Buckley");
+        assert!(edit.validate().is_ok());
+
+        let part = Song {
+            start_time: Duration::from_millis(500),
+            length: Duration::from_millis(500),
+            ..song
+        };
+        assert!(matches!(TagEdit::new(vec![part]), Err(TagEditError::PartialSong(_))));
+    }
+}
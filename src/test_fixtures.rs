@@ -0,0 +1,49 @@
+use std::{
+    f32::consts::PI,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{output::WavWriter, structs::Song};
+
+const SAMPLE_RATE: u32 = 8_000;
+
+/// A directory of its own in the temporary directory. It's removed, along with everything in it, when dropped, so tests
+/// that fail don't leave it behind either.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("jolteon-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Writes a mono WAV file of a 440 Hz tone, `secs` seconds long, along with the directories it goes in, and reads it
+/// back as a song.
+pub fn write_wav(path: &Path, secs: u32) -> Song {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let mut writer = WavWriter::create(path, 1, SAMPLE_RATE).unwrap();
+    for i in 0..secs * SAMPLE_RATE {
+        let sample = 0.5 * (2.0 * PI * 440.0 * i as f32 / SAMPLE_RATE as f32).sin();
+        writer.write_frame(&[sample]).unwrap();
+    }
+    writer.finish().unwrap();
+    Song::from_file(path).unwrap()
+}
@@ -5,10 +5,16 @@ use ratatui::{
     prelude::*,
     style::Style,
     text::Line,
-    widgets::{Block, Borders, Gauge, TitlePosition},
+    widgets::{Block, Borders, Gauge, TitlePosition, WidgetRef},
 };
 
-use crate::{duration::duration_to_string, main_player::RepeatMode, structs::Song, theme::Theme};
+use crate::{
+    components::AlbumArt,
+    duration::duration_to_string,
+    main_player::{RepeatMode, ShuffleMode},
+    structs::Song,
+    theme::Theme,
+};
 
 pub fn song_to_string(song: &Song) -> String {
     let title = song.title.clone();
@@ -20,7 +26,7 @@ pub fn song_to_string(song: &Song) -> String {
     }
 }
 
-pub struct CurrentlyPlaying {
+pub struct CurrentlyPlaying<'a> {
     theme: Theme,
     current_song: Option<Song>,
     current_song_position: Duration,
@@ -28,11 +34,15 @@ pub struct CurrentlyPlaying {
     queue_song_count: usize,
     is_paused: bool,
     repeat_mode: RepeatMode,
+    shuffle_mode: ShuffleMode,
+    is_radio_on: bool,
     volume: u32,
+    speed: u32,
     frame: u64,
+    album_art: &'a AlbumArt,
 }
 
-impl CurrentlyPlaying {
+impl<'a> CurrentlyPlaying<'a> {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         theme: Theme,
@@ -42,8 +52,12 @@ impl CurrentlyPlaying {
         queue_song_count: usize,
         is_paused: bool,
         repeat_mode: RepeatMode,
+        shuffle_mode: ShuffleMode,
+        is_radio_on: bool,
         volume: u32,
+        speed: u32,
         frame: u64,
+        album_art: &'a AlbumArt,
     ) -> Self {
         Self {
             theme,
@@ -53,8 +67,12 @@ impl CurrentlyPlaying {
             queue_song_count,
             is_paused,
             repeat_mode,
+            shuffle_mode,
+            is_radio_on,
             volume,
+            speed,
             frame,
+            album_art,
         }
     }
 }
@@ -108,8 +126,25 @@ fn render_anim(area: Rect, buf: &mut Buffer, frame: u64, theme: Theme) {
     };
 }
 
-impl Widget for CurrentlyPlaying {
+impl Widget for CurrentlyPlaying<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        self.album_art
+            .set_path(self.current_song.as_ref().map(|song| song.path.as_path()));
+
+        let area = if self.album_art.has_picture() {
+            // Cells are about twice as tall as they are wide, so this is roughly square.
+            let [area_album_art, _, area] = Layout::horizontal([
+                Constraint::Length(area.height * 2),
+                Constraint::Length(1),
+                Constraint::Fill(1),
+            ])
+            .areas(area);
+            self.album_art.render_ref(area_album_art, buf);
+            area
+        } else {
+            area
+        };
+
         let [area_top, area_bottom] = Layout::vertical([Constraint::Length(2), Constraint::Length(1)]).areas(area);
 
         if let Some(ref current_song) = self.current_song {
@@ -175,7 +210,7 @@ impl Widget for CurrentlyPlaying {
         }
 
         let [_, area_bottom_right, _] =
-            Layout::horizontal([Constraint::Fill(1), Constraint::Length(30), Constraint::Length(1)]).areas(area_bottom);
+            Layout::horizontal([Constraint::Fill(1), Constraint::Length(40), Constraint::Length(1)]).areas(area_bottom);
 
         let mut status = vec![];
         if self.is_paused {
@@ -190,6 +225,22 @@ impl Widget for CurrentlyPlaying {
                 status.push("REPEAT QUEUE");
             }
         }
+        match self.shuffle_mode {
+            ShuffleMode::Off => {}
+            ShuffleMode::Tracks => {
+                status.push("SHUFFLE");
+            }
+            ShuffleMode::Albums => {
+                status.push("SHUFFLE ALBUMS");
+            }
+        }
+        if self.is_radio_on {
+            status.push("RADIO");
+        }
+        let speed = format!("{:.2}×", self.speed as f32 / 100.);
+        if self.speed != 100 {
+            status.push(speed.as_str());
+        }
         let vol = format!("{}%", self.volume);
         status.push(vol.as_str());
 
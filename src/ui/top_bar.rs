@@ -27,18 +27,20 @@ fn time_format() -> String {
 
 pub struct TopBar<'a> {
     theme: Theme,
-    settings: Settings,
+    settings: &'a Settings,
     tab_titles: &'a [&'a str],
     active_tab: usize,
+    equalizer_preset: Option<&'a str>,
     frame_count: u64,
 }
 
 impl<'a> TopBar<'a> {
     pub fn new(
-        settings: Settings,
+        settings: &'a Settings,
         theme: Theme,
         tab_titles: &'a [&'a str],
         active_tab: usize,
+        equalizer_preset: Option<&'a str>,
         frame_count: u64,
     ) -> Self {
         Self {
@@ -46,6 +48,7 @@ impl<'a> TopBar<'a> {
             theme,
             tab_titles,
             active_tab,
+            equalizer_preset,
             frame_count,
         }
     }
@@ -79,13 +82,16 @@ impl Widget for TopBar<'_> {
             );
         tabs.render(area, buf);
 
-        if self.settings.clock_display || self.settings.debug_frame_counter {
+        if self.settings.clock_display || self.settings.debug_frame_counter || self.equalizer_preset.is_some() {
             // TODO: measure rendered width of tabs and remaining horizontal space,
             //   and decide whether we should push this span elsewhere (where, tho?)
             let mut s: Vec<String> = Vec::new();
             if self.settings.debug_frame_counter {
                 s.push(format!("FRAME {}", self.frame_count));
             }
+            if let Some(equalizer_preset) = self.equalizer_preset {
+                s.push(format!("EQ {equalizer_preset}"));
+            }
             if self.settings.clock_display {
                 s.push(time_format());
             }